@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::started::host_name;
+use crate::{calculate_file_hash, ExecutionResult};
+
+/// File name of the manifest written into every run directory
+pub const MANIFEST_FILE: &str = "fastsave.yaml";
+
+/// File name of the manifest of runs archived with `--format json`
+pub const JSON_MANIFEST_FILE: &str = "fastsave.json";
+
+/// How the manifest is serialized, set with `--format` or `format` in the config file
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    #[default]
+    Yaml,
+    Json,
+}
+
+impl ManifestFormat {
+    /// Format of a manifest file, by its extension
+    pub fn of(path: &Path) -> ManifestFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ManifestFormat::Json,
+            _ => ManifestFormat::Yaml,
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ManifestFormat::Yaml => MANIFEST_FILE,
+            ManifestFormat::Json => JSON_MANIFEST_FILE,
+        }
+    }
+
+    pub fn serialize(self, result: &ExecutionResult) -> Result<String, Box<dyn Error>> {
+        Ok(match self {
+            ManifestFormat::Yaml => serde_yaml::to_string(result)?,
+            ManifestFormat::Json => serde_json::to_string_pretty(result)? + "\n",
+        })
+    }
+
+    pub fn deserialize(self, contents: &str) -> Result<ExecutionResult, Box<dyn Error>> {
+        Ok(match self {
+            ManifestFormat::Yaml => serde_yaml::from_str(contents)?,
+            ManifestFormat::Json => serde_json::from_str(contents)?,
+        })
+    }
+}
+
+/// Whether `name` is the file name of a manifest, in either format
+pub fn is_manifest(name: &str) -> bool {
+    name == MANIFEST_FILE || name == JSON_MANIFEST_FILE
+}
+
+/// Path of the manifest of a run: `fastsave.json` if the run was archived as JSON, else `fastsave.yaml`
+pub fn manifest_path(run_dir: &Path) -> PathBuf {
+    let json = run_dir.join(JSON_MANIFEST_FILE);
+    if json.is_file() {
+        json
+    } else {
+        run_dir.join(MANIFEST_FILE)
+    }
+}
+
+/// File name of the input fed to the script with `--stdin`
+pub const STDIN_FILE: &str = "stdin.txt";
+
+/// Name of the copy of the `--params` file, followed by the extension of the original
+pub const PARAMS_FILE_STEM: &str = "params";
+
+/// A parameters file given with `--params`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParamsFile {
+    /// Name of the copy in the run directory, like `params.yaml`
+    pub file: String,
+    /// Path the file was copied from
+    pub source: String,
+    pub sha256: String,
+}
+
+/// Where runs are placed inside the archive
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Nest runs in a directory per machine, `<archive>/<host>/`, so the nodes of a cluster
+    /// writing to one shared archive don't crowd a single directory
+    pub per_host: bool,
+    /// Pack the files of every run into a single `run.tar`, like `--tar`
+    pub tar: bool,
+}
+
+/// Directory new runs are created in
+pub fn run_base_dir(archive_dir: &Path, layout: &LayoutConfig) -> PathBuf {
+    if !layout.per_host {
+        return archive_dir.to_path_buf();
+    }
+    let host: String = host_name()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    archive_dir.join(if host.is_empty() || host.starts_with('.') { "unknown-host".to_string() } else { host })
+}
+
+pub struct ArchivedRun {
+    pub run_dir: PathBuf,
+    pub result: ExecutionResult,
+}
+
+impl ArchivedRun {
+    pub fn id(&self) -> String {
+        run_id(&self.run_dir)
+    }
+}
+
+/// The run id is the name of the run directory, e.g. `2025-01-17_run_simulation_run3`
+pub fn run_id(run_dir: &Path) -> String {
+    run_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| run_dir.to_string_lossy().into_owned())
+}
+
+/// Reads a manifest in the format given by its extension
+pub fn load_manifest(path: &Path) -> Result<ExecutionResult, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    ManifestFormat::of(path).deserialize(&contents)
+}
+
+/// Rewrites the manifest of a run in the format it was archived in, YAML for a new run
+pub fn save_manifest(run_dir: &Path, result: &ExecutionResult) -> Result<(), Box<dyn Error>> {
+    save_manifest_as(run_dir, result, ManifestFormat::of(&manifest_path(run_dir)))
+}
+
+/// Writes the manifest of a new run in `format`
+pub fn save_manifest_as(run_dir: &Path, result: &ExecutionResult, format: ManifestFormat) -> Result<(), Box<dyn Error>> {
+    fs::write(run_dir.join(format.file_name()), format.serialize(result)?)?;
+    Ok(())
+}
+
+/// Copies the script's input from a file, or from our own stdin if `source` is `-`, into the run directory
+pub fn archive_stdin(source: &str, run_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let path = run_dir.join(STDIN_FILE);
+    if source == "-" {
+        std::io::copy(&mut std::io::stdin().lock(), &mut fs::File::create(&path)?)?;
+    } else {
+        fs::copy(source, &path).map_err(|e| format!("Failed to read stdin file {}: {}", source, e))?;
+    }
+    Ok(path)
+}
+
+/// Copies the parameters file into the run directory, keeping its extension so the script can tell the format
+pub fn archive_params(source: &str, run_dir: &Path) -> Result<ParamsFile, Box<dyn Error>> {
+    let file = match Path::new(source).extension() {
+        Some(extension) => format!("{}.{}", PARAMS_FILE_STEM, extension.to_string_lossy()),
+        None => PARAMS_FILE_STEM.to_string(),
+    };
+    let path = run_dir.join(&file);
+    fs::copy(source, &path).map_err(|e| format!("Failed to read parameters file {}: {}", source, e))?;
+    Ok(ParamsFile { file, source: source.to_string(), sha256: calculate_file_hash(&path)? })
+}
+
+/// Resolves a run given either as a path to its directory or as a run id within the archive
+pub fn resolve_run(archive_dir: &Path, run: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let as_path = Path::new(run);
+    if manifest_path(as_path).is_file() {
+        return Ok(as_path.to_path_buf());
+    }
+
+    let in_archive = archive_dir.join(run);
+    if manifest_path(&in_archive).is_file() {
+        return Ok(in_archive);
+    }
+
+    // Run ids are unique per directory, so runs of different hosts may share one
+    let matches: Vec<PathBuf> = find_runs(archive_dir)?
+        .into_iter()
+        .filter(|archived| archived.id() == run)
+        .map(|archived| archived.run_dir)
+        .collect();
+    match &matches[..] {
+        [] => Err(format!("Run not found: {}", run).into()),
+        [run_dir] => Ok(run_dir.clone()),
+        _ => {
+            let paths: Vec<String> = matches
+                .iter()
+                .map(|run_dir| run_dir.strip_prefix(archive_dir).unwrap_or(run_dir).to_string_lossy().into_owned())
+                .collect();
+            Err(format!("Run id {} is ambiguous, give one of: {}", run, paths.join(", ")).into())
+        }
+    }
+}
+
+/// Collects all runs below the archive directory, ordered by start time
+pub fn find_runs(archive_dir: &Path) -> Result<Vec<ArchivedRun>, Box<dyn Error>> {
+    let mut runs = Vec::new();
+    if archive_dir.is_dir() {
+        collect_runs(archive_dir, &mut runs)?;
+    }
+    runs.sort_by_key(|run| run.result.start_time);
+    Ok(runs)
+}
+
+fn collect_runs(dir: &Path, runs: &mut Vec<ArchivedRun>) -> Result<(), Box<dyn Error>> {
+    let manifest = manifest_path(dir);
+    if manifest.is_file() {
+        match load_manifest(&manifest) {
+            Ok(result) => runs.push(ArchivedRun { run_dir: dir.to_path_buf(), result }),
+            Err(e) => tracing::debug!("Skipping unreadable manifest {}: {}", manifest.display(), e),
+        }
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Don't follow symlinks so linked views of the archive aren't counted twice
+        if entry.file_type()?.is_dir() {
+            collect_runs(&entry.path(), runs)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a duration like `90s`, `30m`, `12h`, `7d` or `2w`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}': expected e.g. 30m, 12h, 7d", s);
+    let split = s.len().checked_sub(1).filter(|&i| s.is_char_boundary(i)).ok_or_else(invalid)?;
+    let (amount, unit) = s.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a `--since` value: a relative age like `30m`, `12h`, `7d` or `2w`,
+/// a date (`2025-01-17`, local midnight) or an RFC 3339 timestamp
+pub fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or("invalid date")?;
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| format!("invalid date '{}'", s));
+    }
+
+    let age = parse_duration(s).map_err(|_| format!("invalid time '{}': expected e.g. 7d, 12h, 2025-01-17", s))?;
+    Ok(Utc::now() - age)
+}
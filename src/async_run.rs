@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::io;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+pub use crate::observer::OutputLine;
+use crate::command::ScriptCommand;
+use crate::redact::Redactor;
+use crate::{get_git_info, script_interpreter, ExecutionResult, FastsaveConfig};
+
+/// A script started with `execute_script_async`
+pub struct RunHandle {
+    lines: mpsc::UnboundedReceiver<OutputLine>,
+    cancel: Option<oneshot::Sender<()>>,
+    result: JoinHandle<Result<ExecutionResult, Box<dyn Error + Send + Sync>>>,
+}
+
+impl RunHandle {
+    /// The next line the script wrote to stdout or stderr; `None` once both are closed
+    pub async fn next_line(&mut self) -> Option<OutputLine> {
+        self.lines.recv().await
+    }
+
+    /// Kills the script. The run still completes, with exit code -1 and the output captured so far.
+    pub fn cancel(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            let _ = cancel.send(());
+        }
+    }
+
+    /// Waits for the script to exit. Output lines not taken with `next_line` are still part of the result.
+    pub async fn wait(self) -> Result<ExecutionResult, Box<dyn Error + Send + Sync>> {
+        // Dropping the sender would not cancel the run, but keep it until the end anyway
+        let _cancel = self.cancel;
+        self.result.await?
+    }
+}
+
+/// Starts the script like `execute_script_with_config` without blocking a thread while it runs.
+///
+/// Must be called from within a tokio runtime. Output is not echoed to the terminal;
+/// take it from the returned handle instead. Dropping the handle leaves the script running.
+pub fn execute_script_async(script_path: &str, output_dir: &str, message: Option<String>, script_args: &[String], interpreter_override: Option<&String>, config: &FastsaveConfig) -> Result<RunHandle, Box<dyn Error>> {
+    let interpreter = script_interpreter(script_path, interpreter_override, config)?;
+    let redactor = Arc::new(Redactor::new(&config.redaction)?);
+    let script_command = ScriptCommand::new(&interpreter, script_path, output_dir, script_args);
+    let command_line = script_command.command_line();
+
+    let start_time = Utc::now();
+    let mut command = Command::new(&script_command.program);
+    command
+        .args(&script_command.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Own process group, so cancelling also reaches the processes the script started
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command.spawn()?;
+
+    let (line_sender, lines) = mpsc::unbounded_channel();
+    let stdout = tokio::spawn(capture_lines(child.stdout.take().expect("Failed to capture stdout"), line_sender.clone(), OutputLine::Stdout, redactor.clone()));
+    let stderr = tokio::spawn(capture_lines(child.stderr.take().expect("Failed to capture stderr"), line_sender, OutputLine::Stderr, redactor));
+    let (cancel, mut cancelled) = oneshot::channel();
+
+    let script_path = script_path.to_string();
+    let result = tokio::spawn(async move {
+        let git_path = script_path.clone();
+        let git_info = tokio::task::spawn_blocking(move || get_git_info(&git_path));
+
+        // A dropped sender doesn't match `Ok`, so only an explicit cancel kills the script
+        let status = tokio::select! {
+            status = child.wait() => Some(status),
+            Ok(()) = &mut cancelled => None,
+        };
+        let status = match status {
+            Some(status) => status?,
+            None => {
+                kill_script(&mut child).await?;
+                child.wait().await?
+            }
+        };
+        let end_time = Utc::now();
+
+        Ok(ExecutionResult {
+            script_path,
+            start_time,
+            end_time,
+            duration_ms: (end_time - start_time).num_milliseconds().max(0) as u64,
+            exit_code: status.code().unwrap_or(-1),
+            stdout: stdout.await?,
+            stderr: stderr.await?,
+            message,
+            git_info: git_info.await?,
+            command: Some(script_command),
+            command_line,
+            ..Default::default()
+        })
+    });
+
+    Ok(RunHandle { lines, cancel: Some(cancel), result })
+}
+
+/// Collects the lines of `stream`, forwarding each one while anyone listens. Invalid UTF-8
+/// is replaced, so the pipe is read to the end.
+async fn capture_lines<R: AsyncRead + Unpin>(stream: R, sender: mpsc::UnboundedSender<OutputLine>, wrap: fn(String) -> OutputLine, redactor: Arc<Redactor>) -> String {
+    let mut captured = String::new();
+    let mut reader = BufReader::new(stream);
+    let mut bytes = Vec::new();
+    while let Ok(1..) = reader.read_until(b'\n', &mut bytes).await {
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
+            }
+        }
+        let line = redactor.redact(&String::from_utf8_lossy(&bytes));
+        bytes.clear();
+        captured.push_str(&line);
+        captured.push('\n');
+        let _ = sender.send(wrap(line));
+    }
+    captured
+}
+
+/// Kills the script and, on Unix, its process group; a subprocess left behind would keep the output pipes open
+async fn kill_script(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: plain system call; the group was created for this script in `execute_script_async`
+        unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+    }
+    child.kill().await
+}
@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, run_id};
+use crate::logs::{self, STDERR_LIVE, STDERR_LOG, STDOUT_LIVE, STDOUT_LOG};
+use crate::notify::{format_duration, run_succeeded};
+use crate::started::{load_started, STARTED_FILE};
+use crate::status::{run_state, unfinished_runs, RunState};
+
+/// How often the logs and the run directory are checked for news
+const POLL: Duration = Duration::from_millis(250);
+
+#[derive(Args)]
+pub struct AttachArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// One output stream of the run followed by `attach`
+struct Stream {
+    compressed: &'static str,
+    live: &'static str,
+    /// Bytes of the stream printed so far
+    printed: u64,
+    /// Start of a line whose end wasn't written yet
+    partial: Vec<u8>,
+    out: fn(&[u8]),
+}
+
+impl Stream {
+    fn new(compressed: &'static str, live: &'static str, out: fn(&[u8])) -> Stream {
+        Stream { compressed, live, printed: 0, partial: Vec::new(), out }
+    }
+
+    /// Prints the lines completed since the last poll
+    fn poll(&mut self, run_dir: &Path) -> io::Result<()> {
+        let offset = self.printed + self.partial.len() as u64;
+        self.partial.extend(logs::read_running_log(run_dir, self.compressed, self.live, offset)?);
+        if let Some(end) = self.partial.iter().rposition(|&byte| byte == b'\n') {
+            let rest = self.partial.split_off(end + 1);
+            (self.out)(&self.partial);
+            self.printed += self.partial.len() as u64;
+            self.partial = rest;
+        }
+        Ok(())
+    }
+
+    /// Prints what the script wrote after the last poll, from the finished output
+    fn finish(&self, output: &str) {
+        if let Some(rest) = output.as_bytes().get(self.printed as usize..) {
+            (self.out)(rest);
+        }
+    }
+}
+
+fn print_stdout(bytes: &[u8]) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(bytes).and_then(|()| stdout.flush());
+}
+
+fn print_stderr(bytes: &[u8]) {
+    let _ = io::stderr().lock().write_all(bytes);
+}
+
+/// Directory of a run in progress, which `resolve_run` can't find without a manifest, or of a finished run
+fn resolve_attachable(archive_dir: &Path, run: &str) -> Result<PathBuf, Box<dyn Error>> {
+    for run_dir in [Path::new(run).to_path_buf(), archive_dir.join(run)] {
+        if run_dir.join(STARTED_FILE).is_file() {
+            return Ok(run_dir);
+        }
+    }
+    match unfinished_runs(archive_dir)?.into_iter().find(|(run_dir, _)| run_id(run_dir) == run) {
+        Some((run_dir, _)) => Ok(run_dir),
+        None => resolve_run(archive_dir, run),
+    }
+}
+
+/// Follows the output of a run until its manifest is saved, then reports how it ended.
+/// Fails if the run failed or its heartbeat stopped before it finished.
+pub fn attach(run_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let mut stdout = Stream::new(STDOUT_LOG, STDOUT_LIVE, print_stdout);
+    let mut stderr = Stream::new(STDERR_LOG, STDERR_LIVE, print_stderr);
+    let result = loop {
+        let manifest = manifest_path(run_dir);
+        if manifest.is_file() {
+            // A manifest that is still being written is read at the next poll
+            if let Ok(result) = load_manifest(&manifest) {
+                break result;
+            }
+        }
+        stdout.poll(run_dir)?;
+        stderr.poll(run_dir)?;
+        match load_started(run_dir) {
+            // Only the heartbeat matters here, so a run is never considered hung
+            Ok(start) if run_state(&start, Utc::now(), chrono::Duration::MAX) == RunState::Lost => {
+                return Err(format!("Run {} was lost: its heartbeat stopped before it finished", run_id(run_dir)).into());
+            }
+            Err(_) if !run_dir.join(STARTED_FILE).is_file() && !manifest_path(run_dir).is_file() => {
+                return Err(format!("Run {} was deleted before it finished", run_id(run_dir)).into());
+            }
+            _ => {}
+        }
+        thread::sleep(POLL);
+    };
+
+    // The live logs are gone once the manifest is saved, so the rest comes from the finished run
+    let (full_stdout, full_stderr) = logs::full_output(run_dir, &result);
+    stdout.finish(&full_stdout);
+    stderr.finish(&full_stderr);
+
+    let id = run_id(run_dir);
+    let outcome = format!("exit code {} after {}", result.exit_code, format_duration(result.duration_ms));
+    if !run_succeeded(&result) {
+        return Err(format!("Run {} failed with {}", id, outcome).into());
+    }
+    println!("Run {} finished with {}", id, outcome);
+    Ok(())
+}
+
+pub fn run_attach(args: &AttachArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_attachable(Path::new(&args.archive_dir), &args.run)?;
+    attach(&run_dir)
+}
@@ -0,0 +1,216 @@
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, ArchivedRun, STDIN_FILE};
+use crate::calculate_file_hash;
+use crate::digest::run_digest;
+use crate::started::host_name;
+use crate::tarball;
+
+/// Attestation written next to the files of a run unless `--attestation` names another path
+pub const ATTESTATION_FILE: &str = "attestation.yaml";
+
+#[derive(Args)]
+pub struct AttestArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Check an attestation against the run instead of signing one
+    #[arg(long = "verify")]
+    pub verify: bool,
+
+    /// PEM file with the RSA or EC private key to sign with, or with --verify the public key of the signer
+    #[arg(long = "key", value_name = "PEM")]
+    pub key: String,
+
+    /// Who signs, recorded in the attestation; defaults to user@host
+    #[arg(long = "signer", value_name = "NAME", conflicts_with = "verify")]
+    pub signer: Option<String>,
+
+    /// Path of the attestation file, by default attestation.yaml in the run directory
+    #[arg(long = "attestation", value_name = "PATH")]
+    pub attestation: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// What the signer vouches for, signed as the YAML text it is written as
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttestationStatement {
+    pub run_id: String,
+    pub run_digest: String,
+    pub signer: String,
+    pub signed_at: DateTime<Utc>,
+}
+
+/// A statement and its signature, in hex
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Attestation {
+    #[serde(flatten)]
+    pub statement: AttestationStatement,
+    pub signature: String,
+}
+
+/// Hashes the files of the run again, in `run.tar` if it is packed, and recomputes the run
+/// digest from them. Fails if a file is missing or differs from the manifest, or the digest
+/// recorded in the manifest doesn't match its fields.
+pub fn verified_digest(run: &ArchivedRun) -> Result<String, Box<dyn Error>> {
+    let result = &run.result;
+    let packed = if tarball::is_packed(&run.run_dir) { Some(tarball::file_hashes(&run.run_dir)?) } else { None };
+    let params_file = result.params.as_ref().map(|params| params.file.as_str());
+    let actual_hash = |file: &str| -> Result<String, Box<dyn Error>> {
+        match &packed {
+            Some(hashes) if file != STDIN_FILE && Some(file) != params_file => hashes.get(file).cloned().ok_or_else(|| format!("{} is not in {}", file, tarball::RUN_TAR_FILE).into()),
+            _ => calculate_file_hash(&run.run_dir.join(file)).map_err(|e| format!("Failed to hash {}: {}", file, e).into()),
+        }
+    };
+
+    let recorded = result.file_hashes.iter().map(|(file, hash)| (file.as_str(), hash));
+    let stdin = result.stdin_hash.as_ref().map(|hash| (STDIN_FILE, hash));
+    let params = result.params.as_ref().map(|params| (params.file.as_str(), &params.sha256));
+    let mut changed = Vec::new();
+    for (file, hash) in recorded.chain(stdin).chain(params) {
+        if actual_hash(file)? != *hash {
+            changed.push(file);
+        }
+    }
+    if !changed.is_empty() {
+        return Err(format!("Files of {} differ from its manifest: {}", run.id(), changed.join(", ")).into());
+    }
+
+    let digest = run_digest(result);
+    if result.run_digest.as_ref().is_some_and(|recorded| *recorded != digest) {
+        return Err(format!("The run digest in the manifest of {} doesn't match its fields", run.id()).into());
+    }
+    Ok(digest)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid signature '{}': expected hex digits", hex);
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid())).collect()
+}
+
+/// Runs `openssl dgst -sha256` with `args`, feeding it `data`; returns its stdout and whether it succeeded
+fn openssl_dgst(args: &[&str], data: &[u8]) -> Result<(Vec<u8>, bool, String), Box<dyn Error>> {
+    let mut child = Command::new("openssl")
+        .arg("dgst")
+        .arg("-sha256")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run openssl, is it installed? {}", e))?;
+    child.stdin.take().expect("stdin is piped").write_all(data)?;
+    let output = child.wait_with_output()?;
+    Ok((output.stdout, output.status.success(), String::from_utf8_lossy(&output.stderr).trim().to_string()))
+}
+
+/// Signs the SHA-256 of `data` with the private key in `key`; returns the signature in hex
+pub fn sign(data: &[u8], key: &Path) -> Result<String, Box<dyn Error>> {
+    let (signature, success, stderr) = openssl_dgst(&["-sign", &key.to_string_lossy()], data)?;
+    if !success {
+        return Err(format!("Failed to sign with {}: {}", key.display(), stderr).into());
+    }
+    Ok(to_hex(&signature))
+}
+
+/// Whether `signature` in hex is a signature of `data` by the private key belonging to `public_key`
+pub fn verify_signature(data: &[u8], signature: &str, public_key: &Path) -> Result<bool, Box<dyn Error>> {
+    // openssl fails the same way for a key it can't read as for a wrong signature
+    fs::File::open(public_key).map_err(|e| format!("Failed to read the public key {}: {}", public_key.display(), e))?;
+    // openssl reads the signature from a file only
+    let mut signature_file = tempfile::NamedTempFile::new()?;
+    signature_file.write_all(&from_hex(signature)?)?;
+    let (_, success, stderr) = openssl_dgst(&["-verify", &public_key.to_string_lossy(), "-signature", &signature_file.path().to_string_lossy()], data)?;
+    if !success {
+        tracing::debug!("openssl rejected the signature: {}", stderr);
+    }
+    Ok(success)
+}
+
+/// The signed part of an attestation file: everything but the signature line
+fn signed_text(text: &str) -> String {
+    text.lines().filter(|line| !line.starts_with("signature:")).map(|line| format!("{}\n", line)).collect()
+}
+
+/// The user running fastsave, from the environment or else `whoami`
+fn user_name() -> Option<String> {
+    let variables: &[&str] = if cfg!(windows) { &["USERNAME"] } else { &["USER", "LOGNAME"] };
+    variables
+        .iter()
+        .find_map(|variable| std::env::var(variable).ok().filter(|user| !user.trim().is_empty()))
+        .or_else(|| Command::new("whoami").output().ok().filter(|output| output.status.success()).map(|output| String::from_utf8_lossy(&output.stdout).into_owned()))
+        .map(|user| user.trim().to_string())
+        .filter(|user| !user.is_empty())
+}
+
+fn attestation_path(run_dir: &Path, attestation: Option<&str>) -> PathBuf {
+    attestation.map_or_else(|| run_dir.join(ATTESTATION_FILE), PathBuf::from)
+}
+
+/// Checks the files of the run and signs its digest with the private key in `key`,
+/// writing the attestation to `path`
+pub fn create_attestation(run: &ArchivedRun, key: &Path, signer: &str, path: &Path) -> Result<Attestation, Box<dyn Error>> {
+    if signer.contains('\n') {
+        return Err("The signer must be a single line".into());
+    }
+    let statement = AttestationStatement { run_id: run.id(), run_digest: verified_digest(run)?, signer: signer.to_string(), signed_at: Utc::now() };
+    let text = serde_yaml::to_string(&statement)?;
+    let signature = sign(text.as_bytes(), key)?;
+    fs::write(path, format!("{}signature: {}\n", text, signature))?;
+    Ok(Attestation { statement, signature })
+}
+
+/// Checks the signature of the attestation at `path` with the public key in `public_key`,
+/// and that the run still has the attested digest
+pub fn verify_attestation(run: &ArchivedRun, path: &Path, public_key: &Path) -> Result<Attestation, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read the attestation {}: {}", path.display(), e))?;
+    let attestation: Attestation = serde_yaml::from_str(&text).map_err(|e| format!("Failed to parse the attestation {}: {}", path.display(), e))?;
+    if !verify_signature(signed_text(&text).as_bytes(), &attestation.signature, public_key)? {
+        return Err(format!("The signature of {} doesn't match {}", path.display(), public_key.display()).into());
+    }
+    let digest = verified_digest(run)?;
+    if digest != attestation.statement.run_digest {
+        return Err(format!("{} has the digest {}, but {} was attested", run.id(), digest, attestation.statement.run_digest).into());
+    }
+    Ok(attestation)
+}
+
+pub fn run_attest(args: &AttestArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(Path::new(&args.archive_dir), &args.run)?;
+    let run = ArchivedRun { result: load_manifest(&manifest_path(&run_dir))?, run_dir };
+    let path = attestation_path(&run.run_dir, args.attestation.as_deref());
+    let key = Path::new(&args.key);
+
+    if args.verify {
+        let attestation = verify_attestation(&run, &path, key)?;
+        let statement = &attestation.statement;
+        println!("Attestation verified: {} has the digest {}", statement.run_id, statement.run_digest);
+        println!("Signed by {} at {}", statement.signer, statement.signed_at.to_rfc3339());
+    } else {
+        let signer = match &args.signer {
+            Some(signer) => signer.clone(),
+            None => format!("{}@{}", user_name().ok_or("Failed to determine the user name, give the signer with --signer")?, host_name()),
+        };
+        let attestation = create_attestation(&run, key, &signer, &path)?;
+        println!("Attested {} with the digest {}: {}", run.id(), attestation.statement.run_digest, path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::Serialize;
+
+use crate::archive::{load_manifest, manifest_path, ArchivedRun, STDIN_FILE};
+use crate::baseline::load_baselines;
+use crate::batch::{BatchResult, BATCH_FILE};
+use crate::benchmark::{BenchmarkResult, BENCHMARK_FILE};
+use crate::calculate_file_hash;
+use crate::digest::run_digest;
+use crate::experiment::EXPERIMENTS_DIR;
+use crate::tarball;
+
+#[derive(Args)]
+pub struct AuditArgs {
+    /// Print the report as JSON
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A manifest, baseline or benchmark file, or output file that can't be read or parsed
+    Unreadable,
+    /// An output file listed in a manifest doesn't exist
+    MissingFile,
+    /// An output file or the archived stdin doesn't match its recorded hash, or the manifest its run digest
+    HashMismatch,
+    /// An experiment link doesn't point to a run
+    BrokenLink,
+    /// A baseline, benchmark or parent refers to a run that doesn't exist
+    MissingRun,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditIssue {
+    pub kind: IssueKind,
+    /// Path of the affected file, link or index
+    pub path: String,
+    pub detail: String,
+}
+
+#[derive(Serialize)]
+pub struct AuditReport {
+    pub archive_dir: String,
+    pub audited_at: DateTime<Utc>,
+    pub runs: usize,
+    pub files: usize,
+    pub issues: Vec<AuditIssue>,
+}
+
+impl AuditReport {
+    fn issue(&mut self, kind: IssueKind, path: &Path, detail: impl Into<String>) {
+        self.issues.push(AuditIssue { kind, path: path.to_string_lossy().into_owned(), detail: detail.into() });
+    }
+}
+
+/// Loads every manifest below `dir` like `find_runs`, reporting the ones that can't be parsed instead of skipping them
+fn collect_runs(dir: &Path, runs: &mut Vec<ArchivedRun>, report: &mut AuditReport) -> Result<(), Box<dyn Error>> {
+    let manifest = manifest_path(dir);
+    if manifest.is_file() {
+        match load_manifest(&manifest) {
+            Ok(result) => runs.push(ArchivedRun { run_dir: dir.to_path_buf(), result }),
+            Err(e) => report.issue(IssueKind::Unreadable, &manifest, e.to_string()),
+        }
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_runs(&entry.path(), runs, report)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_files(run: &ArchivedRun, report: &mut AuditReport) {
+    let recorded = run.result.file_hashes.iter().map(|(file, hash)| (file.as_str(), hash));
+    let stdin = run.result.stdin_hash.as_ref().map(|hash| (STDIN_FILE, hash));
+    let params = run.result.params.as_ref().map(|params| (params.file.as_str(), &params.sha256));
+    let params_file = run.result.params.as_ref().map(|params| params.file.as_str());
+    // The files of a packed run are checked inside run.tar; the input and parameters stay outside
+    let packed = if tarball::is_packed(&run.run_dir) {
+        match tarball::file_hashes(&run.run_dir) {
+            Ok(hashes) => Some(hashes),
+            Err(e) => {
+                report.issue(IssueKind::Unreadable, &run.run_dir.join(tarball::RUN_TAR_FILE), e.to_string());
+                return;
+            }
+        }
+    } else {
+        None
+    };
+    for (file, hash) in recorded.chain(stdin).chain(params) {
+        let path = run.run_dir.join(file);
+        report.files += 1;
+        let actual = match &packed {
+            Some(hashes) if file != STDIN_FILE && Some(file) != params_file => {
+                hashes.get(file).cloned().ok_or_else(|| format!("not in {}", tarball::RUN_TAR_FILE).into())
+            }
+            _ => calculate_file_hash(&path),
+        };
+        match actual {
+            Ok(actual) if &actual != hash => {
+                report.issue(IssueKind::HashMismatch, &path, format!("expected {}, found {}", hash, actual))
+            }
+            Ok(_) => {}
+            Err(e) if !path.exists() => report.issue(IssueKind::MissingFile, &path, e.to_string()),
+            Err(e) => report.issue(IssueKind::Unreadable, &path, e.to_string()),
+        }
+    }
+}
+
+fn check_experiment_links(archive_dir: &Path, report: &mut AuditReport) -> Result<(), Box<dyn Error>> {
+    let experiments_dir = archive_dir.join(EXPERIMENTS_DIR);
+    if !experiments_dir.is_dir() {
+        return Ok(());
+    }
+    for experiment in fs::read_dir(experiments_dir)? {
+        let experiment = experiment?.path();
+        if !experiment.is_dir() {
+            continue;
+        }
+        for link in fs::read_dir(&experiment)? {
+            let link = link?.path();
+            // Follows the link, so a dangling one has no manifest
+            if !manifest_path(&link).is_file() {
+                let target = fs::read_link(&link).map(|target| target.display().to_string()).unwrap_or_default();
+                report.issue(IssueKind::BrokenLink, &link, format!("no run at {}", target));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the archive without modifying it
+pub fn audit_archive(archive_dir: &Path) -> Result<AuditReport, Box<dyn Error>> {
+    let mut report = AuditReport {
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+        audited_at: Utc::now(),
+        runs: 0,
+        files: 0,
+        issues: Vec::new(),
+    };
+    let mut runs = Vec::new();
+    if archive_dir.is_dir() {
+        collect_runs(archive_dir, &mut runs, &mut report)?;
+    }
+    runs.sort_by_key(|run| run.result.start_time);
+    report.runs = runs.len();
+    let run_ids: HashSet<String> = runs.iter().map(ArchivedRun::id).collect();
+
+    for run in &runs {
+        check_files(run, &mut report);
+        if let Some(digest) = run.result.run_digest.as_ref().filter(|digest| **digest != run_digest(&run.result)) {
+            report.issue(IssueKind::HashMismatch, &manifest_path(&run.run_dir), format!("run digest {} doesn't match the manifest", digest));
+        }
+        for parent in run.result.parents.iter().filter(|parent| !run_ids.contains(*parent)) {
+            report.issue(IssueKind::MissingRun, &manifest_path(&run.run_dir), format!("parent run {} not found", parent));
+        }
+    }
+
+    check_experiment_links(archive_dir, &mut report)?;
+
+    let baselines_path = archive_dir.join(crate::baseline::BASELINES_FILE);
+    match load_baselines(archive_dir) {
+        Ok(baselines) => {
+            for (script, run) in baselines.iter().filter(|(_, run)| !run_ids.contains(*run)) {
+                report.issue(IssueKind::MissingRun, &baselines_path, format!("baseline {} of {} not found", run, script));
+            }
+        }
+        Err(e) => report.issue(IssueKind::Unreadable, &baselines_path, e.to_string()),
+    }
+
+    for batch in summary_files(archive_dir, BATCH_FILE)? {
+        match fs::read_to_string(&batch).map_err(|e| e.to_string()).and_then(|contents| {
+            serde_yaml::from_str::<BatchResult>(&contents).map_err(|e| e.to_string())
+        }) {
+            Ok(summary) => {
+                for id in summary.jobs.iter().filter_map(|job| job.outcome.id.as_ref()).filter(|id| !run_ids.contains(*id)) {
+                    report.issue(IssueKind::MissingRun, &batch, format!("batch run {} not found", id));
+                }
+            }
+            Err(e) => report.issue(IssueKind::Unreadable, &batch, e),
+        }
+    }
+
+    for benchmark in summary_files(archive_dir, BENCHMARK_FILE)? {
+        match fs::read_to_string(&benchmark).map_err(|e| e.to_string()).and_then(|contents| {
+            serde_yaml::from_str::<BenchmarkResult>(&contents).map_err(|e| e.to_string())
+        }) {
+            Ok(summary) => {
+                for run in summary.runs.iter().filter(|run| !run_ids.contains(&run.id)) {
+                    report.issue(IssueKind::MissingRun, &benchmark, format!("benchmark run {} not found", run.id));
+                }
+            }
+            Err(e) => report.issue(IssueKind::Unreadable, &benchmark, e),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Benchmark or batch summaries directly inside the archive directory
+fn summary_files(archive_dir: &Path, name: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !archive_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(archive_dir)? {
+        let path = entry?.path().join(name);
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn run_audit(args: &AuditArgs) -> Result<(), Box<dyn Error>> {
+    let report = audit_archive(Path::new(&args.archive_dir))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("Audited {} runs with {} files in {}", report.runs, report.files, report.archive_dir);
+        for issue in &report.issues {
+            let kind = serde_json::to_value(issue.kind)?;
+            println!("{}: {} ({})", kind.as_str().unwrap_or_default(), issue.path, issue.detail);
+        }
+    }
+
+    if !report.issues.is_empty() {
+        return Err(format!("Audit found {} issues", report.issues.len()).into());
+    }
+    if !args.json {
+        println!("No issues found");
+    }
+    Ok(())
+}
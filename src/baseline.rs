@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, run_id};
+use crate::{get_script_basename, ExecutionResult};
+
+/// File inside the archive mapping script names to their baseline run
+pub const BASELINES_FILE: &str = "baselines.yaml";
+
+#[derive(Args)]
+pub struct BaselineArgs {
+    #[command(subcommand)]
+    pub action: BaselineAction,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive", global = true)]
+    pub archive_dir: String,
+}
+
+#[derive(Subcommand)]
+pub enum BaselineAction {
+    /// Use a run as the baseline for later runs of the same script
+    Set {
+        /// Run id or path to the run directory
+        run: String,
+    },
+    /// Remove the baseline of a script
+    Clear {
+        /// Script name without extension
+        script: String,
+    },
+}
+
+/// Differences between the outputs of a run and a reference run
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct OutputComparison {
+    pub changed_files: Vec<String>,
+    pub missing_files: Vec<String>,
+    pub added_files: Vec<String>,
+    pub changed_metrics: Vec<String>,
+}
+
+impl OutputComparison {
+    /// New files are not a deviation; changed or missing outputs and metrics are
+    pub fn has_deviations(&self) -> bool {
+        !self.changed_files.is_empty() || !self.missing_files.is_empty() || !self.changed_metrics.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BaselineComparison {
+    pub baseline_run: String,
+    pub regression: bool,
+    #[serde(flatten)]
+    pub differences: OutputComparison,
+}
+
+pub fn compare_outputs(reference: &ExecutionResult, result: &ExecutionResult) -> OutputComparison {
+    let mut comparison = OutputComparison::default();
+
+    for (file, hash) in &reference.file_hashes {
+        match result.file_hashes.get(file) {
+            Some(new_hash) if new_hash != hash => comparison.changed_files.push(file.clone()),
+            Some(_) => {}
+            None => comparison.missing_files.push(file.clone()),
+        }
+    }
+    comparison.added_files = result.file_hashes
+        .keys()
+        .filter(|file| !reference.file_hashes.contains_key(*file))
+        .cloned()
+        .collect();
+
+    comparison.changed_metrics = reference.metrics
+        .iter()
+        .filter(|(name, value)| result.metrics.get(*name) != Some(value))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    comparison.changed_files.sort();
+    comparison.missing_files.sort();
+    comparison.added_files.sort();
+    comparison.changed_metrics.sort();
+    comparison
+}
+
+pub fn load_baselines(archive_dir: &Path) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let path = archive_dir.join(BASELINES_FILE);
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn save_baselines(archive_dir: &Path, baselines: &BTreeMap<String, String>) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(archive_dir)?;
+    fs::write(archive_dir.join(BASELINES_FILE), serde_yaml::to_string(baselines)?)?;
+    Ok(())
+}
+
+/// Marks a run as the baseline for its script and returns the script name
+pub fn set_baseline(archive_dir: &Path, run: &str) -> Result<String, Box<dyn Error>> {
+    let run_dir = resolve_run(archive_dir, run)?;
+    let result = load_manifest(&manifest_path(&run_dir))?;
+    let script = get_script_basename(&result.script_path);
+
+    let mut baselines = load_baselines(archive_dir)?;
+    baselines.insert(script.clone(), run_id(&run_dir));
+    save_baselines(archive_dir, &baselines)?;
+    Ok(script)
+}
+
+pub fn clear_baseline(archive_dir: &Path, script: &str) -> Result<bool, Box<dyn Error>> {
+    let mut baselines = load_baselines(archive_dir)?;
+    let removed = baselines.remove(script).is_some();
+    save_baselines(archive_dir, &baselines)?;
+    Ok(removed)
+}
+
+/// Compares a finished run against the baseline of its script, if one is set
+pub fn compare_with_baseline(archive_dir: &Path, result: &ExecutionResult) -> Result<Option<BaselineComparison>, Box<dyn Error>> {
+    let script = get_script_basename(&result.script_path);
+    let baseline_run = match load_baselines(archive_dir)?.remove(&script) {
+        Some(run) => run,
+        None => return Ok(None),
+    };
+
+    let baseline_dir = resolve_run(archive_dir, &baseline_run)?;
+    let baseline = load_manifest(&manifest_path(&baseline_dir))?;
+    let differences = compare_outputs(&baseline, result);
+
+    Ok(Some(BaselineComparison {
+        baseline_run,
+        regression: differences.has_deviations(),
+        differences,
+    }))
+}
+
+pub fn run_baseline(args: &BaselineArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    match &args.action {
+        BaselineAction::Set { run } => {
+            let script = set_baseline(archive_dir, run)?;
+            println!("Baseline for {} set to {}", script, run);
+        }
+        BaselineAction::Clear { script } => {
+            if clear_baseline(archive_dir, script)? {
+                println!("Baseline for {} cleared", script);
+            } else {
+                println!("No baseline set for {}", script);
+            }
+        }
+    }
+    Ok(())
+}
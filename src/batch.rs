@@ -0,0 +1,446 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{load_manifest, manifest_path, resolve_run};
+use crate::compare::metric_value;
+use crate::experiment::symlink_dir;
+use crate::notify::{format_duration, run_succeeded};
+use crate::table::Table;
+use crate::{create_numbered_dir, run_script, Cli};
+
+/// Summary file written into the batch directory
+pub const BATCH_FILE: &str = "batch.yaml";
+
+/// Parameters and metrics of every job, written into the batch directory
+pub const RESULTS_FILE: &str = "batch-results.csv";
+
+/// Link in the batch directory to the run that did best on the objective
+pub const BEST_LINK: &str = "best";
+
+#[derive(Args)]
+pub struct BatchArgs {
+    /// File with one script and its arguments per line, or `-` to read them from stdin;
+    /// empty lines and lines starting with # are skipped
+    pub jobs_file: String,
+
+    /// Number of jobs to run at the same time
+    #[arg(short = 'j', long = "jobs", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: u32,
+
+    /// Optional message recorded with every run
+    #[arg(short = 'm', long = "message")]
+    pub message: Option<String>,
+
+    /// Pick the run with the lowest value of this metric as the best (e.g. loss or metrics.loss)
+    #[arg(long = "minimize", value_name = "METRIC", conflicts_with = "maximize")]
+    pub minimize: Option<String>,
+
+    /// Pick the run with the highest value of this metric as the best
+    #[arg(long = "maximize", value_name = "METRIC")]
+    pub maximize: Option<String>,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// A line of the jobs file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    /// Line number in the jobs file, starting at 1
+    pub line: usize,
+    pub script: String,
+    pub script_args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Success,
+    /// The script ran but failed
+    Failed,
+    /// fastsave reported an error, e.g. the script doesn't exist or its outputs failed a check
+    Error,
+}
+
+/// How a job went, as recorded in the batch and queue summaries
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobOutcome {
+    pub status: JobStatus,
+    /// Id of the archived run; missing if the job never got a run directory
+    pub id: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl JobOutcome {
+    /// The status for a summary table, with the exit code of failed scripts
+    pub fn status_text(&self) -> String {
+        match self.status {
+            JobStatus::Success => "success".to_string(),
+            JobStatus::Failed => format!("failed ({})", self.exit_code.unwrap_or(-1)),
+            JobStatus::Error => "error".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchJob {
+    #[serde(flatten)]
+    pub job: Job,
+    #[serde(flatten)]
+    pub outcome: JobOutcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Goal {
+    Minimize,
+    Maximize,
+}
+
+/// What makes a job of the batch the best one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub goal: Goal,
+    pub metric: String,
+}
+
+impl Objective {
+    /// The objective given with `--minimize` or `--maximize`; `metrics.` in front of the metric is optional
+    pub fn from_args(args: &BatchArgs) -> Option<Objective> {
+        let (goal, metric) = match (&args.minimize, &args.maximize) {
+            (Some(metric), _) => (Goal::Minimize, metric),
+            (None, Some(metric)) => (Goal::Maximize, metric),
+            (None, None) => return None,
+        };
+        let metric = metric.strip_prefix("metrics.").unwrap_or(metric).to_string();
+        Some(Objective { goal, metric })
+    }
+
+    /// The successful job with the best finite value of the metric; the first one on a tie
+    pub fn best(&self, jobs: &[BatchJob], metrics: &[BTreeMap<String, serde_yaml::Value>]) -> Option<BestJob> {
+        let candidates = jobs.iter().zip(metrics).filter_map(|(job, metrics)| {
+            let id = job.outcome.id.as_ref().filter(|_| job.outcome.status == JobStatus::Success)?;
+            let value = metrics.get(&self.metric)?.as_f64().filter(|value| value.is_finite())?;
+            Some(BestJob { line: job.job.line, id: id.clone(), value })
+        });
+        candidates.reduce(|best, job| {
+            let better = match self.goal {
+                Goal::Minimize => job.value < best.value,
+                Goal::Maximize => job.value > best.value,
+            };
+            if better { job } else { best }
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BestJob {
+    /// Line of the job in the jobs file
+    pub line: usize,
+    /// Id of its run
+    pub id: String,
+    /// Its value of the objective's metric
+    pub value: f64,
+}
+
+/// Range of the numeric values of a metric across the successful jobs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricSummary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub metrics: BTreeMap<String, MetricSummary>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchResult {
+    pub jobs_file: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// In the order of the jobs file
+    pub jobs: Vec<BatchJob>,
+    /// Missing in batches run by older versions
+    #[serde(default)]
+    pub summary: Option<BatchSummary>,
+    #[serde(default)]
+    pub objective: Option<Objective>,
+    /// The job that did best on the objective, if any job reported its metric
+    #[serde(default)]
+    pub best: Option<BestJob>,
+}
+
+/// Points `best` in the batch directory at the run directory of the best job
+fn link_best(archive_dir: &Path, batch_dir: &Path, best: &BestJob) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(archive_dir, &best.id)?;
+    let relative_run = run_dir.strip_prefix(archive_dir)?;
+    let link = batch_dir.join(BEST_LINK);
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link)?;
+    }
+    symlink_dir(&Path::new("..").join(relative_run), &link)?;
+    Ok(())
+}
+
+/// Parameters in the arguments of a job: `--name value` and `--name=value`, and `true`
+/// for a `--flag` without value. Positional arguments have no name and are left out.
+pub fn job_parameters(args: &[String]) -> BTreeMap<String, String> {
+    let mut parameters = BTreeMap::new();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--").filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => match args.next_if(|value| !value.starts_with("--")) {
+                Some(value) => (name, value.clone()),
+                None => (name, "true".to_string()),
+            },
+        };
+        parameters.insert(name.to_string(), value);
+    }
+    parameters
+}
+
+/// Metrics of the archived run of every job; empty for jobs without a readable run
+pub fn job_metrics(archive_dir: &Path, jobs: &[BatchJob]) -> Vec<BTreeMap<String, serde_yaml::Value>> {
+    jobs.iter()
+        .map(|job| {
+            let run_dir = resolve_run(archive_dir, job.outcome.id.as_deref()?).ok()?;
+            load_manifest(&manifest_path(&run_dir)).ok().map(|result| result.metrics)
+        })
+        .map(Option::unwrap_or_default)
+        .collect()
+}
+
+/// Counts the outcomes and summarizes the numeric metrics of the successful jobs
+pub fn summarize(jobs: &[BatchJob], metrics: &[BTreeMap<String, serde_yaml::Value>]) -> BatchSummary {
+    let succeeded = jobs.iter().filter(|job| job.outcome.status == JobStatus::Success).count();
+    let mut values: BTreeMap<&String, Vec<f64>> = BTreeMap::new();
+    for (job, metrics) in jobs.iter().zip(metrics) {
+        if job.outcome.status != JobStatus::Success {
+            continue;
+        }
+        for (name, value) in metrics {
+            if let Some(value) = value.as_f64() {
+                values.entry(name).or_default().push(value);
+            }
+        }
+    }
+    let metrics = values
+        .into_iter()
+        .map(|(name, values)| {
+            let summary = MetricSummary {
+                count: values.len(),
+                min: values.iter().copied().fold(f64::INFINITY, f64::min),
+                max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                mean: values.iter().sum::<f64>() / values.len() as f64,
+            };
+            (name.clone(), summary)
+        })
+        .collect();
+    BatchSummary { succeeded, failed: jobs.len() - succeeded, metrics }
+}
+
+/// One row per job with its parameters and metrics, for `batch-results.csv`
+pub fn results_table(jobs: &[BatchJob], metrics: &[BTreeMap<String, serde_yaml::Value>]) -> Table {
+    let parameters: Vec<_> = jobs.iter().map(|job| job_parameters(&job.job.script_args)).collect();
+    let parameter_names: BTreeSet<&String> = parameters.iter().flat_map(|parameters| parameters.keys()).collect();
+    let metric_names: BTreeSet<&String> = metrics.iter().flat_map(|metrics| metrics.keys()).collect();
+
+    let mut headers: Vec<String> = ["line", "script", "status", "run", "duration_ms"].iter().map(|h| h.to_string()).collect();
+    headers.extend(parameter_names.iter().map(|name| name.to_string()));
+    headers.extend(metric_names.iter().map(|name| name.to_string()));
+    let mut table = Table::new(headers);
+    for ((job, parameters), metrics) in jobs.iter().zip(&parameters).zip(metrics) {
+        let mut row = vec![
+            job.job.line.to_string(),
+            job.job.script.clone(),
+            job.outcome.status_text(),
+            job.outcome.id.clone().unwrap_or_default(),
+            job.outcome.duration_ms.map(|duration| duration.to_string()).unwrap_or_default(),
+        ];
+        row.extend(parameter_names.iter().map(|name| parameters.get(*name).cloned().unwrap_or_default()));
+        row.extend(metric_names.iter().map(|name| metrics.get(*name).map(|value| metric_value(Some(value))).unwrap_or_default()));
+        table.rows.push(row);
+    }
+    table
+}
+
+/// Splits a line into words at whitespace, keeping quoted parts together like a shell would
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') if c == '"' => quoted.extend(chars.next()),
+                        Some(other) => quoted.push(other),
+                        None => return Err(format!("unterminated {} quote", c)),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Reads the jobs, skipping empty lines and comments
+pub fn parse_jobs(contents: &str) -> Result<Vec<Job>, Box<dyn Error>> {
+    let mut jobs = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = split_words(line).map_err(|e| format!("Line {} of the jobs file: {}", i + 1, e))?;
+        let script = words.remove(0);
+        jobs.push(Job { line: i + 1, script, script_args: words });
+    }
+    Ok(jobs)
+}
+
+/// Runs and archives a script like a normal run, turning errors into the outcome
+pub fn run_job(cli: &Cli) -> JobOutcome {
+    match run_script(cli) {
+        Ok(run) => JobOutcome {
+            status: if run_succeeded(&run.result) { JobStatus::Success } else { JobStatus::Failed },
+            id: Some(run.id()),
+            exit_code: Some(run.result.exit_code),
+            duration_ms: Some(run.result.duration_ms),
+            error: None,
+        },
+        Err(e) => JobOutcome { status: JobStatus::Error, id: None, exit_code: None, duration_ms: None, error: Some(e.to_string()) },
+    }
+}
+
+/// Runs every job of the jobs file, `args.jobs` at a time; every job is archived as a normal run
+/// and referenced by id from the summary in the batch directory
+pub fn run_batch_jobs(args: &BatchArgs) -> Result<(String, BatchResult), Box<dyn Error>> {
+    let (contents, name) = if args.jobs_file == "-" {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents).map_err(|e| format!("Failed to read jobs from stdin: {}", e))?;
+        (contents, "stdin")
+    } else {
+        let contents = fs::read_to_string(&args.jobs_file)
+            .map_err(|e| format!("Failed to read jobs file {}: {}", args.jobs_file, e))?;
+        (contents, args.jobs_file.as_str())
+    };
+    let jobs = parse_jobs(&contents)?;
+    if jobs.is_empty() {
+        return Err(format!("No jobs in {}", name).into());
+    }
+
+    let batch_dir = create_numbered_dir(&args.archive_dir, name, "batch")?;
+    let start_time = Utc::now();
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<BatchJob>>> = Mutex::new(jobs.iter().map(|_| None).collect());
+    let workers = (args.jobs as usize).min(jobs.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else { break };
+                    tracing::info!("Fastsave batch job {}/{}: {}", index + 1, jobs.len(), job.script);
+                    let cli = Cli {
+                        archive_dir: args.archive_dir.clone(),
+                        message: args.message.clone(),
+                        config_path: args.config_path.clone(),
+                        script_args: job.script_args.clone(),
+                        ..Cli::new(job.script.clone())
+                    };
+                    let outcome = run_job(&cli);
+                    if let Some(error) = &outcome.error {
+                        tracing::warn!("Job on line {} failed: {}", job.line, error);
+                    }
+                    let result = BatchJob { job: job.clone(), outcome };
+                    results.lock().unwrap_or_else(|poisoned| poisoned.into_inner())[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    let end_time = Utc::now();
+    let jobs: Vec<BatchJob> = results.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).into_iter().flatten().collect();
+    let metrics = job_metrics(Path::new(&args.archive_dir), &jobs);
+    let summary = summarize(&jobs, &metrics);
+    fs::write(Path::new(&batch_dir).join(RESULTS_FILE), results_table(&jobs, &metrics).to_csv())?;
+
+    let objective = Objective::from_args(args);
+    let best = objective.as_ref().and_then(|objective| objective.best(&jobs, &metrics));
+    let batch = BatchResult { jobs_file: args.jobs_file.clone(), start_time, end_time, jobs, summary: Some(summary), objective, best };
+    fs::write(Path::new(&batch_dir).join(BATCH_FILE), serde_yaml::to_string(&batch)?)?;
+
+    // The summary is written first, so the jobs aren't lost if the link can't be made
+    match (&batch.objective, &batch.best) {
+        (Some(_), Some(best)) => {
+            if let Err(e) = link_best(Path::new(&args.archive_dir), Path::new(&batch_dir), best) {
+                tracing::warn!("Failed to link the best run {}: {}", best.id, e);
+            }
+        }
+        (Some(objective), None) => tracing::warn!("No successful job reported the metric {}", objective.metric),
+        _ => {}
+    }
+    Ok((batch_dir, batch))
+}
+
+pub fn run_batch(args: &BatchArgs) -> Result<(), Box<dyn Error>> {
+    let (batch_dir, batch) = run_batch_jobs(args)?;
+
+    let headers = ["LINE", "SCRIPT", "STATUS", "DURATION", "RUN"];
+    let mut table = Table::new(headers.iter().map(|h| h.to_string()).collect());
+    for job in &batch.jobs {
+        table.rows.push(vec![
+            job.job.line.to_string(),
+            job.job.script.clone(),
+            job.outcome.status_text(),
+            job.outcome.duration_ms.map(format_duration).unwrap_or_default(),
+            job.outcome.id.clone().unwrap_or_default(),
+        ]);
+    }
+    print!("{}", table.to_text());
+    if let (Some(objective), Some(best)) = (&batch.objective, &batch.best) {
+        println!("Best run: {} with {} {} (line {}), linked as {}/{}", best.id, objective.metric, best.value, best.line, batch_dir, BEST_LINK);
+    }
+    println!("Fastsave batch completed. Summary saved to: {}/{}, results to {}", batch_dir, BATCH_FILE, RESULTS_FILE);
+
+    let failed = batch.jobs.iter().filter(|job| job.outcome.status != JobStatus::Success).count();
+    if failed > 0 {
+        return Err(format!("{} of {} jobs did not succeed", failed, batch.jobs.len()).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{self, STDIN_FILE};
+use crate::observer::NoObserver;
+use crate::table::format_size;
+use crate::{create_numbered_dir, run_failure, run_script_unchecked, Cli, FastsaveConfig};
+
+/// Summary file written into the benchmark directory
+pub const BENCHMARK_FILE: &str = "benchmark.yaml";
+
+#[derive(Serialize, Deserialize)]
+pub struct BenchmarkRun {
+    pub id: String,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    /// User plus system CPU seconds; Linux only
+    #[serde(default)]
+    pub cpu_seconds: Option<f64>,
+    /// Linux only
+    #[serde(default)]
+    pub peak_memory_bytes: Option<u64>,
+    /// Warmup runs are archived but excluded from the statistics
+    #[serde(default)]
+    pub warmup: bool,
+    /// Why the run failed --check-against, --expect or the source check; the benchmark keeps going
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DurationStats {
+    pub min_ms: u64,
+    pub max_ms: u64,
+    pub mean_ms: f64,
+    pub stddev_ms: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub script_path: String,
+    pub start_time: DateTime<Utc>,
+    pub repetitions: u32,
+    #[serde(default)]
+    pub warmup: u32,
+    pub runs: Vec<BenchmarkRun>,
+    pub duration: Option<DurationStats>,
+    /// Over the measured runs that recorded their CPU time
+    #[serde(default)]
+    pub cpu_seconds: Option<Stats>,
+    /// Over the measured runs that recorded their peak memory
+    #[serde(default)]
+    pub peak_memory_bytes: Option<Stats>,
+}
+
+impl BenchmarkResult {
+    /// The statistics, one line each, as printed after the benchmark
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        if let Some(stats) = &self.duration {
+            let _ = writeln!(
+                out,
+                "Duration over {} runs: min {} ms, mean {:.1} ms, max {} ms, stddev {:.1} ms",
+                self.repetitions, stats.min_ms, stats.mean_ms, stats.max_ms, stats.stddev_ms
+            );
+        }
+        if let Some(stats) = &self.cpu_seconds {
+            let _ = writeln!(
+                out,
+                "CPU time: min {:.2} s, mean {:.2} s, max {:.2} s, stddev {:.2} s",
+                stats.min, stats.mean, stats.max, stats.stddev
+            );
+        }
+        if let Some(stats) = &self.peak_memory_bytes {
+            let [min, mean, max, stddev] = [stats.min, stats.mean, stats.max, stats.stddev].map(|bytes| format_size(bytes.round() as u64));
+            let _ = writeln!(out, "Peak memory: min {}, mean {}, max {}, stddev {}", min, mean, max, stddev);
+        }
+        for run in self.runs.iter().filter(|run| run.error.is_some()) {
+            let _ = writeln!(out, "Run {} failed: {}", run.id, run.error.as_deref().unwrap_or_default());
+        }
+        out
+    }
+
+    /// Number of runs that failed their checks
+    pub fn failed(&self) -> usize {
+        self.runs.iter().filter(|run| run.error.is_some()).count()
+    }
+}
+
+/// Minimum, maximum, mean and sample standard deviation of the values
+pub fn stats(values: &[f64]) -> Option<Stats> {
+    let min = values.iter().copied().reduce(f64::min)?;
+    let max = values.iter().copied().reduce(f64::max)?;
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let stddev = if values.len() > 1 {
+        let variance = values.iter().map(|&value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        variance.sqrt()
+    } else {
+        0.0
+    };
+    Some(Stats { min, max, mean, stddev })
+}
+
+/// Minimum, maximum, mean and sample standard deviation of the durations
+pub fn duration_stats(durations: &[u64]) -> Option<DurationStats> {
+    let min_ms = *durations.iter().min()?;
+    let max_ms = *durations.iter().max()?;
+    let Stats { mean, stddev, .. } = stats(&durations.iter().map(|&d| d as f64).collect::<Vec<_>>())?;
+    Some(DurationStats { min_ms, max_ms, mean_ms: mean, stddev_ms: stddev })
+}
+
+/// Runs the script repeatedly; every repetition is archived as a normal run and
+/// referenced by id from the summary in the benchmark directory. Runs that fail their
+/// checks are recorded with the error; if a run cannot be started at all, the summary
+/// of the runs so far is still written. Returns the directory and the summary.
+pub fn run_benchmark(cli: &Cli, repetitions: u32, warmup: u32) -> Result<(String, BenchmarkResult), Box<dyn Error>> {
+    let config = FastsaveConfig::load_with_config_path(cli.config_path.as_deref());
+    let base_dir = archive::run_base_dir(Path::new(&cli.archive_dir), &config.layout);
+    let benchmark_dir = create_numbered_dir(&base_dir.to_string_lossy(), &cli.script, "benchmark")?;
+    let start_time = Utc::now();
+
+    let mut run_cli = Cli { repeat: None, ..cli.clone() };
+
+    let mut runs = Vec::new();
+    for i in 1..=warmup + repetitions {
+        let is_warmup = i <= warmup;
+        if is_warmup {
+            tracing::info!("Fastsave benchmark warmup {}/{}", i, warmup);
+        } else {
+            tracing::info!("Fastsave benchmark repetition {}/{}", i - warmup, repetitions);
+        }
+        let run = match run_script_unchecked(&run_cli, &NoObserver) {
+            Ok(run) => run,
+            Err(e) => {
+                write_summary(&benchmark_dir, &summarize(cli, start_time, repetitions, warmup, runs))?;
+                return Err(e);
+            }
+        };
+        // Our own stdin can be read only once, later runs get the copy archived by the first
+        if run_cli.stdin.as_deref() == Some("-") {
+            run_cli.stdin = Some(run.run_dir.join(STDIN_FILE).to_string_lossy().into_owned());
+        }
+        let error = run_failure(&run);
+        if let Some(error) = &error {
+            tracing::warn!("{}", error);
+        }
+        runs.push(BenchmarkRun {
+            id: run.id(),
+            duration_ms: run.result.duration_ms,
+            exit_code: run.result.exit_code,
+            cpu_seconds: run.result.cpu_time.as_ref().map(|cpu| cpu.user_seconds + cpu.system_seconds),
+            peak_memory_bytes: run.result.peak_memory_bytes,
+            warmup: is_warmup,
+            error,
+        });
+    }
+
+    let benchmark = summarize(cli, start_time, repetitions, warmup, runs);
+    write_summary(&benchmark_dir, &benchmark)?;
+    Ok((benchmark_dir, benchmark))
+}
+
+fn summarize(cli: &Cli, start_time: DateTime<Utc>, repetitions: u32, warmup: u32, runs: Vec<BenchmarkRun>) -> BenchmarkResult {
+    let measured: Vec<&BenchmarkRun> = runs.iter().filter(|run| !run.warmup).collect();
+    let durations: Vec<u64> = measured.iter().map(|run| run.duration_ms).collect();
+    let cpu_seconds: Vec<f64> = measured.iter().filter_map(|run| run.cpu_seconds).collect();
+    let peak_memory: Vec<f64> = measured.iter().filter_map(|run| run.peak_memory_bytes).map(|bytes| bytes as f64).collect();
+    BenchmarkResult {
+        script_path: cli.script.clone(),
+        start_time,
+        repetitions,
+        warmup,
+        duration: duration_stats(&durations),
+        cpu_seconds: stats(&cpu_seconds),
+        peak_memory_bytes: stats(&peak_memory),
+        runs,
+    }
+}
+
+fn write_summary(benchmark_dir: &str, benchmark: &BenchmarkResult) -> Result<(), Box<dyn Error>> {
+    fs::write(Path::new(benchmark_dir).join(BENCHMARK_FILE), serde_yaml::to_string(benchmark)?)?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::load_manifest;
+use crate::baseline::{compare_outputs, OutputComparison};
+use crate::ExecutionResult;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckResult {
+    pub reference: String,
+    pub passed: bool,
+    #[serde(flatten)]
+    pub differences: OutputComparison,
+}
+
+/// Parses `NAME` or `NAME=TOLERANCE`; a missing tolerance means exact comparison
+pub fn parse_metric_tolerance(s: &str) -> Result<(String, f64), String> {
+    match s.split_once('=') {
+        Some((name, tolerance)) => {
+            let tolerance: f64 = tolerance
+                .parse()
+                .map_err(|_| format!("invalid tolerance '{}' for metric '{}'", tolerance, name))?;
+            if name.is_empty() || tolerance < 0.0 {
+                return Err(format!("invalid metric check '{}'", s));
+            }
+            Ok((name.to_string(), tolerance))
+        }
+        None if !s.is_empty() => Ok((s.to_string(), 0.0)),
+        None => Err("metric name must not be empty".to_string()),
+    }
+}
+
+pub fn values_match(expected: &serde_yaml::Value, actual: &serde_yaml::Value, tolerance: f64) -> bool {
+    match (expected.as_f64(), actual.as_f64()) {
+        (Some(expected), Some(actual)) => (expected - actual).abs() <= tolerance,
+        _ => expected == actual,
+    }
+}
+
+/// Compares produced files and the selected metrics against a reference manifest
+pub fn check_against(reference: &ExecutionResult, result: &ExecutionResult, metrics: &[(String, f64)]) -> OutputComparison {
+    let mut differences = compare_outputs(reference, result);
+
+    differences.changed_metrics = metrics
+        .iter()
+        .filter(|(name, tolerance)| {
+            match (reference.metrics.get(name), result.metrics.get(name)) {
+                (Some(expected), Some(actual)) => !values_match(expected, actual, *tolerance),
+                _ => true,
+            }
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    differences
+}
+
+pub fn check_against_file(reference_path: &Path, result: &ExecutionResult, metrics: &[(String, f64)]) -> Result<CheckResult, Box<dyn Error>> {
+    let reference = load_manifest(reference_path)
+        .map_err(|e| format!("Failed to load reference manifest {}: {}", reference_path.display(), e))?;
+    let differences = check_against(&reference, result, metrics);
+
+    Ok(CheckResult {
+        reference: reference_path.to_string_lossy().into_owned(),
+        passed: !differences.has_deviations(),
+        differences,
+    })
+}
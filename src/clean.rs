@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{find_runs, parse_duration, ArchivedRun};
+use crate::experiment::EXPERIMENTS_DIR;
+use crate::table::format_size;
+use crate::{get_script_basename, FastsaveConfig};
+
+#[derive(Args)]
+pub struct CleanArgs {
+    /// Only list the runs the retention rules would delete
+    #[arg(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// Which archived runs `fastsave clean` deletes. A run is deleted if any rule matches it
+/// or newer runs of its script fill its `keep_last`, unless it is pinned or has one of
+/// `keep_tags`:
+///
+/// ```yaml
+/// retention:
+///   keep_tags: [paper]
+///   rules:
+///     - untagged: true
+///       older_than: 14d
+///   scripts:
+///     run_simulation:
+///       keep_last: 20
+///   default:
+///     keep_last: 100
+///   auto_clean: true
+/// ```
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Runs with any of these tags are never deleted
+    pub keep_tags: Vec<String>,
+    pub rules: Vec<RetentionRule>,
+    /// Limits by script name without extension, like `run_simulation`
+    pub scripts: BTreeMap<String, ScriptRetention>,
+    /// Limit for the scripts not listed in `scripts`
+    pub default: Option<ScriptRetention>,
+    /// Apply the policy before every run, so the archive stays bounded without running `clean`
+    pub auto_clean: bool,
+}
+
+impl RetentionConfig {
+    /// Whether `clean` has anything to do
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.scripts.is_empty() && self.default.is_none()
+    }
+
+    fn keep_last(&self, script: &str) -> Option<usize> {
+        self.scripts.get(script).or(self.default.as_ref()).map(|retention| retention.keep_last)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScriptRetention {
+    /// Number of the most recent runs of the script to keep
+    pub keep_last: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionRule {
+    /// Only runs with this tag
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Only runs without any tag
+    #[serde(default)]
+    pub untagged: bool,
+    /// Age since the run ended, like `14d`
+    pub older_than: String,
+}
+
+impl RetentionRule {
+    fn matches(&self, run: &ArchivedRun) -> bool {
+        let tags = &run.result.tags;
+        self.tag.as_ref().is_none_or(|tag| tags.contains(tag)) && (!self.untagged || tags.is_empty())
+    }
+
+    /// The rule as shown next to the runs it deletes, e.g. `tag:scratch older than 2d`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(tag) = &self.tag {
+            parts.push(format!("tag:{}", tag));
+        }
+        if self.untagged {
+            parts.push("untagged".to_string());
+        }
+        parts.push(format!("older than {}", self.older_than));
+        parts.join(" ")
+    }
+}
+
+/// Whether the run has a directory to itself. A run written with `--no-subfolder` lies in
+/// the archive directory, which holds the other runs as well.
+pub fn has_own_dir(archive_dir: &Path, run: &ArchivedRun) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    !canonical(archive_dir).starts_with(canonical(&run.run_dir))
+}
+
+/// The runs the retention policy deletes at `now`, each with why: the first rule matching
+/// it, or the `keep_last` limit of its script. Pinned runs and runs with a kept tag don't
+/// count toward the limit, so they never push other runs out; neither do runs without a
+/// directory of their own, which are never deleted.
+pub fn expired_runs<'a>(archive_dir: &Path, runs: &'a [ArchivedRun], config: &RetentionConfig, now: DateTime<Utc>) -> Result<Vec<(&'a ArchivedRun, String)>, String> {
+    let rules = config
+        .rules
+        .iter()
+        .map(|rule| Ok((rule, parse_duration(&rule.older_than)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let deletable: Vec<&ArchivedRun> = runs
+        .iter()
+        .filter(|run| !run.result.pinned && !run.result.tags.iter().any(|tag| config.keep_tags.contains(tag)))
+        .filter(|run| has_own_dir(archive_dir, run) && !runs.iter().any(|other| other.run_dir != run.run_dir && other.run_dir.starts_with(&run.run_dir)))
+        .collect();
+
+    // Runs beyond the most recent `keep_last` of their script
+    let mut by_script: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, run) in deletable.iter().enumerate() {
+        by_script.entry(get_script_basename(&run.result.script_path)).or_default().push(i);
+    }
+    let mut surplus = BTreeMap::new();
+    for (script, mut indices) in by_script {
+        let Some(keep_last) = config.keep_last(&script) else { continue };
+        indices.sort_by_key(|&i| std::cmp::Reverse(deletable[i].result.start_time));
+        for i in indices.into_iter().skip(keep_last) {
+            surplus.insert(i, format!("keep last {} of {}", keep_last, script));
+        }
+    }
+
+    Ok(deletable
+        .iter()
+        .enumerate()
+        .filter_map(|(i, run)| {
+            let age = now - run.result.end_time;
+            let reason = match rules.iter().find(|(rule, max_age)| age > *max_age && rule.matches(run)) {
+                Some((rule, _)) => rule.describe(),
+                None => surplus.remove(&i)?,
+            };
+            Some((*run, reason))
+        })
+        .collect())
+}
+
+/// Deletes the run directory and its link in the experiment it belongs to. Refuses a run
+/// without a directory of its own, since that would delete the archive.
+pub fn remove_run(archive_dir: &Path, run: &ArchivedRun) -> Result<(), Box<dyn Error>> {
+    if !has_own_dir(archive_dir, run) {
+        return Err(format!("Not deleting {}: the run was written into the archive directory itself with --no-subfolder", run.run_dir.display()).into());
+    }
+    if let Some(experiment) = &run.result.experiment {
+        let link = archive_dir.join(EXPERIMENTS_DIR).join(experiment).join(run.id());
+        if link.symlink_metadata().is_ok() {
+            fs::remove_file(&link)?;
+        }
+    }
+    fs::remove_dir_all(&run.run_dir)?;
+    Ok(())
+}
+
+/// Deletes the runs the policy selects before a run starts, returning how many and their size
+pub fn auto_clean(archive_dir: &Path, config: &RetentionConfig) -> Result<(usize, u64), Box<dyn Error>> {
+    let runs = find_runs(archive_dir)?;
+    let expired = expired_runs(archive_dir, &runs, config, Utc::now())?;
+    let mut freed = 0;
+    for (run, reason) in &expired {
+        tracing::debug!("Deleting {} ({})", run.id(), reason);
+        remove_run(archive_dir, run)?;
+        freed += run.result.total_output_bytes.unwrap_or(0);
+    }
+    Ok((expired.len(), freed))
+}
+
+pub fn run_clean(args: &CleanArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let config = FastsaveConfig::load_with_config_path(args.config_path.as_deref()).retention;
+    if config.is_empty() {
+        println!("No retention rules configured, nothing to clean");
+        return Ok(());
+    }
+
+    let runs = find_runs(archive_dir)?;
+    let expired = expired_runs(archive_dir, &runs, &config, Utc::now())?;
+    let mut freed = 0;
+    for (run, reason) in &expired {
+        if args.dry_run {
+            println!("Would delete {} ({})", run.id(), reason);
+        } else {
+            remove_run(archive_dir, run)?;
+            println!("Deleted {} ({})", run.id(), reason);
+        }
+        freed += run.result.total_output_bytes.unwrap_or(0);
+    }
+    let verb = if args.dry_run { "Would delete" } else { "Deleted" };
+    println!("{} {} of {} runs, {}", verb, expired.len(), runs.len(), format_size(freed));
+    Ok(())
+}
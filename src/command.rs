@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Argument fastsave passes the output directory with, right after the script
+pub const OUTPUT_DIR_ARG: &str = "--output_dir";
+
+/// The command a script was run with, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ScriptCommand {
+    /// Interpreter that ran the script
+    pub program: String,
+    /// Script path, `--output_dir` with the run directory, then the arguments of the script
+    pub args: Vec<String>,
+}
+
+/// Settings of one script in the `scripts` section, keyed by its name without extension:
+///
+/// ```yaml
+/// scripts:
+///   run_simulation:
+///     args: [--solver, cg, --tol, 1e-8]
+/// ```
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Arguments put before the ones given on the command line
+    pub args: Vec<String>,
+}
+
+/// An invocation runnable as `fastsave @<name>`, in the `aliases` section:
+///
+/// ```yaml
+/// aliases:
+///   sim:
+///     script: sims/run_simulation.py
+///     args: [--rows, 3]
+///     archive_dir: archive/sims
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AliasConfig {
+    pub script: String,
+    /// Arguments put before the ones given after the alias, merged like default arguments
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Archive to use unless `-a` names another
+    #[serde(default)]
+    pub archive_dir: Option<String>,
+    /// Interpreter to use unless `-i` names another
+    #[serde(default)]
+    pub interpreter: Option<String>,
+    /// Experiment to group the runs into unless `-e` names another
+    #[serde(default)]
+    pub experiment: Option<String>,
+}
+
+/// Name of the option `arg` is, like `--tol` for `--tol` and `--tol=1e-8`; negative numbers aren't options
+fn option_name(arg: &str) -> Option<&str> {
+    if arg.len() < 2 || !arg.starts_with('-') || arg.parse::<f64>().is_ok() {
+        return None;
+    }
+    Some(arg.split_once('=').map_or(arg, |(name, _)| name))
+}
+
+/// The default arguments followed by the given ones. A default option the given arguments
+/// set as well is left out with its value, which is the word after it unless it's written
+/// as `--name=value` or followed by another option, so the command line wins.
+pub fn merge_args(defaults: &[String], args: &[String]) -> Vec<String> {
+    let given: Vec<&str> = args.iter().take_while(|arg| *arg != "--").filter_map(|arg| option_name(arg)).collect();
+    let mut merged = Vec::new();
+    let mut defaults = defaults.iter().peekable();
+    while let Some(arg) = defaults.next() {
+        match option_name(arg) {
+            Some(name) if given.contains(&name) => {
+                if !arg.contains('=') {
+                    defaults.next_if(|value| option_name(value).is_none());
+                }
+            }
+            _ => merged.push(arg.clone()),
+        }
+    }
+    merged.extend(args.iter().cloned());
+    merged
+}
+
+/// Quotes `arg` for POSIX shells, leaving plain words as they are
+pub fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// Splits an interpreter command like `mpirun -np 8 python` into words the way a POSIX
+/// shell does, with single and double quotes and backslash escapes, but no expansions.
+/// On Windows, backslashes outside of double quotes are kept as they are.
+pub fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated single quote in '{}'", command)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("unterminated double quote in '{}'", command)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated double quote in '{}'", command)),
+                    }
+                }
+            }
+            // Escapes the next character, except on Windows where it separates path components
+            '\\' if !cfg!(windows) => {
+                word.get_or_insert_with(String::new).push(chars.next().ok_or_else(|| format!("trailing backslash in '{}'", command))?)
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err("empty interpreter command".to_string());
+    }
+    Ok(words)
+}
+
+impl ScriptCommand {
+    /// The command running `script_path` with `interpreter`, which is the program followed
+    /// by the arguments that come before the script, like `mpirun -np 8 python`
+    pub fn new(interpreter: &[String], script_path: &str, output_dir: &str, script_args: &[String]) -> ScriptCommand {
+        let (program, interpreter_args) = interpreter.split_first().expect("interpreter command without a program");
+        let mut args = interpreter_args.to_vec();
+        args.extend([script_path.to_string(), OUTPUT_DIR_ARG.to_string(), output_dir.to_string()]);
+        args.extend(script_args.iter().cloned());
+        ScriptCommand { program: program.clone(), args }
+    }
+
+    /// The interpreter command the script was run with, like it's given with `--interpreter`
+    pub fn interpreter(&self) -> String {
+        let interpreter_args = match self.args.iter().position(|arg| arg == OUTPUT_DIR_ARG) {
+            Some(position) => &self.args[..position.saturating_sub(1)],
+            None => &[],
+        };
+        std::iter::once(&self.program)
+            .chain(interpreter_args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Arguments given to the script itself, after the output directory; all arguments of a
+    /// command run with `fastsave exec`, which gets no output directory
+    pub fn script_args(&self) -> &[String] {
+        match self.args.iter().position(|arg| arg == OUTPUT_DIR_ARG) {
+            Some(position) => self.args.get(position + 2..).unwrap_or_default(),
+            None => &self.args,
+        }
+    }
+
+    /// The command as a line to paste into a shell
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// `path` and, on Windows, `path` with each extension of `PATHEXT`
+fn executable_candidates(path: PathBuf) -> Vec<PathBuf> {
+    let mut candidates = vec![path.clone()];
+    if cfg!(windows) && path.extension().is_none() {
+        let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        candidates.extend(extensions.split(';').filter(|ext| !ext.is_empty()).map(|ext| path.with_extension(ext.trim_start_matches('.'))));
+    }
+    candidates
+}
+
+/// Absolute path of the interpreter `program`, looked up in `PATH` unless it is a path
+/// itself. Symlinks are kept, so a virtual environment's `python` isn't resolved to the
+/// system interpreter it links to.
+pub fn resolve_program(program: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = Path::new(program);
+    let candidates = if path.components().count() > 1 {
+        executable_candidates(path.to_path_buf())
+    } else {
+        let dirs = std::env::var_os("PATH").unwrap_or_default();
+        std::env::split_paths(&dirs).flat_map(|dir| executable_candidates(dir.join(program))).collect()
+    };
+    let found = candidates.into_iter().find(|candidate| is_executable(candidate)).ok_or_else(|| {
+        format!("Interpreter '{}' not found in PATH; install it or configure another one with --interpreter or in the config file", program)
+    })?;
+    Ok(std::path::absolute(found)?)
+}
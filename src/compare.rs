@@ -0,0 +1,127 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, ArchivedRun};
+use crate::command::shell_quote;
+use crate::notify::format_duration;
+use crate::table::Table;
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// Runs to compare (ids or paths)
+    #[arg(required = true, num_args = 2..)]
+    pub runs: Vec<String>,
+
+    /// Only show the rows whose values differ between the runs
+    #[arg(long = "differences")]
+    pub differences: bool,
+
+    /// Print the comparison as CSV
+    #[arg(long = "csv")]
+    pub csv: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// A metric value on one line, `-` if the run doesn't have it
+pub fn metric_value(value: Option<&serde_yaml::Value>) -> String {
+    match value {
+        Some(value) => serde_yaml::to_string(value).unwrap_or_default().trim_end().replace('\n', " "),
+        None => "-".to_string(),
+    }
+}
+
+/// Side-by-side table of the runs, one column per run and one row per argument list,
+/// commit, parameters file, duration, exit code, run digest, metric and output file. Parameters and
+/// output files are shown by the start of their hash, so runs with the same file show the
+/// same value.
+pub fn comparison_table(runs: &[ArchivedRun], differences: bool) -> Table {
+    let mut headers = vec![String::new()];
+    headers.extend(runs.iter().map(ArchivedRun::id));
+    let mut table = Table::new(headers);
+    let mut add_row = |name: String, values: Vec<String>| {
+        if differences && values.iter().all(|value| *value == values[0]) {
+            return;
+        }
+        table.rows.push(std::iter::once(name).chain(values).collect());
+    };
+
+    let results: Vec<_> = runs.iter().map(|run| &run.result).collect();
+    add_row(
+        "args".to_string(),
+        results
+            .iter()
+            .map(|result| match &result.command {
+                Some(command) => command.script_args().iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" "),
+                None => result.command_line.clone(),
+            })
+            .collect(),
+    );
+    add_row(
+        "commit".to_string(),
+        results
+            .iter()
+            .map(|result| match &result.git_info {
+                Some(git) => format!("{}{}", &git.commit_hash[..git.commit_hash.len().min(8)], if git.is_dirty { " (dirty)" } else { "" }),
+                None => "-".to_string(),
+            })
+            .collect(),
+    );
+    if results.iter().any(|result| result.params.is_some()) {
+        add_row(
+            "params".to_string(),
+            results
+                .iter()
+                .map(|result| result.params.as_ref().map_or("-".to_string(), |params| params.sha256[..params.sha256.len().min(8)].to_string()))
+                .collect(),
+        );
+    }
+    add_row("duration".to_string(), results.iter().map(|result| format_duration(result.duration_ms)).collect());
+    add_row("exit code".to_string(), results.iter().map(|result| result.exit_code.to_string()).collect());
+    if results.iter().any(|result| result.run_digest.is_some()) {
+        add_row(
+            "digest".to_string(),
+            results.iter().map(|result| result.run_digest.as_ref().map_or("-".to_string(), |digest| digest[..digest.len().min(12)].to_string())).collect(),
+        );
+    }
+
+    let metrics: BTreeSet<&String> = results.iter().flat_map(|result| result.metrics.keys()).collect();
+    for name in metrics {
+        add_row(format!("metric {}", name), results.iter().map(|result| metric_value(result.metrics.get(name))).collect());
+    }
+    let files: BTreeSet<&String> = results.iter().flat_map(|result| result.file_hashes.keys()).collect();
+    for name in files {
+        let hashes = results
+            .iter()
+            .map(|result| result.file_hashes.get(name).map_or("-".to_string(), |hash| hash[..hash.len().min(8)].to_string()))
+            .collect();
+        add_row(format!("file {}", name), hashes);
+    }
+    table
+}
+
+pub fn run_compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let runs = args
+        .runs
+        .iter()
+        .map(|run| {
+            let run_dir = resolve_run(archive_dir, run)?;
+            let result = load_manifest(&manifest_path(&run_dir))?;
+            Ok(ArchivedRun { run_dir, result })
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    let table = comparison_table(&runs, args.differences);
+    if args.csv {
+        print!("{}", table.to_csv());
+    } else {
+        print!("{}", table.to_text());
+    }
+    Ok(())
+}
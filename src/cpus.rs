@@ -0,0 +1,150 @@
+use std::process::{Child, Command, ExitStatus};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variables that set the size of the thread pools of common runtimes and
+/// numerical libraries: OpenMP, MKL, OpenBLAS, Apple Accelerate, numexpr, Julia and Rayon
+const THREAD_VARIABLES: [&str; 7] = [
+    "OMP_NUM_THREADS",
+    "MKL_NUM_THREADS",
+    "OPENBLAS_NUM_THREADS",
+    "VECLIB_MAXIMUM_THREADS",
+    "NUMEXPR_NUM_THREADS",
+    "JULIA_NUM_THREADS",
+    "RAYON_NUM_THREADS",
+];
+
+/// The CPUs and threads a run was limited to, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CpuPinning {
+    /// CPU list the script was pinned to, in the format of `taskset -c`
+    pub cpus: Option<String>,
+    /// Thread count exported to the script
+    pub threads: Option<u32>,
+}
+
+/// Parses a CPU list like `0-7` or `0,2,4-6`; returns the list unchanged
+pub fn parse_cpu_list(list: &str) -> Result<String, String> {
+    cpu_count(list).map(|_| list.to_string())
+}
+
+/// Number of CPUs in a CPU list
+pub fn cpu_count(list: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid CPU list '{}': expected numbers and ranges like 0-7,12", list);
+    let mut count = 0;
+    for part in list.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first: u32 = first.trim().parse().map_err(|_| invalid())?;
+        let last: u32 = last.trim().parse().map_err(|_| invalid())?;
+        if last < first {
+            return Err(invalid());
+        }
+        count += last - first + 1;
+    }
+    Ok(count)
+}
+
+impl CpuPinning {
+    /// Without `--threads`, a script pinned to CPUs gets one thread per CPU
+    pub fn new(cpus: Option<&str>, threads: Option<u32>) -> Result<Option<CpuPinning>, String> {
+        let threads = match (cpus, threads) {
+            (_, Some(threads)) => Some(threads),
+            (Some(cpus), None) => Some(cpu_count(cpus)?),
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(CpuPinning { cpus: cpus.map(str::to_string), threads }))
+    }
+
+    /// Thread count variables for the environment of the script
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        match self.threads {
+            Some(threads) => THREAD_VARIABLES.into_iter().map(|name| (name, threads.to_string())).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Command running `cmd` with its affinity set to `cpus`. The affinity is inherited,
+/// so a sandbox or network namespace around the script is pinned as a whole.
+pub fn pin(cmd: &Command, cpus: &str) -> Command {
+    let mut pinned = Command::new("taskset");
+    pinned.arg("-c").arg(cpus).arg(cmd.get_program()).args(cmd.get_args());
+    pinned
+}
+
+/// CPU time of a run besides its wall time, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CpuTime {
+    /// CPU seconds the script and the processes it waited for spent in user mode
+    pub user_seconds: f64,
+    /// CPU seconds they spent in the kernel, e.g. for I/O
+    pub system_seconds: f64,
+    /// CPU time per wall time and thread: close to 1 for a compute-bound run that keeps
+    /// all its threads busy, well below 1 for one that waits on I/O
+    pub parallel_efficiency: f64,
+}
+
+impl CpuTime {
+    /// Threads are those given with `--threads` or `--cpus`, else one
+    pub fn new(user_seconds: f64, system_seconds: f64, duration_ms: u64, threads: Option<u32>) -> CpuTime {
+        let wall_seconds = duration_ms.max(1) as f64 / 1000.0;
+        let parallel_efficiency = (user_seconds + system_seconds) / wall_seconds / threads.unwrap_or(1) as f64;
+        CpuTime { user_seconds, system_seconds, parallel_efficiency }
+    }
+}
+
+/// Waits until the process exits without reaping it, then reads the user and system CPU
+/// seconds of it and its reaped children, which `/proc` keeps until the process is reaped.
+/// The caller reaps it with `Child::wait` afterwards.
+#[cfg(target_os = "linux")]
+pub fn exited_cpu_seconds(pid: u32) -> Option<(f64, f64)> {
+    // Kernels report CPU time in ticks of USER_HZ, which is 100 on all common platforms
+    const TICKS_PER_SECOND: f64 = 100.0;
+
+    // SAFETY: plain system call on our own child; WNOWAIT leaves it to be reaped by `Child::wait`
+    let waited = unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+    };
+    if waited != 0 {
+        return None;
+    }
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces, the fields after it don't
+    let fields: Vec<u64> = stat.rsplit_once(')')?.1.split_whitespace().skip(11).take(4).map_while(|field| field.parse().ok()).collect();
+    let [user, system, children_user, children_system] = fields[..] else { return None };
+    Some(((user + children_user) as f64 / TICKS_PER_SECOND, (system + children_system) as f64 / TICKS_PER_SECOND))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn exited_cpu_seconds(_pid: u32) -> Option<(f64, f64)> {
+    None
+}
+
+/// Reaps the process like `Child::wait`, along with the peak resident memory in bytes of it
+/// or the largest of the processes it waited for
+#[cfg(target_os = "linux")]
+pub fn wait_with_peak_memory(child: &mut Child) -> std::io::Result<(ExitStatus, Option<u64>)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    // SAFETY: plain system call on our own child, which nothing else waits for; `child`
+    // isn't waited for or killed after it was reaped here
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        if unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) } >= 0 {
+            break;
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    // Linux reports the maximum resident set size in kilobytes
+    Ok((ExitStatus::from_raw(status), Some(usage.ru_maxrss as u64 * 1024)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn wait_with_peak_memory(child: &mut Child) -> std::io::Result<(ExitStatus, Option<u64>)> {
+    child.wait().map(|status| (status, None))
+}
@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SuccessConfig {
+    /// Exit codes that count as success
+    pub exit_codes: Vec<i32>,
+    /// Regex patterns that mark the run as failed if they match a line of stderr, even with an accepted exit code
+    pub failure_patterns: Vec<String>,
+}
+
+impl Default for SuccessConfig {
+    fn default() -> Self {
+        SuccessConfig { exit_codes: vec![0], failure_patterns: Vec::new() }
+    }
+}
+
+pub struct SuccessCriteria {
+    exit_codes: Vec<i32>,
+    failure_patterns: Vec<Regex>,
+}
+
+impl SuccessCriteria {
+    pub fn new(config: &SuccessConfig) -> Result<Self, Box<dyn Error>> {
+        let failure_patterns = config
+            .failure_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| format!("Invalid failure pattern '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SuccessCriteria { exit_codes: config.exit_codes.clone(), failure_patterns })
+    }
+
+    /// Whether the script itself succeeded, judged by its exit code and stderr
+    pub fn script_succeeded(&self, exit_code: i32, stderr: &str) -> bool {
+        if !self.exit_codes.contains(&exit_code) {
+            return false;
+        }
+        match stderr.lines().find(|line| self.failure_patterns.iter().any(|pattern| pattern.is_match(line))) {
+            Some(line) => {
+                tracing::warn!("Run marked as failed, stderr matched a failure pattern: {}", line);
+                false
+            }
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+
+use crate::archive::STDIN_FILE;
+use crate::baseline::{compare_outputs, OutputComparison};
+use crate::{run_script, Cli};
+
+pub struct DeterminismReport {
+    pub first_run: String,
+    pub second_run: String,
+    pub differences: OutputComparison,
+}
+
+impl DeterminismReport {
+    pub fn is_deterministic(&self) -> bool {
+        !self.differences.has_deviations() && self.differences.added_files.is_empty()
+    }
+}
+
+/// Runs the script twice into sibling run directories and compares their outputs
+pub fn determinism_check(cli: &Cli) -> Result<DeterminismReport, Box<dyn Error>> {
+    let run_cli = Cli { determinism_check: false, ..cli.clone() };
+
+    let first = run_script(&run_cli)?;
+    // Our own stdin can be read only once, so the second run gets the copy archived by the first
+    let run_cli = match run_cli.stdin.as_deref() {
+        Some("-") => Cli { stdin: Some(first.run_dir.join(STDIN_FILE).to_string_lossy().into_owned()), ..run_cli },
+        _ => run_cli,
+    };
+    let second = run_script(&run_cli)?;
+
+    Ok(DeterminismReport {
+        first_run: first.id(),
+        second_run: second.id(),
+        differences: compare_outputs(&first.result, &second.result),
+    })
+}
+
+pub fn run_determinism_check(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let report = determinism_check(cli)?;
+    println!("Compared runs {} and {}", report.first_run, report.second_run);
+
+    if report.is_deterministic() {
+        println!("Deterministic: all output files and metrics are identical");
+        return Ok(());
+    }
+
+    let differences = &report.differences;
+    for file in &differences.changed_files {
+        println!("  changed: {}", file);
+    }
+    for file in &differences.missing_files {
+        println!("  only in first run: {}", file);
+    }
+    for file in &differences.added_files {
+        println!("  only in second run: {}", file);
+    }
+    for metric in &differences.changed_metrics {
+        println!("  metric changed: {}", metric);
+    }
+    Err("Script is not deterministic".into())
+}
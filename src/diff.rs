@@ -0,0 +1,603 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+use glob::Pattern;
+use similar::TextDiff;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, run_id};
+use crate::provenance::{ENVIRONMENT_DIR, PACKAGE_LISTS};
+use crate::started::GIT_PATCH_FILE;
+use crate::tarball;
+use crate::{ExecutionResult, GitInfo};
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// First run (id or path)
+    pub a: String,
+
+    /// Second run (id or path)
+    pub b: String,
+
+    /// Compare numeric output files value by value with this tolerance instead of by hash
+    #[arg(long = "tolerance", value_name = "TOL")]
+    pub tolerance: Option<f64>,
+
+    /// Only compare output files matching this glob pattern
+    #[arg(long = "files", value_name = "GLOB")]
+    pub files: Option<String>,
+
+    /// Show unified diffs of changed text files
+    #[arg(long = "content")]
+    pub content: bool,
+
+    /// Compare the environments the runs ran in instead of their outputs: interpreter,
+    /// machine, numerical libraries and the package lists of --lock-env
+    #[arg(long = "env", conflicts_with_all = ["tolerance", "files", "content"])]
+    pub env: bool,
+
+    /// Compare the code the runs ran: the commits between them, their branches and their
+    /// uncommitted changes
+    #[arg(long = "git", conflicts_with_all = ["tolerance", "files", "content", "env"])]
+    pub git: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FileStatus {
+    Identical,
+    Changed,
+    WithinTolerance,
+    ExceedsTolerance,
+    OnlyInA,
+    OnlyInB,
+}
+
+/// Largest absolute and relative difference between corresponding numbers
+#[derive(Debug, Default, PartialEq)]
+pub struct NumericDeviation {
+    pub max_abs: f64,
+    pub max_rel: f64,
+}
+
+pub struct FileDiff {
+    pub file: String,
+    pub status: FileStatus,
+    pub deviation: Option<NumericDeviation>,
+}
+
+pub struct RunDiff {
+    pub a: String,
+    pub b: String,
+    pub files: Vec<FileDiff>,
+    pub metrics: Vec<(String, Option<serde_yaml::Value>, Option<serde_yaml::Value>)>,
+}
+
+pub fn diff_runs(a_dir: &Path, b_dir: &Path, tolerance: Option<f64>) -> Result<RunDiff, Box<dyn Error>> {
+    let a = load_manifest(&manifest_path(a_dir))?;
+    let b = load_manifest(&manifest_path(b_dir))?;
+
+    let names: BTreeSet<&String> = a.file_hashes.keys().chain(b.file_hashes.keys()).collect();
+    let mut files = Vec::new();
+    for name in names {
+        let (status, deviation) = match (a.file_hashes.get(name), b.file_hashes.get(name)) {
+            (Some(hash_a), Some(hash_b)) if hash_a == hash_b => (FileStatus::Identical, None),
+            (Some(_), Some(_)) => match tolerance {
+                Some(tolerance) => compare_within_tolerance(a_dir, b_dir, name, tolerance),
+                None => (FileStatus::Changed, None),
+            },
+            (Some(_), None) => (FileStatus::OnlyInA, None),
+            (None, _) => (FileStatus::OnlyInB, None),
+        };
+        files.push(FileDiff { file: name.clone(), status, deviation });
+    }
+
+    let metric_names: BTreeSet<&String> = a.metrics.keys().chain(b.metrics.keys()).collect();
+    let metrics = metric_names
+        .into_iter()
+        .filter(|name| a.metrics.get(*name) != b.metrics.get(*name))
+        .map(|name| (name.clone(), a.metrics.get(name).cloned(), b.metrics.get(name).cloned()))
+        .collect();
+
+    Ok(RunDiff { a: run_id(a_dir), b: run_id(b_dir), files, metrics })
+}
+
+fn compare_within_tolerance(a_dir: &Path, b_dir: &Path, file: &str, tolerance: f64) -> (FileStatus, Option<NumericDeviation>) {
+    // Packed runs are read from their run.tar
+    let read = |dir: &Path| String::from_utf8(tarball::read_run_file(dir, file).ok()?).ok();
+    let deviation = match (read(a_dir), read(b_dir)) {
+        (Some(text_a), Some(text_b)) => numeric_deviation(file, &text_a, &text_b),
+        _ => None,
+    };
+    match deviation {
+        Some(deviation) => {
+            let within = deviation.max_abs <= tolerance || deviation.max_rel <= tolerance;
+            let status = if within { FileStatus::WithinTolerance } else { FileStatus::ExceedsTolerance };
+            (status, Some(deviation))
+        }
+        // Not numeric or structurally different, so only the hash comparison applies
+        None => (FileStatus::Changed, None),
+    }
+}
+
+/// Compares the contents of two versions of the numeric file `file` value by value. Returns
+/// `None` if they can't be read as numbers or differ in structure (shape, keys or non-numeric content).
+pub fn numeric_deviation(file: &str, text_a: &str, text_b: &str) -> Option<NumericDeviation> {
+    let is_json = Path::new(file).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let mut deviation = NumericDeviation::default();
+    if is_json {
+        let value_a: serde_json::Value = serde_json::from_str(text_a).ok()?;
+        let value_b: serde_json::Value = serde_json::from_str(text_b).ok()?;
+        compare_json(&value_a, &value_b, &mut deviation)?;
+    } else {
+        compare_delimited(text_a, text_b, &mut deviation)?;
+    }
+    Some(deviation)
+}
+
+fn record(deviation: &mut NumericDeviation, x: f64, y: f64) {
+    // `f64::max` drops NaN, so a NaN or infinity on either side only passes if both sides agree
+    if !x.is_finite() || !y.is_finite() {
+        if x != y && !(x.is_nan() && y.is_nan()) {
+            deviation.max_abs = f64::INFINITY;
+            deviation.max_rel = f64::INFINITY;
+        }
+        return;
+    }
+    let abs = (x - y).abs();
+    let scale = x.abs().max(y.abs());
+    let rel = if scale > 0.0 { abs / scale } else { 0.0 };
+    deviation.max_abs = deviation.max_abs.max(abs);
+    deviation.max_rel = deviation.max_rel.max(rel);
+}
+
+fn compare_json(a: &serde_json::Value, b: &serde_json::Value, deviation: &mut NumericDeviation) -> Option<()> {
+    use serde_json::Value;
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => record(deviation, x.as_f64()?, y.as_f64()?),
+        (Value::Array(xs), Value::Array(ys)) if xs.len() == ys.len() => {
+            for (x, y) in xs.iter().zip(ys) {
+                compare_json(x, y, deviation)?;
+            }
+        }
+        (Value::Object(xs), Value::Object(ys)) if xs.len() == ys.len() => {
+            for (key, x) in xs {
+                compare_json(x, ys.get(key)?, deviation)?;
+            }
+        }
+        (x, y) if x == y => {}
+        _ => return None,
+    }
+    Some(())
+}
+
+/// CSV, TSV and whitespace separated tables
+fn compare_delimited(a: &str, b: &str, deviation: &mut NumericDeviation) -> Option<()> {
+    let split = |line: &str| -> Vec<String> {
+        line.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_string())
+            .collect()
+    };
+
+    let lines_a: Vec<&str> = a.lines().collect();
+    let lines_b: Vec<&str> = b.lines().collect();
+    if lines_a.len() != lines_b.len() {
+        return None;
+    }
+
+    for (line_a, line_b) in lines_a.iter().zip(&lines_b) {
+        let tokens_a = split(line_a);
+        let tokens_b = split(line_b);
+        if tokens_a.len() != tokens_b.len() {
+            return None;
+        }
+        for (x, y) in tokens_a.iter().zip(&tokens_b) {
+            match (x.parse::<f64>(), y.parse::<f64>()) {
+                (Ok(x), Ok(y)) => record(deviation, x, y),
+                _ if x == y => {}
+                _ => return None,
+            }
+        }
+    }
+    Some(())
+}
+
+/// Unified diff of a text file present in both runs; `None` for binary files
+pub fn content_diff(a_dir: &Path, b_dir: &Path, file: &str) -> Option<String> {
+    let a = String::from_utf8(tarball::read_run_file(a_dir, file).ok()?).ok()?;
+    let b = String::from_utf8(tarball::read_run_file(b_dir, file).ok()?).ok()?;
+    let diff = TextDiff::from_lines(&a, &b)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("A/{}", file), &format!("B/{}", file))
+        .to_string();
+    Some(diff)
+}
+
+fn format_value(value: &Option<serde_yaml::Value>) -> String {
+    match value {
+        Some(value) => serde_yaml::to_string(value).unwrap_or_default().trim_end().replace('\n', " "),
+        None => "-".to_string(),
+    }
+}
+
+pub fn format_diff(diff: &RunDiff) -> String {
+    let mut out = format!("Comparing {} (A) and {} (B)\n", diff.a, diff.b);
+
+    out.push_str("Files:\n");
+    for file in &diff.files {
+        let status = match file.status {
+            FileStatus::Identical => "identical",
+            FileStatus::Changed => "changed",
+            FileStatus::WithinTolerance => "within tolerance",
+            FileStatus::ExceedsTolerance => "exceeds tolerance",
+            FileStatus::OnlyInA => "only in A",
+            FileStatus::OnlyInB => "only in B",
+        };
+        out.push_str(&format!("  {:<18} {}", status, file.file));
+        if let Some(deviation) = &file.deviation {
+            out.push_str(&format!("  (max abs {:e}, max rel {:e})", deviation.max_abs, deviation.max_rel));
+        }
+        out.push('\n');
+    }
+
+    if !diff.metrics.is_empty() {
+        out.push_str("Metrics:\n");
+        for (name, a, b) in &diff.metrics {
+            out.push_str(&format!("  {}: {} -> {}\n", name, format_value(a), format_value(b)));
+        }
+    }
+
+    out
+}
+
+/// A package list or lockfile of `--lock-env` in either run, with the packages whose
+/// versions differ for the package lists of pip and conda
+pub struct EnvironmentFileDiff {
+    pub file: String,
+    pub status: FileStatus,
+    /// Name, version in A and version in B
+    pub packages: Vec<(String, Option<String>, Option<String>)>,
+}
+
+pub struct EnvironmentDiff {
+    pub a: String,
+    pub b: String,
+    /// Recorded properties of the interpreter and machine that differ: name, value in A, value in B
+    pub properties: Vec<(String, String, String)>,
+    pub libraries_only_in_a: Vec<String>,
+    pub libraries_only_in_b: Vec<String>,
+    pub files: Vec<EnvironmentFileDiff>,
+}
+
+impl EnvironmentDiff {
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+            && self.libraries_only_in_a.is_empty()
+            && self.libraries_only_in_b.is_empty()
+            && self.files.iter().all(|file| file.status == FileStatus::Identical)
+    }
+}
+
+/// Package versions in `pip freeze` output (`name==1.0`, `name @ file:///...`) or a conda
+/// environment export (`- name=1.0=build`); other lines are skipped
+pub fn parse_packages(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.starts_with('#') || line.ends_with(':') {
+                return None;
+            }
+            let line = line.strip_prefix("- ").unwrap_or(line);
+            let (name, version) = line
+                .split_once("==")
+                .or_else(|| line.split_once(" @ "))
+                .or_else(|| line.split_once('='))?;
+            let version = version.split('=').next().unwrap_or(version);
+            Some((name.trim().to_lowercase(), version.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Properties of the interpreter and machine of a run, for the environment comparison
+fn environment_properties(result: &ExecutionResult) -> Vec<(&'static str, String)> {
+    let system = result.system_info.as_ref();
+    let value = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    vec![
+        ("interpreter", value(result.interpreter_path.clone())),
+        ("interpreter hash", value(result.interpreter_hash.as_ref().map(|hash| hash[..hash.len().min(12)].to_string()))),
+        ("host", value(result.host.clone())),
+        ("system", value(system.map(|system| format!("{} {}", system.os, system.arch)))),
+        ("gpus", value(system.filter(|system| !system.gpus.is_empty()).map(|system| {
+            system.gpus.iter().map(|gpu| gpu.name.as_str()).collect::<Vec<_>>().join(", ")
+        }))),
+        ("cuda", value(system.and_then(|system| system.cuda_version.clone()))),
+        ("rocm", value(system.and_then(|system| system.rocm_version.clone()))),
+        ("container image", value(system.and_then(|system| system.container.as_ref()?.image.clone()))),
+    ]
+}
+
+/// Compares what two runs ran with: the properties of their interpreter and machine,
+/// the numerical libraries of `--record-libraries` and the package lists and lockfiles of
+/// `--lock-env`, package by package where the files list packages
+pub fn diff_environments(a_dir: &Path, b_dir: &Path) -> Result<EnvironmentDiff, Box<dyn Error>> {
+    let a = load_manifest(&manifest_path(a_dir))?;
+    let b = load_manifest(&manifest_path(b_dir))?;
+
+    let properties = environment_properties(&a)
+        .into_iter()
+        .zip(environment_properties(&b))
+        .filter(|((_, value_a), (_, value_b))| value_a != value_b)
+        .map(|((name, value_a), (_, value_b))| (name.to_string(), value_a, value_b))
+        .collect();
+
+    let only_in = |x: &ExecutionResult, y: &ExecutionResult| -> Vec<String> {
+        x.numerical_libraries.iter().filter(|library| !y.numerical_libraries.contains(library)).cloned().collect()
+    };
+
+    let hashes = |result: &ExecutionResult| -> BTreeMap<String, String> {
+        result.environment_files.iter().map(|file| (file.file.clone(), file.sha256.clone())).collect()
+    };
+    let (hashes_a, hashes_b) = (hashes(&a), hashes(&b));
+    let names: BTreeSet<&String> = hashes_a.keys().chain(hashes_b.keys()).collect();
+    let mut files = Vec::new();
+    for name in names {
+        let status = match (hashes_a.get(name), hashes_b.get(name)) {
+            (Some(hash_a), Some(hash_b)) if hash_a == hash_b => FileStatus::Identical,
+            (Some(_), Some(_)) => FileStatus::Changed,
+            (Some(_), None) => FileStatus::OnlyInA,
+            (None, _) => FileStatus::OnlyInB,
+        };
+        let lists_packages = PACKAGE_LISTS.iter().any(|list| *name == format!("{}/{}", ENVIRONMENT_DIR, list));
+        let packages = if status == FileStatus::Changed && lists_packages {
+            let read = |dir: &Path| tarball::read_run_file(dir, name).map(|content| parse_packages(&String::from_utf8_lossy(&content)));
+            let (packages_a, packages_b) = (read(a_dir)?, read(b_dir)?);
+            let package_names: BTreeSet<&String> = packages_a.keys().chain(packages_b.keys()).collect();
+            package_names
+                .into_iter()
+                .filter(|package| packages_a.get(*package) != packages_b.get(*package))
+                .map(|package| (package.clone(), packages_a.get(package).cloned(), packages_b.get(package).cloned()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        files.push(EnvironmentFileDiff { file: name.clone(), status, packages });
+    }
+
+    Ok(EnvironmentDiff {
+        a: run_id(a_dir),
+        b: run_id(b_dir),
+        properties,
+        libraries_only_in_a: only_in(&a, &b),
+        libraries_only_in_b: only_in(&b, &a),
+        files,
+    })
+}
+
+pub fn format_environment_diff(diff: &EnvironmentDiff) -> String {
+    let mut out = format!("Comparing the environments of {} (A) and {} (B)\n", diff.a, diff.b);
+    if diff.is_empty() {
+        out.push_str("No differences in the recorded environments\n");
+    }
+
+    if !diff.properties.is_empty() {
+        out.push_str("Interpreter and machine:\n");
+        for (name, a, b) in &diff.properties {
+            out.push_str(&format!("  {}: {} -> {}\n", name, a, b));
+        }
+    }
+    if !diff.libraries_only_in_a.is_empty() || !diff.libraries_only_in_b.is_empty() {
+        out.push_str("Numerical libraries:\n");
+        for library in &diff.libraries_only_in_a {
+            out.push_str(&format!("  only in A: {}\n", library));
+        }
+        for library in &diff.libraries_only_in_b {
+            out.push_str(&format!("  only in B: {}\n", library));
+        }
+    }
+
+    if diff.files.is_empty() {
+        out.push_str("Neither run archived its packages, record them with --lock-env\n");
+    } else {
+        out.push_str("Packages and lockfiles:\n");
+        for file in &diff.files {
+            let status = match file.status {
+                FileStatus::Identical => "identical",
+                FileStatus::OnlyInA => "only in A",
+                FileStatus::OnlyInB => "only in B",
+                _ => "changed",
+            };
+            out.push_str(&format!("  {:<18} {}\n", status, file.file));
+            for (package, a, b) in &file.packages {
+                let version = |version: &Option<String>| version.clone().unwrap_or_else(|| "-".to_string());
+                out.push_str(&format!("    {}: {} -> {}\n", package, version(a), version(b)));
+            }
+        }
+    }
+    out
+}
+
+pub struct GitDiff {
+    pub a: String,
+    pub b: String,
+    /// Commit and branch of each run, `None` if it didn't run from a repository
+    pub git_a: Option<GitInfo>,
+    pub git_b: Option<GitInfo>,
+    /// `git log --left-right` between the commits, `<` marking commits only reachable from A
+    /// and `>` only from B; `None` if the repository isn't available or lacks a commit
+    pub log: Option<Vec<String>>,
+    /// Files with uncommitted changes in only one run or in both
+    pub dirty_only_in_a: Vec<String>,
+    pub dirty_only_in_b: Vec<String>,
+    pub dirty_in_both: Vec<String>,
+    /// Whether both runs recorded their uncommitted changes as a patch
+    pub patches_recorded: bool,
+    /// Unified diff between the uncommitted patches of the runs, if they differ
+    pub patch_diff: Option<String>,
+}
+
+/// Paths of the files in `git status --porcelain` lines, whose first line lost the leading
+/// space of its status when the output was trimmed
+fn dirty_files(git: Option<&GitInfo>) -> BTreeSet<String> {
+    git.map(|git| {
+        git.uncommitted_changes
+            .iter()
+            .filter_map(|line| line.trim_start().split_once(char::is_whitespace))
+            .map(|(_, path)| path.trim_start().to_string())
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Commits reachable from only one of the two, if the repository has both
+fn commits_between(repo_root: &Path, a: &str, b: &str) -> Option<Vec<String>> {
+    let output = std::process::Command::new("git")
+        .current_dir(repo_root)
+        .args(["log", "--oneline", "--left-right", &format!("{}...{}", a, b)])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+/// Compares the code two runs ran. The commits between them are looked up in the
+/// repository of B, or of A if B's no longer exists.
+pub fn diff_git(a_dir: &Path, b_dir: &Path) -> Result<GitDiff, Box<dyn Error>> {
+    let a = load_manifest(&manifest_path(a_dir))?;
+    let b = load_manifest(&manifest_path(b_dir))?;
+
+    let log = match (&a.git_info, &b.git_info) {
+        (Some(git_a), Some(git_b)) => [b.repo_location(), a.repo_location()]
+            .into_iter()
+            .flatten()
+            .find(|repo_root| repo_root.is_dir())
+            .and_then(|repo_root| commits_between(&repo_root, &git_a.commit_hash, &git_b.commit_hash)),
+        _ => None,
+    };
+
+    let (dirty_a, dirty_b) = (dirty_files(a.git_info.as_ref()), dirty_files(b.git_info.as_ref()));
+    // A clean run has an empty patch, a dirty run from before patches were recorded has none
+    let patch = |dir: &Path, result: &ExecutionResult| match &result.git_info {
+        Some(git) if git.is_dirty => tarball::read_run_file(dir, GIT_PATCH_FILE).ok().map(|content| String::from_utf8_lossy(&content).into_owned()),
+        _ => Some(String::new()),
+    };
+    let (patch_a, patch_b) = (patch(a_dir, &a), patch(b_dir, &b));
+    let patch_diff = match (&patch_a, &patch_b) {
+        (Some(patch_a), Some(patch_b)) if patch_a != patch_b => Some(
+            TextDiff::from_lines(patch_a, patch_b)
+                .unified_diff()
+                .header(&format!("A/{}", GIT_PATCH_FILE), &format!("B/{}", GIT_PATCH_FILE))
+                .to_string(),
+        ),
+        _ => None,
+    };
+
+    Ok(GitDiff {
+        a: run_id(a_dir),
+        b: run_id(b_dir),
+        log,
+        dirty_only_in_a: dirty_a.difference(&dirty_b).cloned().collect(),
+        dirty_only_in_b: dirty_b.difference(&dirty_a).cloned().collect(),
+        dirty_in_both: dirty_a.intersection(&dirty_b).cloned().collect(),
+        patches_recorded: patch_a.is_some() && patch_b.is_some(),
+        patch_diff,
+        git_a: a.git_info,
+        git_b: b.git_info,
+    })
+}
+
+pub fn format_git_diff(diff: &GitDiff) -> String {
+    let mut out = format!("Comparing the code of {} (A) and {} (B)\n", diff.a, diff.b);
+    let (git_a, git_b) = match (&diff.git_a, &diff.git_b) {
+        (Some(git_a), Some(git_b)) => (git_a, git_b),
+        (git_a, _) => {
+            let run = if git_a.is_none() { "A" } else { "B" };
+            out.push_str(&format!("{} didn't run from a git repository\n", run));
+            return out;
+        }
+    };
+    let short = |hash: &str| hash[..hash.len().min(8)].to_string();
+
+    if git_a.remote_url != git_b.remote_url {
+        out.push_str(&format!("Remote: {} -> {}\n", git_a.remote_url, git_b.remote_url));
+    }
+    if git_a.branch != git_b.branch {
+        out.push_str(&format!("Branch: {} -> {}\n", git_a.branch, git_b.branch));
+    }
+    if git_a.commit_hash == git_b.commit_hash {
+        out.push_str(&format!("Same commit {}\n", short(&git_a.commit_hash)));
+    } else {
+        out.push_str(&format!("Commit: {} -> {}\n", short(&git_a.commit_hash), short(&git_b.commit_hash)));
+        match &diff.log {
+            Some(log) => {
+                out.push_str("Commits between them (< only in A, > only in B):\n");
+                for line in log {
+                    out.push_str(&format!("  {}\n", line));
+                }
+            }
+            None => out.push_str("The repository isn't available or lacks one of the commits, can't list the commits between them\n"),
+        }
+    }
+
+    if !git_a.is_dirty && !git_b.is_dirty {
+        out.push_str("No uncommitted changes in either run\n");
+        return out;
+    }
+    out.push_str("Uncommitted changes:\n");
+    for file in &diff.dirty_only_in_a {
+        out.push_str(&format!("  only in A: {}\n", file));
+    }
+    for file in &diff.dirty_only_in_b {
+        out.push_str(&format!("  only in B: {}\n", file));
+    }
+    for file in &diff.dirty_in_both {
+        out.push_str(&format!("  in both:   {}\n", file));
+    }
+    match &diff.patch_diff {
+        Some(patch_diff) => out.push_str(patch_diff),
+        None if diff.patches_recorded => out.push_str("The uncommitted patches are identical\n"),
+        None => out.push_str("A dirty run didn't record its patch, can't compare the changes\n"),
+    }
+    out
+}
+
+pub fn run_diff(args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let a_dir = resolve_run(archive_dir, &args.a)?;
+    let b_dir = resolve_run(archive_dir, &args.b)?;
+    if args.env {
+        print!("{}", format_environment_diff(&diff_environments(&a_dir, &b_dir)?));
+        return Ok(());
+    }
+    if args.git {
+        print!("{}", format_git_diff(&diff_git(&a_dir, &b_dir)?));
+        return Ok(());
+    }
+    let mut diff = diff_runs(&a_dir, &b_dir, args.tolerance)?;
+
+    if let Some(files) = &args.files {
+        let pattern = Pattern::new(files).map_err(|e| format!("Invalid file pattern '{}': {}", files, e))?;
+        diff.files.retain(|file| pattern.matches(&file.file));
+    }
+    print!("{}", format_diff(&diff));
+
+    if args.content {
+        let changed = diff.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Changed | FileStatus::ExceedsTolerance));
+        for file in changed {
+            match content_diff(&a_dir, &b_dir, &file.file) {
+                Some(unified) => print!("\n{}", unified),
+                None => println!("\nBinary file {} differs", file.file),
+            }
+        }
+    }
+    Ok(())
+}
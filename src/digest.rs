@@ -0,0 +1,47 @@
+use sha2::{Digest, Sha256};
+
+use crate::{get_script_basename, ExecutionResult};
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// The script arguments with the path of the archived parameters file replaced by `{params}`,
+/// since the path names the run directory
+fn normalized_args(result: &ExecutionResult) -> Vec<String> {
+    let Some(command) = &result.command else {
+        return Vec::new();
+    };
+    command
+        .script_args()
+        .iter()
+        .map(|arg| match result.params.as_ref().and_then(|params| Some((arg.find(&format!("/{}", params.file))?, params.file.len()))) {
+            // The path starts after the `=` of an option like `--config=...`
+            Some((end, file_len)) => {
+                let start = arg[..end].rfind('=').map_or(0, |position| position + 1);
+                format!("{}{{params}}{}", &arg[..start], &arg[end + 1 + file_len..])
+            }
+            None => arg.clone(),
+        })
+        .collect()
+}
+
+/// A single hash that is the same for two runs exactly if they ran the same script with the
+/// same arguments, commit and inputs, ended with the same exit code and wrote the same output
+/// files. Each output file contributes the hash of its name and content hash, and these are
+/// hashed in the order of their names into the root of the outputs, which is hashed with the
+/// other fields. Times, host, paths, captured output and the environment are left out, so the
+/// digest can be recomputed from the manifest on any machine.
+pub fn run_digest(result: &ExecutionResult) -> String {
+    let leaves: String = result.file_hashes.iter().map(|(file, hash)| sha256(format!("{}\0{}", file, hash).as_bytes())).collect();
+    let fields = [
+        format!("script\0{}", get_script_basename(&result.script_path)),
+        format!("args\0{}", normalized_args(result).join("\0")),
+        format!("commit\0{}", result.git_info.as_ref().map(|git| format!("{}{}", git.commit_hash, if git.is_dirty { "\0dirty" } else { "" })).unwrap_or_default()),
+        format!("stdin\0{}", result.stdin_hash.as_deref().unwrap_or_default()),
+        format!("params\0{}", result.params.as_ref().map(|params| params.sha256.as_str()).unwrap_or_default()),
+        format!("exit_code\0{}", result.exit_code),
+        format!("outputs\0{}", sha256(leaves.as_bytes())),
+    ];
+    sha256(fields.join("\n").as_bytes())
+}
@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::table::format_size;
+use crate::timeout::{still_running, stop_script};
+
+/// Stops scripts before they fill the filesystem of the archive:
+///
+/// ```yaml
+/// disk:
+///   min_free: 5G
+///   poll_secs: 10
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// Stop the script when less space is left, like `--min-free-space`
+    pub min_free: Option<String>,
+    /// Interval between two checks of the free space
+    pub poll_secs: u64,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        DiskConfig { min_free: None, poll_secs: 10 }
+    }
+}
+
+/// Parses a size like `500M`, `5G` or `1.5TiB` into bytes; the units are powers of 1024
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size '{}'", s))?;
+    let exponent = match unit.trim().trim_end_matches("iB").trim_end_matches('B').to_ascii_uppercase().as_str() {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(format!("invalid size '{}', use a unit like K, M, G or T", s)),
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+/// Space left for unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Result<u64, String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path_c = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    // SAFETY: plain system call with a NUL-terminated path, filling in a zeroed struct
+    let (status, stats) = unsafe {
+        let mut stats: libc::statvfs = std::mem::zeroed();
+        (libc::statvfs(path_c.as_ptr(), &mut stats), stats)
+    };
+    if status != 0 {
+        return Err(format!("Failed to read the free space of {}: {}", path.display(), std::io::Error::last_os_error()));
+    }
+    // The field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Result<u64, String> {
+    Err("free space is only monitored on Unix".to_string())
+}
+
+/// Watches the free space on the filesystem of a run directory while the script runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskMonitor {
+    pub run_dir: PathBuf,
+    pub min_free: u64,
+    pub poll: Duration,
+}
+
+impl DiskMonitor {
+    /// The size given on the command line takes precedence over the config. `None` without a minimum.
+    pub fn new(run_dir: &Path, min_free: Option<u64>, config: &DiskConfig) -> Result<Option<DiskMonitor>, String> {
+        let Some(min_free) = min_free.map(Ok).or_else(|| config.min_free.as_deref().map(parse_size)).transpose()? else {
+            return Ok(None);
+        };
+        free_space(run_dir)?;
+        Ok(Some(DiskMonitor { run_dir: run_dir.to_path_buf(), min_free, poll: Duration::from_secs(config.poll_secs.max(1)) }))
+    }
+
+    /// Checks the free space until `stopped` is signalled or dropped once the script exits,
+    /// stopping the script with process id `pid` when it drops below `min_free`. Returns
+    /// whether the script was stopped.
+    pub fn watch(&self, pid: u32, stopped: &Receiver<()>) -> bool {
+        loop {
+            match free_space(&self.run_dir) {
+                Ok(free) if free < self.min_free => {
+                    tracing::warn!("Stopping the script, only {} are left on the disk of {}", format_size(free), self.run_dir.display());
+                    return stop_script(pid, stopped);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("{}", e),
+            }
+            if !still_running(stopped, self.poll) {
+                return false;
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+use crate::archive::{load_manifest, manifest_path, ArchivedRun};
+use crate::notify::{format_duration, EmailConfig, RunNotification, SmtpSecurity};
+use crate::show::format_run;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Replaces `{placeholder}`s with the notification's values; `{summary}` is the `fastsave show` output
+pub fn render_template(template: &str, notification: &RunNotification, summary: &str) -> String {
+    template
+        .replace("{run_id}", &notification.run_id)
+        .replace("{script}", &notification.script)
+        .replace("{status}", notification.status)
+        .replace("{exit_code}", &notification.exit_code.to_string())
+        .replace("{duration}", &format_duration(notification.duration_ms))
+        .replace("{message}", notification.message.as_deref().unwrap_or_default())
+        .replace("{link}", &notification.link)
+        .replace("{summary}", summary)
+}
+
+pub fn send_email(email: &EmailConfig, notification: &RunNotification, run_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let result = load_manifest(&manifest_path(run_dir))?;
+    let archive_dir = run_dir.parent().unwrap_or(run_dir);
+    let summary = format_run(archive_dir, &ArchivedRun { run_dir: run_dir.to_path_buf(), result });
+
+    let mut builder = Message::builder()
+        .from(email.from.parse()?)
+        .subject(render_template(&email.subject, notification, &summary))
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in &email.to {
+        builder = builder.to(recipient.parse()?);
+    }
+    let message = builder.body(render_template(&email.body, notification, &summary))?;
+
+    let mut transport = match email.security {
+        SmtpSecurity::Tls => SmtpTransport::relay(&email.smtp_server)?,
+        SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&email.smtp_server)?,
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&email.smtp_server),
+    }
+    .timeout(Some(SMTP_TIMEOUT));
+    if let Some(port) = email.port {
+        transport = transport.port(port);
+    }
+
+    let username = email.username.clone().or_else(|| std::env::var("FASTSAVE_SMTP_USERNAME").ok());
+    let password = email.password.clone().or_else(|| std::env::var("FASTSAVE_SMTP_PASSWORD").ok());
+    if let (Some(username), Some(password)) = (username, password) {
+        transport = transport.credentials(Credentials::new(username, password));
+    }
+
+    transport.build().send(&message)?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::manifest_path;
+use crate::command::split_command;
+use crate::{experiment, parse_key_value, run_script, tag, Cli};
+
+#[derive(Args)]
+pub struct ExecArgs {
+    /// Command line to run, split into words like the interpreter setting, without a shell
+    #[arg(long = "command", value_name = "COMMAND", conflicts_with = "args", required_unless_present = "args")]
+    pub command: Option<String>,
+
+    /// Program and arguments to run, after `--`
+    #[arg(last = true, value_name = "PROGRAM")]
+    pub args: Vec<String>,
+
+    /// Name of the run in place of a script name; defaults to the program
+    #[arg(long = "name", value_name = "NAME", value_parser = parse_run_name)]
+    pub name: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+
+    /// Optional message to include in the results
+    #[arg(short = 'm', long = "message")]
+    pub message: Option<String>,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Attach metadata to the run (repeatable)
+    #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub metadata: Vec<(String, String)>,
+
+    /// Group the run into a named experiment
+    #[arg(short = 'e', long = "experiment", value_parser = experiment::parse_experiment_name)]
+    pub experiment: Option<String>,
+
+    /// Tag the run (repeatable)
+    #[arg(long = "tag", value_name = "TAG", value_parser = tag::parse_tag)]
+    pub tags: Vec<String>,
+
+    /// Fail the run unless it produced this non-empty output file or glob (repeatable)
+    #[arg(long = "expect", value_name = "GLOB")]
+    pub expect: Vec<String>,
+
+    /// Stop the command after this long, e.g. 12h; SIGTERM first, SIGKILL 10s later
+    #[arg(long = "timeout", value_name = "DURATION", value_parser = crate::archive::parse_duration)]
+    pub timeout: Option<chrono::Duration>,
+}
+
+/// Names end up in run directory names, so they are single words without path separators
+pub fn parse_run_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.starts_with(['@', '.']) || name.contains(|c: char| c.is_whitespace() || c == '/' || c == '\\') {
+        return Err(format!("invalid name '{}': must be a single word without slashes", name));
+    }
+    Ok(name.to_string())
+}
+
+impl ExecArgs {
+    /// Arguments for archiving the command like a script run named after it
+    pub fn cli(&self) -> Result<Cli, Box<dyn Error>> {
+        let words = match &self.command {
+            Some(command) => split_command(command).map_err(|e| format!("Invalid command: {}", e))?,
+            None => self.args.clone(),
+        };
+        let program = words.first().ok_or("No command given")?;
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => parse_run_name(&Path::new(program).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default())?,
+        };
+        Ok(Cli {
+            archive_dir: self.archive_dir.clone(),
+            message: self.message.clone(),
+            config_path: self.config_path.clone(),
+            metadata: self.metadata.clone(),
+            experiment: self.experiment.clone(),
+            tags: self.tags.clone(),
+            expect: self.expect.clone(),
+            timeout: self.timeout,
+            no_default_args: true,
+            exec: Some(words),
+            ..Cli::new(name)
+        })
+    }
+}
+
+pub fn run_exec(args: &ExecArgs) -> Result<(), Box<dyn Error>> {
+    let run = run_script(&args.cli()?)?;
+    println!("Fastsave completed. Output saved to: {}", manifest_path(&run.run_dir).display());
+    if let Some(digest) = &run.result.run_digest {
+        println!("Run digest: {}", digest);
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory inside the archive holding one folder of run links per experiment
+pub const EXPERIMENTS_DIR: &str = "experiments";
+
+pub fn parse_experiment_name(name: &str) -> Result<String, String> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(name.to_string()),
+        _ => Err(format!("invalid experiment name '{}': must be a single path component", name)),
+    }
+}
+
+/// Links the run directory into `<archive>/experiments/<name>/`
+pub fn add_to_experiment(archive_dir: &Path, run_dir: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    let relative_run = match run_dir.strip_prefix(archive_dir) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        // Runs stored directly in the archive dir (--no-subfolder) have nothing to link
+        _ => return Ok(()),
+    };
+
+    let experiment_dir = archive_dir.join(EXPERIMENTS_DIR).join(name);
+    fs::create_dir_all(&experiment_dir)?;
+
+    let link = experiment_dir.join(run_dir.file_name().ok_or("Run directory has no name")?);
+    let target = PathBuf::from("..").join("..").join(relative_run);
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link)?;
+    }
+    symlink_dir(&target, &link)?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub(crate) fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use clap::{ArgGroup, Args};
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, run_id};
+use crate::report::{html_report, markdown_summary};
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("format").required(true).args(["markdown", "html"])))]
+pub struct ExportArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Export a markdown summary
+    #[arg(long = "markdown")]
+    pub markdown: bool,
+
+    /// Export a standalone HTML report
+    #[arg(long = "html")]
+    pub html: bool,
+
+    /// Write to this file instead of stdout
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub fn run_export(args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(Path::new(&args.archive_dir), &args.run)?;
+    let result = load_manifest(&manifest_path(&run_dir))?;
+
+    // The script arguments aren't part of the manifest
+    let exported = if args.markdown {
+        markdown_summary(&run_id(&run_dir), &result)
+    } else {
+        html_report(&run_id(&run_dir), &result)
+    };
+
+    match &args.output {
+        Some(path) => fs::write(path, exported)?,
+        None => print!("{}", exported),
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use regex::Regex;
+
+use crate::archive::{find_runs, parse_since};
+use crate::get_script_basename;
+use crate::logs;
+use crate::notify::run_succeeded;
+use crate::table::Table;
+
+#[derive(Args)]
+pub struct FailuresArgs {
+    /// Only include runs started after this time (e.g. 7d, 12h, 2025-01-17)
+    #[arg(long = "since", value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only include runs of this script (name without extension)
+    #[arg(long = "script")]
+    pub script: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// Failed runs of one script that ended with the same error
+pub struct FailureGroup {
+    pub script: String,
+    /// Last stderr line with numbers and hashes masked, or the exit code without stderr
+    pub signature: String,
+    pub count: usize,
+    pub latest_run: String,
+    pub latest_time: DateTime<Utc>,
+}
+
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(0x)?[0-9a-fA-F]*[0-9][0-9a-fA-F]*\b").expect("valid regex"));
+
+/// The line that identifies how a run failed: the last line of stderr, which holds the exception
+/// of a traceback, with the parts that differ between occurrences of the same error masked
+pub fn failure_signature(stderr: &str, exit_code: i32) -> String {
+    match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+        Some(line) => NUMBER.replace_all(line, "N").into_owned(),
+        None => format!("exit code {} without stderr", exit_code),
+    }
+}
+
+/// Failed runs grouped by script and failure signature, most frequent first
+pub fn group_failures(
+    archive_dir: &Path,
+    script: Option<&str>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<FailureGroup>, Box<dyn Error>> {
+    let mut groups: BTreeMap<(String, String), FailureGroup> = BTreeMap::new();
+
+    // Runs are ordered by start time, so the last one seen is the most recent
+    for run in find_runs(archive_dir)? {
+        let result = &run.result;
+        let name = get_script_basename(&result.script_path);
+        if run_succeeded(result)
+            || script.is_some_and(|script| name != script)
+            || since.is_some_and(|since| result.start_time < since)
+        {
+            continue;
+        }
+        // With compressed logs the manifest only has the tail of stderr
+        let (_, stderr) = logs::full_output(&run.run_dir, result);
+        let signature = failure_signature(&stderr, result.exit_code);
+        let group = groups.entry((name.clone(), signature.clone())).or_insert_with(|| FailureGroup {
+            script: name,
+            signature,
+            count: 0,
+            latest_run: String::new(),
+            latest_time: result.start_time,
+        });
+        group.count += 1;
+        group.latest_run = run.id();
+        group.latest_time = result.start_time;
+    }
+
+    let mut groups: Vec<FailureGroup> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then(b.latest_time.cmp(&a.latest_time)));
+    Ok(groups)
+}
+
+pub fn run_failures(args: &FailuresArgs) -> Result<(), Box<dyn Error>> {
+    let groups = group_failures(Path::new(&args.archive_dir), args.script.as_deref(), args.since)?;
+    if groups.is_empty() {
+        println!("No failed runs found");
+        return Ok(());
+    }
+
+    let headers = ["SCRIPT", "COUNT", "LAST SEEN", "LAST RUN", "ERROR"];
+    let mut table = Table::new(headers.iter().map(|h| h.to_string()).collect());
+    for group in &groups {
+        table.rows.push(vec![
+            group.script.clone(),
+            group.count.to_string(),
+            group.latest_time.format("%Y-%m-%d %H:%M").to_string(),
+            group.latest_run.clone(),
+            group.signature.clone(),
+        ]);
+    }
+    print!("{}", table.to_text());
+    Ok(())
+}
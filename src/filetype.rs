@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes read from the start of a file to detect its kind
+const SNIFF_LENGTH: u64 = 4096;
+
+const SIGNATURES: [(&[u8], &str); 9] = [
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\x89HDF\r\n\x1a\n", "hdf5"),
+    (b"\x93NUMPY", "npy"),
+    (b"PAR1", "parquet"),
+    (b"%PDF", "pdf"),
+    (b"\xff\xd8\xff", "jpeg"),
+    (b"GIF8", "gif"),
+    (b"\x1f\x8b", "gzip"),
+    (b"PK\x03\x04", "zip"),
+];
+
+/// Detects the kind of a file, e.g. `csv`, `hdf5`, `png`, `npz` or `parquet`, from its first bytes.
+/// Empty files and unrecognized binary data have no kind.
+pub fn detect_kind(path: &Path) -> io::Result<Option<&'static str>> {
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_LENGTH).read_to_end(&mut head)?;
+    Ok(kind_of(&head))
+}
+
+fn kind_of(head: &[u8]) -> Option<&'static str> {
+    if head.is_empty() {
+        return None;
+    }
+    if let Some((_, kind)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(if *kind == "zip" && first_zip_entry(head).is_some_and(|name| name.ends_with(b".npy")) {
+            "npz"
+        } else {
+            kind
+        });
+    }
+    text_kind(head)
+}
+
+/// Name of the first entry from the local file header at the start of a zip archive
+fn first_zip_entry(head: &[u8]) -> Option<&[u8]> {
+    let length = u16::from_le_bytes([*head.get(26)?, *head.get(27)?]) as usize;
+    head.get(30..30 + length)
+}
+
+fn text_kind(head: &[u8]) -> Option<&'static str> {
+    if head.contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // The sniffed bytes may end within a multi-byte character
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return Some("json");
+    }
+    if trimmed.starts_with("<?xml") {
+        return Some("xml");
+    }
+
+    // Without the possibly truncated last line
+    let lines: Vec<&str> = match text.rsplit_once('\n') {
+        Some((complete, _)) => complete.lines().filter(|line| !line.is_empty()).collect(),
+        None => vec![text],
+    };
+    let Some(first) = lines.first() else {
+        return Some("text");
+    };
+    for separator in [',', '\t'] {
+        let columns = first.matches(separator).count();
+        if columns > 0 && lines.iter().all(|line| line.matches(separator).count() == columns) {
+            return Some(if separator == ',' { "csv" } else { "tsv" });
+        }
+    }
+    Some("text")
+}
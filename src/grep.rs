@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use regex::{Regex, RegexBuilder};
+
+use crate::archive::{find_runs, parse_since};
+use crate::get_script_basename;
+use crate::logs;
+
+#[derive(Args)]
+pub struct GrepArgs {
+    /// Regular expression to search for
+    pub pattern: String,
+
+    /// Only search runs of this script (name without extension)
+    #[arg(long = "script")]
+    pub script: Option<String>,
+
+    /// Only search runs started after this time (e.g. 7d, 12h, 2025-01-17)
+    #[arg(long = "since", value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Number of context lines to show around each match
+    #[arg(short = 'C', long = "context", default_value_t = 2)]
+    pub context: usize,
+
+    /// Match case-insensitively
+    #[arg(short = 'i', long = "ignore-case")]
+    pub ignore_case: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub struct GrepMatch {
+    pub run_id: String,
+    pub stream: &'static str,
+    /// 1-based number of the matching line
+    pub line_number: usize,
+    /// The matching line with its context as (line number, text)
+    pub lines: Vec<(usize, String)>,
+}
+
+pub fn grep_runs(
+    archive_dir: &Path,
+    pattern: &Regex,
+    script: Option<&str>,
+    since: Option<DateTime<Utc>>,
+    context: usize,
+) -> Result<Vec<GrepMatch>, Box<dyn Error>> {
+    let mut matches = Vec::new();
+
+    for run in find_runs(archive_dir)? {
+        let result = &run.result;
+        if script.is_some_and(|script| get_script_basename(&result.script_path) != script) {
+            continue;
+        }
+        if since.is_some_and(|since| result.start_time < since) {
+            continue;
+        }
+
+        let (stdout, stderr) = logs::full_output(&run.run_dir, result);
+        for (stream, output) in [("stdout", &stdout), ("stderr", &stderr)] {
+            let lines: Vec<&str> = output.lines().collect();
+            for (index, line) in lines.iter().enumerate() {
+                if !pattern.is_match(line) {
+                    continue;
+                }
+                let first = index.saturating_sub(context);
+                let last = (index + context).min(lines.len() - 1);
+                matches.push(GrepMatch {
+                    run_id: run.id(),
+                    stream,
+                    line_number: index + 1,
+                    lines: (first..=last).map(|i| (i + 1, lines[i].to_string())).collect(),
+                });
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+pub fn run_grep(args: &GrepArgs) -> Result<(), Box<dyn Error>> {
+    let pattern = RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()?;
+    let matches = grep_runs(
+        Path::new(&args.archive_dir),
+        &pattern,
+        args.script.as_deref(),
+        args.since,
+        args.context,
+    )?;
+
+    for m in &matches {
+        println!("{} ({}):", m.run_id, m.stream);
+        for (number, text) in &m.lines {
+            let marker = if *number == m.line_number { '>' } else { ' ' };
+            println!("{} {:>5}: {}", marker, number, text);
+        }
+    }
+
+    let mut runs: Vec<&str> = matches.iter().map(|m| m.run_id.as_str()).collect();
+    runs.dedup();
+    println!("{} matches in {} runs", matches.len(), runs.len());
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::started::{load_started, STARTED_FILE};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// Seconds between updates of the heartbeat in `started.yaml`; 0 disables heartbeats
+    pub interval_secs: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig { interval_secs: 30 }
+    }
+}
+
+/// Sign of life of a running script, updated periodically in `started.yaml`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    /// When fastsave last updated the heartbeat
+    pub time: DateTime<Utc>,
+    /// Seconds until the next update is due
+    pub interval_secs: u64,
+    pub stdout_lines: u64,
+    pub stderr_lines: u64,
+    /// CPU time used by the script so far; `None` where it can't be read
+    pub cpu_seconds: Option<f64>,
+    /// Resident memory of the script; `None` where it can't be read
+    pub memory_bytes: Option<u64>,
+    /// When the script last wrote output or used CPU time
+    pub last_progress: DateTime<Utc>,
+}
+
+impl Heartbeat {
+    /// The next heartbeat, keeping the time of the last progress unless the script made some since `previous`
+    pub fn next(previous: Option<&Heartbeat>, interval_secs: u64, stdout_lines: u64, stderr_lines: u64, pid: u32) -> Heartbeat {
+        let now = Utc::now();
+        let (cpu_seconds, memory_bytes) = match process_usage(pid) {
+            Some((cpu, memory)) => (Some(cpu), Some(memory)),
+            None => (None, None),
+        };
+        let progressed = previous.is_none_or(|previous| {
+            stdout_lines + stderr_lines > previous.stdout_lines + previous.stderr_lines
+                || cpu_seconds.zip(previous.cpu_seconds).is_some_and(|(cpu, before)| cpu > before)
+        });
+        Heartbeat {
+            time: now,
+            interval_secs,
+            stdout_lines,
+            stderr_lines,
+            cpu_seconds,
+            memory_bytes,
+            last_progress: if progressed { now } else { previous.map_or(now, |previous| previous.last_progress) },
+        }
+    }
+}
+
+/// Stores the heartbeat in the run's `started.yaml`, if the run has one
+pub fn write_heartbeat(run_dir: &Path, heartbeat: &Heartbeat) -> Result<(), Box<dyn Error>> {
+    if !run_dir.join(STARTED_FILE).is_file() {
+        return Ok(());
+    }
+    let mut start = load_started(run_dir)?;
+    start.heartbeat = Some(heartbeat.clone());
+    // Write next to the record and rename, so `status` never reads a half-written file
+    let temporary = run_dir.join(format!(".{}.tmp", STARTED_FILE));
+    fs::write(&temporary, serde_yaml::to_string(&start)?)?;
+    fs::rename(&temporary, run_dir.join(STARTED_FILE))?;
+    Ok(())
+}
+
+/// CPU seconds and resident memory in bytes of a process, read from `/proc`
+#[cfg(target_os = "linux")]
+pub fn process_usage(pid: u32) -> Option<(f64, u64)> {
+    // Kernels report CPU time in ticks of USER_HZ, which is 100 on all common platforms
+    const TICKS_PER_SECOND: f64 = 100.0;
+
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces, the fields after it don't
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let user_ticks: u64 = fields.get(11)?.parse().ok()?;
+    let system_ticks: u64 = fields.get(12)?.parse().ok()?;
+
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let resident_kb: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(((user_ticks + system_ticks) as f64 / TICKS_PER_SECOND, resident_kb * 1024))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_usage(_pid: u32) -> Option<(f64, u64)> {
+    None
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Shell commands run after the run directory is created, before the script starts
+    pub pre_run: Vec<String>,
+    /// Shell commands run after the script finished and the manifest is written
+    pub post_run: Vec<String>,
+    /// Like `post_run`, but only for failed runs; stderr is passed on stdin
+    pub on_failure: Vec<String>,
+}
+
+/// Outcome of a hook command, archived in the manifest
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HookResult {
+    pub stage: String,
+    pub command: String,
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
+
+impl HookResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// Runs a hook command through the shell with the run context in its environment
+pub fn run_hook(stage: &str, command: &str, env: &[(&str, String)], stdin: Option<&str>) -> Result<HookResult, Box<dyn Error>> {
+    tracing::info!("Fastsave {} hook: {}", stage, command);
+    let start = Instant::now();
+
+    let mut child = shell(command)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start {} hook '{}': {}", stage, command, e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // A hook that doesn't read its input closes the pipe early; that is not an error
+        let _ = pipe.write_all(input.as_bytes());
+    }
+    let output = child.wait_with_output()?;
+
+    let result = HookResult {
+        stage: stage.to_string(),
+        command: command.to_string(),
+        exit_code: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    };
+    print!("{}", result.stdout);
+    eprint!("{}", result.stderr);
+    Ok(result)
+}
+
+/// Runs the commands in order, stopping after the first one that fails
+pub fn run_hooks(stage: &str, commands: &[String], env: &[(&str, String)], stdin: Option<&str>) -> Result<Vec<HookResult>, Box<dyn Error>> {
+    let mut results = Vec::new();
+    for command in commands {
+        let result = run_hook(stage, command, env, stdin)?;
+        let failed = !result.succeeded();
+        results.push(result);
+        if failed {
+            break;
+        }
+    }
+    Ok(results)
+}
@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use glob::Pattern;
+
+/// Output files that aren't hashed, read from the current directory like `fastsave.yaml`
+pub const IGNORE_FILE: &str = ".fastsaveignore";
+
+/// Glob patterns of output files to leave out of the manifest, one per line, with `#` comments
+#[derive(Default)]
+pub struct IgnorePatterns {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnorePatterns {
+    pub fn parse(text: &str) -> Result<IgnorePatterns, String> {
+        let patterns = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| Pattern::new(line).map_err(|e| format!("Invalid pattern '{}' in {}: {}", line, IGNORE_FILE, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(IgnorePatterns { patterns })
+    }
+
+    /// The patterns of `.fastsaveignore` in `dir`, none if there is no such file
+    pub fn load(dir: &Path) -> Result<IgnorePatterns, Box<dyn Error>> {
+        match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => Ok(IgnorePatterns::parse(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(IgnorePatterns::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", IGNORE_FILE, e).into()),
+        }
+    }
+
+    pub fn matches(&self, file: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(file))
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+
+use crate::archive::{find_runs, load_manifest, manifest_path, ArchivedRun};
+use crate::experiment::{add_to_experiment, EXPERIMENTS_DIR};
+
+#[derive(Args)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub action: IndexAction,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive", global = true)]
+    pub archive_dir: String,
+}
+
+#[derive(Subcommand)]
+pub enum IndexAction {
+    /// Report runs missing from the indexes and index entries without a run
+    Verify,
+    /// Reconstruct the indexes from the manifests
+    Rebuild,
+}
+
+/// Differences between an index and the runs in the archive
+pub struct IndexDiff {
+    pub index: &'static str,
+    /// Runs missing from the index
+    pub added: Vec<String>,
+    /// Index entries whose run no longer exists or doesn't belong there
+    pub removed: Vec<String>,
+}
+
+impl IndexDiff {
+    pub fn is_consistent(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Links as `<experiment>/<run id>`, split into valid ones and ones that are dangling or point to another run
+fn experiment_links(archive_dir: &Path) -> Result<(BTreeSet<String>, BTreeSet<String>), Box<dyn Error>> {
+    let mut valid = BTreeSet::new();
+    let mut stale = BTreeSet::new();
+    let experiments_dir = archive_dir.join(EXPERIMENTS_DIR);
+    if !experiments_dir.is_dir() {
+        return Ok((valid, stale));
+    }
+    for experiment in fs::read_dir(experiments_dir)? {
+        let experiment = experiment?;
+        if !experiment.file_type()?.is_dir() {
+            continue;
+        }
+        let name = experiment.file_name().to_string_lossy().into_owned();
+        for link in fs::read_dir(experiment.path())? {
+            let link = link?;
+            let id = link.file_name().to_string_lossy().into_owned();
+            let belongs = load_manifest(&manifest_path(&link.path()))
+                .is_ok_and(|result| result.experiment.as_deref() == Some(name.as_str()));
+            let entry = format!("{}/{}", name, id);
+            if belongs {
+                valid.insert(entry);
+            } else {
+                stale.insert(entry);
+            }
+        }
+    }
+    Ok((valid, stale))
+}
+
+/// Members of experiments that are linked from `experiments/`, i.e. not stored directly in the archive directory
+fn expected_links(archive_dir: &Path, runs: &[ArchivedRun]) -> BTreeSet<String> {
+    runs.iter()
+        .filter(|run| run.run_dir != archive_dir)
+        .filter_map(|run| Some(format!("{}/{}", run.result.experiment.as_ref()?, run.id())))
+        .collect()
+}
+
+fn verify_experiments(archive_dir: &Path, runs: &[ArchivedRun]) -> Result<IndexDiff, Box<dyn Error>> {
+    let (valid, mut stale) = experiment_links(archive_dir)?;
+    let expected = expected_links(archive_dir, runs);
+    stale.extend(valid.difference(&expected).cloned());
+    Ok(IndexDiff {
+        index: "experiments",
+        added: expected.difference(&valid).cloned().collect(),
+        removed: stale.into_iter().collect(),
+    })
+}
+
+#[cfg(feature = "search")]
+fn verify_search(archive_dir: &Path, runs: &[ArchivedRun]) -> Result<Option<IndexDiff>, Box<dyn Error>> {
+    let Some(indexed) = crate::search::indexed_runs(archive_dir)? else {
+        return Ok(None);
+    };
+    let ids: BTreeSet<String> = runs.iter().map(ArchivedRun::id).collect();
+    Ok(Some(IndexDiff {
+        index: "search",
+        added: ids.difference(&indexed).cloned().collect(),
+        removed: indexed.difference(&ids).cloned().collect(),
+    }))
+}
+
+#[cfg(not(feature = "search"))]
+fn verify_search(_: &Path, _: &[ArchivedRun]) -> Result<Option<IndexDiff>, Box<dyn Error>> {
+    Ok(None)
+}
+
+/// Compares the experiment links and, with the `search` feature, the full-text index with the manifests
+pub fn verify_indexes(archive_dir: &Path) -> Result<Vec<IndexDiff>, Box<dyn Error>> {
+    let runs = find_runs(archive_dir)?;
+    let experiments = verify_experiments(archive_dir, &runs)?;
+    Ok(std::iter::once(experiments).chain(verify_search(archive_dir, &runs)?).collect())
+}
+
+/// Reconstructs the indexes from the manifests and returns what changed
+pub fn rebuild_indexes(archive_dir: &Path) -> Result<Vec<IndexDiff>, Box<dyn Error>> {
+    let diffs = verify_indexes(archive_dir)?;
+    let runs = find_runs(archive_dir)?;
+
+    let experiments_dir = archive_dir.join(EXPERIMENTS_DIR);
+    for entry in &diffs[0].removed {
+        let link = experiments_dir.join(entry);
+        if link.symlink_metadata().is_ok_and(|metadata| !metadata.is_dir()) {
+            fs::remove_file(&link)?;
+        }
+    }
+    for run in runs.iter().filter(|run| run.run_dir != archive_dir) {
+        if let Some(experiment) = &run.result.experiment {
+            add_to_experiment(archive_dir, &run.run_dir, experiment)?;
+        }
+    }
+
+    // The full-text index is rebuilt even if it doesn't exist yet, unlike after a run
+    #[cfg(feature = "search")]
+    crate::search::rebuild_index(archive_dir)?;
+
+    Ok(diffs)
+}
+
+pub fn run_index(args: &IndexArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let diffs = match args.action {
+        IndexAction::Verify => verify_indexes(archive_dir)?,
+        IndexAction::Rebuild => rebuild_indexes(archive_dir)?,
+    };
+
+    for diff in &diffs {
+        if diff.is_consistent() {
+            println!("{}: consistent", diff.index);
+            continue;
+        }
+        println!("{}: {} runs missing, {} stale entries", diff.index, diff.added.len(), diff.removed.len());
+        for entry in &diff.added {
+            println!("  + {}", entry);
+        }
+        for entry in &diff.removed {
+            println!("  - {}", entry);
+        }
+    }
+
+    if matches!(args.action, IndexAction::Verify) && !diffs.iter().all(IndexDiff::is_consistent) {
+        return Err("Indexes are inconsistent, run `fastsave index rebuild` to reconstruct them".into());
+    }
+    Ok(())
+}
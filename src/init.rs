@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::ignore::IGNORE_FILE;
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Project directory to set up
+    #[arg(default_value = ".")]
+    pub dir: String,
+
+    /// Also write a template script showing the `--output_dir` convention (`.py` or `.sh`)
+    #[arg(long = "script", value_name = "NAME")]
+    pub script: Option<String>,
+
+    /// Overwrite existing files instead of leaving them alone
+    #[arg(long = "force")]
+    pub force: bool,
+
+    /// Archive directory path, relative to the project directory
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+const CONFIG_TEMPLATE: &str = r#"# fastsave configuration, see the manual for all sections
+interpreters:
+  py: python
+  sh: sh
+
+# Metrics parsed from the script output
+# metrics:
+#   patterns:
+#     - 'loss=(?P<loss>[\d.]+)'
+
+# Arguments every run of a script starts with, keyed by the script name without extension
+# scripts:
+#   run_simulation:
+#     args: [--solver, cg]
+
+# Invocations run as `fastsave @name`
+# aliases:
+#   sim:
+#     script: run_simulation.py
+#     args: [--rows, 3]
+
+# Runs `fastsave clean` deletes
+# retention:
+#   rules:
+#     - untagged: true
+#       older_than: 30d
+"#;
+
+const IGNORE_TEMPLATE: &str = "# Output files fastsave doesn't hash or list in the manifest, one glob pattern per line
+*.tmp
+*.swp
+";
+
+const PYTHON_TEMPLATE: &str = r#"#!/usr/bin/env python3
+"""Run with `fastsave {name}`; every output goes into the directory given as --output_dir."""
+import argparse
+import json
+from pathlib import Path
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--output_dir", default=".", help="Run directory created by fastsave")
+    args = parser.parse_args()
+    output_dir = Path(args.output_dir)
+
+    print("Running...")
+    (output_dir / "result.txt").write_text("Hello from fastsave\n")
+    # Metrics in metrics.json end up in the manifest
+    (output_dir / "metrics.json").write_text(json.dumps({"answer": 42}))
+
+
+if __name__ == "__main__":
+    main()
+"#;
+
+const SHELL_TEMPLATE: &str = r#"#!/bin/sh
+# Run with `fastsave {name}`; every output goes into the directory given as --output_dir
+set -e
+output_dir=.
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --output_dir) output_dir=$2; shift 2 ;;
+        *) shift ;;
+    esac
+done
+
+echo "Running..."
+echo "Hello from fastsave" > "$output_dir/result.txt"
+# Metrics in metrics.json end up in the manifest
+echo '{"answer": 42}' > "$output_dir/metrics.json"
+"#;
+
+/// What `init` did with one file
+#[derive(Debug, PartialEq)]
+pub enum InitAction {
+    Created,
+    Overwritten,
+    Updated,
+    Kept,
+}
+
+/// Writes `content` to `path` unless the file exists and `force` isn't set
+fn write_new(path: &Path, content: &str, force: bool) -> Result<InitAction, Box<dyn Error>> {
+    let existed = path.exists();
+    if existed && !force {
+        return Ok(InitAction::Kept);
+    }
+    fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(if existed { InitAction::Overwritten } else { InitAction::Created })
+}
+
+/// Adds the archive to `.gitignore`, keeping the entries that are already there
+fn ignore_archive(gitignore: &Path, archive_dir: &str) -> Result<InitAction, Box<dyn Error>> {
+    let entry = format!("/{}/", archive_dir.trim_matches('/'));
+    let existing = match fs::read_to_string(gitignore) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let bare = entry.trim_matches('/');
+    if existing.lines().map(str::trim).any(|line| line.trim_matches('/') == bare) {
+        return Ok(InitAction::Kept);
+    }
+    let separator = if existing.is_empty() || existing.ends_with('\n') { "" } else { "\n" };
+    fs::write(gitignore, format!("{}{}{}\n", existing, separator, entry))?;
+    Ok(if existing.is_empty() { InitAction::Created } else { InitAction::Updated })
+}
+
+fn script_template(name: &str) -> Result<String, Box<dyn Error>> {
+    let template = match Path::new(name).extension().and_then(|extension| extension.to_str()) {
+        Some("py") => PYTHON_TEMPLATE,
+        Some("sh") => SHELL_TEMPLATE,
+        _ => return Err(format!("No template for {}, name the script *.py or *.sh", name).into()),
+    };
+    Ok(template.replace("{name}", name))
+}
+
+/// Sets up `dir` for fastsave: a config file, `.fastsaveignore`, the archive in `.gitignore`
+/// and optionally a template script. Existing files are kept unless `force` is set; the
+/// archive entry is only ever added.
+pub fn init_project(dir: &Path, archive_dir: &str, script: Option<&str>, force: bool) -> Result<Vec<(PathBuf, InitAction)>, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let mut actions = Vec::new();
+    let config = dir.join("fastsave.yaml");
+    actions.push((config.clone(), write_new(&config, CONFIG_TEMPLATE, force)?));
+    let ignore = dir.join(IGNORE_FILE);
+    actions.push((ignore.clone(), write_new(&ignore, IGNORE_TEMPLATE, force)?));
+    let gitignore = dir.join(".gitignore");
+    actions.push((gitignore.clone(), ignore_archive(&gitignore, archive_dir)?));
+    if let Some(name) = script {
+        let path = dir.join(name);
+        let action = write_new(&path, &script_template(name)?, force)?;
+        #[cfg(unix)]
+        if action != InitAction::Kept {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        actions.push((path, action));
+    }
+    Ok(actions)
+}
+
+pub fn run_init(args: &InitArgs) -> Result<(), Box<dyn Error>> {
+    let actions = init_project(Path::new(&args.dir), &args.archive_dir, args.script.as_deref(), args.force)?;
+    for (path, action) in &actions {
+        let verb = match action {
+            InitAction::Created => "Created",
+            InitAction::Overwritten => "Overwrote",
+            InitAction::Updated => "Updated",
+            InitAction::Kept => "Kept existing",
+        };
+        println!("{} {}", verb, path.display());
+    }
+    if let Some(script) = &args.script {
+        println!("Try it with: fastsave {}", script);
+    }
+    Ok(())
+}
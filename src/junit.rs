@@ -0,0 +1,74 @@
+use std::fmt::Write;
+
+use crate::notify::run_succeeded;
+use crate::{get_script_basename, ExecutionResult};
+
+/// Renders runs as a JUnit test suite, one test case per run
+pub fn junit_xml(runs: &[(String, &ExecutionResult)]) -> String {
+    let failures = runs.iter().filter(|(_, result)| failure_message(result).is_some()).count();
+    let total_ms: u64 = runs.iter().map(|(_, result)| result.duration_ms).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuite name=\"fastsave\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">",
+        runs.len(), failures, total_ms as f64 / 1000.0
+    );
+
+    for (id, result) in runs {
+        let _ = writeln!(
+            xml,
+            "  <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\">",
+            escape_xml(&get_script_basename(&result.script_path)),
+            escape_xml(id),
+            result.duration_ms as f64 / 1000.0
+        );
+        if let Some(message) = failure_message(result) {
+            let _ = writeln!(
+                xml,
+                "    <failure message=\"{}\">{}</failure>",
+                escape_xml(&message),
+                escape_xml(&result.stderr)
+            );
+        }
+        let _ = writeln!(xml, "    <system-out>{}</system-out>", escape_xml(&result.stdout));
+        let _ = writeln!(xml, "    <system-err>{}</system-err>", escape_xml(&result.stderr));
+        xml.push_str("  </testcase>\n");
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn failure_message(result: &ExecutionResult) -> Option<String> {
+    if run_succeeded(result) {
+        return None;
+    }
+    if let Some(check) = result.check.as_ref().filter(|check| !check.passed) {
+        return Some(format!("Outputs deviate from reference {}", check.reference));
+    }
+    if result.validation.as_ref().is_some_and(|validation| !validation.passed) {
+        return Some("Expected outputs are missing or empty".to_string());
+    }
+    if result.exit_code != 0 {
+        return Some(format!("Script exited with code {}", result.exit_code));
+    }
+    Some("stderr matched a failure pattern".to_string())
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
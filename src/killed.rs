@@ -0,0 +1,119 @@
+use std::fs;
+use std::process::ExitStatus;
+
+use serde::{Deserialize, Serialize};
+
+/// SIGKILL, which the OOM killer sends
+const SIGKILL: i32 = 9;
+
+/// Why a script was killed, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KilledReason {
+    /// The kernel OOM killer ended the script or one of its processes
+    Oom,
+    /// The script was ended by a signal sent by someone else
+    Signal,
+    /// fastsave stopped the script when it reached its `--timeout`
+    Timeout,
+    /// fastsave stopped the script when the disk of the archive ran full
+    DiskFull,
+}
+
+impl KilledReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KilledReason::Oom => "oom",
+            KilledReason::Signal => "signal",
+            KilledReason::Timeout => "timeout",
+            KilledReason::DiskFull => "disk_full",
+        }
+    }
+
+    /// Whether fastsave stopped the script, which fails the run even if it exited with 0
+    pub fn stopped_by_fastsave(self) -> bool {
+        matches!(self, KilledReason::Timeout | KilledReason::DiskFull)
+    }
+}
+
+/// Signal that ended the process, if it didn't exit on its own
+#[cfg(unix)]
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Counts of OOM kills, taken before and after the run. A count that went up while the
+/// script ran tells an OOM kill apart from a SIGKILL sent by a user or a scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OomCounters {
+    /// Kills in the memory cgroup of fastsave, which the script inherits
+    cgroup: Option<u64>,
+    /// Kills on the whole machine
+    system: Option<u64>,
+}
+
+/// Value of `key` in files of `key value` lines, like `/proc/vmstat` and `memory.events`
+fn counter(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok()).flatten()
+    })
+}
+
+/// Reads the `oom_kill` count of the memory cgroup of this process, from cgroup v2's
+/// `memory.events` or cgroup v1's `memory.oom_control`
+fn cgroup_oom_kills() -> Option<u64> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mut candidates = Vec::new();
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (id, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let path = path.trim_start_matches('/');
+        if id == "0" && controllers.is_empty() {
+            candidates.push(format!("/sys/fs/cgroup/{}/memory.events", path));
+        } else if controllers.split(',').any(|controller| controller == "memory") {
+            candidates.push(format!("/sys/fs/cgroup/memory/{}/memory.oom_control", path));
+            // Inside a container the cgroup of the process is mounted as the root
+            candidates.push("/sys/fs/cgroup/memory/memory.oom_control".to_string());
+        }
+    }
+    candidates
+        .iter()
+        .find_map(|file| fs::read_to_string(file).ok().and_then(|content| counter(&content, "oom_kill")))
+}
+
+impl OomCounters {
+    pub fn read() -> OomCounters {
+        let system = fs::read_to_string("/proc/vmstat").ok().and_then(|content| counter(&content, "oom_kill"));
+        OomCounters { cgroup: cgroup_oom_kills(), system }
+    }
+
+    /// Whether the OOM killer struck since `self` was read, in the cgroup if it could be
+    /// read both times, else anywhere on the machine
+    pub fn increased(&self, after: &OomCounters) -> bool {
+        match (self.cgroup, after.cgroup) {
+            (Some(before), Some(after)) => after > before,
+            _ => matches!((self.system, after.system), (Some(before), Some(after)) if after > before),
+        }
+    }
+}
+
+/// Why the script was killed, if it was. A script whose child process was killed by the
+/// OOM killer usually exits with 128 + 9 = 137, like shells do, so that counts as well.
+pub fn killed_reason(status: &ExitStatus, before: &OomCounters) -> Option<KilledReason> {
+    let signal = exit_signal(status);
+    let killed = signal == Some(SIGKILL) || status.code() == Some(128 + SIGKILL);
+    if killed && before.increased(&OomCounters::read()) {
+        Some(KilledReason::Oom)
+    } else if signal.is_some() {
+        Some(KilledReason::Signal)
+    } else {
+        None
+    }
+}
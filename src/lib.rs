@@ -1,20 +1,101 @@
 use serde::{Deserialize, Serialize};
 use std::process::Command;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use std::error::Error;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use chrono::{DateTime, Utc, Local};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use sha2::{Sha256, Digest};
 use std::io::Read;
-use serde_yaml;
 use std::process::Stdio;
 use std::io::{self, Write, BufRead, BufReader};
-use shellexpand;
 
-#[derive(Parser)]
+pub mod archive;
+pub mod attach;
+pub mod attest;
+pub mod audit;
+#[cfg(feature = "async")]
+pub mod async_run;
+pub mod baseline;
+pub mod batch;
+pub mod benchmark;
+pub mod check;
+pub mod command;
+pub mod compare;
+pub mod clean;
+pub mod cpus;
+pub mod criteria;
+pub mod determinism;
+pub mod diff;
+pub mod digest;
+pub mod disk;
+#[cfg(feature = "email")]
+pub mod email;
+pub mod exec;
+pub mod experiment;
+pub mod export;
+pub mod failures;
+pub mod filetype;
+pub mod grep;
+pub mod heartbeat;
+pub mod hooks;
+pub mod ignore;
+pub mod index;
+pub mod init;
+pub mod junit;
+pub mod killed;
+pub mod list;
+pub mod lock;
+pub mod logs;
+pub mod metrics;
+pub mod note;
+pub mod notify;
+pub mod observer;
+pub mod paths;
+pub mod pin;
+pub mod plot;
+pub mod preview;
+pub mod protect;
+pub mod provenance;
+pub mod query;
+pub mod queue;
+pub mod redact;
+pub mod remote;
+pub mod repro;
+pub mod report;
+pub mod runner;
+pub mod sandbox;
+pub mod scratch;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod show;
+pub mod started;
+pub mod stats;
+pub mod status;
+pub mod system;
+pub mod table;
+pub mod tag;
+pub mod tarball;
+pub mod thumbnail;
+pub mod timeline;
+pub mod timeout;
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod validate;
+
+use archive::{save_manifest, save_manifest_as, ArchivedRun};
+use metrics::{MetricExtractor, MetricsConfig, read_metrics_file};
+use observer::{NoObserver, RunObserver};
+use redact::Redactor;
+
+#[derive(Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     /// Path to the script to execute
@@ -36,6 +117,10 @@ pub struct Cli {
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     pub script_args: Vec<String>,
 
+    /// Don't put the script's default arguments from the `scripts` section of the config before script_args
+    #[arg(long = "no-default-args")]
+    pub no_default_args: bool,
+
     /// Override the interpreter for the script
     #[arg(short = 'i', long = "interpreter")]
     pub interpreter: Option<String>,
@@ -43,6 +128,310 @@ pub struct Cli {
     /// Override the config file path
     #[arg(short = 'c', long = "config")]
     pub config_path: Option<String>,
+
+    /// Attach metadata to the run (repeatable)
+    #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub metadata: Vec<(String, String)>,
+
+    /// Group the run into a named experiment
+    #[arg(short = 'e', long = "experiment", value_parser = experiment::parse_experiment_name)]
+    pub experiment: Option<String>,
+
+    /// Tag the run, e.g. to keep it from `fastsave clean` (repeatable)
+    #[arg(long = "tag", value_name = "TAG", value_parser = tag::parse_tag)]
+    pub tags: Vec<String>,
+
+    /// Id or path of a run whose outputs this run consumes (repeatable)
+    #[arg(long = "parent", value_name = "RUN")]
+    pub parents: Vec<String>,
+
+    /// Fail if the outputs deviate from this reference manifest
+    #[arg(long = "check-against", value_name = "MANIFEST")]
+    pub check_against: Option<String>,
+
+    /// Metric compared by --check-against, with an optional absolute tolerance (repeatable)
+    #[arg(long = "check-metric", value_name = "NAME[=TOL]", value_parser = check::parse_metric_tolerance)]
+    pub check_metrics: Vec<(String, f64)>,
+
+    /// Write a JUnit XML report of the run to this path
+    #[arg(long = "junit", value_name = "PATH")]
+    pub junit: Option<String>,
+
+    /// Run the script N times and report timing statistics
+    #[arg(long = "repeat", value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "no_subfolder")]
+    pub repeat: Option<u32>,
+
+    /// Number of additional warmup runs excluded from the benchmark statistics
+    #[arg(long = "warmup", value_name = "K", requires = "repeat")]
+    pub warmup: Option<u32>,
+
+    /// Run the script twice and report output files that differ
+    #[arg(long = "determinism-check", conflicts_with_all = ["repeat", "no_subfolder"])]
+    pub determinism_check: bool,
+
+    /// Show a desktop notification when the run completes (requires the `desktop` feature)
+    #[arg(long = "notify")]
+    pub notify: bool,
+
+    /// Only notify about runs that took at least this long, e.g. 10m
+    #[arg(long = "notify-after", value_name = "DURATION", requires = "notify", value_parser = archive::parse_duration)]
+    pub notify_after: Option<chrono::Duration>,
+
+    /// Stop the script after this long, e.g. 12h; SIGTERM first, SIGKILL 10s later
+    #[arg(long = "timeout", value_name = "DURATION", value_parser = archive::parse_duration)]
+    pub timeout: Option<chrono::Duration>,
+
+    /// Send the script SIGUSR1 this long before its timeout, so it can save its state, e.g. 15m
+    #[arg(long = "warn-before", value_name = "DURATION", value_parser = archive::parse_duration)]
+    pub warn_before: Option<chrono::Duration>,
+
+    /// Stop the script when less space is left on the archive's disk, e.g. 5G
+    #[arg(long = "min-free-space", value_name = "SIZE", value_parser = disk::parse_size)]
+    pub min_free_space: Option<u64>,
+
+    /// Replace the home directory and user name in recorded paths, for manifests shared externally
+    #[arg(long = "anonymize-paths")]
+    pub anonymize_paths: bool,
+
+    /// Fail the run unless it produced this non-empty output file or glob (repeatable)
+    #[arg(long = "expect", value_name = "GLOB")]
+    pub expect: Vec<String>,
+
+    /// Feed this file to the script's stdin, or our own stdin with `-`; archived as stdin.txt
+    #[arg(long = "stdin", value_name = "FILE")]
+    pub stdin: Option<String>,
+
+    /// Parameters file to archive with the run; the script finds the copy in `{params}` in its
+    /// arguments and in FASTSAVE_PARAMS
+    #[arg(long = "params", value_name = "FILE")]
+    pub params: Option<String>,
+
+    /// Write a report of the run into its directory (repeatable)
+    #[arg(long = "report", value_name = "FORMAT", value_enum)]
+    pub report: Vec<report::ReportFormat>,
+
+    /// Hold this named lock while the script runs, waiting for other runs holding it
+    #[arg(long = "lock", value_name = "NAME", value_parser = lock::parse_lock_name)]
+    pub lock: Option<String>,
+
+    /// Fail instead of waiting if the lock of the script is held
+    #[arg(long = "no-wait")]
+    pub no_wait: bool,
+
+    /// Run the script in a bubblewrap sandbox where only the output directory is writable
+    #[arg(long = "sandbox")]
+    pub sandbox: bool,
+
+    /// File or directory the script reads, mounted read-only into the sandbox and checked
+    /// by --read-only-project (repeatable)
+    #[arg(long = "input", value_name = "PATH")]
+    pub inputs: Vec<String>,
+
+    /// Run the script without network access, in its own network namespace (Linux)
+    #[arg(long = "no-network")]
+    pub no_network: bool,
+
+    /// Record the files outside the project the script read, traced with strace (Linux)
+    #[arg(long = "trace-inputs")]
+    pub trace_inputs: bool,
+
+    /// Fail the run if the script modified tracked files of its repository or the inputs
+    #[arg(long = "read-only-project")]
+    pub read_only_project: bool,
+
+    /// Give the script a scratch directory of its own as TMPDIR, removed after the run
+    #[arg(long = "scratch")]
+    pub scratch: bool,
+
+    /// Pin the script to these CPUs, e.g. 0-7 or 0,2,4 (Linux)
+    #[arg(long = "cpus", value_name = "LIST", value_parser = cpus::parse_cpu_list)]
+    pub cpus: Option<String>,
+
+    /// Export this thread count as OMP_NUM_THREADS, JULIA_NUM_THREADS and the like;
+    /// defaults to the number of CPUs given with --cpus
+    #[arg(long = "threads", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Record the SHA-256 of the interpreter executable in the manifest
+    #[arg(long = "hash-interpreter")]
+    pub hash_interpreter: bool,
+
+    /// Write the shared libraries of the interpreter (ldd or otool -L) into the run directory
+    #[arg(long = "record-libraries")]
+    pub record_libraries: bool,
+
+    /// Archive the package lists and lockfiles of the environment (pip freeze, conda env export,
+    /// Cargo.lock, Manifest.toml, package-lock.json, ...) in environment/ of the run directory
+    #[arg(long = "lock-env")]
+    pub lock_env: bool,
+
+    /// Pack the files of the run into a single run.tar, keeping the manifest next to it
+    #[arg(long = "tar", conflicts_with = "no_subfolder")]
+    pub tar: bool,
+
+    /// Write the manifest as fastsave.json or fastsave.yaml, overriding `format` in the config file
+    #[arg(long = "format", value_name = "FORMAT")]
+    pub format: Option<archive::ManifestFormat>,
+
+    /// Write stdout and stderr through zstd into the run directory, keeping only their
+    /// last lines in the manifest (needs the `compress` feature)
+    #[arg(long = "compress-logs")]
+    pub compress_logs: bool,
+
+    /// Upload output files to the remote of the config file while the script writes them
+    #[arg(long = "live-upload")]
+    pub live_upload: bool,
+
+    /// Priority of the queued job this run belongs to, set by `fastsave daemon`
+    #[arg(skip)]
+    pub priority: Option<queue::Priority>,
+
+    /// Program and arguments run instead of a script, set by `fastsave exec`; `script` then
+    /// only names the run, and the git information is taken from the working directory
+    #[arg(skip)]
+    pub exec: Option<Vec<String>>,
+}
+
+impl Cli {
+    /// Arguments for running `script` with the command line defaults
+    pub fn new(script: impl Into<String>) -> Cli {
+        Cli {
+            script: script.into(),
+            archive_dir: "archive".to_string(),
+            message: None,
+            no_subfolder: false,
+            script_args: Vec::new(),
+            no_default_args: false,
+            interpreter: None,
+            config_path: None,
+            metadata: Vec::new(),
+            experiment: None,
+            tags: Vec::new(),
+            parents: Vec::new(),
+            check_against: None,
+            check_metrics: Vec::new(),
+            junit: None,
+            repeat: None,
+            warmup: None,
+            determinism_check: false,
+            notify: false,
+            notify_after: None,
+            timeout: None,
+            warn_before: None,
+            min_free_space: None,
+            anonymize_paths: false,
+            expect: Vec::new(),
+            stdin: None,
+            params: None,
+            report: Vec::new(),
+            lock: None,
+            no_wait: false,
+            sandbox: false,
+            inputs: Vec::new(),
+            no_network: false,
+            trace_inputs: false,
+            read_only_project: false,
+            scratch: false,
+            cpus: None,
+            threads: None,
+            hash_interpreter: false,
+            record_libraries: false,
+            lock_env: false,
+            tar: false,
+            format: None,
+            compress_logs: false,
+            live_upload: false,
+            priority: None,
+            exec: None,
+        }
+    }
+}
+
+pub(crate) fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid KEY=VALUE: no '=' found in '{}'", s))?;
+    if key.is_empty() {
+        return Err(format!("invalid KEY=VALUE: empty key in '{}'", s));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+pub struct App {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    #[command(flatten)]
+    pub run: Option<Cli>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Run an SQL query over the archived runs
+    Query(query::QueryArgs),
+    /// Follow the output of a run in progress until it ends, then report how it ended
+    Attach(attach::AttachArgs),
+    /// Sign the digest of a run for third parties, or verify such an attestation
+    Attest(attest::AttestArgs),
+    /// Verify manifests, output hashes and links across the archive
+    Audit(audit::AuditArgs),
+    /// Manage baseline runs that later runs are compared against
+    Baseline(baseline::BaselineArgs),
+    /// Run the scripts listed in a jobs file, one per line
+    Batch(batch::BatchArgs),
+    /// Run and archive a command instead of a script, e.g. `fastsave exec -- make bench`
+    Exec(exec::ExecArgs),
+    /// Delete archived runs according to the retention rules of the config file
+    Clean(clean::CleanArgs),
+    /// Compare arguments, commits, durations, metrics and outputs of several runs side by side
+    Compare(compare::CompareArgs),
+    /// Run jobs submitted to the queue of the archive with a pool of workers
+    Daemon(queue::DaemonArgs),
+    /// Compare the outputs of two runs
+    Diff(diff::DiffArgs),
+    /// Export a run as a markdown summary or HTML report
+    Export(export::ExportArgs),
+    /// Group failed runs by script and error
+    Failures(failures::FailuresArgs),
+    /// Search captured output across archived runs
+    Grep(grep::GrepArgs),
+    /// Verify or rebuild the experiment links and search index from the manifests
+    Index(index::IndexArgs),
+    /// Set up a project with a config file, ignore files and optionally a template script
+    Init(init::InitArgs),
+    /// List archived runs
+    List(list::ListArgs),
+    /// Add to or replace the message of a completed run
+    Note(note::NoteArgs),
+    /// Protect a run from being deleted by any cleanup
+    Pin(pin::PinArgs),
+    /// Plot a metric of the runs of a batch against a parameter as SVG
+    Plot(plot::PlotArgs),
+    /// Rerun an archived run and check whether its outputs and metrics are reproduced
+    Repro(repro::ReproArgs),
+    /// Full-text search over messages, captured output and metadata
+    #[cfg(feature = "search")]
+    Search(search::SearchArgs),
+    /// Serve a web dashboard and JSON API over the archive
+    #[cfg(feature = "serve")]
+    Serve(serve::ServeArgs),
+    /// Show the details and lineage of a run
+    Show(show::ShowArgs),
+    /// Show how the duration and metrics of a script develop over time
+    Stats(stats::StatsArgs),
+    /// Show runs in progress and whether they are still making progress
+    Status(status::StatusArgs),
+    /// Add a script to the queue run by `fastsave daemon`
+    Submit(queue::SubmitArgs),
+    /// Add or remove tags of a completed run
+    Tag(tag::TagArgs),
+    /// Browse the archive in an interactive terminal UI
+    #[cfg(feature = "tui")]
+    Ui(tui::UiArgs),
+    /// Upload a run to the remote of the config file
+    Upload(remote::UploadArgs),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -55,9 +444,24 @@ pub struct GitInfo {
     pub uncommitted_changes: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Details of an output file besides its hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutputFile {
+    pub size_bytes: u64,
+    /// Detected from the content, e.g. `csv` or `hdf5`; `None` for empty or unrecognized files
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Path of the thumbnail of an image, relative to the run directory
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct ExecutionResult {
+    /// Relative to `project_root` if the script lies inside it
     pub script_path: String,
+    #[serde(default)]
+    pub project_root: Option<String>,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub duration_ms: u64,
@@ -66,13 +470,186 @@ pub struct ExecutionResult {
     pub stderr: String,
     pub message: Option<String>,
     pub git_info: Option<GitInfo>,
-    pub file_hashes: HashMap<String, String>,
-    pub command_string: String,
+    pub file_hashes: BTreeMap<String, String>,
+    /// Same keys as `file_hashes`; empty in manifests written by older versions
+    #[serde(default)]
+    pub files: BTreeMap<String, OutputFile>,
+    /// Size of everything in the output directory, including subdirectories
+    #[serde(default)]
+    pub total_output_bytes: Option<u64>,
+    /// First lines of small text outputs
+    #[serde(default)]
+    pub previews: BTreeMap<String, String>,
+    /// Interpreter and arguments the script was run with; missing in manifests written by older versions
+    #[serde(default)]
+    pub command: Option<command::ScriptCommand>,
+    /// `command` quoted for pasting into a shell; older versions recorded only the
+    /// interpreter and script as `command_string`
+    #[serde(alias = "command_string")]
+    pub command_line: String,
+    /// Absolute path `command.program` was found at
+    #[serde(default)]
+    pub interpreter_path: Option<String>,
+    /// SHA-256 of the interpreter executable, with `--hash-interpreter`
+    #[serde(default)]
+    pub interpreter_hash: Option<String>,
+    /// BLAS, LAPACK and other numerical libraries the interpreter is linked against, with
+    /// `--record-libraries`; the full listing is archived as `libraries.txt`
+    #[serde(default)]
+    pub numerical_libraries: Vec<String>,
+    /// Package lists and lockfiles archived in `environment/`, with `--lock-env`
+    #[serde(default)]
+    pub environment_files: Vec<provenance::EnvironmentFile>,
+    #[serde(default)]
+    pub metrics: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub message_edited: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub experiment: Option<String>,
+    /// Tags from `--tag` and `fastsave tag`, which retention rules can select runs by
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Set by `fastsave pin`; pinned runs are never deleted by `fastsave clean` or the retention policy
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub parents: Vec<String>,
+    #[serde(default)]
+    pub baseline: Option<baseline::BaselineComparison>,
+    #[serde(default)]
+    pub check: Option<check::CheckResult>,
+    #[serde(default)]
+    pub hooks: Vec<hooks::HookResult>,
+    #[serde(default)]
+    pub validation: Option<validate::ValidationResult>,
+    /// Verdict of the success criteria, `--check-against` and expected outputs;
+    /// missing in manifests written by older versions
+    #[serde(default)]
+    pub success: Option<bool>,
+    /// SHA-256 of the input archived as `stdin.txt`, if the script was given one
+    #[serde(default)]
+    pub stdin_hash: Option<String>,
+    /// The parameters file given with `--params` and its copy in the run directory
+    #[serde(default)]
+    pub params: Option<archive::ParamsFile>,
+    /// Machine the run was executed on
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Operating system and accelerators of the machine
+    #[serde(default)]
+    pub system_info: Option<system::SystemInfo>,
+    /// Priority of the queued job, for runs started by `fastsave daemon`
+    #[serde(default)]
+    pub priority: Option<queue::Priority>,
+    /// What the script could access, if it ran in a sandbox
+    #[serde(default)]
+    pub sandbox: Option<sandbox::SandboxSpec>,
+    /// The script ran without network access
+    #[serde(default)]
+    pub network_isolated: bool,
+    /// Tracked files and inputs the script changed, if the project was read-only
+    #[serde(default)]
+    pub modified_sources: Option<Vec<String>>,
+    /// Files outside the project and the declared inputs the script read, with `--trace-inputs`
+    #[serde(default)]
+    pub traced_inputs: Option<Vec<trace::TracedInput>>,
+    /// Size of the run's scratch directory and the files kept from it
+    #[serde(default)]
+    pub scratch: Option<scratch::ScratchUsage>,
+    /// CPUs and thread count the script was limited to
+    #[serde(default)]
+    pub cpu_pinning: Option<cpus::CpuPinning>,
+    /// User and system CPU time next to `duration_ms`; Linux only
+    #[serde(default)]
+    pub cpu_time: Option<cpus::CpuTime>,
+    /// Peak resident memory in bytes of the script or the largest process it waited for; Linux only
+    #[serde(default)]
+    pub peak_memory_bytes: Option<u64>,
+    /// Signal that ended the script, which leaves `exit_code` at -1
+    #[serde(default)]
+    pub exit_signal: Option<i32>,
+    /// Why the script was killed, if it was
+    #[serde(default)]
+    pub killed_reason: Option<killed::KilledReason>,
+    /// Whether stdout and stderr were written to `stdout.log.zst` and `stderr.log.zst`,
+    /// leaving their last lines in `stdout` and `stderr`
+    #[serde(default)]
+    pub compressed_logs: bool,
+    /// The run was a command of `fastsave exec` rather than a script, so `script_path` only names it
+    #[serde(default)]
+    pub exec: bool,
+    /// When each output file appeared and was last written while the script ran, keyed like `file_hashes`
+    #[serde(default)]
+    pub file_timeline: BTreeMap<String, timeline::FileTimes>,
+    /// Hash over the output file hashes and the fields that decide whether two runs are the same,
+    /// see `digest::run_digest`
+    #[serde(default)]
+    pub run_digest: Option<String>,
+}
+
+impl ExecutionResult {
+    /// Where the script is, resolving a path recorded relative to the project root
+    pub fn script_location(&self) -> PathBuf {
+        match &self.project_root {
+            Some(root) if Path::new(&self.script_path).is_relative() => {
+                Path::new(shellexpand::tilde(root).as_ref()).join(&self.script_path)
+            }
+            _ => PathBuf::from(&self.script_path),
+        }
+    }
+
+    /// The program and arguments of a run of `fastsave exec`
+    pub fn exec_command(&self) -> Option<Vec<String>> {
+        let command = self.command.as_ref().filter(|_| self.exec)?;
+        Some(std::iter::once(&command.program).chain(&command.args).cloned().collect())
+    }
+
+    /// Where the git repository is, resolving a root recorded relative to the project root
+    pub fn repo_location(&self) -> Option<PathBuf> {
+        let repo_root = &self.git_info.as_ref()?.repo_root;
+        Some(match &self.project_root {
+            Some(root) if Path::new(repo_root).is_relative() => Path::new(shellexpand::tilde(root).as_ref()).join(repo_root),
+            _ => PathBuf::from(repo_root),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct FastsaveConfig {
-    interpreters: HashMap<String, String>,
+    interpreters: BTreeMap<String, String>,
+    pub metrics: MetricsConfig,
+    pub notifications: notify::NotificationsConfig,
+    pub hooks: hooks::HooksConfig,
+    pub paths: paths::PathsConfig,
+    pub redaction: redact::RedactionConfig,
+    /// Output files or globs every run must produce, non-empty
+    pub expect: Vec<String>,
+    pub success: criteria::SuccessConfig,
+    pub previews: preview::PreviewConfig,
+    pub thumbnails: thumbnail::ThumbnailConfig,
+    pub heartbeat: heartbeat::HeartbeatConfig,
+    pub locks: lock::LocksConfig,
+    pub sandbox: sandbox::SandboxConfig,
+    /// Always check that scripts don't modify their sources, like `--read-only-project`
+    pub read_only_project: bool,
+    pub trace: trace::TraceConfig,
+    pub scratch: scratch::ScratchConfig,
+    pub provenance: provenance::ProvenanceConfig,
+    pub layout: archive::LayoutConfig,
+    /// Format of the manifest of new runs, `yaml` or `json`, like `--format`
+    pub format: archive::ManifestFormat,
+    pub remote: remote::RemoteConfig,
+    pub retention: clean::RetentionConfig,
+    pub timeout: timeout::TimeoutConfig,
+    pub disk: disk::DiskConfig,
+    pub timeline: timeline::TimelineConfig,
+    /// Settings by script name without extension, like `run_simulation`
+    pub scripts: BTreeMap<String, command::ScriptConfig>,
+    /// Invocations by name, run as `fastsave @name`
+    pub aliases: BTreeMap<String, command::AliasConfig>,
 }
 
 impl FastsaveConfig {
@@ -80,15 +657,15 @@ impl FastsaveConfig {
         // If config path is provided, try it first
         if let Some(path) = config_path {
             let expanded_path = shellexpand::tilde(path).to_string();
-            println!("Debug: Trying to load config from custom path: {}", expanded_path);
+            tracing::debug!("Trying to load config from custom path: {}", expanded_path);
             if let Ok(contents) = fs::read_to_string(&expanded_path) {
-                println!("Debug: Found config file with contents:\n{}", contents);
+                tracing::trace!("Found config file with contents:\n{}", contents);
                 match serde_yaml::from_str(&contents) {
                     Ok(config) => {
-                        println!("Debug: Successfully parsed config");
+                        tracing::debug!("Successfully parsed config");
                         return config;
                     }
-                    Err(e) => println!("Debug: Failed to parse custom config: {}", e),
+                    Err(e) => tracing::warn!("Failed to parse config {}: {}", expanded_path, e),
                 }
             }
         }
@@ -101,20 +678,20 @@ impl FastsaveConfig {
 
         for path in config_paths.iter() {
             let expanded_path = shellexpand::tilde(path).to_string();
-            println!("Debug: Trying to load config from: {}", expanded_path);
+            tracing::debug!("Trying to load config from: {}", expanded_path);
             if let Ok(contents) = fs::read_to_string(&expanded_path) {
-                println!("Debug: Found config file with contents:\n{}", contents);
+                tracing::trace!("Found config file with contents:\n{}", contents);
                 match serde_yaml::from_str(&contents) {
                     Ok(config) => {
-                        println!("Debug: Successfully parsed config");
+                        tracing::debug!("Successfully parsed config");
                         return config;
                     }
-                    Err(e) => println!("Debug: Failed to parse config: {}", e),
+                    Err(e) => tracing::warn!("Failed to parse config {}: {}", expanded_path, e),
                 }
             }
         }
         
-        println!("Debug: No config file found, using default config");
+        tracing::debug!("No config file found, using default config");
         FastsaveConfig::default()
     }
 
@@ -127,7 +704,7 @@ impl FastsaveConfig {
         // Remove the leading dot if present and convert to lowercase
         let ext = extension.trim_start_matches('.').to_lowercase();
         let result = self.interpreters.get(&ext);
-        println!("Debug: Looking up interpreter for extension '{}', found: {:?}", ext, result);
+        tracing::debug!("Looking up interpreter for extension '{}', found: {:?}", ext, result);
         result
     }
 }
@@ -141,14 +718,16 @@ pub fn get_script_basename(script_path: &str) -> String {
 }
 
 pub fn get_next_run_number(base_dir: &str, script_name: &str, date: &str) -> u32 {
+    next_dir_number(base_dir, &format!("{}_{}_run", date, script_name))
+}
+
+fn next_dir_number(base_dir: &str, prefix: &str) -> u32 {
     if let Ok(entries) = fs::read_dir(base_dir) {
-        let prefix = format!("{}_{}_run", date, script_name);
-        
         entries
             .filter_map(|entry| entry.ok())
             .filter_map(|entry| entry.file_name().into_string().ok())
-            .filter(|name| name.starts_with(&prefix))
-            .filter_map(|name| name.strip_prefix(&prefix).and_then(|n| n.parse::<u32>().ok()))
+            .filter(|name| name.starts_with(prefix))
+            .filter_map(|name| name.strip_prefix(prefix).and_then(|n| n.parse::<u32>().ok()))
             .max()
             .map_or(1, |max| max + 1)
     } else {
@@ -157,30 +736,42 @@ pub fn get_next_run_number(base_dir: &str, script_name: &str, date: &str) -> u32
 }
 
 pub fn create_run_dir(base_dir: &str, script_path: &str) -> Result<String, Box<dyn Error>> {
+    create_numbered_dir(base_dir, script_path, "run")
+}
+
+/// Creates `<base_dir>/<date>_<script>_<kind><N>` with the next free number
+pub fn create_numbered_dir(base_dir: &str, script_path: &str, kind: &str) -> Result<String, Box<dyn Error>> {
     fs::create_dir_all(base_dir)?;
 
     let date = Local::now().format("%Y-%m-%d").to_string();
     let script_name = get_script_basename(script_path);
-    let run_number = get_next_run_number(base_dir, &script_name, &date);
-    
-    let dir_name = format!("{}_{}_run{}", date, script_name, run_number);
-    let dir_path = Path::new(base_dir).join(dir_name);
-    
-    fs::create_dir_all(&dir_path)?;
+    let prefix = format!("{}_{}_{}", date, script_name, kind);
+    let mut number = next_dir_number(base_dir, &prefix);
+
+    // create_dir fails if the directory exists, so concurrent runs never share a directory
+    let dir_path = loop {
+        let dir_path = Path::new(base_dir).join(format!("{}{}", prefix, number));
+        match fs::create_dir(&dir_path) {
+            Ok(()) => break dir_path,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => number += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
     
     Ok(dir_path.to_string_lossy().into_owned())
 }
 
-pub fn get_output_dir(cli: &Cli) -> Result<String, Box<dyn Error>> {
+pub fn get_output_dir(cli: &Cli, config: &FastsaveConfig) -> Result<String, Box<dyn Error>> {
     if cli.no_subfolder {
         fs::create_dir_all(&cli.archive_dir)?;
         Ok(cli.archive_dir.clone())
     } else {
-        create_run_dir(&cli.archive_dir, &cli.script)
+        let base_dir = archive::run_base_dir(Path::new(&cli.archive_dir), &config.layout);
+        create_run_dir(&base_dir.to_string_lossy(), &cli.script)
     }
 }
 
-fn find_git_root(start_path: &Path) -> Option<PathBuf> {
+pub(crate) fn find_git_root(start_path: &Path) -> Option<PathBuf> {
     let mut current = if start_path.is_absolute() {
         start_path.to_path_buf()
     } else {
@@ -200,7 +791,7 @@ fn find_git_root(start_path: &Path) -> Option<PathBuf> {
     highest_git_root
 }
 
-fn run_git_command(repo_path: &Path, args: &[&str]) -> Result<String, Box<dyn Error>> {
+pub(crate) fn run_git_command(repo_path: &Path, args: &[&str]) -> Result<String, Box<dyn Error>> {
     let output = Command::new("git")
         .current_dir(repo_path)
         .args(args)
@@ -221,7 +812,7 @@ pub fn get_git_info(script_path: &str) -> Option<GitInfo> {
     let repo_root = find_git_root(&script_dir)?;
     
     // Print debug information
-    println!("Debug: Found git root at: {}", repo_root.display());
+    tracing::debug!("Found git root at: {}", repo_root.display());
     
     let result = (|| -> Result<GitInfo, Box<dyn Error>> {
         let branch = run_git_command(&repo_root, &["rev-parse", "--abbrev-ref", "HEAD"])?;
@@ -254,13 +845,13 @@ pub fn get_git_info(script_path: &str) -> Option<GitInfo> {
     match result {
         Ok(info) => Some(info),
         Err(e) => {
-            eprintln!("Debug: Error getting git info: {}", e);
+            tracing::debug!("Error getting git info: {}", e);
             None
         }
     }
 }
 
-fn calculate_file_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+pub(crate) fn calculate_file_hash(path: &Path) -> Result<String, Box<dyn Error>> {
     let mut file = fs::File::open(path)?;
     let mut hasher = Sha256::new();
     let mut buffer = Vec::new();
@@ -269,13 +860,26 @@ fn calculate_file_hash(path: &Path) -> Result<String, Box<dyn Error>> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn get_file_hashes(dir: &Path) -> Result<HashMap<String, String>, Box<dyn Error>> {
-    let mut hashes = HashMap::new();
+fn get_file_hashes(dir: &Path) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut hashes = BTreeMap::new();
     
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() {
+        // The archived input is hashed separately as `stdin_hash`, the start record is replaced by
+        // the manifest and the patch, library listing and compressed and live logs are written by fastsave
+        let own_files = [
+            archive::STDIN_FILE,
+            started::STARTED_FILE,
+            started::GIT_PATCH_FILE,
+            provenance::LIBRARIES_FILE,
+            logs::STDOUT_LOG,
+            logs::STDERR_LOG,
+            logs::STDOUT_LIVE,
+            logs::STDERR_LIVE,
+            trace::TRACE_LOG,
+        ];
+        if path.is_file() && !own_files.iter().any(|file| entry.file_name() == *file) {
             let relative_path = path.strip_prefix(dir)?;
             let hash = calculate_file_hash(&path)?;
             hashes.insert(relative_path.to_string_lossy().to_string(), hash);
@@ -285,60 +889,217 @@ fn get_file_hashes(dir: &Path) -> Result<HashMap<String, String>, Box<dyn Error>
     Ok(hashes)
 }
 
+fn get_output_files(dir: &Path, file_hashes: &BTreeMap<String, String>) -> Result<BTreeMap<String, OutputFile>, Box<dyn Error>> {
+    file_hashes
+        .keys()
+        .map(|file| {
+            let path = dir.join(file);
+            let output_file = OutputFile {
+                size_bytes: fs::metadata(&path)?.len(),
+                kind: filetype::detect_kind(&path)?.map(str::to_string),
+                thumbnail: None,
+            };
+            Ok((file.clone(), output_file))
+        })
+        .collect()
+}
+
+/// Total size of the files in `dir` and its subdirectories
+pub(crate) fn dir_size(dir: &Path) -> Result<u64, Box<dyn Error>> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() && ![started::STARTED_FILE, logs::STDOUT_LIVE, logs::STDERR_LIVE].contains(&entry.file_name().to_string_lossy().as_ref()) {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
 pub fn execute_script(script_path: &str, output_dir: &str, message: Option<String>, script_args: &[String], interpreter_override: Option<&String>, config_path: Option<&str>) -> Result<ExecutionResult, Box<dyn Error>> {
-    let start_time = SystemTime::now();
-    let start_datetime = DateTime::<Utc>::from(start_time);
+    let config = FastsaveConfig::load_with_config_path(config_path);
+    execute_script_with_config(script_path, output_dir, message, script_args, interpreter_override, &config)
+}
 
-    let git_info = get_git_info(script_path);
+pub fn execute_script_with_config(script_path: &str, output_dir: &str, message: Option<String>, script_args: &[String], interpreter_override: Option<&String>, config: &FastsaveConfig) -> Result<ExecutionResult, Box<dyn Error>> {
+    execute_script_observed(script_path, output_dir, message, script_args, interpreter_override, config, &NoObserver)
+}
+
+/// Like `execute_script_with_config`, reporting progress to `observer` while the script runs
+pub fn execute_script_observed(script_path: &str, output_dir: &str, message: Option<String>, script_args: &[String], interpreter_override: Option<&String>, config: &FastsaveConfig, observer: &dyn RunObserver) -> Result<ExecutionResult, Box<dyn Error>> {
+    let result = spawn_script(script_path, output_dir, message, script_args, interpreter_override, config, &SpawnOptions::default(), observer)?;
+    observer.on_complete(&result);
+    Ok(result)
+}
 
+/// The interpreter command for the script split into words: the program, then the
+/// arguments it gets before the script, like `uv run python`
+pub(crate) fn script_interpreter(script_path: &str, interpreter_override: Option<&String>, config: &FastsaveConfig) -> Result<Vec<String>, Box<dyn Error>> {
     let path = Path::new(script_path);
     let extension = path.extension()
         .and_then(|ext| ext.to_str())
         .ok_or("Unable to determine script type: no file extension")?;
     
-    let program = if let Some(interpreter) = interpreter_override {
+    let interpreter = if let Some(interpreter) = interpreter_override {
         interpreter.clone()
+    } else if let Some(interpreter) = config.get_interpreter(extension) {
+        interpreter.to_string()
     } else {
-        let config = FastsaveConfig::load_with_config_path(config_path);
-        if let Some(interpreter) = config.get_interpreter(extension) {
-            interpreter.to_string()
-        } else {
-            // Fall back to built-in defaults
-            match extension.to_lowercase().as_str() {
-                "py" => "python".to_string(),
-                "sh" => "sh".to_string(),
-                "jl" => "julia".to_string(),
-                "m" => "matlab".to_string(),
-                _ => return Err(format!("Unsupported script type: {}", extension).into()),
+        // Fall back to built-in defaults
+        match extension.to_lowercase().as_str() {
+            "py" => "python".to_string(),
+            "sh" => "sh".to_string(),
+            "jl" => "julia".to_string(),
+            "m" => "matlab".to_string(),
+            _ => return Err(format!("Unsupported script type: {}", extension).into()),
+        }
+    };
+    // A path with spaces configured before commands were split keeps working
+    if Path::new(&interpreter).is_file() {
+        return Ok(vec![interpreter]);
+    }
+    Ok(command::split_command(&interpreter).map_err(|e| format!("Invalid interpreter: {}", e))?)
+}
+
+/// How `spawn_script` sets up the script's process beyond the command line
+#[derive(Default)]
+struct SpawnOptions<'a> {
+    /// File fed to the script's stdin
+    stdin: Option<&'a Path>,
+    sandbox: Option<&'a sandbox::SandboxSpec>,
+    no_network: bool,
+    /// Added to the environment of the script
+    env: Vec<(&'static str, String)>,
+    /// Sampled for its peak size at every heartbeat
+    scratch: Option<&'a scratch::ScratchDir>,
+    /// CPU list the script is pinned to
+    cpus: Option<&'a str>,
+    /// Threads the script was told to use
+    threads: Option<u32>,
+    /// Write stdout and stderr into compressed logs instead of keeping them in memory
+    compress_logs: bool,
+    /// Also write stdout and stderr into live logs for `fastsave attach` when they aren't compressed
+    live_logs: bool,
+    /// Polled for completed output files while the script runs
+    live_upload: Option<&'a remote::LiveUpload>,
+    /// Stops the script when it runs too long, warning it before
+    timeout: Option<&'a timeout::Timeout>,
+    /// Stops the script when the disk runs full
+    disk_monitor: Option<&'a disk::DiskMonitor>,
+    /// Records when the script writes its outputs
+    timeline: bool,
+    /// Traces the files the script reads
+    tracer: Option<&'a trace::InputTracer>,
+    /// Command run instead of the script
+    exec: Option<&'a [String]>,
+}
+
+/// The lines the script writes, without line breaks. Invalid UTF-8 is replaced rather than
+/// ending the capture, which would leave the script writing into a pipe nobody reads.
+fn output_lines(mut reader: impl BufRead) -> impl Iterator<Item = String> {
+    std::iter::from_fn(move || {
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+                Some(String::from_utf8_lossy(&line).into_owned())
             }
         }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_script(script_path: &str, output_dir: &str, message: Option<String>, script_args: &[String], interpreter_override: Option<&String>, config: &FastsaveConfig, options: &SpawnOptions, observer: &dyn RunObserver) -> Result<ExecutionResult, Box<dyn Error>> {
+    let SpawnOptions { stdin, sandbox, no_network, cpus, .. } = *options;
+    let start_time = SystemTime::now();
+    let start_datetime = DateTime::<Utc>::from(start_time);
+
+    let git_info = get_git_info(script_path);
+    let interpreter = match options.exec {
+        Some(words) => words.to_vec(),
+        None => script_interpreter(script_path, interpreter_override, config)?,
     };
+    let interpreter_path = command::resolve_program(&interpreter[0])?;
+    let redactor = Redactor::new(&config.redaction)?;
 
-    // Build command string for logging and saving
-    let command_string = format!("{} {}", 
-        program,
-        script_path
-    );
+    // Build the command for logging and saving; the sandbox may start in another
+    // directory, so paths are passed absolute there
+    let command = match (options.exec, sandbox) {
+        (Some(words), _) => command::ScriptCommand { program: words[0].clone(), args: words[1..].to_vec() },
+        (None, Some(spec)) => {
+            let script = fs::canonicalize(script_path)?;
+            command::ScriptCommand::new(&interpreter, &script.to_string_lossy(), &spec.read_write[0], script_args)
+        }
+        (None, None) => command::ScriptCommand::new(&interpreter, script_path, output_dir, script_args),
+    };
+    let command_line = command.command_line();
 
-    // Print the command before executing
-    println!("Fastsave executes:\n{}", command_string);
-    io::stdout().flush()?;
+    tracing::info!("Fastsave executes: {}", command_line);
 
-    // Build command with stdio configuration
-    let mut cmd = Command::new(program);
-    cmd.arg(script_path)
-        .arg("--output_dir")
-        .arg(output_dir)
-        .stdout(Stdio::piped())
+    // Build command with stdio configuration, running the interpreter that was recorded
+    let interpreter = interpreter_path.to_string_lossy().into_owned();
+    let mut cmd = match sandbox {
+        Some(spec) => spec.command(&interpreter),
+        None if no_network => sandbox::unshare_network(&interpreter),
+        None => Command::new(&interpreter),
+    };
+    cmd.args(&command.args);
+    if let Some(cpus) = cpus {
+        cmd = cpus::pin(&cmd, cpus);
+    }
+    if let Some(tracer) = options.tracer {
+        cmd = tracer.command(&cmd);
+    }
+    cmd.stdout(Stdio::piped())
         .stderr(Stdio::piped());
-    
-    // Add any additional script arguments
-    for arg in script_args {
-        cmd.arg(arg);
+    if let Some(stdin) = stdin {
+        cmd.stdin(fs::File::open(stdin)?);
     }
+    cmd.envs(options.env.iter().map(|(name, value)| (name, value)));
+
+    let (mut stdout_log, mut stderr_log) = if options.compress_logs {
+        let create = |name| logs::CompressedLog::create(Path::new(output_dir), name).map_err(|e| format!("Failed to create {}: {}", name, e));
+        (Some(create(logs::STDOUT_LOG)?), Some(create(logs::STDERR_LOG)?))
+    } else {
+        (None, None)
+    };
+    let (mut stdout_live, mut stderr_live) = if options.live_logs && !options.compress_logs {
+        let create = |name| logs::LiveLog::create(Path::new(output_dir), name);
+        (Some(create(logs::STDOUT_LIVE)), Some(create(logs::STDERR_LIVE)))
+    } else {
+        (None, None)
+    };
+
+    // Watching starts before the script, so files it writes right away are seen
+    let timeline_watcher = match options.timeline.then(|| timeline::TimelineWatcher::start(Path::new(output_dir))) {
+        Some(Ok(watcher)) => Some(watcher),
+        // The timeline is a convenience and never keeps the script from running
+        Some(Err(e)) => {
+            tracing::warn!("{}", e);
+            None
+        }
+        None => None,
+    };
 
     // Spawn the command
-    let mut child = cmd.spawn()?;
+    observer.on_start(script_path, output_dir);
+    let oom_counters = killed::OomCounters::read();
+    let mut child = cmd.spawn().map_err(|e| match sandbox {
+        _ if options.tracer.is_some() && e.kind() == io::ErrorKind::NotFound => format!("Failed to trace the inputs, is strace installed? {}", e).into(),
+        _ if cpus.is_some() && e.kind() == io::ErrorKind::NotFound => format!("Failed to pin the script to CPUs, is taskset installed? {}", e).into(),
+        Some(spec) if e.kind() == io::ErrorKind::NotFound => format!("Failed to start the sandbox, is {} installed? {}", spec.tool, e).into(),
+        None if no_network && e.kind() == io::ErrorKind::NotFound => format!("Failed to isolate the network, is unshare installed? {}", e).into(),
+        _ => Box::<dyn Error>::from(e),
+    })?;
     
     // Get handles to stdout and stderr
     let stdout = child.stdout.take().expect("Failed to capture stdout");
@@ -347,82 +1108,646 @@ pub fn execute_script(script_path: &str, output_dir: &str, message: Option<Strin
     let stdout_reader = BufReader::new(stdout);
     let stderr_reader = BufReader::new(stderr);
 
-    // Capture output while also displaying it
-    let mut captured_stdout = String::new();
-    let mut captured_stderr = String::new();
+    let stdout_lines = AtomicU64::new(0);
+    let stderr_lines = AtomicU64::new(0);
+    let (stop_heartbeat, heartbeat_stopped) = mpsc::channel::<()>();
+    let (stop_upload, upload_stopped) = mpsc::channel::<()>();
+    let (stop_watchdog, watchdog_stopped) = mpsc::channel::<()>();
+    let (stop_disk_monitor, disk_monitor_stopped) = mpsc::channel::<()>();
+    let (stop_timeline, timeline_stopped) = mpsc::channel::<()>();
 
-    // Create separate threads for stdout and stderr
-    let stdout_handle = std::thread::spawn(move || {
-        for line in stdout_reader.lines() {
-            if let Ok(line) = line {
+    // Capture output while also displaying it, in scoped threads so they can share the observer
+    let (status, cpu_seconds, stopped, file_timeline, stdout, stderr) = std::thread::scope(|scope| {
+        let stdout_handle = scope.spawn(|| {
+            let mut captured_stdout = String::new();
+            for line in output_lines(stdout_reader) {
+                let line = redactor.redact(&line);
                 println!("{}", line);
                 io::stdout().flush().unwrap();
-                captured_stdout.push_str(&line);
-                captured_stdout.push('\n');
+                observer.on_stdout_line(&line);
+                stdout_lines.fetch_add(1, Ordering::Relaxed);
+                match &mut stdout_log {
+                    Some(log) => log.write_line(&line),
+                    None => {
+                        if let Some(live) = &mut stdout_live {
+                            live.write_line(&line);
+                        }
+                        captured_stdout.push_str(&line);
+                        captured_stdout.push('\n');
+                    }
+                }
             }
-        }
-        captured_stdout
-    });
+            stdout_log.map_or(captured_stdout, logs::CompressedLog::finish)
+        });
 
-    let stderr_handle = std::thread::spawn(move || {
-        for line in stderr_reader.lines() {
-            if let Ok(line) = line {
+        let stderr_handle = scope.spawn(|| {
+            let mut captured_stderr = String::new();
+            for line in output_lines(stderr_reader) {
+                let line = redactor.redact(&line);
                 eprintln!("{}", line);
                 io::stderr().flush().unwrap();
-                captured_stderr.push_str(&line);
-                captured_stderr.push('\n');
+                observer.on_stderr_line(&line);
+                stderr_lines.fetch_add(1, Ordering::Relaxed);
+                match &mut stderr_log {
+                    Some(log) => log.write_line(&line),
+                    None => {
+                        if let Some(live) = &mut stderr_live {
+                            live.write_line(&line);
+                        }
+                        captured_stderr.push_str(&line);
+                        captured_stderr.push('\n');
+                    }
+                }
             }
+            stderr_log.map_or(captured_stderr, logs::CompressedLog::finish)
+        });
+
+        let interval_secs = config.heartbeat.interval_secs;
+        if interval_secs > 0 {
+            let pid = child.id();
+            let run_dir = Path::new(output_dir);
+            let (stdout_lines, stderr_lines) = (&stdout_lines, &stderr_lines);
+            scope.spawn(move || {
+                let mut previous = None;
+                // Beats until the sender is dropped once the script exits
+                while let Err(RecvTimeoutError::Timeout) = heartbeat_stopped.recv_timeout(Duration::from_secs(interval_secs)) {
+                    let heartbeat = heartbeat::Heartbeat::next(
+                        previous.as_ref(),
+                        interval_secs,
+                        stdout_lines.load(Ordering::Relaxed),
+                        stderr_lines.load(Ordering::Relaxed),
+                        pid,
+                    );
+                    if let Err(e) = heartbeat::write_heartbeat(run_dir, &heartbeat) {
+                        tracing::warn!("Failed to write heartbeat: {}", e);
+                    }
+                    if let Some(scratch) = options.scratch {
+                        scratch.sample();
+                    }
+                    previous = Some(heartbeat);
+                }
+            });
         }
-        captured_stderr
-    });
 
-    // Wait for the command to complete
-    let status = child.wait()?;
+        if let Some(live_upload) = options.live_upload {
+            let poll_secs = config.remote.poll_secs.max(1);
+            scope.spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = upload_stopped.recv_timeout(Duration::from_secs(poll_secs)) {
+                    live_upload.poll();
+                }
+            });
+        }
+
+        let watchdog = options.timeout.map(|timeout| {
+            let pid = child.id();
+            scope.spawn(move || {
+                timeout.watch(pid, &watchdog_stopped, || {
+                    if timeout.notify {
+                        let remaining = timeout.warn_before.unwrap_or_default();
+                        for error in notify::send_timeout_warning(&config.notifications, Path::new(output_dir), script_path, remaining) {
+                            tracing::warn!("Failed to send the timeout warning: {}", error);
+                        }
+                    }
+                })
+            })
+        });
+
+        let disk_monitor = options.disk_monitor.map(|monitor| {
+            let pid = child.id();
+            scope.spawn(move || monitor.watch(pid, &disk_monitor_stopped))
+        });
 
-    // Get the captured output
-    let stdout = stdout_handle.join().unwrap_or_default();
-    let stderr = stderr_handle.join().unwrap_or_default();
+        let timeline = timeline_watcher.map(|watcher| {
+            scope.spawn(move || watcher.collect(start_time, &timeline_stopped))
+        });
+
+        // Wait for the command to complete
+        let cpu_seconds = cpus::exited_cpu_seconds(child.id());
+        let status = cpus::wait_with_peak_memory(&mut child);
+        drop(stop_heartbeat);
+        drop(stop_upload);
+        drop(stop_watchdog);
+        drop(stop_disk_monitor);
+        drop(stop_timeline);
+        let stopped_by = |watcher: Option<std::thread::ScopedJoinHandle<bool>>| watcher.is_some_and(|watcher| watcher.join().unwrap_or(false));
+        let stopped = if stopped_by(watchdog) {
+            Some(killed::KilledReason::Timeout)
+        } else if stopped_by(disk_monitor) {
+            Some(killed::KilledReason::DiskFull)
+        } else {
+            None
+        };
+
+        let file_timeline = timeline.and_then(|timeline| timeline.join().ok()).unwrap_or_default();
+
+        // Get the captured output
+        (status, cpu_seconds, stopped, file_timeline, stdout_handle.join().unwrap_or_default(), stderr_handle.join().unwrap_or_default())
+    });
+    let (status, peak_memory_bytes) = status?;
+    // A script stopped by fastsave may still exit on its own after SIGTERM
+    let killed_reason = stopped.or_else(|| killed::killed_reason(&status, &oom_counters));
+    if killed_reason == Some(killed::KilledReason::Oom) {
+        tracing::warn!("The script was killed by the OOM killer");
+    }
 
     let end_time = SystemTime::now();
     let end_datetime = DateTime::<Utc>::from(end_time);
     let duration = end_time.duration_since(start_time)?;
+    let duration_ms = duration.as_millis() as u64;
+    let cpu_time = cpu_seconds.map(|(user, system)| cpus::CpuTime::new(user, system, duration_ms, options.threads));
 
     let result = ExecutionResult {
         script_path: script_path.to_string(),
+        project_root: None,
         start_time: start_datetime,
         end_time: end_datetime,
-        duration_ms: duration.as_millis() as u64,
+        duration_ms,
         exit_code: status.code().unwrap_or(-1),
         stdout,
         stderr,
         message,
         git_info,
-        file_hashes: HashMap::new(),
-        command_string,
+        file_hashes: BTreeMap::new(),
+        files: BTreeMap::new(),
+        total_output_bytes: None,
+        previews: BTreeMap::new(),
+        command: Some(command),
+        command_line,
+        interpreter_path: Some(interpreter),
+        interpreter_hash: None,
+        numerical_libraries: Vec::new(),
+        environment_files: Vec::new(),
+        metrics: BTreeMap::new(),
+        metadata: BTreeMap::new(),
+        message_edited: None,
+        experiment: None,
+        tags: Vec::new(),
+        pinned: false,
+        parents: Vec::new(),
+        baseline: None,
+        check: None,
+        hooks: Vec::new(),
+        validation: None,
+        success: None,
+        stdin_hash: None,
+        params: None,
+        host: None,
+        system_info: None,
+        priority: None,
+        sandbox: None,
+        network_isolated: false,
+        modified_sources: None,
+        traced_inputs: None,
+        scratch: None,
+        cpu_pinning: None,
+        cpu_time,
+        peak_memory_bytes,
+        exit_signal: killed::exit_signal(&status),
+        killed_reason,
+        compressed_logs: options.compress_logs,
+        file_timeline,
+        run_digest: None,
+        exec: options.exec.is_some(),
     };
 
     Ok(result)
 }
 
-pub fn run_script(cli: &Cli) -> Result<String, Box<dyn Error>> {
-    let output_dir = get_output_dir(cli)?;
-    let output_file = Path::new(&output_dir).join("fastsave.yaml");
+/// Runs the script and archives it; returns the run directory with the result saved in its manifest
+pub fn run_script(cli: &Cli) -> Result<ArchivedRun, Box<dyn Error>> {
+    run_script_with_observer(cli, &NoObserver)
+}
+
+/// The run an `@name` script stands for: the script of the alias with its arguments merged
+/// with the given ones, and its archive, interpreter and experiment unless the command line
+/// sets them. `None` if the script isn't an alias.
+pub fn resolve_alias(cli: &Cli, config: &FastsaveConfig) -> Result<Option<Cli>, Box<dyn Error>> {
+    let Some(name) = cli.script.strip_prefix('@') else {
+        return Ok(None);
+    };
+    let alias = config.aliases.get(name).ok_or_else(|| {
+        let known: Vec<String> = config.aliases.keys().map(|name| format!("@{}", name)).collect();
+        if known.is_empty() {
+            format!("Unknown alias @{}, define it in the `aliases` section of the config file", name)
+        } else {
+            format!("Unknown alias @{}, the config file defines {}", name, known.join(", "))
+        }
+    })?;
+    let experiment = match (&cli.experiment, &alias.experiment) {
+        (None, Some(experiment)) => Some(experiment::parse_experiment_name(experiment)?),
+        (experiment, _) => experiment.clone(),
+    };
+    // `-a` can't be told apart from its default, which the alias overrides
+    let archive_dir = match &alias.archive_dir {
+        Some(archive_dir) if cli.archive_dir == "archive" => shellexpand::tilde(archive_dir).into_owned(),
+        _ => cli.archive_dir.clone(),
+    };
+    Ok(Some(Cli {
+        script: shellexpand::tilde(&alias.script).into_owned(),
+        script_args: command::merge_args(&alias.args, &cli.script_args),
+        archive_dir,
+        interpreter: cli.interpreter.clone().or_else(|| alias.interpreter.clone()),
+        experiment,
+        ..cli.clone()
+    }))
+}
 
-    let mut result = execute_script(
+/// Like `run_script`, reporting progress to `observer`
+pub fn run_script_with_observer(cli: &Cli, observer: &dyn RunObserver) -> Result<ArchivedRun, Box<dyn Error>> {
+    let run = run_script_unchecked(cli, observer)?;
+    match run_failure(&run) {
+        Some(failure) => Err(failure.into()),
+        None => Ok(run),
+    }
+}
+
+/// Why fastsave fails a run it archived: outputs deviating from `--check-against`, missing
+/// expected outputs or sources modified in a read-only project
+pub(crate) fn run_failure(run: &ArchivedRun) -> Option<String> {
+    let result = &run.result;
+    let output_dir = run.run_dir.display();
+    if let Some(check) = result.check.as_ref().filter(|c| !c.passed) {
+        let differences = &check.differences;
+        return Some(format!(
+            "Outputs deviate from reference {} (changed files: {:?}, missing files: {:?}, changed metrics: {:?}). Results saved to: {}",
+            check.reference, differences.changed_files, differences.missing_files, differences.changed_metrics, output_dir
+        ));
+    }
+    if let Some(validation) = result.validation.as_ref().filter(|v| !v.passed) {
+        return Some(format!(
+            "Expected outputs are missing or empty (missing: {:?}, empty: {:?}). Results saved to: {}",
+            validation.missing, validation.empty, output_dir
+        ));
+    }
+    if let Some(modified) = result.modified_sources.as_ref().filter(|modified| !modified.is_empty()) {
+        return Some(format!(
+            "The script modified sources or inputs of the read-only project: {:?}. Results saved to: {}",
+            modified, output_dir
+        ));
+    }
+    None
+}
+
+/// Like `run_script_with_observer`, but returns a run that fails after it was archived, see `run_failure`
+pub(crate) fn run_script_unchecked(cli: &Cli, observer: &dyn RunObserver) -> Result<ArchivedRun, Box<dyn Error>> {
+    let config = FastsaveConfig::load_with_config_path(cli.config_path.as_deref());
+    // Compile metric patterns up front so a typo doesn't surface only after a long run
+    let metric_extractor = MetricExtractor::new(&config.metrics)?;
+    let redactor = Redactor::new(&config.redaction)?;
+    let success_criteria = criteria::SuccessCriteria::new(&config.success)?;
+    let ignored = ignore::IgnorePatterns::load(Path::new("."))?;
+    let aliased;
+    let cli = match resolve_alias(cli, &config)? {
+        Some(resolved) => {
+            aliased = resolved;
+            &aliased
+        }
+        None => cli,
+    };
+    let with_defaults;
+    let cli = match config.scripts.get(&get_script_basename(&cli.script)).filter(|_| !cli.no_default_args) {
+        Some(defaults) => {
+            with_defaults = Cli { script_args: command::merge_args(&defaults.args, &cli.script_args), ..cli.clone() };
+            &with_defaults
+        }
+        None => cli,
+    };
+
+    // Before resolving the parents, so a parent the policy deletes is reported as missing
+    if config.retention.auto_clean && !config.retention.is_empty() {
+        match clean::auto_clean(Path::new(&cli.archive_dir), &config.retention) {
+            Ok((0, _)) => {}
+            Ok((deleted, freed)) => tracing::info!("Retention policy removed {} runs ({})", deleted, table::format_size(freed)),
+            // Cleaning up is housekeeping and never keeps the script from running
+            Err(e) => tracing::warn!("Failed to apply the retention policy: {}", e),
+        }
+    }
+
+    // Record parents by run id, failing before the run if one doesn't exist
+    let parent_dirs = cli.parents
+        .iter()
+        .map(|parent| archive::resolve_run(Path::new(&cli.archive_dir), parent))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents: Vec<String> = parent_dirs.iter().map(|dir| archive::run_id(dir)).collect();
+
+    if cli.compress_logs && !cfg!(feature = "compress") {
+        return Err("--compress-logs needs fastsave built with the `compress` feature".into());
+    }
+    if cli.live_upload && config.remote.dir.is_none() {
+        return Err("--live-upload needs a remote directory, set `dir` in the `remote` section of the config file".into());
+    }
+    let timeout = timeout::Timeout::new(cli.timeout, cli.warn_before, &config.timeout)?;
+    // A missing interpreter fails here rather than leaving an empty run directory behind
+    match &cli.exec {
+        Some(words) => {
+            let program = words.first().ok_or("No command given")?;
+            command::resolve_program(program).map_err(|_| format!("Command '{}' not found in PATH", program))?;
+        }
+        None => {
+            command::resolve_program(&script_interpreter(&cli.script, cli.interpreter.as_ref(), &config)?[0])?;
+        }
+    }
+
+    // Held until the run is archived, so a waiting run sees the complete outputs of the previous one
+    let lock_name = cli.lock.clone().or_else(|| config.locks.get(&get_script_basename(&cli.script)).cloned());
+    let _lock = lock_name
+        .map(|name| lock::acquire(Path::new(&cli.archive_dir), &name, !cli.no_wait))
+        .transpose()?;
+
+    let output_dir = get_output_dir(cli, &config)?;
+    let start = started::write_started(Path::new(&output_dir), cli)?;
+    let disk_monitor = disk::DiskMonitor::new(Path::new(&output_dir), cli.min_free_space, &config.disk)?;
+    let remote_dir = if cli.no_subfolder {
+        if config.remote.dir.is_some() {
+            tracing::warn!("Not uploading the run: --no-subfolder writes into the archive directory itself");
+        }
+        None
+    } else {
+        config.remote.run_dir(Path::new(&cli.archive_dir), Path::new(&output_dir))
+    };
+    let stdin = match &cli.stdin {
+        Some(source) => Some(archive::archive_stdin(source, Path::new(&output_dir))?),
+        None => None,
+    };
+    let params = cli.params.as_deref().map(|source| archive::archive_params(source, Path::new(&output_dir))).transpose()?;
+    let params_path = params.as_ref().map(|params| Path::new(&output_dir).join(&params.file).to_string_lossy().into_owned());
+    let script_args: Vec<String> = match &params_path {
+        Some(path) => cli.script_args.iter().map(|arg| arg.replace("{params}", path)).collect(),
+        None => cli.script_args.clone(),
+    };
+
+    let scratch = if cli.scratch || config.scratch.enabled {
+        Some(scratch::ScratchDir::create(&config.scratch, &archive::run_id(Path::new(&output_dir)))?)
+    } else {
+        None
+    };
+
+    let sandbox = if cli.sandbox {
+        let inputs: Vec<&Path> = cli.inputs.iter().map(Path::new).chain(parent_dirs.iter().map(PathBuf::as_path)).collect();
+        let mut spec = sandbox::SandboxSpec::new(&cli.script, &output_dir, &inputs, &config.sandbox)?;
+        spec.network = !cli.no_network;
+        if let Some(scratch) = &scratch {
+            spec.read_write.push(scratch.path.to_string_lossy().into_owned());
+        }
+        Some(spec)
+    } else {
+        None
+    };
+
+    let mut hook_env = vec![
+        ("FASTSAVE_OUTPUT_DIR", output_dir.clone()),
+        ("FASTSAVE_RUN_ID", archive::run_id(Path::new(&output_dir))),
+        ("FASTSAVE_SCRIPT", cli.script.clone()),
+    ];
+    let pre_run = hooks::run_hooks("pre_run", &config.hooks.pre_run, &hook_env, None)?;
+    if let Some(failed) = pre_run.iter().find(|hook| !hook.succeeded()) {
+        return Err(format!(
+            "Pre-run hook '{}' failed with exit code {}, the script was not run: {}",
+            failed.command, failed.exit_code, failed.stderr.trim_end()
+        ).into());
+    }
+
+    let read_only = cli.read_only_project || config.read_only_project;
+    let inputs: Vec<PathBuf> = cli.inputs.iter().map(PathBuf::from).collect();
+    let sources = if read_only { Some(protect::SourceSnapshot::take(&cli.script, &inputs)?) } else { None };
+    let tracer = if cli.trace_inputs || config.trace.enabled {
+        let script_dir = Path::new(&cli.script).parent().unwrap_or(Path::new("")).to_path_buf();
+        let project_root = match &config.paths.project_root {
+            Some(root) => PathBuf::from(shellexpand::tilde(root).as_ref()),
+            None => find_git_root(&script_dir).unwrap_or(script_dir),
+        };
+        let known: Vec<PathBuf> = [project_root, PathBuf::from(&cli.archive_dir), PathBuf::from(&output_dir), PathBuf::from(&cli.script)]
+            .into_iter()
+            .chain(inputs.iter().cloned())
+            .chain(parent_dirs.iter().cloned())
+            .chain(scratch.as_ref().map(|scratch| scratch.path.clone()))
+            .collect();
+        Some(trace::InputTracer::new(Path::new(&output_dir), &known, &config.trace)?)
+    } else {
+        None
+    };
+
+    let cpu_pinning = cpus::CpuPinning::new(cli.cpus.as_deref(), cli.threads)?;
+    let live_upload = match &remote_dir {
+        Some(target) if cli.live_upload || config.remote.live_upload => Some(remote::LiveUpload::new(Path::new(&output_dir), target.clone())),
+        _ => None,
+    };
+    let mut env = scratch.as_ref().map(scratch::ScratchDir::env).unwrap_or_default();
+    env.extend(cpu_pinning.as_ref().map(cpus::CpuPinning::env).unwrap_or_default());
+    env.extend(params_path.map(|path| ("FASTSAVE_PARAMS", path)));
+    if cli.exec.is_some() {
+        // A command gets no --output_dir argument; in the sandbox the path is absolute
+        let script_output_dir = sandbox.as_ref().map_or_else(|| output_dir.clone(), |spec| spec.read_write[0].clone());
+        env.extend([("FASTSAVE_OUTPUT_DIR", script_output_dir), ("FASTSAVE_RUN_ID", archive::run_id(Path::new(&output_dir)))]);
+    }
+    let options = SpawnOptions {
+        stdin: stdin.as_deref(),
+        sandbox: sandbox.as_ref(),
+        no_network: cli.no_network,
+        env,
+        scratch: scratch.as_ref(),
+        cpus: cli.cpus.as_deref(),
+        threads: cpu_pinning.as_ref().and_then(|pinning| pinning.threads),
+        compress_logs: cli.compress_logs,
+        live_logs: true,
+        live_upload: live_upload.as_ref(),
+        timeout: timeout.as_ref(),
+        disk_monitor: disk_monitor.as_ref(),
+        timeline: config.timeline.enabled,
+        tracer: tracer.as_ref(),
+        exec: cli.exec.as_deref(),
+    };
+    let spawned = spawn_script(
         &cli.script, 
         &output_dir, 
         cli.message.clone(), 
-        &cli.script_args,
+        &script_args,
         cli.interpreter.as_ref(),
-        cli.config_path.as_deref(),
-    )?;
+        &config,
+        &options,
+        observer,
+    );
+    // Cleaned up even if the script couldn't be started
+    let scratch_usage = scratch.map(|scratch| scratch.finish(Path::new(&output_dir), &config.scratch.keep)).transpose()?;
+    let mut result = spawned?;
+    result.scratch = scratch_usage;
+    if cli.hash_interpreter || config.provenance.hash_interpreter {
+        // Hashed after the run, so hashing a large binary doesn't delay the start
+        result.interpreter_hash = result.interpreter_path.as_deref().map(|path| calculate_file_hash(Path::new(path))).transpose()?;
+    }
+    if let Some(interpreter) = result.interpreter_path.as_deref().filter(|_| cli.record_libraries || config.provenance.libraries) {
+        // Launcher scripts like pyenv shims have no libraries to list, which doesn't fail the run
+        match provenance::linked_libraries(Path::new(interpreter)) {
+            Ok(listing) => {
+                fs::write(Path::new(&output_dir).join(provenance::LIBRARIES_FILE), &listing)?;
+                result.numerical_libraries = provenance::numerical_libraries(&listing);
+            }
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+    if cli.lock_env || config.provenance.lock_env {
+        let interpreter = result.interpreter_path.as_deref().map(Path::new);
+        result.environment_files = provenance::lock_environment(Path::new(&output_dir), &result.script_location(), interpreter)?;
+    }
+    // The run directory lies inside the repository when the archive does, so
+    // the git state from before fastsave wrote into it is the one to keep
+    result.git_info = start.git_info;
+    if let Some(before) = &sources {
+        let after = protect::SourceSnapshot::take(&cli.script, &inputs)?;
+        result.modified_sources = Some(before.modified_since(&after));
+    }
+    if let Some(tracer) = tracer {
+        match tracer.finish(&std::env::current_dir()?) {
+            Ok(traced) => {
+                if !traced.is_empty() {
+                    tracing::warn!("The script read {} files outside the project that aren't declared as inputs", traced.len());
+                }
+                result.traced_inputs = Some(traced);
+            }
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+
+    paths::relativize_paths(&mut result, &config.paths);
+    if cli.anonymize_paths || config.paths.anonymize {
+        paths::anonymize_paths(&mut result);
+    }
+    result.metadata = cli.metadata.iter().cloned().collect();
+    result.experiment = cli.experiment.clone();
+    result.tags = cli.tags.clone();
+    result.parents = parents;
+    result.hooks = pre_run;
+    result.stdin_hash = stdin.as_deref().map(calculate_file_hash).transpose()?;
+    result.params = params;
+    result.host = Some(start.host.clone()).filter(|host| !host.is_empty());
+    result.system_info = Some(system::collect());
+    result.priority = cli.priority;
+    result.sandbox = sandbox;
+    result.network_isolated = cli.no_network;
+    result.cpu_pinning = cpu_pinning;
+    // The script's output is redacted while it is captured, the hooks' output only here
+    redactor.redact_result(&mut result);
+    // Compressed logs leave only their tail in the manifest; metrics and failure patterns
+    // printed earlier are found in the full output
+    let (full_stdout, full_stderr) = logs::full_output(Path::new(&output_dir), &result);
+    result.metrics = metric_extractor.extract(&full_stdout);
+
+    // Metrics reported through a file take precedence over values parsed from stdout
+    match read_metrics_file(Path::new(&output_dir)) {
+        Ok(file_metrics) => result.metrics.extend(file_metrics),
+        Err(e) => tracing::warn!("{}", e),
+    }
 
     // Calculate hashes for all generated files
     result.file_hashes = get_file_hashes(Path::new(&output_dir))?;
+    if let Some(params) = &result.params {
+        // Hashed separately, like the input
+        result.file_hashes.remove(&params.file);
+    }
+    result.file_hashes.retain(|file, _| !ignored.matches(file));
+    result.files = get_output_files(Path::new(&output_dir), &result.file_hashes)?;
+    // Drops fastsave's own files and files the script removed again
+    result.file_timeline.retain(|file, _| result.file_hashes.contains_key(file));
+    if config.thumbnails.enabled {
+        thumbnail::create_thumbnails(Path::new(&output_dir), &mut result.files, &config.thumbnails);
+    }
+    result.total_output_bytes = Some(dir_size(Path::new(&output_dir))?);
+    result.previews = preview::collect_previews(Path::new(&output_dir), &result.files, &config.previews)?;
+    for preview in result.previews.values_mut() {
+        *preview = redactor.redact(preview);
+    }
+
+    match baseline::compare_with_baseline(Path::new(&cli.archive_dir), &result) {
+        Ok(comparison) => {
+            if let Some(comparison) = comparison.as_ref().filter(|c| c.regression) {
+                tracing::warn!("Outputs deviate from baseline {}", comparison.baseline_run);
+            }
+            result.baseline = comparison;
+        }
+        Err(e) => tracing::warn!("Failed to compare with baseline: {}", e),
+    }
+
+    if let Some(reference) = &cli.check_against {
+        result.check = Some(check::check_against_file(Path::new(reference), &result, &cli.check_metrics)?);
+    }
+
+    let expected: Vec<String> = config.expect.iter().chain(&cli.expect).cloned().collect();
+    if !expected.is_empty() {
+        result.validation = Some(validate::validate_outputs(Path::new(&output_dir), &expected)?);
+    }
+    result.success = Some(
+        success_criteria.script_succeeded(result.exit_code, &full_stderr)
+            && result.check.as_ref().is_none_or(|check| check.passed)
+            && result.validation.as_ref().is_none_or(|validation| validation.passed)
+            && result.modified_sources.as_ref().is_none_or(Vec::is_empty)
+            && !result.killed_reason.is_some_and(killed::KilledReason::stopped_by_fastsave),
+    );
+
+    result.run_digest = Some(digest::run_digest(&result));
+
+    save_manifest_as(Path::new(&output_dir), &result, cli.format.unwrap_or(config.format))?;
+    started::remove_started(Path::new(&output_dir));
+    logs::remove_live_logs(Path::new(&output_dir));
+
+    // Post-run hooks see the final manifest; their results are added to it afterwards
+    let on_failure: &[String] = if notify::run_succeeded(&result) { &[] } else { &config.hooks.on_failure };
+    if !config.hooks.post_run.is_empty() || !on_failure.is_empty() {
+        hook_env.push(("FASTSAVE_EXIT_CODE", result.exit_code.to_string()));
+        hook_env.push(("FASTSAVE_DURATION_MS", result.duration_ms.to_string()));
+        let mut after_run = hooks::run_hooks("post_run", &config.hooks.post_run, &hook_env, None)?;
+        after_run.extend(hooks::run_hooks("on_failure", on_failure, &hook_env, Some(&result.stderr))?);
+        for failed in after_run.iter().filter(|hook| !hook.succeeded()) {
+            tracing::warn!("{} hook '{}' failed with exit code {}", failed.stage, failed.command, failed.exit_code);
+        }
+        result.hooks.extend(after_run);
+        redactor.redact_result(&mut result);
+        save_manifest(Path::new(&output_dir), &result)?;
+    }
+
+    #[cfg(feature = "search")]
+    if let Err(e) = search::update_index(Path::new(&cli.archive_dir), Path::new(&output_dir), &result) {
+        tracing::warn!("Failed to update search index: {}", e);
+    }
+
+    if let Some(experiment) = &cli.experiment {
+        experiment::add_to_experiment(Path::new(&cli.archive_dir), Path::new(&output_dir), experiment)?;
+    }
+
+    if let Some(junit_path) = &cli.junit {
+        let run_id = archive::run_id(Path::new(&output_dir));
+        fs::write(junit_path, junit::junit_xml(&[(run_id, &result)]))?;
+    }
+    report::write_reports(Path::new(&output_dir), &result, &cli.report)?;
+
+    for error in notify::send_notifications(&config.notifications, Path::new(&output_dir), &result) {
+        tracing::warn!("Failed to send notification: {}", error);
+    }
+    if cli.notify && cli.notify_after.is_none_or(|min| result.duration_ms as i64 >= min.num_milliseconds()) {
+        let notification = notify::run_notification(&config.notifications, Path::new(&output_dir), &result);
+        if let Err(e) = notify::show_desktop_notification(&notification) {
+            tracing::warn!("Failed to show desktop notification: {}", e);
+        }
+    }
+
+    if cli.tar || config.layout.tar {
+        if cli.no_subfolder {
+            tracing::warn!("Not packing the run into {}: --no-subfolder writes into the archive directory itself", tarball::RUN_TAR_FILE);
+        } else {
+            let params = result.params.as_ref().map(|params| params.file.as_str());
+            tarball::pack_run(Path::new(&output_dir), params).map_err(|e| format!("Failed to pack the run into {}: {}", tarball::RUN_TAR_FILE, e))?;
+        }
+    }
+
+    // A failed upload leaves the run archived locally, where a later sync can pick it up
+    if let Some(target) = &remote_dir {
+        match remote::upload_run(Path::new(&output_dir), target, false) {
+            Ok(uploaded) => tracing::info!("Uploaded {} files to {}", uploaded, target.display()),
+            Err(e) => tracing::warn!("Failed to upload the run to {}: {}; `fastsave upload --resume` continues it", target.display(), e),
+        }
+    }
 
-    // Save results to YAML file instead of JSON
-    let yaml = serde_yaml::to_string(&result)?;
-    fs::write(&output_file, yaml)?;
+    observer.on_complete(&result);
 
-    Ok(output_dir)
+    // The caller fails the run only now that the manifest is saved, so a deviating run stays inspectable
+    Ok(ArchivedRun { run_dir: PathBuf::from(output_dir), result })
 } 
\ No newline at end of file
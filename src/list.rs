@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{find_runs, ArchivedRun};
+use crate::get_script_basename;
+use crate::table::{format_size, Table};
+
+#[derive(Args)]
+pub struct ListArgs {
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+
+    /// Only list runs belonging to this experiment
+    #[arg(short = 'e', long = "experiment")]
+    pub experiment: Option<String>,
+}
+
+pub fn list_runs(archive_dir: &Path, experiment: Option<&str>) -> Result<Vec<ArchivedRun>, Box<dyn Error>> {
+    let runs = find_runs(archive_dir)?
+        .into_iter()
+        .filter(|run| experiment.is_none() || run.result.experiment.as_deref() == experiment)
+        .collect();
+    Ok(runs)
+}
+
+pub fn run_list(args: &ListArgs) -> Result<(), Box<dyn Error>> {
+    let runs = list_runs(Path::new(&args.archive_dir), args.experiment.as_deref())?;
+
+    let headers = ["ID", "SCRIPT", "START", "DURATION", "EXIT", "SIZE", "PINNED", "MESSAGE"];
+    let mut table = Table::new(headers.iter().map(|h| h.to_string()).collect());
+    for run in &runs {
+        let result = &run.result;
+        table.rows.push(vec![
+            run.id(),
+            get_script_basename(&result.script_path),
+            result.start_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            format!("{:.1}s", result.duration_ms as f64 / 1000.0),
+            result.exit_code.to_string(),
+            result.total_output_bytes.map(format_size).unwrap_or_default(),
+            if result.pinned { "yes" } else { "" }.to_string(),
+            result.message.as_deref().and_then(|m| m.lines().next()).unwrap_or("").to_string(),
+        ]);
+    }
+    print!("{}", table.to_text());
+
+    Ok(())
+}
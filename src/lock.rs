@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::started::host_name;
+
+/// Directory of the lock files inside the archive
+pub const LOCKS_DIR: &str = ".locks";
+
+/// Named locks per script, keyed by script name without extension:
+///
+/// ```yaml
+/// locks:
+///   update_database: database
+/// ```
+pub type LocksConfig = BTreeMap<String, String>;
+
+/// Lock names become file names, so they must be a single path component
+pub fn parse_lock_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']);
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!("invalid lock name '{}': must be a file name", name))
+    }
+}
+
+/// A held lock; released when dropped, or by the OS when fastsave dies
+pub struct RunLock {
+    _file: File,
+}
+
+fn lock_path(archive_dir: &Path, name: &str) -> PathBuf {
+    archive_dir.join(LOCKS_DIR).join(format!("{}.lock", name))
+}
+
+/// Acquires the named lock, waiting for the current holder to finish unless `wait` is false
+pub fn acquire(archive_dir: &Path, name: &str, wait: bool) -> Result<RunLock, Box<dyn Error>> {
+    parse_lock_name(name)?;
+    let path = lock_path(archive_dir, name);
+    fs::create_dir_all(path.parent().ok_or("lock without directory")?)?;
+    // Not truncated on open: the content names the holder until we have the lock
+    let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            let holder = Some(holder.trim()).filter(|holder| !holder.is_empty()).unwrap_or("another process");
+            if !wait {
+                return Err(format!("Lock '{}' is held by {}, not running the script", name, holder).into());
+            }
+            tracing::info!("Waiting for lock '{}' held by {}", name, holder);
+            file.lock()?;
+        }
+        Err(TryLockError::Error(e)) => return Err(format!("Failed to lock {}: {}", path.display(), e).into()),
+    }
+
+    file.set_len(0)?;
+    writeln!(file, "process {} on {}", std::process::id(), host_name())?;
+    Ok(RunLock { _file: file })
+}
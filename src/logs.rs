@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, LineWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::tarball;
+use crate::ExecutionResult;
+
+/// Standard output of a run written with `--compress-logs`
+pub const STDOUT_LOG: &str = "stdout.log.zst";
+/// Standard error of a run written with `--compress-logs`
+pub const STDERR_LOG: &str = "stderr.log.zst";
+
+/// Standard output of a run without `--compress-logs`, written while the script runs so
+/// `fastsave attach` can follow it, and removed once the manifest is saved
+pub const STDOUT_LIVE: &str = ".stdout.live";
+/// Standard error of a run without `--compress-logs`, like `STDOUT_LIVE`
+pub const STDERR_LIVE: &str = ".stderr.live";
+
+/// Last lines of each stream kept in the manifest when the logs are compressed
+pub const TAIL_LINES: usize = 1000;
+
+/// Longest a line stays in the encoder of a compressed log before it can be read back
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(feature = "compress")]
+type Encoder = zstd::Encoder<'static, BufWriter<File>>;
+
+#[cfg(feature = "compress")]
+fn encoder(file: File) -> io::Result<Encoder> {
+    zstd::Encoder::new(BufWriter::new(file), 3)
+}
+
+#[cfg(feature = "compress")]
+fn finish(encoder: Encoder) -> io::Result<()> {
+    encoder.finish()?.flush()
+}
+
+#[cfg(feature = "compress")]
+fn decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::decode_all(data)
+}
+
+/// Everything decodable so far from a log that is still being written
+#[cfg(feature = "compress")]
+fn decode_partial(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    // The end of the frame is missing until the log is finished
+    let _ = zstd::Decoder::new(data)?.read_to_end(&mut decoded);
+    Ok(decoded)
+}
+
+#[cfg(not(feature = "compress"))]
+type Encoder = BufWriter<File>;
+
+#[cfg(not(feature = "compress"))]
+fn without_feature() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "fastsave was built without the `compress` feature")
+}
+
+#[cfg(not(feature = "compress"))]
+fn encoder(_: File) -> io::Result<Encoder> {
+    Err(without_feature())
+}
+
+#[cfg(not(feature = "compress"))]
+fn finish(mut encoder: Encoder) -> io::Result<()> {
+    encoder.flush()
+}
+
+#[cfg(not(feature = "compress"))]
+fn decode(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(without_feature())
+}
+
+#[cfg(not(feature = "compress"))]
+fn decode_partial(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(without_feature())
+}
+
+/// One output stream written through zstd into the run directory while the script runs.
+/// Only its last lines are kept in memory, for the manifest.
+pub struct CompressedLog {
+    name: &'static str,
+    encoder: Option<Encoder>,
+    tail: VecDeque<String>,
+    lines: u64,
+    flushed: Instant,
+}
+
+impl CompressedLog {
+    pub fn create(run_dir: &Path, name: &'static str) -> io::Result<CompressedLog> {
+        let encoder = encoder(File::create(run_dir.join(name))?)?;
+        Ok(CompressedLog { name, encoder: Some(encoder), tail: VecDeque::new(), lines: 0, flushed: Instant::now() })
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        if let Some(encoder) = &mut self.encoder {
+            let mut written = writeln!(encoder, "{}", line);
+            // Completes the current block, so `fastsave attach` sees the line
+            if written.is_ok() && self.flushed.elapsed() >= FLUSH_INTERVAL {
+                written = encoder.flush();
+                self.flushed = Instant::now();
+            }
+            if let Err(e) = written {
+                // The tail still makes it into the manifest
+                tracing::warn!("Failed to write {}: {}", self.name, e);
+                self.encoder = None;
+            }
+        }
+        if self.tail.len() == TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(line.to_string());
+        self.lines += 1;
+    }
+
+    /// Completes the log and returns its tail for the manifest, headed by a note on the
+    /// lines left out
+    pub fn finish(self) -> String {
+        if let Some(Err(e)) = self.encoder.map(finish) {
+            tracing::warn!("Failed to write {}: {}", self.name, e);
+        }
+        let mut tail = String::new();
+        let omitted = self.lines - self.tail.len() as u64;
+        if omitted > 0 {
+            tail.push_str(&format!("[{} earlier lines in {}]\n", omitted, self.name));
+        }
+        for line in self.tail {
+            tail.push_str(&line);
+            tail.push('\n');
+        }
+        tail
+    }
+}
+
+/// One output stream of a run without `--compress-logs`, written into the run directory line
+/// by line while the script runs
+pub struct LiveLog {
+    name: &'static str,
+    file: Option<LineWriter<File>>,
+}
+
+impl LiveLog {
+    /// Only `fastsave attach` reads the log, so a log that can't be created just stays empty
+    pub fn create(run_dir: &Path, name: &'static str) -> LiveLog {
+        let file = File::create(run_dir.join(name))
+            .inspect_err(|e| tracing::warn!("Failed to create {}: {}", name, e))
+            .ok()
+            .map(LineWriter::new);
+        LiveLog { name, file }
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = writeln!(file, "{}", line) {
+                tracing::warn!("Failed to write {}: {}", self.name, e);
+                self.file = None;
+            }
+        }
+    }
+}
+
+/// Removes the live logs once the manifest holds the output
+pub fn remove_live_logs(run_dir: &Path) {
+    for name in [STDOUT_LIVE, STDERR_LIVE] {
+        match fs::remove_file(run_dir.join(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => tracing::debug!("Failed to remove {}: {}", name, e),
+            _ => {}
+        }
+    }
+}
+
+/// What a running script has written to one of its streams after the first `offset` bytes,
+/// from the compressed log or else the live log. Empty while neither exists.
+pub fn read_running_log(run_dir: &Path, compressed: &str, live: &str, offset: u64) -> io::Result<Vec<u8>> {
+    let open = |name: &str| match File::open(run_dir.join(name)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        other => other.map(Some),
+    };
+    let mut data = Vec::new();
+    if let Some(mut file) = open(compressed)? {
+        file.read_to_end(&mut data)?;
+        // A compressed log can only be decoded from its start
+        let decoded = decode_partial(&data)?;
+        return Ok(decoded.get(offset as usize..).unwrap_or_default().to_vec());
+    }
+    if let Some(mut file) = open(live)? {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+/// Decompressed content of a log of the run, also from a run packed with `--tar`
+pub fn read_log(run_dir: &Path, name: &str) -> io::Result<String> {
+    let data = decode(&tarball::read_run_file(run_dir, name)?)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Full standard output and error of a run, decompressed from its logs if they were
+/// compressed. Falls back to the tail in the manifest if a log can't be read.
+pub fn full_output(run_dir: &Path, result: &ExecutionResult) -> (String, String) {
+    if !result.compressed_logs {
+        return (result.stdout.clone(), result.stderr.clone());
+    }
+    let read = |name: &str, tail: &String| {
+        read_log(run_dir, name).unwrap_or_else(|e| {
+            tracing::warn!("Failed to read {}: {}", name, e);
+            tail.clone()
+        })
+    };
+    (read(STDOUT_LOG, &result.stdout), read(STDERR_LOG, &result.stderr))
+}
@@ -1,10 +1,75 @@
 use std::error::Error;
 use clap::Parser;
-use fastsave::{Cli, run_script};
+use tracing_subscriber::EnvFilter;
+use fastsave::{App, Commands, FastsaveConfig, resolve_alias, run_script, archive, attach, attest, audit, baseline, batch, benchmark, exec, clean, compare, determinism, diff, export, failures, grep, index, init, list, note, pin, plot, query, queue, remote, repro, show, stats, status, tag};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let output_dir = run_script(&cli)?;
-    println!("Fastsave completed. Output saved to: {}/fastsave.yaml", output_dir);
-    Ok(())
-}
\ No newline at end of file
+    // Progress and warnings by default; FASTSAVE_LOG=debug (or any tracing filter) for more
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_env("FASTSAVE_LOG").unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_target(false)
+        .init();
+
+    let app = App::parse();
+    match app.command {
+        Some(Commands::Query(args)) => query::run_query(&args),
+        Some(Commands::Attach(args)) => attach::run_attach(&args),
+        Some(Commands::Attest(args)) => attest::run_attest(&args),
+        Some(Commands::Audit(args)) => audit::run_audit(&args),
+        Some(Commands::Baseline(args)) => baseline::run_baseline(&args),
+        Some(Commands::Batch(args)) => batch::run_batch(&args),
+        Some(Commands::Exec(args)) => exec::run_exec(&args),
+        Some(Commands::Clean(args)) => clean::run_clean(&args),
+        Some(Commands::Compare(args)) => compare::run_compare(&args),
+        Some(Commands::Daemon(args)) => queue::run_daemon(&args),
+        Some(Commands::Diff(args)) => diff::run_diff(&args),
+        Some(Commands::Export(args)) => export::run_export(&args),
+        Some(Commands::Failures(args)) => failures::run_failures(&args),
+        Some(Commands::Grep(args)) => grep::run_grep(&args),
+        Some(Commands::Index(args)) => index::run_index(&args),
+        Some(Commands::Init(args)) => init::run_init(&args),
+        Some(Commands::List(args)) => list::run_list(&args),
+        Some(Commands::Note(args)) => note::run_note(&args),
+        Some(Commands::Pin(args)) => pin::run_pin(&args),
+        Some(Commands::Plot(args)) => plot::run_plot(&args),
+        Some(Commands::Repro(args)) => repro::run_repro(&args),
+        #[cfg(feature = "search")]
+        Some(Commands::Search(args)) => fastsave::search::run_search(&args),
+        #[cfg(feature = "serve")]
+        Some(Commands::Serve(args)) => fastsave::serve::run_serve(&args),
+        Some(Commands::Show(args)) => show::run_show(&args),
+        Some(Commands::Stats(args)) => stats::run_stats(&args),
+        Some(Commands::Status(args)) => status::run_status(&args),
+        Some(Commands::Submit(args)) => queue::run_submit(&args),
+        Some(Commands::Tag(args)) => tag::run_tag(&args),
+        #[cfg(feature = "tui")]
+        Some(Commands::Ui(args)) => fastsave::tui::run_ui(&args),
+        Some(Commands::Upload(args)) => remote::run_upload(&args),
+        None => {
+            let cli = app.run.ok_or("No script given")?;
+            // Resolved here as well, so repeated runs and their summaries use the script of the alias
+            let config = FastsaveConfig::load_with_config_path(cli.config_path.as_deref());
+            let cli = resolve_alias(&cli, &config)?.unwrap_or(cli);
+            if cli.determinism_check {
+                return determinism::run_determinism_check(&cli);
+            }
+            if let Some(repetitions) = cli.repeat {
+                let (benchmark_dir, summary) = benchmark::run_benchmark(&cli, repetitions, cli.warmup.unwrap_or(0))?;
+                print!("{}", summary.summary());
+                println!("Fastsave benchmark completed. Summary saved to: {}/{}", benchmark_dir, benchmark::BENCHMARK_FILE);
+                if summary.failed() > 0 {
+                    return Err(format!("{} of {} benchmark runs failed their checks", summary.failed(), summary.runs.len()).into());
+                }
+                return Ok(());
+            }
+            let run = run_script(&cli)?;
+            println!("Fastsave completed. Output saved to: {}", archive::manifest_path(&run.run_dir).display());
+            if let Some(digest) = &run.result.run_digest {
+                println!("Run digest: {}", digest);
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Files a script can write into its output dir to report metrics directly
+pub const METRICS_FILES: [&str; 3] = ["metrics.yaml", "metrics.yml", "metrics.json"];
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Regex patterns with named groups, e.g. `loss=(?P<loss>[\d.]+)`, applied to stdout
+    pub patterns: Vec<String>,
+    /// Record every matched value instead of only the last one
+    pub series: bool,
+}
+
+pub struct MetricExtractor {
+    patterns: Vec<Regex>,
+    series: bool,
+}
+
+impl MetricExtractor {
+    pub fn new(config: &MetricsConfig) -> Result<Self, Box<dyn Error>> {
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| format!("Invalid metric pattern '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MetricExtractor { patterns, series: config.series })
+    }
+
+    /// Collects the values of all named groups matched in the captured output
+    pub fn extract(&self, output: &str) -> BTreeMap<String, serde_yaml::Value> {
+        let mut values: BTreeMap<String, Vec<serde_yaml::Value>> = BTreeMap::new();
+
+        for line in output.lines() {
+            for pattern in &self.patterns {
+                for captures in pattern.captures_iter(line) {
+                    for name in pattern.capture_names().flatten() {
+                        if let Some(m) = captures.name(name) {
+                            values.entry(name.to_string()).or_default().push(parse_value(m.as_str()));
+                        }
+                    }
+                }
+            }
+        }
+
+        values
+            .into_iter()
+            .filter_map(|(name, mut series)| {
+                let value = if self.series {
+                    serde_yaml::Value::Sequence(series)
+                } else {
+                    series.pop()?
+                };
+                Some((name, value))
+            })
+            .collect()
+    }
+}
+
+fn parse_value(raw: &str) -> serde_yaml::Value {
+    if let Ok(number) = raw.parse::<i64>() {
+        serde_yaml::Value::from(number)
+    } else if let Ok(number) = raw.parse::<f64>() {
+        serde_yaml::Value::from(number)
+    } else {
+        serde_yaml::Value::String(raw.to_string())
+    }
+}
+
+/// Reads the metrics file written by the script, if there is one.
+/// JSON is valid YAML, so both formats go through the YAML parser.
+pub fn read_metrics_file(output_dir: &Path) -> Result<BTreeMap<String, serde_yaml::Value>, Box<dyn Error>> {
+    for name in METRICS_FILES {
+        let path = output_dir.join(name);
+        if path.is_file() {
+            let contents = fs::read_to_string(&path)?;
+            return serde_yaml::from_str(&contents)
+                .map_err(|e| format!("Invalid metrics file {}: {}", path.display(), e).into());
+        }
+    }
+    Ok(BTreeMap::new())
+}
@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::path::Path;
+
+use chrono::Utc;
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, save_manifest};
+
+#[derive(Args)]
+pub struct NoteArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Text to add to the run's message
+    pub text: String,
+
+    /// Replace the existing message instead of appending to it
+    #[arg(long = "replace")]
+    pub replace: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub fn add_note(run_dir: &Path, text: &str, replace: bool) -> Result<(), Box<dyn Error>> {
+    let mut result = load_manifest(&manifest_path(run_dir))?;
+
+    result.message = match result.message.take() {
+        Some(existing) if !replace && !existing.is_empty() => Some(format!("{}\n{}", existing, text)),
+        _ => Some(text.to_string()),
+    };
+    result.message_edited = Some(Utc::now());
+
+    save_manifest(run_dir, &result)
+}
+
+pub fn run_note(args: &NoteArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(Path::new(&args.archive_dir), &args.run)?;
+    add_note(&run_dir, &args.text, args.replace)?;
+
+    #[cfg(feature = "search")]
+    {
+        let result = load_manifest(&manifest_path(&run_dir))?;
+        crate::search::update_index(Path::new(&args.archive_dir), &run_dir, &result)?;
+    }
+    println!("Updated message of {}", run_dir.display());
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{parse_duration, run_id};
+use crate::killed::KilledReason;
+use crate::{get_script_basename, ExecutionResult};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Base URL of `fastsave serve`; notifications then link to the run's page
+    pub dashboard_url: Option<String>,
+    pub webhooks: Vec<WebhookConfig>,
+    /// Formatted messages to Slack or Mattermost incoming webhooks
+    pub chat: Vec<ChatConfig>,
+    /// Emails via SMTP (requires the `email` feature)
+    pub email: Vec<EmailConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub on: NotifyOn,
+    /// Additional attempts after a failed delivery
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Incoming webhook URL
+    pub url: String,
+    #[serde(default)]
+    pub service: ChatService,
+    /// Overrides the webhook's default channel
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub on: NotifyOn,
+    /// Only notify about runs that took at least this long, e.g. `1h`
+    pub min_duration: Option<String>,
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatService {
+    #[default]
+    Slack,
+    Mattermost,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_server: String,
+    /// Defaults to the standard port of the security mode
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Falls back to the `FASTSAVE_SMTP_USERNAME` environment variable
+    pub username: Option<String>,
+    /// Falls back to the `FASTSAVE_SMTP_PASSWORD` environment variable
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub on: NotifyOn,
+    pub min_duration: Option<String>,
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    #[serde(default = "default_body")]
+    pub body: String,
+}
+
+fn default_subject() -> String {
+    "[fastsave] {script} {status} after {duration}".to_string()
+}
+
+fn default_body() -> String {
+    "{link}\n\n{summary}".to_string()
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS (port 587)
+    #[default]
+    StartTls,
+    /// Implicit TLS (port 465)
+    Tls,
+    /// Unencrypted, e.g. for a relay on localhost (port 25)
+    None,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    Success,
+    Failure,
+}
+
+impl NotifyOn {
+    fn matches(self, succeeded: bool) -> bool {
+        match self {
+            NotifyOn::Always => true,
+            NotifyOn::Success => succeeded,
+            NotifyOn::Failure => !succeeded,
+        }
+    }
+}
+
+/// JSON payload posted to webhooks
+#[derive(Serialize)]
+pub struct RunNotification {
+    pub run_id: String,
+    pub script: String,
+    pub status: &'static str,
+    pub exit_code: i32,
+    pub duration_ms: u64,
+    pub message: Option<String>,
+    /// Why the script was killed, e.g. `disk_full` when fastsave stopped it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub killed_reason: Option<&'static str>,
+    /// Dashboard page of the run if `dashboard_url` is configured, otherwise the run directory
+    pub link: String,
+}
+
+/// The recorded verdict; for older manifests a run fails if the script exits non-zero or
+/// its outputs don't match the `--check-against` reference
+pub fn run_succeeded(result: &ExecutionResult) -> bool {
+    result.success.unwrap_or_else(|| {
+        result.exit_code == 0
+            && result.check.as_ref().is_none_or(|check| check.passed)
+            && result.validation.as_ref().is_none_or(|validation| validation.passed)
+    })
+}
+
+/// Dashboard page of the run if `dashboard_url` is configured, otherwise the run directory
+fn run_link(config: &NotificationsConfig, run_dir: &Path) -> String {
+    match &config.dashboard_url {
+        Some(base) => format!("{}/runs/{}", base.trim_end_matches('/'), run_id(run_dir)),
+        None => run_dir.canonicalize().unwrap_or_else(|_| run_dir.to_path_buf()).to_string_lossy().into_owned(),
+    }
+}
+
+pub fn run_notification(config: &NotificationsConfig, run_dir: &Path, result: &ExecutionResult) -> RunNotification {
+    let link = run_link(config, run_dir);
+    RunNotification {
+        run_id: run_id(run_dir),
+        script: get_script_basename(&result.script_path),
+        status: if run_succeeded(result) { "success" } else { "failure" },
+        exit_code: result.exit_code,
+        duration_ms: result.duration_ms,
+        message: result.message.clone(),
+        killed_reason: result.killed_reason.map(KilledReason::as_str),
+        link,
+    }
+}
+
+/// Posts JSON to `url`, retrying transport errors, rate limiting and server errors with exponential backoff
+pub fn post_json<T: Serialize>(url: &str, payload: &T, retries: u32) -> Result<(), String> {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let mut attempt = 0;
+    loop {
+        let error = match agent.post(url).send_json(payload) {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(code, _)) if code != 429 && code < 500 => {
+                return Err(format!("{} responded with status {}", url, code));
+            }
+            Err(e) => e.to_string(),
+        };
+        if attempt >= retries {
+            return Err(error);
+        }
+        thread::sleep(Duration::from_secs(1 << attempt.min(5)));
+        attempt += 1;
+    }
+}
+
+/// Compact human readable duration, e.g. `4.2s`, `3m 05s` or `1h 02m`
+pub fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    match seconds {
+        0..=59 => format!("{:.1}s", duration_ms as f64 / 1000.0),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Message text for chat services; Slack uses its own link syntax, Mattermost Markdown
+pub fn chat_message(notification: &RunNotification, service: ChatService) -> String {
+    let (icon, outcome) = match notification.killed_reason {
+        _ if notification.status == "success" => (":white_check_mark:", "finished".to_string()),
+        Some(reason) => (":x:", format!("failed ({})", reason)),
+        None => (":x:", format!("failed (exit code {})", notification.exit_code)),
+    };
+    let mut text = format!(
+        "{} *{}* {} after {}",
+        icon,
+        notification.script,
+        outcome,
+        format_duration(notification.duration_ms)
+    );
+    if let Some(message) = notification.message.as_deref().filter(|m| !m.is_empty()) {
+        for line in message.lines() {
+            text.push_str(&format!("\n> {}", line));
+        }
+    }
+    match service {
+        ChatService::Slack => text.push_str(&format!("\n<{}|{}>", notification.link, notification.run_id)),
+        ChatService::Mattermost => text.push_str(&format!("\n[{}]({})", notification.run_id, notification.link)),
+    }
+    text
+}
+
+/// Whether the run lasted at least `min_duration`, if one is given
+fn long_enough(min_duration: Option<&str>, result: &ExecutionResult) -> Result<bool, String> {
+    match min_duration {
+        Some(min_duration) => Ok(result.duration_ms as i64 >= parse_duration(min_duration)?.num_milliseconds()),
+        None => Ok(true),
+    }
+}
+
+fn send_chat(chat: &ChatConfig, notification: &RunNotification, result: &ExecutionResult) -> Result<(), String> {
+    if !long_enough(chat.min_duration.as_deref(), result)? {
+        return Ok(());
+    }
+
+    let mut payload = serde_json::json!({ "text": chat_message(notification, chat.service) });
+    if let Some(channel) = &chat.channel {
+        payload["channel"] = serde_json::Value::from(channel.as_str());
+    }
+    post_json(&chat.url, &payload, chat.retries)
+}
+
+/// Sends the notifications configured for a completed run; returns the deliveries that failed
+pub fn send_notifications(config: &NotificationsConfig, run_dir: &Path, result: &ExecutionResult) -> Vec<String> {
+    let notification = run_notification(config, run_dir, result);
+    let succeeded = run_succeeded(result);
+
+    let webhooks = config
+        .webhooks
+        .iter()
+        .filter(|webhook| webhook.on.matches(succeeded))
+        .filter_map(|webhook| post_json(&webhook.url, &notification, webhook.retries).err());
+    let chats = config
+        .chat
+        .iter()
+        .filter(|chat| chat.on.matches(succeeded))
+        .filter_map(|chat| send_chat(chat, &notification, result).err());
+    let emails = config
+        .email
+        .iter()
+        .filter(|email| email.on.matches(succeeded))
+        .filter_map(|email| send_email(email, &notification, run_dir, result).err());
+    webhooks.chain(chats).chain(emails).collect()
+}
+
+/// JSON payload posted to webhooks when a running script is about to reach its timeout
+#[derive(Serialize)]
+pub struct TimeoutWarning {
+    pub run_id: String,
+    pub script: String,
+    pub status: &'static str,
+    /// Time left until the script is stopped
+    pub remaining_ms: u64,
+    pub link: String,
+}
+
+/// Warns the webhooks and chats that are notified about failed runs that the script in
+/// `run_dir` will be stopped in `remaining`; returns the deliveries that failed
+pub fn send_timeout_warning(config: &NotificationsConfig, run_dir: &Path, script_path: &str, remaining: Duration) -> Vec<String> {
+    let warning = TimeoutWarning {
+        run_id: run_id(run_dir),
+        script: get_script_basename(script_path),
+        status: "timeout_warning",
+        remaining_ms: remaining.as_millis() as u64,
+        link: run_link(config, run_dir),
+    };
+    let text = format!(":hourglass: *{}* reaches its timeout in {}", warning.script, format_duration(warning.remaining_ms));
+
+    let webhooks = config
+        .webhooks
+        .iter()
+        .filter(|webhook| webhook.on.matches(false))
+        .filter_map(|webhook| post_json(&webhook.url, &warning, webhook.retries).err());
+    let chats = config.chat.iter().filter(|chat| chat.on.matches(false)).filter_map(|chat| {
+        let link = match chat.service {
+            ChatService::Slack => format!("<{}|{}>", warning.link, warning.run_id),
+            ChatService::Mattermost => format!("[{}]({})", warning.run_id, warning.link),
+        };
+        let mut payload = serde_json::json!({ "text": format!("{}\n{}", text, link) });
+        if let Some(channel) = &chat.channel {
+            payload["channel"] = serde_json::Value::from(channel.as_str());
+        }
+        post_json(&chat.url, &payload, chat.retries).err()
+    });
+    webhooks.chain(chats).collect()
+}
+
+#[cfg(feature = "email")]
+fn send_email(email: &EmailConfig, notification: &RunNotification, run_dir: &Path, result: &ExecutionResult) -> Result<(), String> {
+    if !long_enough(email.min_duration.as_deref(), result)? {
+        return Ok(());
+    }
+    crate::email::send_email(email, notification, run_dir).map_err(|e| format!("email via {}: {}", email.smtp_server, e))
+}
+
+#[cfg(not(feature = "email"))]
+fn send_email(_: &EmailConfig, _: &RunNotification, _: &Path, _: &ExecutionResult) -> Result<(), String> {
+    Err("email notifications are configured, but fastsave was built without the `email` feature".to_string())
+}
+
+#[cfg(feature = "desktop")]
+pub fn show_desktop_notification(notification: &RunNotification) -> Result<(), String> {
+    let outcome = if notification.status == "success" {
+        "finished".to_string()
+    } else {
+        format!("failed with exit code {}", notification.exit_code)
+    };
+    notify_rust::Notification::new()
+        .appname("fastsave")
+        .summary(&format!("{} {}", notification.script, outcome))
+        .body(&format!("{} after {}", notification.run_id, format_duration(notification.duration_ms)))
+        .show()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "desktop"))]
+pub fn show_desktop_notification(_: &RunNotification) -> Result<(), String> {
+    Err("fastsave was built without the `desktop` feature".to_string())
+}
@@ -0,0 +1,102 @@
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+use crate::ExecutionResult;
+
+/// Receives progress events of a run, e.g. to stream output into a GUI.
+///
+/// Output lines are delivered from the two threads reading stdout and stderr,
+/// so implementations must be `Sync`. All methods default to doing nothing.
+pub trait RunObserver: Sync {
+    /// The script is about to be started
+    fn on_start(&self, _script_path: &str, _output_dir: &str) {}
+
+    /// A line the script wrote to stdout, without the line break
+    fn on_stdout_line(&self, _line: &str) {}
+
+    /// A line the script wrote to stderr, without the line break
+    fn on_stderr_line(&self, _line: &str) {}
+
+    /// The run is complete. With `run_script_with_observer` this is called once
+    /// the manifest is written and `result` is what it contains.
+    fn on_complete(&self, _result: &ExecutionResult) {}
+}
+
+/// Observer that ignores all events
+pub struct NoObserver;
+
+impl RunObserver for NoObserver {}
+
+/// A line of output, without the line break
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Forwards output lines into a channel, e.g. to a UI thread. Lines are dropped once the receiver is gone.
+impl RunObserver for Sender<OutputLine> {
+    fn on_stdout_line(&self, line: &str) {
+        let _ = self.send(OutputLine::Stdout(line.to_string()));
+    }
+
+    fn on_stderr_line(&self, line: &str) {
+        let _ = self.send(OutputLine::Stderr(line.to_string()));
+    }
+}
+
+type LineCallback<'a> = Mutex<Box<dyn FnMut(&str) + Send + 'a>>;
+
+/// Observer calling closures for output lines as they arrive:
+///
+/// ```no_run
+/// # use fastsave::observer::LineCallbacks;
+/// # use fastsave::runner::Runner;
+/// let mut steps = 0;
+/// let callbacks = LineCallbacks::new().on_stdout(|line| {
+///     if line.starts_with("step ") {
+///         steps += 1;
+///     }
+/// });
+/// Runner::new("train.py").observer(&callbacks).execute()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Default)]
+pub struct LineCallbacks<'a> {
+    stdout: Option<LineCallback<'a>>,
+    stderr: Option<LineCallback<'a>>,
+}
+
+impl<'a> LineCallbacks<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_stdout(mut self, callback: impl FnMut(&str) + Send + 'a) -> Self {
+        self.stdout = Some(Mutex::new(Box::new(callback)));
+        self
+    }
+
+    pub fn on_stderr(mut self, callback: impl FnMut(&str) + Send + 'a) -> Self {
+        self.stderr = Some(Mutex::new(Box::new(callback)));
+        self
+    }
+}
+
+fn call(callback: &Option<LineCallback>, line: &str) {
+    if let Some(callback) = callback {
+        // A callback that panicked already reported that; keep delivering to it
+        let mut callback = callback.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        callback(line);
+    }
+}
+
+impl RunObserver for LineCallbacks<'_> {
+    fn on_stdout_line(&self, line: &str) {
+        call(&self.stdout, line);
+    }
+
+    fn on_stderr_line(&self, line: &str) {
+        call(&self.stderr, line);
+    }
+}
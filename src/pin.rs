@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, save_manifest};
+
+#[derive(Args)]
+pub struct PinArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Unpin the run, leaving it to the retention policy again
+    #[arg(long = "unpin")]
+    pub unpin: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// Marks the run as protected from `fastsave clean` and the retention policy, or removes the mark
+pub fn set_pinned(run_dir: &Path, pinned: bool) -> Result<(), Box<dyn Error>> {
+    let mut result = load_manifest(&manifest_path(run_dir))?;
+    result.pinned = pinned;
+    save_manifest(run_dir, &result)
+}
+
+pub fn run_pin(args: &PinArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(Path::new(&args.archive_dir), &args.run)?;
+    set_pinned(&run_dir, !args.unpin)?;
+    if args.unpin {
+        println!("Unpinned {}", run_dir.display());
+    } else {
+        println!("Pinned {}, it won't be deleted by any cleanup", run_dir.display());
+    }
+    Ok(())
+}
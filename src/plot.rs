@@ -0,0 +1,228 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::batch::{job_metrics, job_parameters, BatchResult, JobStatus, BATCH_FILE};
+use crate::junit::escape_xml as escape;
+
+#[derive(Args)]
+pub struct PlotArgs {
+    /// Batch directory name or path
+    pub batch: String,
+
+    /// Parameter or metric on the horizontal axis
+    #[arg(long = "x", value_name = "NAME")]
+    pub x: String,
+
+    /// Metric or parameter on the vertical axis
+    #[arg(long = "y", value_name = "NAME")]
+    pub y: String,
+
+    /// Logarithmic horizontal axis, e.g. for learning rates
+    #[arg(long = "log-x")]
+    pub log_x: bool,
+
+    /// Logarithmic vertical axis
+    #[arg(long = "log-y")]
+    pub log_y: bool,
+
+    /// Where to write the SVG; defaults to `plot_<X>_<Y>.svg` in the batch directory
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// A job of the batch placed in the plot
+#[derive(Debug, PartialEq)]
+pub struct PlotPoint {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// The numeric values of `x` and `y` of every successful job. Names are looked up among
+/// the parameters of the job first, then among the metrics of its run; `metrics.loss`
+/// only looks among the metrics.
+pub fn batch_points(archive_dir: &Path, batch: &BatchResult, x: &str, y: &str) -> Vec<PlotPoint> {
+    let metrics = job_metrics(archive_dir, &batch.jobs);
+    batch
+        .jobs
+        .iter()
+        .zip(&metrics)
+        .filter(|(job, _)| job.outcome.status == JobStatus::Success)
+        .filter_map(|(job, metrics)| {
+            let parameters = job_parameters(&job.job.script_args);
+            let value = |name: &str| match name.strip_prefix("metrics.") {
+                Some(metric) => metrics.get(metric)?.as_f64(),
+                None => match parameters.get(name) {
+                    Some(parameter) => parameter.parse().ok(),
+                    None => metrics.get(name)?.as_f64(),
+                },
+            };
+            Some(PlotPoint { id: job.outcome.id.clone()?, x: value(x)?, y: value(y)? })
+        })
+        .collect()
+}
+
+/// A tick label with few digits, in scientific notation for very small and large values
+fn format_tick(value: f64) -> String {
+    if value == 0.0 || (1e-3..1e5).contains(&value.abs()) {
+        let text = format!("{:.4}", value);
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        format!("{:.1e}", value)
+    }
+}
+
+/// Maps values of one axis onto `0.0..=1.0`, logarithmically if asked to
+struct Axis {
+    log: bool,
+    min: f64,
+    span: f64,
+}
+
+impl Axis {
+    fn new(values: impl Iterator<Item = f64>, log: bool, name: &str) -> Result<Axis, String> {
+        let values: Vec<f64> = values.collect();
+        if log && values.iter().any(|value| *value <= 0.0) {
+            return Err(format!("{} has values that are not positive and can't be shown on a logarithmic axis", name));
+        }
+        let transformed: Vec<f64> = values.iter().map(|value| if log { value.log10() } else { *value }).collect();
+        let min = transformed.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = transformed.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        // A single value sits in the middle of the axis
+        let (min, span) = if max > min { (min, max - min) } else { (min - 0.5, 1.0) };
+        Ok(Axis { log, min, span })
+    }
+
+    fn position(&self, value: f64) -> f64 {
+        ((if self.log { value.log10() } else { value }) - self.min) / self.span
+    }
+
+    /// Values at evenly spaced positions along the axis
+    fn ticks(&self, count: usize) -> Vec<(f64, f64)> {
+        (0..count)
+            .map(|i| {
+                let position = i as f64 / (count - 1) as f64;
+                let value = self.min + position * self.span;
+                (position, if self.log { 10f64.powf(value) } else { value })
+            })
+            .collect()
+    }
+}
+
+/// Scatter plot of the points as SVG, with labelled axes and the run id of every point as its tooltip
+pub fn scatter_svg(points: &[PlotPoint], x_name: &str, y_name: &str, log_x: bool, log_y: bool) -> Result<String, String> {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 400.0;
+    const LEFT: f64 = 70.0;
+    const RIGHT: f64 = 20.0;
+    const TOP: f64 = 30.0;
+    const BOTTOM: f64 = 50.0;
+    const TICKS: usize = 5;
+
+    let x_axis = Axis::new(points.iter().map(|point| point.x), log_x, x_name)?;
+    let y_axis = Axis::new(points.iter().map(|point| point.y), log_y, y_name)?;
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let to_x = |position: f64| LEFT + position * plot_width;
+    let to_y = |position: f64| TOP + (1.0 - position) * plot_height;
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <text x=\"{cx}\" y=\"18\" text-anchor=\"middle\" font-size=\"14\">{y} vs {x}</text>\n",
+        w = WIDTH,
+        h = HEIGHT,
+        cx = LEFT + plot_width / 2.0,
+        x = escape(x_name),
+        y = escape(y_name),
+    );
+    let _ = writeln!(
+        out,
+        "<path d=\"M{left},{top} V{bottom} H{right}\" fill=\"none\" stroke=\"#333\"/>",
+        left = LEFT,
+        top = TOP,
+        bottom = TOP + plot_height,
+        right = LEFT + plot_width
+    );
+    for (position, value) in x_axis.ticks(TICKS) {
+        let x = to_x(position);
+        let _ = writeln!(
+            out,
+            "<line x1=\"{x:.1}\" y1=\"{y}\" x2=\"{x:.1}\" y2=\"{end}\" stroke=\"#333\"/><text x=\"{x:.1}\" y=\"{label}\" text-anchor=\"middle\">{text}</text>",
+            y = TOP + plot_height,
+            end = TOP + plot_height + 5.0,
+            label = TOP + plot_height + 18.0,
+            text = format_tick(value)
+        );
+    }
+    for (position, value) in y_axis.ticks(TICKS) {
+        let y = to_y(position);
+        let _ = writeln!(
+            out,
+            "<line x1=\"{start}\" y1=\"{y:.1}\" x2=\"{x}\" y2=\"{y:.1}\" stroke=\"#333\"/><text x=\"{label}\" y=\"{y:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\">{text}</text>",
+            start = LEFT - 5.0,
+            x = LEFT,
+            label = LEFT - 8.0,
+            text = format_tick(value)
+        );
+    }
+    let _ = writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>", LEFT + plot_width / 2.0, HEIGHT - 8.0, escape(x_name));
+    let _ = writeln!(
+        out,
+        "<text x=\"14\" y=\"{y}\" text-anchor=\"middle\" transform=\"rotate(-90 14 {y})\">{}</text>",
+        escape(y_name),
+        y = TOP + plot_height / 2.0
+    );
+    for point in points {
+        let _ = writeln!(
+            out,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"#36c\"><title>{}: {} = {}, {} = {}</title></circle>",
+            to_x(x_axis.position(point.x)),
+            to_y(y_axis.position(point.y)),
+            escape(&point.id),
+            escape(x_name),
+            point.x,
+            escape(y_name),
+            point.y
+        );
+    }
+    out.push_str("</svg>\n");
+    Ok(out)
+}
+
+/// Directory of a batch given by its name in the archive or its path
+fn resolve_batch(archive_dir: &Path, batch: &str) -> Result<PathBuf, Box<dyn Error>> {
+    [PathBuf::from(batch), archive_dir.join(batch)]
+        .into_iter()
+        .find(|dir| dir.join(BATCH_FILE).is_file())
+        .ok_or_else(|| format!("No batch '{}' in {}", batch, archive_dir.display()).into())
+}
+
+pub fn run_plot(args: &PlotArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let batch_dir = resolve_batch(archive_dir, &args.batch)?;
+    let batch: BatchResult = serde_yaml::from_str(&fs::read_to_string(batch_dir.join(BATCH_FILE))?)?;
+
+    let points = batch_points(archive_dir, &batch, &args.x, &args.y);
+    if points.is_empty() {
+        return Err(format!("No successful job of the batch has numeric values of both {} and {}", args.x, args.y).into());
+    }
+    let svg = scatter_svg(&points, &args.x, &args.y, args.log_x, args.log_y)?;
+
+    let file_name = |name: &str| name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_");
+    let output = match &args.output {
+        Some(output) => PathBuf::from(output),
+        None => batch_dir.join(format!("plot_{}_{}.svg", file_name(&args.x), file_name(&args.y))),
+    };
+    fs::write(&output, svg)?;
+    println!("Plotted {} runs to {}", points.len(), output.display());
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::OutputFile;
+
+/// File kinds whose content is embedded as a preview
+const TEXT_KINDS: [&str; 5] = ["text", "csv", "tsv", "json", "xml"];
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewConfig {
+    /// Text outputs up to this size in bytes get a preview; 0 disables previews
+    pub max_bytes: u64,
+    /// Number of lines embedded per file
+    pub max_lines: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig { max_bytes: 4096, max_lines: 20 }
+    }
+}
+
+/// The first lines of every small text output, keyed like the file hashes
+pub fn collect_previews(
+    output_dir: &Path,
+    files: &BTreeMap<String, OutputFile>,
+    config: &PreviewConfig,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut previews = BTreeMap::new();
+    for (name, file) in files {
+        let is_text = file.kind.as_deref().is_some_and(|kind| TEXT_KINDS.contains(&kind));
+        if !is_text || file.size_bytes > config.max_bytes {
+            continue;
+        }
+        let bytes = fs::read(output_dir.join(name))?;
+        let contents = String::from_utf8_lossy(&bytes);
+        let mut preview: String = contents.lines().take(config.max_lines).flat_map(|line| [line, "\n"]).collect();
+        if contents.lines().nth(config.max_lines).is_some() {
+            preview.push_str("...\n");
+        }
+        previews.insert(name.clone(), preview);
+    }
+    Ok(previews)
+}
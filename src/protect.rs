@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::{calculate_file_hash, find_git_root, run_git_command};
+
+/// Hashes of the files a read-only run must not modify. For the repository these are only the
+/// tracked files that differ from `HEAD`, since the others are recorded by git already;
+/// `None` marks a deleted file.
+pub struct SourceSnapshot {
+    files: BTreeMap<String, Option<String>>,
+}
+
+fn hash(path: &Path) -> Option<String> {
+    path.is_file().then(|| calculate_file_hash(path).ok()).flatten()
+}
+
+/// Tracked files with changes, staged or not, relative to the repository root
+fn changed_tracked_files(repo_root: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = run_git_command(repo_root, &["diff", "--name-only", "-z", "HEAD"])?;
+    Ok(output.split('\0').filter(|path| !path.is_empty()).map(str::to_string).collect())
+}
+
+fn collect_input_files(path: &Path, files: &mut BTreeMap<String, Option<String>>) -> Result<(), Box<dyn Error>> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_input_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.insert(path.to_string_lossy().into_owned(), hash(path));
+    }
+    Ok(())
+}
+
+impl SourceSnapshot {
+    /// Records the state of the repository containing the script and of the declared inputs
+    pub fn take(script_path: &str, inputs: &[PathBuf]) -> Result<SourceSnapshot, Box<dyn Error>> {
+        let mut files = BTreeMap::new();
+        let script_dir = Path::new(script_path).parent().unwrap_or(Path::new(""));
+        if let Some(repo_root) = find_git_root(script_dir) {
+            for path in changed_tracked_files(&repo_root)? {
+                let content = hash(&repo_root.join(&path));
+                files.insert(path, content);
+            }
+        }
+        for input in inputs {
+            collect_input_files(input, &mut files)?;
+        }
+        Ok(SourceSnapshot { files })
+    }
+
+    /// Files that were modified, created among the tracked files or deleted since `self`
+    pub fn modified_since(&self, after: &SourceSnapshot) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .files
+            .keys()
+            .chain(after.files.keys())
+            .filter(|path| self.files.get(*path) != after.files.get(*path))
+            .cloned()
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
@@ -0,0 +1,165 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{calculate_file_hash, find_git_root};
+
+/// Listing of the libraries the interpreter is linked against, written into the run directory
+pub const LIBRARIES_FILE: &str = "libraries.txt";
+
+/// Directory in the run directory holding the environment descriptors gathered with `--lock-env`
+pub const ENVIRONMENT_DIR: &str = "environment";
+
+/// Package lists written into `environment/` from the output of `pip freeze` and `conda env export`
+pub const PACKAGE_LISTS: [&str; 2] = ["requirements.txt", "conda-environment.yaml"];
+
+/// Lockfiles of package managers, copied from the directory of the script or the closest
+/// parent directory up to the repository root that has them
+const LOCKFILES: [&str; 8] = [
+    "Cargo.lock",
+    "Manifest.toml",
+    "package-lock.json",
+    "yarn.lock",
+    "poetry.lock",
+    "uv.lock",
+    "Pipfile.lock",
+    "renv.lock",
+];
+
+/// Parts of library names that mark numerical libraries, whose version changes results
+const NUMERICAL_LIBRARIES: [&str; 8] = ["blas", "lapack", "mkl", "atlas", "fftw", "gfortran", "cusolver", "rocsolver"];
+
+/// What fastsave records about the runtime of a script beyond its interpreter path:
+///
+/// ```yaml
+/// provenance:
+///   hash_interpreter: true
+///   libraries: true
+///   lock_env: true
+/// ```
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProvenanceConfig {
+    /// Record the SHA-256 of the interpreter executable, like `--hash-interpreter`
+    pub hash_interpreter: bool,
+    /// Record the shared libraries of the interpreter, like `--record-libraries`
+    pub libraries: bool,
+    /// Gather the package lists and lockfiles of the environment, like `--lock-env`
+    pub lock_env: bool,
+}
+
+/// A package list or lockfile archived in `environment/`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnvironmentFile {
+    /// Path in the run directory, like `environment/requirements.txt`
+    pub file: String,
+    /// Command that produced it, or the path it was copied from
+    pub source: String,
+    pub sha256: String,
+}
+
+/// Lists the shared libraries `program` is linked against, with `ldd`, or `otool -L` on macOS
+pub fn linked_libraries(program: &Path) -> Result<String, Box<dyn Error>> {
+    let mut cmd = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("otool");
+        cmd.arg("-L");
+        cmd
+    } else {
+        Command::new("ldd")
+    };
+    let output = cmd.arg(program).output().map_err(|e| format!("Failed to list the libraries of {}: {}", program.display(), e))?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(if output.stderr.is_empty() { &output.stdout } else { &output.stderr }).trim().to_string();
+        return Err(format!("Failed to list the libraries of {}: {}", program.display(), reason).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Paths of the numerical libraries in a listing of `linked_libraries`. Symlinks are resolved,
+/// since the file they point to usually carries the version, like `libopenblasp-r0.3.21.so`.
+pub fn numerical_libraries(listing: &str) -> Vec<String> {
+    let mut libraries: Vec<String> = listing
+        .lines()
+        .filter_map(|line| {
+            // `libname.so => /path/libname.so (0x...)` from ldd, `/path/libname.dylib (compatibility ...)` from otool
+            let path = line.split_once("=>").map_or(line, |(_, path)| path).trim();
+            let path = path.split(" (").next()?.trim();
+            let name = Path::new(path).file_name()?.to_string_lossy().to_lowercase();
+            NUMERICAL_LIBRARIES.iter().any(|library| name.contains(library)).then(|| {
+                std::fs::canonicalize(path).map_or_else(|_| path.to_string(), |real| real.to_string_lossy().into_owned())
+            })
+        })
+        .collect();
+    libraries.sort();
+    libraries.dedup();
+    libraries
+}
+
+/// Output of a command that lists the installed packages, `None` if it can't be run
+fn package_list(program: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new(program).args(args).output();
+    match output {
+        Ok(output) if output.status.success() => Some(output.stdout),
+        Ok(output) => {
+            tracing::warn!("{} {} failed: {}", program.display(), args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Failed to run {}: {}", program.display(), e);
+            None
+        }
+    }
+}
+
+/// The directory with the lockfiles of the project: the first directory from the script's up to
+/// the repository root that has one
+fn lockfile_dir(script_path: &Path) -> Option<PathBuf> {
+    let script_dir = std::path::absolute(script_path.parent()?).ok()?;
+    let root = find_git_root(&script_dir).unwrap_or_else(|| script_dir.clone());
+    script_dir
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&root))
+        .find(|dir| LOCKFILES.iter().any(|lockfile| dir.join(lockfile).is_file()))
+        .map(Path::to_path_buf)
+}
+
+/// Gathers whichever environment descriptors apply into `environment/` of the run directory:
+/// `pip freeze` of a Python interpreter, `conda env export` of an active conda environment and
+/// the lockfiles of the project. Descriptors that can't be gathered are skipped with a warning.
+pub fn lock_environment(run_dir: &Path, script_path: &Path, interpreter: Option<&Path>) -> Result<Vec<EnvironmentFile>, Box<dyn Error>> {
+    let dir = run_dir.join(ENVIRONMENT_DIR);
+    let mut files = Vec::new();
+    let mut add = |name: &str, content: &[u8], source: String| -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        fs::write(&path, content)?;
+        files.push(EnvironmentFile { file: format!("{}/{}", ENVIRONMENT_DIR, name), source, sha256: calculate_file_hash(&path)? });
+        Ok(())
+    };
+
+    let is_python = |program: &Path| program.file_name().is_some_and(|name| name.to_string_lossy().starts_with("python"));
+    if let Some(python) = interpreter.filter(|program| is_python(program)) {
+        let args = ["-m", "pip", "freeze"];
+        if let Some(packages) = package_list(python, &args) {
+            add(PACKAGE_LISTS[0], &packages, format!("{} {}", python.display(), args.join(" ")))?;
+        }
+    }
+    if std::env::var_os("CONDA_PREFIX").is_some() {
+        let args = ["env", "export"];
+        if let Some(environment) = package_list(Path::new("conda"), &args) {
+            add(PACKAGE_LISTS[1], &environment, format!("conda {}", args.join(" ")))?;
+        }
+    }
+    if let Some(project_dir) = lockfile_dir(script_path) {
+        for lockfile in LOCKFILES {
+            let path = project_dir.join(lockfile);
+            if path.is_file() {
+                add(lockfile, &fs::read(&path)?, path.to_string_lossy().into_owned())?;
+            }
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+use rusqlite::{params, types::ValueRef, Connection};
+
+use crate::archive::find_runs;
+use crate::get_script_basename;
+use crate::table::Table;
+
+#[derive(Args)]
+pub struct QueryArgs {
+    /// SQL statement to run against the `runs` table
+    pub sql: String,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+
+    /// Print the result as CSV instead of a table
+    #[arg(long = "csv")]
+    pub csv: bool,
+}
+
+const SCHEMA: &str = "CREATE TABLE runs (
+    id TEXT NOT NULL,
+    run_dir TEXT NOT NULL,
+    script TEXT NOT NULL,
+    script_path TEXT NOT NULL,
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    exit_code INTEGER NOT NULL,
+    message TEXT,
+    command TEXT NOT NULL,
+    git_branch TEXT,
+    git_commit TEXT,
+    git_dirty INTEGER,
+    stdout TEXT NOT NULL,
+    stderr TEXT NOT NULL,
+    output_bytes INTEGER
+);
+CREATE TABLE metadata (
+    run_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);";
+
+/// Loads every manifest in the archive into an in-memory SQLite database
+pub fn open_runs_db(archive_dir: &Path) -> Result<Connection, Box<dyn Error>> {
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(SCHEMA)?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO runs VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+    )?;
+    let mut insert_metadata = conn.prepare("INSERT INTO metadata VALUES (?1, ?2, ?3)")?;
+    for run in find_runs(archive_dir)? {
+        let result = &run.result;
+        let git = result.git_info.as_ref();
+        insert.execute(params![
+            run.id(),
+            run.run_dir.to_string_lossy(),
+            get_script_basename(&result.script_path),
+            result.script_path,
+            result.start_time.to_rfc3339(),
+            result.end_time.to_rfc3339(),
+            result.duration_ms as i64,
+            result.exit_code,
+            result.message,
+            result.command_line,
+            git.map(|g| g.branch.as_str()),
+            git.map(|g| g.commit_hash.as_str()),
+            git.map(|g| g.is_dirty),
+            result.stdout,
+            result.stderr,
+            result.total_output_bytes.map(|bytes| bytes as i64),
+        ])?;
+        for (key, value) in &result.metadata {
+            insert_metadata.execute(params![run.id(), key, value])?;
+        }
+    }
+    drop(insert);
+    drop(insert_metadata);
+
+    Ok(conn)
+}
+
+pub fn query_runs(archive_dir: &Path, sql: &str) -> Result<Table, Box<dyn Error>> {
+    let conn = open_runs_db(archive_dir)?;
+    let mut stmt = conn.prepare(sql)?;
+    let headers = stmt.column_names().iter().map(|name| name.to_string()).collect();
+    let column_count = stmt.column_count();
+
+    let mut table = Table::new(headers);
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut cells = Vec::with_capacity(column_count);
+        for i in 0..column_count {
+            let cell = match row.get_ref(i)? {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(n) => n.to_string(),
+                ValueRef::Real(x) => x.to_string(),
+                ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+            };
+            cells.push(cell);
+        }
+        table.rows.push(cells);
+    }
+
+    Ok(table)
+}
+
+pub fn run_query(args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    let table = query_runs(Path::new(&args.archive_dir), &args.sql)?;
+    if args.csv {
+        print!("{}", table.to_csv());
+    } else {
+        print!("{}", table.to_text());
+    }
+    Ok(())
+}
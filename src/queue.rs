@@ -0,0 +1,307 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{load_manifest, manifest_path, run_base_dir, run_id};
+use crate::batch::{run_job, JobOutcome, JobStatus};
+use crate::started::host_name;
+use crate::{create_run_dir, Cli, FastsaveConfig};
+
+/// Directory of the job queue inside the archive
+pub const QUEUE_DIR: &str = ".queue";
+
+const PENDING_DIR: &str = "pending";
+const RUNNING_DIR: &str = "running";
+const DONE_DIR: &str = "done";
+
+#[derive(Args)]
+pub struct SubmitArgs {
+    /// Path to the script to execute
+    pub script: String,
+
+    /// Additional arguments to pass to the script
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub script_args: Vec<String>,
+
+    /// Optional message to include in the results
+    #[arg(short = 'm', long = "message")]
+    pub message: Option<String>,
+
+    /// Jobs of higher priority run before all pending jobs of lower priority
+    #[arg(short = 'p', long = "priority", value_enum, default_value_t = Priority::Normal)]
+    pub priority: Priority,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// Prefix of the spec file names, so sorting them by name gives the scheduling order
+    fn rank(self) -> u8 {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// Number of jobs to run at the same time
+    #[arg(short = 'w', long = "workers", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub workers: u32,
+
+    /// Seconds between checks of an empty queue
+    #[arg(long = "poll", value_name = "SECONDS", default_value_t = 5)]
+    pub poll_secs: u64,
+
+    /// Exit once the queue is empty instead of waiting for more jobs
+    #[arg(long = "once")]
+    pub once: bool,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Archive runs in this directory on the local machine and copy each finished run into the archive
+    #[arg(long = "local-archive", value_name = "DIR")]
+    pub local_archive: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// A job waiting in the queue
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueuedJob {
+    /// Absolute, so the daemon finds the script wherever it was started
+    pub script: String,
+    pub script_args: Vec<String>,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    pub submitted_at: DateTime<Utc>,
+}
+
+/// A job the daemon has run, kept in the `done` directory of the queue
+#[derive(Serialize, Deserialize)]
+pub struct FinishedJob {
+    #[serde(flatten)]
+    pub job: QueuedJob,
+    /// Machine whose daemon ran the job
+    pub host: String,
+    pub finished_at: DateTime<Utc>,
+    /// Id of the run in the daemon's `--local-archive`, if it used one
+    pub local_id: Option<String>,
+    #[serde(flatten)]
+    pub outcome: JobOutcome,
+}
+
+fn queue_dir(archive_dir: &Path, state: &str) -> PathBuf {
+    archive_dir.join(QUEUE_DIR).join(state)
+}
+
+/// Adds a job to the queue; returns the path of its spec file
+pub fn submit(archive_dir: &Path, job: &QueuedJob) -> Result<PathBuf, Box<dyn Error>> {
+    let pending = queue_dir(archive_dir, PENDING_DIR);
+    fs::create_dir_all(&pending)?;
+
+    // Names sort by priority, then in submission order; create_new fails on a name another submit just took
+    let stamp = job.submitted_at.format("%Y%m%dT%H%M%S%.6f");
+    let mut number = 1;
+    let (name, mut file) = loop {
+        let name = format!("{}-{}_{}.yaml", job.priority.rank(), stamp, number);
+        if pending.join(&name).exists() {
+            number += 1;
+            continue;
+        }
+        match fs::OpenOptions::new().write(true).create_new(true).open(pending.join(format!(".{}", name))) {
+            Ok(file) => break (name, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => number += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    // Written under a hidden name and renamed, so the daemon never picks up a half-written spec
+    file.write_all(serde_yaml::to_string(job)?.as_bytes())?;
+    let path = pending.join(&name);
+    fs::rename(pending.join(format!(".{}", name)), &path)?;
+    Ok(path)
+}
+
+/// Pending spec files in the order they are run: by priority, then oldest first
+pub fn pending_jobs(archive_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let pending = queue_dir(archive_dir, PENDING_DIR);
+    if !pending.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut specs = Vec::new();
+    for entry in fs::read_dir(&pending)? {
+        let path = entry?.path();
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if !hidden && path.extension().is_some_and(|ext| ext == "yaml") {
+            specs.push(path);
+        }
+    }
+    specs.sort();
+    Ok(specs)
+}
+
+/// Moves the next pending job to `running`. The rename fails for all but one
+/// worker or daemon, so every job is claimed exactly once.
+fn claim_next(archive_dir: &Path) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let running = queue_dir(archive_dir, RUNNING_DIR);
+    fs::create_dir_all(&running)?;
+    for spec in pending_jobs(archive_dir)? {
+        let claimed = running.join(spec.file_name().ok_or("spec without file name")?);
+        if fs::rename(&spec, &claimed).is_ok() {
+            return Ok(Some(claimed));
+        }
+    }
+    Ok(None)
+}
+
+/// Copies a finished run into `base_dir` of the archive under the next free run directory,
+/// since runs archived on other machines may have taken its id; returns the new run directory
+pub fn sync_run(run_dir: &Path, base_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let result = load_manifest(&manifest_path(run_dir))?;
+    let target = PathBuf::from(create_run_dir(&base_dir.to_string_lossy(), &result.script_path)?);
+    copy_dir(run_dir, &target)?;
+    Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a claimed job and moves its spec, with the outcome, to `done`
+fn run_claimed(args: &DaemonArgs, claimed: &Path) -> Result<FinishedJob, Box<dyn Error>> {
+    let job: QueuedJob = serde_yaml::from_str(&fs::read_to_string(claimed)?)?;
+    tracing::info!("Fastsave daemon runs {}", job.script);
+    let archive_dir = Path::new(&args.archive_dir);
+    let cli = Cli {
+        archive_dir: args.local_archive.clone().unwrap_or_else(|| args.archive_dir.clone()),
+        message: job.message.clone(),
+        config_path: args.config_path.clone(),
+        script_args: job.script_args.clone(),
+        priority: Some(job.priority),
+        ..Cli::new(job.script.clone())
+    };
+    let mut outcome = run_job(&cli);
+    let mut local_id = None;
+    if let Some((local_archive, id)) = args.local_archive.as_ref().zip(outcome.id.clone()) {
+        let layout = FastsaveConfig::load_with_config_path(args.config_path.as_deref()).layout;
+        let run_dir = run_base_dir(Path::new(local_archive), &layout).join(&id);
+        match sync_run(&run_dir, &run_base_dir(archive_dir, &layout)) {
+            Ok(synced) => outcome.id = Some(run_id(&synced)),
+            Err(e) => {
+                outcome.id = None;
+                outcome.status = JobStatus::Error;
+                outcome.error = Some(format!("Failed to copy run {} into {}: {}", id, args.archive_dir, e));
+            }
+        }
+        local_id = Some(id);
+    }
+    if let Some(error) = &outcome.error {
+        tracing::warn!("Job {} failed: {}", job.script, error);
+    }
+
+    let finished = FinishedJob { job, host: host_name(), finished_at: Utc::now(), local_id, outcome };
+    let done = queue_dir(archive_dir, DONE_DIR);
+    fs::create_dir_all(&done)?;
+    fs::write(done.join(claimed.file_name().ok_or("spec without file name")?), serde_yaml::to_string(&finished)?)?;
+    fs::remove_file(claimed)?;
+    Ok(finished)
+}
+
+/// Runs queued jobs with `args.workers` at a time, until the queue is empty with `--once`
+/// or forever otherwise; returns the number of jobs run
+pub fn run_queue(args: &DaemonArgs) -> Result<usize, Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let count = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..args.workers {
+            scope.spawn(|| loop {
+                match claim_next(archive_dir) {
+                    Ok(Some(claimed)) => {
+                        if let Err(e) = run_claimed(args, &claimed) {
+                            tracing::error!("Failed to run queued job {}: {}", claimed.display(), e);
+                        }
+                        count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(None) if args.once => break,
+                    Ok(None) => std::thread::sleep(Duration::from_secs(args.poll_secs)),
+                    Err(e) => {
+                        tracing::error!("Failed to read the queue: {}", e);
+                        if args.once {
+                            break;
+                        }
+                        std::thread::sleep(Duration::from_secs(args.poll_secs));
+                    }
+                }
+            });
+        }
+    });
+    Ok(count.into_inner())
+}
+
+pub fn run_submit(args: &SubmitArgs) -> Result<(), Box<dyn Error>> {
+    let script = fs::canonicalize(&args.script).map_err(|e| format!("Script not found: {}: {}", args.script, e))?;
+    let job = QueuedJob {
+        script: script.to_string_lossy().into_owned(),
+        script_args: args.script_args.clone(),
+        message: args.message.clone(),
+        priority: args.priority,
+        submitted_at: Utc::now(),
+    };
+    let path = submit(Path::new(&args.archive_dir), &job)?;
+    println!("Submitted {} as {}", args.script, path.display());
+    Ok(())
+}
+
+pub fn run_daemon(args: &DaemonArgs) -> Result<(), Box<dyn Error>> {
+    tracing::info!(
+        "Fastsave daemon watches {} with {} workers",
+        queue_dir(Path::new(&args.archive_dir), PENDING_DIR).display(),
+        args.workers
+    );
+    let count = run_queue(args)?;
+    println!("Fastsave daemon ran {} jobs", count);
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::error::Error;
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::ExecutionResult;
+
+/// Replacement for redacted secrets
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Values shorter than this aren't redacted when detected by name, e.g. `USE_TOKEN=1`
+const MIN_DETECTED_LENGTH: usize = 8;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Regex patterns for secrets; if a pattern has capture groups, only those are redacted,
+    /// e.g. `password=(\S+)`
+    pub patterns: Vec<String>,
+    /// Environment variables whose values are redacted
+    pub env_vars: Vec<String>,
+    /// Also redact values of environment variables named like secrets, e.g. `GITHUB_TOKEN`
+    pub detect_env: bool,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        RedactionConfig { patterns: Vec::new(), env_vars: Vec::new(), detect_env: true }
+    }
+}
+
+pub struct Redactor {
+    patterns: Vec<Regex>,
+    values: Vec<String>,
+}
+
+fn looks_secret(name: &str) -> bool {
+    let name = name.to_uppercase();
+    ["TOKEN", "SECRET", "PASSWORD", "PASSWD", "API_KEY", "APIKEY", "ACCESS_KEY", "PRIVATE_KEY", "CREDENTIAL"]
+        .iter()
+        .any(|marker| name.contains(marker))
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionConfig) -> Result<Self, Box<dyn Error>> {
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| format!("Invalid redaction pattern '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut values: Vec<String> = config.env_vars.iter().filter_map(|name| std::env::var(name).ok()).collect();
+        if config.detect_env {
+            values.extend(
+                std::env::vars()
+                    .filter(|(name, value)| looks_secret(name) && value.len() >= MIN_DETECTED_LENGTH)
+                    .map(|(_, value)| value),
+            );
+        }
+        values.retain(|value| !value.is_empty());
+        // Longest first, so a secret containing another one is replaced as a whole
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.dedup();
+
+        Ok(Redactor { patterns, values })
+    }
+
+    /// Replaces all secrets in `text` with `[REDACTED]`
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for value in &self.values {
+            if text.contains(value.as_str()) {
+                text = text.replace(value.as_str(), REDACTED);
+            }
+        }
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, |captures: &Captures| redact_groups(captures)).into_owned();
+        }
+        text
+    }
+
+    /// Redacts the captured output of the script and its hooks, and the script arguments
+    pub fn redact_result(&self, result: &mut ExecutionResult) {
+        result.stdout = self.redact(&result.stdout);
+        result.stderr = self.redact(&result.stderr);
+        for hook in &mut result.hooks {
+            hook.stdout = self.redact(&hook.stdout);
+            hook.stderr = self.redact(&hook.stderr);
+        }
+        // Secrets given as script arguments
+        if let Some(command) = &mut result.command {
+            command.args = command.args.iter().map(|arg| self.redact(arg)).collect();
+            result.command_line = command.command_line();
+        }
+    }
+}
+
+/// The whole match with each participating capture group replaced, or the whole match without groups
+fn redact_groups(captures: &Captures) -> String {
+    let whole = captures.get(0).expect("group 0 always matches");
+    if captures.len() == 1 {
+        return REDACTED.to_string();
+    }
+
+    let mut redacted = String::new();
+    let mut position = whole.start();
+    for group in captures.iter().skip(1).flatten() {
+        // Nested groups lie within one already redacted
+        if group.start() < position {
+            continue;
+        }
+        redacted.push_str(&whole.as_str()[position - whole.start()..group.start() - whole.start()]);
+        redacted.push_str(REDACTED);
+        position = group.end();
+    }
+    redacted.push_str(&whole.as_str()[position - whole.start()..]);
+    redacted
+}
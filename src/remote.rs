@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::resolve_run;
+use crate::logs::{STDERR_LIVE, STDOUT_LIVE};
+use crate::started::STARTED_FILE;
+use crate::FastsaveConfig;
+
+/// Progress of an upload, kept in the run directory until the upload completes
+pub const UPLOAD_STATE_FILE: &str = ".upload.yaml";
+
+#[derive(Args)]
+pub struct UploadArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Continue an interrupted upload, appending to the partly uploaded file instead of starting it over
+    #[arg(long = "resume")]
+    pub resume: bool,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// A second archive off the machine, like a mounted network share or object store,
+/// that every run is uploaded to once it is archived:
+///
+/// ```yaml
+/// remote:
+///   dir: /mnt/backup/fastsave
+///   live_upload: true
+///   poll_secs: 10
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Directory the runs are uploaded to, with the same layout as the archive
+    pub dir: Option<String>,
+    /// Upload output files while the script runs, like `--live-upload`
+    pub live_upload: bool,
+    /// How often the run directory is checked for completed files during a live upload
+    pub poll_secs: u64,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig { dir: None, live_upload: false, poll_secs: 10 }
+    }
+}
+
+impl RemoteConfig {
+    /// Where `run_dir` of `archive_dir` is uploaded to, if a remote is configured
+    pub fn run_dir(&self, archive_dir: &Path, run_dir: &Path) -> Option<PathBuf> {
+        let dir = PathBuf::from(shellexpand::tilde(self.dir.as_deref()?).as_ref());
+        let relative = run_dir.strip_prefix(archive_dir).ok().filter(|relative| !relative.as_os_str().is_empty());
+        Some(dir.join(relative.or(run_dir.file_name().map(Path::new))?))
+    }
+}
+
+/// Size and modification time, which tell whether a file changed since it was last seen
+type FileState = (u64, SystemTime);
+
+/// The file being uploaded when an upload was interrupted
+#[derive(Serialize, Deserialize)]
+struct UploadState {
+    target: PathBuf,
+    file: PathBuf,
+    /// Size and modification time of the file when its upload started; if either changed,
+    /// the partial copy is of an older version and the upload starts over
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Files below `dir` by their path relative to it, without the upload state
+fn file_states(dir: &Path, relative: &Path, states: &mut BTreeMap<PathBuf, FileState>) -> io::Result<()> {
+    for entry in fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            file_states(dir, &path, states)?;
+        } else if file_type.is_file() && path != Path::new(UPLOAD_STATE_FILE) {
+            let metadata = entry.metadata()?;
+            states.insert(path, (metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn partial_path(target: &Path) -> PathBuf {
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Copies a file under a temporary `.part` name first, so an interrupted upload never
+/// leaves a truncated file that looks complete. With `resume`, an existing `.part` copy
+/// is continued where it stopped.
+fn upload_file(source: &Path, target: &Path, resume: bool) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = partial_path(target);
+    let mut input = File::open(source)?;
+    let uploaded = fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+    let mut output = if resume && uploaded <= input.metadata()?.len() {
+        input.seek(SeekFrom::Start(uploaded))?;
+        OpenOptions::new().append(true).open(&partial)?
+    } else {
+        File::create(&partial)?
+    };
+    io::copy(&mut input, &mut output)?;
+    output.sync_all()?;
+    fs::rename(&partial, target)
+}
+
+/// Uploads the output files of a running script once they stop changing
+pub struct LiveUpload {
+    run_dir: PathBuf,
+    target: PathBuf,
+    /// Files at the previous poll
+    previous: Mutex<BTreeMap<PathBuf, FileState>>,
+    /// Files as they were uploaded
+    uploaded: Mutex<BTreeMap<PathBuf, FileState>>,
+}
+
+impl LiveUpload {
+    pub fn new(run_dir: &Path, target: PathBuf) -> LiveUpload {
+        LiveUpload { run_dir: run_dir.to_path_buf(), target, previous: Mutex::default(), uploaded: Mutex::default() }
+    }
+
+    /// Uploads the files unchanged since the previous poll. A file the script is still
+    /// writing changes between polls and waits for the next one.
+    pub fn poll(&self) {
+        let mut current = BTreeMap::new();
+        if let Err(e) = file_states(&self.run_dir, Path::new(""), &mut current) {
+            tracing::warn!("Failed to look for completed files: {}", e);
+            return;
+        }
+        for own_file in [STARTED_FILE, STDOUT_LIVE, STDERR_LIVE] {
+            current.remove(Path::new(own_file));
+        }
+        let mut previous = self.previous.lock().unwrap_or_else(|e| e.into_inner());
+        let mut uploaded = self.uploaded.lock().unwrap_or_else(|e| e.into_inner());
+        for (file, state) in &current {
+            if previous.get(file) != Some(state) || uploaded.get(file) == Some(state) {
+                continue;
+            }
+            match upload_file(&self.run_dir.join(file), &self.target.join(file), false) {
+                Ok(()) => {
+                    uploaded.insert(file.clone(), *state);
+                }
+                Err(e) => tracing::warn!("Failed to upload {}: {}", file.display(), e),
+            }
+        }
+        *previous = current;
+    }
+}
+
+/// Makes `target` a copy of the finished run: uploads the files that are missing or differ
+/// in size or are newer than their copy, and removes files the run no longer has, like
+/// the start record. Returns the number of uploaded files.
+///
+/// The file being uploaded is recorded in the run directory until the upload completes.
+/// With `resume`, an upload to the same target that was interrupted continues that file
+/// from its partial copy instead of starting it over.
+pub fn upload_run(run_dir: &Path, target: &Path, resume: bool) -> Result<usize, Box<dyn Error>> {
+    let state_path = run_dir.join(UPLOAD_STATE_FILE);
+    let interrupted: Option<UploadState> = match fs::read_to_string(&state_path) {
+        Ok(state) if resume => Some(serde_yaml::from_str(&state).map_err(|e| format!("Failed to read {}: {}", state_path.display(), e))?),
+        _ => None,
+    };
+
+    let mut local = BTreeMap::new();
+    file_states(run_dir, Path::new(""), &mut local)?;
+    let mut remote = BTreeMap::new();
+    if target.is_dir() {
+        file_states(target, Path::new(""), &mut remote)?;
+    }
+
+    let mut uploaded = 0;
+    for (file, &(size, modified)) in &local {
+        let current = remote.get(file).is_some_and(|&(remote_size, remote_modified)| remote_size == size && remote_modified >= modified);
+        if current {
+            continue;
+        }
+        let state = UploadState { target: target.to_path_buf(), file: file.clone(), size, modified };
+        let continued = interrupted.as_ref().is_some_and(|interrupted| {
+            (&interrupted.target, &interrupted.file, interrupted.size, interrupted.modified) == (&state.target, &state.file, size, modified)
+        });
+        fs::write(&state_path, serde_yaml::to_string(&state)?)?;
+        upload_file(&run_dir.join(file), &target.join(file), continued)
+            .map_err(|e| format!("Failed to upload {}: {}", file.display(), e))?;
+        uploaded += 1;
+    }
+    // The partial copy of a resumed file is gone already
+    for file in remote.keys().filter(|file| !local.contains_key(*file)) {
+        remove_if_exists(&target.join(file))?;
+    }
+    remove_if_exists(&state_path)?;
+    Ok(uploaded)
+}
+
+pub fn run_upload(args: &UploadArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let run_dir = resolve_run(archive_dir, &args.run)?;
+    let remote = FastsaveConfig::load_with_config_path(args.config_path.as_deref()).remote;
+    let target = remote
+        .run_dir(archive_dir, &run_dir)
+        .ok_or("No remote configured, set `dir` in the `remote` section of the config file")?;
+    let uploaded = upload_run(&run_dir, &target, args.resume)?;
+    println!("Uploaded {} files to {}", uploaded, target.display());
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::archive::run_id;
+use crate::command::{shell_quote, ScriptCommand};
+use crate::junit::escape_xml as escape;
+use crate::notify::run_succeeded;
+use crate::table::format_size;
+use crate::ExecutionResult;
+
+/// File name of the HTML report written into the run directory
+pub const HTML_REPORT_FILE: &str = "report.html";
+
+/// File name of the markdown summary written into the run directory
+pub const MARKDOWN_SUMMARY_FILE: &str = "SUMMARY.md";
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ReportFormat {
+    /// Self-contained `report.html` in the run directory
+    Html,
+    /// Concise `SUMMARY.md` for issues and lab notebooks
+    Markdown,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:1em 2em;max-width:70em}\
+table{border-collapse:collapse}td,th{border-bottom:1px solid #ddd;padding:.2em .6em;text-align:left}\
+pre{background:#f6f6f6;padding:.5em;overflow:auto;max-height:40em}.failed{color:#b00}.passed{color:#070}\
+figure{display:inline-block;margin:.5em}img{max-width:30em;border:1px solid #ddd}";
+
+/// Renders a run as a standalone HTML page; outputs are linked relative to the run directory
+pub fn html_report(id: &str, result: &ExecutionResult) -> String {
+    let id = escape(id);
+    let (status_class, status) = if run_succeeded(result) { ("passed", "succeeded") } else { ("failed", "failed") };
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>",
+        id, STYLE
+    );
+    let _ = writeln!(out, "<h1>{}</h1>\n<p class=\"{}\">Run {} with exit code {}</p>", id, status_class, status, result.exit_code);
+
+    out.push_str("<h2>Summary</h2>\n<table>\n");
+    let mut row = |name: &str, value: &str| {
+        let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value));
+    };
+    row("Script", &result.script_path);
+    row("Command", &result.command_line);
+    row("Started", &result.start_time.to_rfc3339());
+    row("Duration", &format!("{:.3}s", result.duration_ms as f64 / 1000.0));
+    if let Some(experiment) = &result.experiment {
+        row("Experiment", experiment);
+    }
+    if let Some(message) = &result.message {
+        row("Message", message);
+    }
+    if let Some(git) = &result.git_info {
+        row("Branch", &git.branch);
+        row("Commit", &git.commit_hash);
+        row("Remote", &git.remote_url);
+        if git.is_dirty {
+            row("Uncommitted", &git.uncommitted_changes.join("\n"));
+        }
+    }
+    out.push_str("</table>\n");
+
+    let script_args = result.command.as_ref().map(ScriptCommand::script_args).unwrap_or_default();
+    if !script_args.is_empty() || !result.metadata.is_empty() {
+        out.push_str("<h2>Parameters</h2>\n<table>\n");
+        if !script_args.is_empty() {
+            let arguments = script_args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ");
+            let _ = writeln!(out, "<tr><th>Arguments</th><td><code>{}</code></td></tr>", escape(&arguments));
+        }
+        for (key, value) in &result.metadata {
+            let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", escape(key), escape(value));
+        }
+        out.push_str("</table>\n");
+    }
+
+    if !result.metrics.is_empty() {
+        out.push_str("<h2>Metrics</h2>\n<table>\n");
+        for (name, value) in &result.metrics {
+            let value = serde_yaml::to_string(value).unwrap_or_default();
+            let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value.trim_end()));
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str("<h2>Files</h2>\n<table><tr><th>File</th><th>Size</th><th>Kind</th><th>SHA-256</th></tr>\n");
+    for (file, hash) in &result.file_hashes {
+        let details = result.files.get(file);
+        let _ = writeln!(
+            out,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+            escape(file),
+            escape(file),
+            details.map(|f| format_size(f.size_bytes)).unwrap_or_default(),
+            details.and_then(|f| f.kind.as_deref()).unwrap_or(""),
+            escape(hash)
+        );
+    }
+    out.push_str("</table>\n");
+
+    let images: Vec<_> = result
+        .files
+        .iter()
+        .filter(|(_, f)| matches!(f.kind.as_deref(), Some("png" | "jpeg" | "gif")))
+        .collect();
+    if !images.is_empty() {
+        out.push_str("<h2>Images</h2>\n");
+        for (file, details) in images {
+            let source = details.thumbnail.as_deref().unwrap_or(file);
+            let _ = writeln!(
+                out,
+                "<figure><a href=\"{}\"><img src=\"{}\" alt=\"{}\"></a><figcaption>{}</figcaption></figure>",
+                escape(file),
+                escape(source),
+                escape(file),
+                escape(file)
+            );
+        }
+    }
+
+    out.push_str("<h2>Logs</h2>\n");
+    for (stream, output) in [("stdout", &result.stdout), ("stderr", &result.stderr)] {
+        let open = if stream == "stderr" && !run_succeeded(result) { " open" } else { "" };
+        let _ = writeln!(out, "<details{}><summary>{}</summary><pre>{}</pre></details>", open, stream, escape(output));
+    }
+    for hook in &result.hooks {
+        let _ = writeln!(
+            out,
+            "<details><summary>{} hook <code>{}</code> (exit code {})</summary><pre>{}{}</pre></details>",
+            escape(&hook.stage),
+            escape(&hook.command),
+            hook.exit_code,
+            escape(&hook.stdout),
+            escape(&hook.stderr)
+        );
+    }
+
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// Makes text safe for a markdown table cell
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Renders the essentials of a run as markdown, for pasting into issues and lab notebooks
+pub fn markdown_summary(id: &str, result: &ExecutionResult) -> String {
+    let status = if run_succeeded(result) { "succeeded" } else { "failed" };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "## {}\n", id);
+    if let Some(message) = &result.message {
+        let _ = writeln!(out, "{}\n", message);
+    }
+    out.push_str("| | |\n|---|---|\n");
+    let _ = writeln!(out, "| Status | {} (exit code {}) |", status, result.exit_code);
+    let _ = writeln!(out, "| Command | `{}` |", table_cell(&result.command_line));
+    if let Some(git) = &result.git_info {
+        let dirty = if git.is_dirty { ", uncommitted changes" } else { "" };
+        let _ = writeln!(out, "| Commit | `{}` ({}{}) |", git.commit_hash, table_cell(&git.branch), dirty);
+    }
+    let _ = writeln!(out, "| Started | {} |", result.start_time.format("%Y-%m-%d %H:%M:%S UTC"));
+    let _ = writeln!(out, "| Duration | {:.3}s |", result.duration_ms as f64 / 1000.0);
+    if let Some(experiment) = &result.experiment {
+        let _ = writeln!(out, "| Experiment | {} |", table_cell(experiment));
+    }
+    for (key, value) in &result.metadata {
+        let _ = writeln!(out, "| {} | {} |", table_cell(key), table_cell(value));
+    }
+
+    if !result.metrics.is_empty() {
+        out.push_str("\n**Metrics**\n\n| Metric | Value |\n|---|---|\n");
+        for (name, value) in &result.metrics {
+            let value = serde_yaml::to_string(value).unwrap_or_default();
+            let _ = writeln!(out, "| {} | {} |", table_cell(name), table_cell(value.trim_end()));
+        }
+    }
+
+    if !result.file_hashes.is_empty() {
+        out.push_str("\n**Outputs**\n\n| File | Size | SHA-256 |\n|---|---|---|\n");
+        for (file, hash) in &result.file_hashes {
+            let size = result.files.get(file).map(|f| format_size(f.size_bytes)).unwrap_or_default();
+            let _ = writeln!(out, "| {} | {} | `{}` |", table_cell(file), size, &hash[..hash.len().min(12)]);
+        }
+    }
+    out
+}
+
+/// Writes the requested reports into the run directory
+pub fn write_reports(run_dir: &Path, result: &ExecutionResult, formats: &[ReportFormat]) -> std::io::Result<()> {
+    for format in formats {
+        match format {
+            ReportFormat::Html => fs::write(run_dir.join(HTML_REPORT_FILE), html_report(&run_id(run_dir), result))?,
+            ReportFormat::Markdown => {
+                fs::write(run_dir.join(MARKDOWN_SUMMARY_FILE), markdown_summary(&run_id(run_dir), result))?
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, ArchivedRun, STDIN_FILE};
+use crate::baseline::{compare_outputs, OutputComparison};
+use crate::check::{parse_metric_tolerance, values_match};
+use crate::metrics::METRICS_FILES;
+use crate::{run_script, Cli, ExecutionResult};
+
+#[derive(Args)]
+pub struct ReproArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Absolute tolerance for a metric, e.g. loss=1e-6 (repeatable); other metrics must match exactly
+    #[arg(long = "tolerance", value_name = "NAME=TOL", value_parser = parse_metric_tolerance)]
+    pub tolerances: Vec<(String, f64)>,
+
+    /// Override the config file path
+    #[arg(short = 'c', long = "config")]
+    pub config_path: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub struct ReproReport {
+    pub original: String,
+    pub reproduction: String,
+    /// Exit codes of the original run and the reproduction
+    pub exit_codes: (i32, i32),
+    pub differences: OutputComparison,
+    /// Differences in what the two runs ran with, which may explain deviating outputs
+    pub notes: Vec<String>,
+}
+
+impl ReproReport {
+    /// Same exit code, and the outputs and metrics of the original were produced again
+    pub fn is_reproduced(&self) -> bool {
+        self.exit_codes.0 == self.exit_codes.1 && !self.differences.has_deviations()
+    }
+}
+
+/// Options that rerun the archived run the way it was run: with the recorded interpreter
+/// and arguments, which include a seed given on the command line, the archived input and
+/// parameters file, and the same provenance records, so the environments can be compared afterwards
+pub fn repro_cli(run: &ArchivedRun, archive_dir: &Path) -> Result<Cli, Box<dyn Error>> {
+    let result = &run.result;
+    let command = result.command.as_ref().ok_or("The manifest doesn't record the arguments of the run, it was written by an older version")?;
+    let stdin = run.run_dir.join(STDIN_FILE);
+    Ok(Cli {
+        // The recorded arguments already include the defaults from the config
+        script_args: command.script_args().to_vec(),
+        no_default_args: true,
+        interpreter: Some(command.interpreter()).filter(|_| !result.exec),
+        message: Some(format!("Reproduction of {}", run.id())),
+        metadata: result.metadata.clone().into_iter().collect(),
+        experiment: result.experiment.clone(),
+        parents: vec![run.id()],
+        stdin: stdin.is_file().then(|| stdin.to_string_lossy().into_owned()),
+        params: result.params.as_ref().map(|params| run.run_dir.join(&params.file).to_string_lossy().into_owned()),
+        hash_interpreter: result.interpreter_hash.is_some(),
+        record_libraries: !result.numerical_libraries.is_empty(),
+        lock_env: !result.environment_files.is_empty(),
+        trace_inputs: result.traced_inputs.is_some(),
+        exec: result.exec_command(),
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+        ..Cli::new(result.script_location().to_string_lossy())
+    })
+}
+
+/// Differences between the code, interpreter and environment of two runs
+fn environment_notes(original: &ExecutionResult, reproduction: &ExecutionResult) -> Vec<String> {
+    let mut notes = Vec::new();
+    let commit = |result: &ExecutionResult| {
+        result.git_info.as_ref().map(|git| format!("{}{}", &git.commit_hash[..git.commit_hash.len().min(8)], if git.is_dirty { " (dirty)" } else { "" }))
+    };
+    if commit(original) != commit(reproduction) {
+        let describe = |commit: Option<String>| commit.unwrap_or_else(|| "no repository".to_string());
+        notes.push(format!("code: {} -> {}", describe(commit(original)), describe(commit(reproduction))));
+    } else if original.git_info.as_ref().is_some_and(|git| git.is_dirty) {
+        notes.push("code: both runs had uncommitted changes, which may differ".to_string());
+    }
+    if original.interpreter_path != reproduction.interpreter_path {
+        notes.push(format!(
+            "interpreter: {} -> {}",
+            original.interpreter_path.as_deref().unwrap_or("-"),
+            reproduction.interpreter_path.as_deref().unwrap_or("-")
+        ));
+    } else if original.interpreter_hash != reproduction.interpreter_hash {
+        notes.push("interpreter: same path, different executable".to_string());
+    }
+    if original.numerical_libraries != reproduction.numerical_libraries {
+        notes.push("numerical libraries differ".to_string());
+    }
+    let environment: BTreeMap<&str, &str> =
+        reproduction.environment_files.iter().map(|file| (file.file.as_str(), file.sha256.as_str())).collect();
+    for file in &original.environment_files {
+        match environment.get(file.file.as_str()) {
+            Some(hash) if *hash == file.sha256 => {}
+            Some(_) => notes.push(format!("environment: {} changed", file.file)),
+            None => notes.push(format!("environment: {} missing", file.file)),
+        }
+    }
+    let read: BTreeMap<&str, Option<&str>> =
+        reproduction.traced_inputs.iter().flatten().map(|input| (input.path.as_str(), input.sha256.as_deref())).collect();
+    for input in original.traced_inputs.iter().flatten() {
+        match read.get(input.path.as_str()) {
+            Some(hash) if *hash == input.sha256.as_deref() => {}
+            Some(_) => notes.push(format!("undeclared input: {} changed", input.path)),
+            None => notes.push(format!("undeclared input: {} not read", input.path)),
+        }
+    }
+    if original.host != reproduction.host {
+        notes.push(format!("host: {} -> {}", original.host.as_deref().unwrap_or("-"), reproduction.host.as_deref().unwrap_or("-")));
+    }
+    notes
+}
+
+/// Reruns an archived run and compares the outputs and metrics of the reproduction with the
+/// original. Metrics must match exactly unless they have a tolerance.
+pub fn reproduce(archive_dir: &Path, run_dir: &Path, tolerances: &[(String, f64)], config_path: Option<&str>) -> Result<ReproReport, Box<dyn Error>> {
+    let original = ArchivedRun { run_dir: run_dir.to_path_buf(), result: load_manifest(&manifest_path(run_dir))? };
+    let cli = Cli { config_path: config_path.map(str::to_string), ..repro_cli(&original, archive_dir)? };
+    let reproduction = run_script(&cli)?;
+
+    let mut differences = compare_outputs(&original.result, &reproduction.result);
+    // A metrics file is compared by its values, within their tolerances
+    differences.changed_files.retain(|file| !METRICS_FILES.contains(&file.as_str()));
+    let tolerances: BTreeMap<&str, f64> = tolerances.iter().map(|(name, tolerance)| (name.as_str(), *tolerance)).collect();
+    differences.changed_metrics.retain(|name| match (original.result.metrics.get(name), reproduction.result.metrics.get(name)) {
+        (Some(expected), Some(actual)) => !values_match(expected, actual, tolerances.get(name.as_str()).copied().unwrap_or(0.0)),
+        _ => true,
+    });
+
+    Ok(ReproReport {
+        original: original.id(),
+        reproduction: reproduction.id(),
+        exit_codes: (original.result.exit_code, reproduction.result.exit_code),
+        notes: environment_notes(&original.result, &reproduction.result),
+        differences,
+    })
+}
+
+pub fn run_repro(args: &ReproArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let run_dir = resolve_run(archive_dir, &args.run)?;
+    let report = reproduce(archive_dir, &run_dir, &args.tolerances, args.config_path.as_deref())?;
+    println!("Reran {} as {}", report.original, report.reproduction);
+
+    let (original_code, code) = report.exit_codes;
+    if original_code != code {
+        println!("  exit code: {} -> {}", original_code, code);
+    }
+    let differences = &report.differences;
+    for file in &differences.changed_files {
+        println!("  changed: {}", file);
+    }
+    for file in &differences.missing_files {
+        println!("  not produced again: {}", file);
+    }
+    for file in &differences.added_files {
+        println!("  new: {}", file);
+    }
+    for metric in &differences.changed_metrics {
+        println!("  metric changed: {}", metric);
+    }
+    if !report.notes.is_empty() {
+        println!("Differences in what the runs ran with:");
+        for note in &report.notes {
+            println!("  {}", note);
+        }
+    }
+
+    if report.is_reproduced() {
+        println!("Reproduced: all output files and metrics of the original were produced again");
+        Ok(())
+    } else {
+        Err(format!("{} was not reproduced", report.original).into())
+    }
+}
@@ -0,0 +1,126 @@
+use std::error::Error;
+
+use crate::archive::ArchivedRun;
+use crate::experiment::parse_experiment_name;
+use crate::observer::{NoObserver, RunObserver};
+use crate::report::ReportFormat;
+use crate::{run_script_with_observer, Cli};
+
+/// Builder for running a script from other programs without going through the command line.
+///
+/// ```no_run
+/// use fastsave::runner::Runner;
+///
+/// let run = Runner::new("train.py")
+///     .arg("--epochs")
+///     .arg("10")
+///     .archive_dir("runs")
+///     .message("baseline")
+///     .execute()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Options not set keep the defaults of the `fastsave` command.
+pub struct Runner<'a> {
+    cli: Cli,
+    observer: Option<&'a dyn RunObserver>,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(script: impl Into<String>) -> Self {
+        Runner { cli: Cli::new(script), observer: None }
+    }
+
+    /// Appends an argument passed to the script
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.cli.script_args.push(arg.into());
+        self
+    }
+
+    /// Appends arguments passed to the script
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli.script_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn archive_dir(mut self, archive_dir: impl Into<String>) -> Self {
+        self.cli.archive_dir = archive_dir.into();
+        self
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.cli.message = Some(message.into());
+        self
+    }
+
+    pub fn interpreter(mut self, interpreter: impl Into<String>) -> Self {
+        self.cli.interpreter = Some(interpreter.into());
+        self
+    }
+
+    /// Reads the configuration from this file instead of `fastsave.yaml`
+    pub fn config(mut self, config_path: impl Into<String>) -> Self {
+        self.cli.config_path = Some(config_path.into());
+        self
+    }
+
+    /// Attaches a metadata entry to the run
+    pub fn meta(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.cli.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Feeds this file to the script's stdin
+    pub fn stdin(mut self, path: impl Into<String>) -> Self {
+        self.cli.stdin = Some(path.into());
+        self
+    }
+
+    /// Writes a report of the run into its directory
+    pub fn report(mut self, format: ReportFormat) -> Self {
+        self.cli.report.push(format);
+        self
+    }
+
+    pub fn experiment(mut self, experiment: impl Into<String>) -> Self {
+        self.cli.experiment = Some(experiment.into());
+        self
+    }
+
+    /// Records a run whose outputs this run consumes, by id or path
+    pub fn parent(mut self, run: impl Into<String>) -> Self {
+        self.cli.parents.push(run.into());
+        self
+    }
+
+    /// Writes the outputs directly into the archive directory
+    pub fn no_subfolder(mut self) -> Self {
+        self.cli.no_subfolder = true;
+        self
+    }
+
+    /// Strips the home directory and user name from recorded paths
+    pub fn anonymize_paths(mut self) -> Self {
+        self.cli.anonymize_paths = true;
+        self
+    }
+
+    /// Reports output lines and completion of the run to `observer`
+    pub fn observer(mut self, observer: &'a dyn RunObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Runs the script and archives it like the `fastsave` command
+    pub fn execute(&self) -> Result<ArchivedRun, Box<dyn Error>> {
+        // The command line validates this while parsing
+        if let Some(experiment) = &self.cli.experiment {
+            parse_experiment_name(experiment)?;
+        }
+        run_script_with_observer(&self.cli, self.observer.unwrap_or(&NoObserver))
+    }
+}
@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// System directories mounted read-only so interpreters and their libraries are found
+const SYSTEM_DIRS: [&str; 7] = ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/opt"];
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Further paths mounted read-only, e.g. a virtual environment outside the system directories
+    pub read_only: Vec<String>,
+}
+
+/// What a sandboxed script could see, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SandboxSpec {
+    /// Program that set up the sandbox
+    pub tool: String,
+    /// Paths the script could read but not modify, besides the system directories
+    pub read_only: Vec<String>,
+    /// Paths the script could modify
+    pub read_write: Vec<String>,
+    /// Whether the script could reach the network
+    pub network: bool,
+    /// Where the script was started: the working directory of fastsave if it is mounted, else the script directory
+    pub working_dir: String,
+}
+
+fn absolute(path: &Path) -> Result<String, Box<dyn Error>> {
+    let canonical = fs::canonicalize(path).map_err(|e| format!("Failed to resolve {} for the sandbox: {}", path.display(), e))?;
+    Ok(canonical.to_string_lossy().into_owned())
+}
+
+impl SandboxSpec {
+    /// The script directory, the declared inputs and the configured paths are read-only,
+    /// only the output directory is writable
+    pub fn new(script_path: &str, output_dir: &str, inputs: &[&Path], config: &SandboxConfig) -> Result<SandboxSpec, Box<dyn Error>> {
+        let script_dir = Path::new(script_path).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut read_only = vec![absolute(script_dir)?];
+        for input in inputs {
+            read_only.push(absolute(input)?);
+        }
+        for path in &config.read_only {
+            read_only.push(absolute(Path::new(shellexpand::tilde(path).as_ref()))?);
+        }
+        read_only.dedup();
+        let read_write = vec![absolute(Path::new(output_dir))?];
+
+        let current_dir = absolute(Path::new("."))?;
+        let mounted = |dir: &String| Path::new(&current_dir).starts_with(dir);
+        let working_dir = if read_only.iter().chain(&read_write).any(mounted) { current_dir } else { read_only[0].clone() };
+        Ok(SandboxSpec { tool: "bwrap".to_string(), read_only, read_write, network: true, working_dir })
+    }
+
+    /// Arguments of bubblewrap that set up the sandbox, up to the command it runs
+    pub fn bwrap_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = ["--die-with-parent", "--unshare-all", "--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        if self.network {
+            args.push("--share-net".to_string());
+        }
+        for dir in SYSTEM_DIRS {
+            args.extend(["--ro-bind-try".to_string(), dir.to_string(), dir.to_string()]);
+        }
+        for path in &self.read_only {
+            args.extend(["--ro-bind".to_string(), path.clone(), path.clone()]);
+        }
+        for path in &self.read_write {
+            args.extend(["--bind".to_string(), path.clone(), path.clone()]);
+        }
+        args.extend(["--chdir".to_string(), self.working_dir.clone()]);
+        args.push("--".to_string());
+        args
+    }
+
+    /// Command running `program` inside the sandbox
+    pub fn command(&self, program: &str) -> Command {
+        let mut cmd = Command::new(&self.tool);
+        cmd.args(self.bwrap_args()).arg(program);
+        cmd
+    }
+}
+
+/// Command running `program` in a network namespace of its own, which has only a loopback
+/// interface. The user namespace maps the current user, so the script keeps its user id.
+pub fn unshare_network(program: &str) -> Command {
+    let mut cmd = Command::new("unshare");
+    cmd.args(["--net", "--map-current-user", "--"]).arg(program);
+    cmd
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dir_size;
+
+/// Subdirectory of the run directory that receives the kept scratch files
+pub const KEPT_SCRATCH_DIR: &str = "scratch";
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ScratchConfig {
+    /// Give every run a scratch directory, like `--scratch`
+    pub enabled: bool,
+    /// Where the scratch directories are created; defaults to the system temporary directory
+    pub dir: Option<String>,
+    /// Globs of scratch files copied into the run directory before the scratch directory is removed
+    pub keep: Vec<String>,
+}
+
+/// How a run used its scratch directory, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScratchUsage {
+    /// Largest size seen at a heartbeat or at the end of the run
+    pub peak_bytes: u64,
+    /// Scratch files copied into `scratch/` of the run directory
+    pub kept: Vec<String>,
+}
+
+/// Temporary directory of a single run, exported to the script as `TMPDIR` and `FASTSAVE_SCRATCH`
+pub struct ScratchDir {
+    pub path: PathBuf,
+    peak_bytes: AtomicU64,
+}
+
+impl ScratchDir {
+    pub fn create(config: &ScratchConfig, run_id: &str) -> Result<ScratchDir, Box<dyn Error>> {
+        let base = match &config.dir {
+            Some(dir) => PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            None => std::env::temp_dir(),
+        };
+        let path = base.join(format!("fastsave-{}-{}", run_id, std::process::id()));
+        fs::create_dir_all(&path).map_err(|e| format!("Failed to create scratch directory {}: {}", path.display(), e))?;
+        Ok(ScratchDir { path, peak_bytes: AtomicU64::new(0) })
+    }
+
+    /// Environment variables pointing the script and the libraries it uses at the scratch directory
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let path = self.path.to_string_lossy().into_owned();
+        ["TMPDIR", "TMP", "TEMP", "FASTSAVE_SCRATCH"].into_iter().map(|name| (name, path.clone())).collect()
+    }
+
+    /// Updates the peak size with the current size
+    pub fn sample(&self) {
+        match dir_size(&self.path) {
+            Ok(size) => {
+                self.peak_bytes.fetch_max(size, Ordering::Relaxed);
+            }
+            Err(e) => tracing::debug!("Failed to measure scratch directory: {}", e),
+        }
+    }
+
+    /// Copies the files matching `keep` into the run directory and removes the scratch directory
+    pub fn finish(self, run_dir: &Path, keep: &[String]) -> Result<ScratchUsage, Box<dyn Error>> {
+        self.sample();
+        let base = glob::Pattern::escape(&self.path.to_string_lossy());
+        let mut kept = Vec::new();
+        for pattern in keep {
+            let matches = glob::glob(&format!("{}/{}", base, pattern))
+                .map_err(|e| format!("Invalid scratch pattern '{}': {}", pattern, e))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for path in matches.into_iter().filter(|path| path.is_file()) {
+                let relative = path.strip_prefix(&self.path)?.to_path_buf();
+                let target = run_dir.join(KEPT_SCRATCH_DIR).join(&relative);
+                fs::create_dir_all(target.parent().ok_or("scratch file without directory")?)?;
+                fs::copy(&path, &target)?;
+                kept.push(relative.to_string_lossy().into_owned());
+            }
+        }
+        kept.sort();
+        kept.dedup();
+
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            tracing::warn!("Failed to remove scratch directory {}: {}", self.path.display(), e);
+        }
+        Ok(ScratchUsage { peak_bytes: self.peak_bytes.into_inner(), kept })
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use tantivy::collector::TopDocs;
+use tantivy::query::{AllQuery, QueryParser};
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexWriter, TantivyDocument, Term};
+
+use crate::archive::{find_runs, run_id};
+use crate::logs::full_output;
+use crate::table::Table;
+use crate::{get_script_basename, ExecutionResult};
+
+/// Directory of the full-text index inside the archive
+pub const INDEX_DIR: &str = ".index";
+
+const WRITER_MEMORY: usize = 50_000_000;
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Text to search for in messages, captured output and metadata
+    #[arg(long = "text")]
+    pub text: String,
+
+    /// Maximum number of runs to show
+    #[arg(long = "limit", default_value_t = 10)]
+    pub limit: usize,
+
+    /// Rebuild the index from the manifests before searching
+    #[arg(long = "rebuild")]
+    pub rebuild: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub struct SearchHit {
+    pub id: String,
+    pub script: String,
+    pub score: f32,
+}
+
+struct Fields {
+    id: Field,
+    script: Field,
+    message: Field,
+    stdout: Field,
+    stderr: Field,
+    metadata: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        id: builder.add_text_field("id", STRING | STORED),
+        script: builder.add_text_field("script", TEXT | STORED),
+        message: builder.add_text_field("message", TEXT),
+        stdout: builder.add_text_field("stdout", TEXT),
+        stderr: builder.add_text_field("stderr", TEXT),
+        metadata: builder.add_text_field("metadata", TEXT),
+    };
+    (builder.build(), fields)
+}
+
+fn index_path(archive_dir: &Path) -> PathBuf {
+    archive_dir.join(INDEX_DIR)
+}
+
+fn add_run(writer: &IndexWriter, fields: &Fields, run_dir: &Path, result: &ExecutionResult) -> Result<(), Box<dyn Error>> {
+    let id = run_id(run_dir);
+    // Compressed-log runs keep only the tail in the manifest
+    let (stdout, stderr) = full_output(run_dir, result);
+    let metadata: Vec<String> = result.metadata.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+    writer.delete_term(Term::from_field_text(fields.id, &id));
+    writer.add_document(doc!(
+        fields.id => id.as_str(),
+        fields.script => get_script_basename(&result.script_path),
+        fields.message => result.message.clone().unwrap_or_default(),
+        fields.stdout => stdout,
+        fields.stderr => stderr,
+        fields.metadata => metadata.join("\n"),
+    ))?;
+    Ok(())
+}
+
+/// Builds the index from all manifests in the archive, replacing an existing one
+pub fn rebuild_index(archive_dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let path = index_path(archive_dir);
+    if path.exists() {
+        std::fs::remove_dir_all(&path)?;
+    }
+    std::fs::create_dir_all(&path)?;
+
+    let (schema, fields) = schema();
+    let index = Index::create_in_dir(&path, schema)?;
+    let mut writer: IndexWriter = index.writer(WRITER_MEMORY)?;
+    let runs = find_runs(archive_dir)?;
+    for run in &runs {
+        add_run(&writer, &fields, &run.run_dir, &run.result)?;
+    }
+    writer.commit()?;
+    Ok(runs.len())
+}
+
+/// Adds or replaces a run in the index. Archives without an index are left
+/// alone; it is only built on the first search.
+pub fn update_index(archive_dir: &Path, run_dir: &Path, result: &ExecutionResult) -> Result<(), Box<dyn Error>> {
+    let path = index_path(archive_dir);
+    if !path.is_dir() {
+        return Ok(());
+    }
+
+    let (_, fields) = schema();
+    let index = Index::open_in_dir(&path)?;
+    let mut writer: IndexWriter = index.writer(WRITER_MEMORY)?;
+    add_run(&writer, &fields, run_dir, result)?;
+    writer.commit()?;
+    Ok(())
+}
+
+/// Ids of all runs in the index, or `None` if the archive has no index
+pub fn indexed_runs(archive_dir: &Path) -> Result<Option<BTreeSet<String>>, Box<dyn Error>> {
+    if !index_path(archive_dir).is_dir() {
+        return Ok(None);
+    }
+
+    let (_, fields) = schema();
+    let index = Index::open_in_dir(index_path(archive_dir))?;
+    let searcher = index.reader()?.searcher();
+    let limit = (searcher.num_docs() as usize).max(1);
+    let mut ids = BTreeSet::new();
+    for (_, address) in searcher.search(&AllQuery, &TopDocs::with_limit(limit))? {
+        let document: TantivyDocument = searcher.doc(address)?;
+        if let Some(id) = document.get_first(fields.id).and_then(|value| value.as_str()) {
+            ids.insert(id.to_string());
+        }
+    }
+    Ok(Some(ids))
+}
+
+/// Returns the best matching runs by relevance, building the index if needed
+pub fn search(archive_dir: &Path, text: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn Error>> {
+    if !index_path(archive_dir).is_dir() {
+        rebuild_index(archive_dir)?;
+    }
+
+    let (_, fields) = schema();
+    let index = Index::open_in_dir(index_path(archive_dir))?;
+    let searcher = index.reader()?.searcher();
+    let parser = QueryParser::for_index(
+        &index,
+        vec![fields.message, fields.stdout, fields.stderr, fields.metadata, fields.script],
+    );
+    let query = parser.parse_query(text)?;
+
+    let mut hits = Vec::new();
+    for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+        let document: TantivyDocument = searcher.doc(address)?;
+        let stored = |field| document.get_first(field).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+        hits.push(SearchHit { id: stored(fields.id), script: stored(fields.script), score });
+    }
+    Ok(hits)
+}
+
+pub fn run_search(args: &SearchArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    if args.rebuild {
+        let count = rebuild_index(archive_dir)?;
+        println!("Indexed {} runs", count);
+    }
+
+    let mut table = Table::new(vec!["ID".to_string(), "SCRIPT".to_string(), "SCORE".to_string()]);
+    for hit in search(archive_dir, &args.text, args.limit)? {
+        table.rows.push(vec![hit.id, hit.script, format!("{:.2}", hit.score)]);
+    }
+    print!("{}", table.to_text());
+    Ok(())
+}
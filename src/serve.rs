@@ -0,0 +1,553 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{find_runs, ArchivedRun};
+use crate::junit::escape_xml as escape;
+use crate::notify::run_succeeded;
+use crate::table::format_size;
+use crate::tarball;
+use crate::{get_script_basename, run_script, Cli, ExecutionResult};
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Port to listen on
+    #[arg(long = "port", default_value_t = 8080)]
+    pub port: u16,
+
+    /// Address to bind to; use 0.0.0.0 to make the dashboard reachable from other hosts
+    #[arg(long = "bind", default_value = "127.0.0.1")]
+    pub bind: String,
+
+    /// Allow starting runs with `POST /api/runs`; scripts have to be below the server's working directory
+    #[arg(long = "allow-run")]
+    pub allow_run: bool,
+
+    /// Token that requests to start runs send as `Authorization: Bearer <TOKEN>`; a random one is printed if not given
+    #[arg(long = "token", requires = "allow_run")]
+    pub token: Option<String>,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// Further headers, like the content security policy of run files
+    pub headers: Vec<(&'static str, &'static str)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn html(title: &str, content: &str) -> Self {
+        let page = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{} - fastsave</title><style>{}</style></head>\n<body><nav><a href=\"/\">Runs</a> <a href=\"/metrics\">Metrics</a></nav>\n{}</body></html>\n",
+            escape(title),
+            STYLE,
+            content
+        );
+        Response { status: 200, content_type: "text/html; charset=utf-8", headers: Vec::new(), body: page.into_bytes() }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response { status, content_type: "text/plain; charset=utf-8", headers: Vec::new(), body: message.as_bytes().to_vec() }
+    }
+
+    fn json<T: Serialize>(status: u16, value: &T) -> Result<Self, Box<dyn Error>> {
+        Ok(Response { status, content_type: "application/json", headers: Vec::new(), body: serde_json::to_vec_pretty(value)? })
+    }
+
+    fn json_error(status: u16, message: &str) -> Result<Self, Box<dyn Error>> {
+        Self::json(status, &serde_json::json!({ "error": message }))
+    }
+}
+
+/// Entry of `GET /api/runs`
+#[derive(Serialize)]
+pub struct RunSummary {
+    pub id: String,
+    pub script: String,
+    pub start_time: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    pub message: Option<String>,
+    pub experiment: Option<String>,
+}
+
+/// Response of `GET /api/runs/{id}` and `POST /api/runs`
+#[derive(Serialize)]
+struct RunDetails<'a> {
+    id: String,
+    #[serde(flatten)]
+    result: &'a ExecutionResult,
+}
+
+/// Body of `POST /api/runs`; the script runs with the interpreter of the server's config
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubmitRun {
+    pub script: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    pub experiment: Option<String>,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:1em 2em}nav a{margin-right:1em}\
+table{border-collapse:collapse}td,th{border-bottom:1px solid #ddd;padding:.2em .6em;text-align:left}\
+pre{background:#f6f6f6;padding:.5em;overflow:auto;max-height:40em}.failed{color:#b00}";
+
+/// Splits a query string into decoded key/value pairs
+pub fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let hex = |byte: u8| (byte as char).to_digit(16);
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    decoded.push((high * 16 + low) as u8);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Runs matching the `script`, `experiment` and `status` filters of the query
+fn filter_runs(runs: Vec<ArchivedRun>, query: &BTreeMap<String, String>) -> Vec<ArchivedRun> {
+    let wanted = |key: &str| query.get(key).map(String::as_str).filter(|value| !value.is_empty());
+    runs.into_iter()
+        .filter(|run| wanted("script").is_none_or(|script| get_script_basename(&run.result.script_path) == script))
+        .filter(|run| wanted("experiment").is_none_or(|experiment| run.result.experiment.as_deref() == Some(experiment)))
+        .filter(|run| match wanted("status") {
+            Some("failed") => !run_succeeded(&run.result),
+            Some("ok") => run_succeeded(&run.result),
+            _ => true,
+        })
+        .collect()
+}
+
+fn run_table(runs: &[ArchivedRun], query: &BTreeMap<String, String>) -> String {
+    let value = |key: &str| escape(query.get(key).map(String::as_str).unwrap_or_default());
+    let mut out = format!(
+        "<h1>Runs</h1>\n<form>Script <input name=\"script\" value=\"{}\"> Experiment <input name=\"experiment\" value=\"{}\"> \
+         Status <select name=\"status\"><option value=\"\">all</option><option{}>ok</option><option{}>failed</option></select> \
+         <button>Filter</button></form>\n",
+        value("script"),
+        value("experiment"),
+        if query.get("status").is_some_and(|s| s == "ok") { " selected" } else { "" },
+        if query.get("status").is_some_and(|s| s == "failed") { " selected" } else { "" },
+    );
+    out.push_str("<table><tr><th>ID</th><th>Script</th><th>Start</th><th>Duration</th><th>Exit</th><th>Message</th></tr>\n");
+    for run in runs.iter().rev() {
+        let result = &run.result;
+        let class = if run_succeeded(result) { "" } else { " class=\"failed\"" };
+        let _ = writeln!(
+            out,
+            "<tr{}><td><a href=\"/runs/{}\">{}</a></td><td>{}</td><td>{}</td><td>{:.1}s</td><td>{}</td><td>{}</td></tr>",
+            class,
+            escape(&run.id()),
+            escape(&run.id()),
+            escape(&get_script_basename(&result.script_path)),
+            result.start_time.format("%Y-%m-%d %H:%M:%S"),
+            result.duration_ms as f64 / 1000.0,
+            result.exit_code,
+            escape(result.message.as_deref().and_then(|m| m.lines().next()).unwrap_or("")),
+        );
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+fn run_page(run: &ArchivedRun) -> String {
+    let result = &run.result;
+    let id = escape(&run.id());
+    let mut out = format!("<h1>{}</h1>\n<table>\n", id);
+    let mut row = |name: &str, value: &str| {
+        let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", name, escape(value));
+    };
+    row("Script", &result.script_path);
+    if let Some(root) = &result.project_root {
+        row("Project", root);
+    }
+    row("Command", &result.command_line);
+    row("Started", &result.start_time.to_rfc3339());
+    row("Duration", &format!("{:.3}s", result.duration_ms as f64 / 1000.0));
+    row("Exit code", &result.exit_code.to_string());
+    if let Some(experiment) = &result.experiment {
+        row("Experiment", experiment);
+    }
+    if let Some(git) = &result.git_info {
+        let dirty = if git.is_dirty { " (dirty)" } else { "" };
+        row("Git", &format!("{} @ {}{}", git.branch, git.commit_hash, dirty));
+    }
+    if let Some(message) = &result.message {
+        row("Message", message);
+    }
+    for (key, value) in &result.metadata {
+        row(key, value);
+    }
+    for (name, value) in &result.metrics {
+        row(name, serde_yaml::to_string(value).unwrap_or_default().trim_end());
+    }
+    out.push_str("</table>\n<h2>Files</h2>\n<table><tr><th>File</th><th>Size</th><th>Kind</th><th>SHA-256</th></tr>\n");
+
+    for (file, hash) in &result.file_hashes {
+        let details = result.files.get(file);
+        let _ = writeln!(
+            out,
+            "<tr><td><a href=\"/runs/{}/files/{}\">{}</a></td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+            id,
+            escape(file),
+            escape(file),
+            details.map(|f| format_size(f.size_bytes)).unwrap_or_default(),
+            details.and_then(|f| f.kind.as_deref()).unwrap_or(""),
+            escape(hash)
+        );
+    }
+    out.push_str("</table>\n");
+
+    let thumbnails: Vec<_> = result.files.iter().filter_map(|(file, f)| Some((file, f.thumbnail.as_ref()?))).collect();
+    if !thumbnails.is_empty() {
+        out.push_str("<h2>Images</h2>\n<p>\n");
+        for (file, thumbnail) in thumbnails {
+            let _ = writeln!(
+                out,
+                "<a href=\"/runs/{}/files/{}\"><img src=\"/runs/{}/files/{}\" alt=\"{}\" title=\"{}\"></a>",
+                id,
+                escape(file),
+                id,
+                escape(thumbnail),
+                escape(file),
+                escape(file)
+            );
+        }
+        out.push_str("</p>\n");
+    }
+
+    for (stream, output) in [("stdout", &result.stdout), ("stderr", &result.stderr)] {
+        let open = if stream == "stderr" && !run_succeeded(result) { " open" } else { "" };
+        let _ = writeln!(
+            out,
+            "<details{}><summary>{} ({} lines)</summary><pre>{}</pre></details>",
+            open,
+            stream,
+            output.lines().count(),
+            escape(output)
+        );
+    }
+    out
+}
+
+/// Line chart of one metric over the runs, in start order
+fn metric_chart(name: &str, points: &[(String, f64)]) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 10.0;
+
+    let min = points.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+    let max = points.iter().map(|(_, y)| *y).fold(f64::NEG_INFINITY, f64::max);
+    let span = if max > min { max - min } else { 1.0 };
+    let step = if points.len() > 1 { (WIDTH - 2.0 * MARGIN) / (points.len() - 1) as f64 } else { 0.0 };
+    let coordinates: Vec<(f64, f64)> = points
+        .iter()
+        .enumerate()
+        .map(|(i, (_, y))| (MARGIN + i as f64 * step, HEIGHT - MARGIN - (y - min) / span * (HEIGHT - 2.0 * MARGIN)))
+        .collect();
+
+    let mut out = format!(
+        "<h2>{}</h2>\n<p>min {} / max {}</p>\n<svg width=\"{}\" height=\"{}\" style=\"border:1px solid #ddd\">\n<polyline fill=\"none\" stroke=\"#36c\" points=\"",
+        escape(name),
+        min,
+        max,
+        WIDTH,
+        HEIGHT
+    );
+    for (x, y) in &coordinates {
+        let _ = write!(out, "{:.1},{:.1} ", x, y);
+    }
+    out.push_str("\"/>\n");
+    for ((id, value), (x, y)) in points.iter().zip(&coordinates) {
+        let _ = writeln!(
+            out,
+            "<a href=\"/runs/{}\"><circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"#36c\"><title>{}: {}</title></circle></a>",
+            escape(id),
+            x,
+            y,
+            escape(id),
+            value
+        );
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn metrics_page(runs: &[ArchivedRun]) -> String {
+    let mut series: BTreeMap<&str, Vec<(String, f64)>> = BTreeMap::new();
+    for run in runs {
+        for (name, value) in &run.result.metrics {
+            if let Some(value) = value.as_f64() {
+                series.entry(name).or_default().push((run.id(), value));
+            }
+        }
+    }
+
+    let mut out = String::from("<h1>Metrics</h1>\n<form>Script <input name=\"script\"> <button>Filter</button></form>\n");
+    if series.is_empty() {
+        out.push_str("<p>No numeric metrics recorded.</p>\n");
+    }
+    for (name, points) in series {
+        out.push_str(&metric_chart(name, &points));
+    }
+    out
+}
+
+/// Value of the header `name` of a request, matched case-insensitively
+fn header<'a>(headers: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.trim())
+}
+
+/// Refuses requests to start a run that a web page could have sent on the user's behalf:
+/// browsers only send a JSON body across origins after a preflight, which is never answered,
+/// and tell their origin, which has to be the dashboard itself. A page served under a name
+/// rebound to the server's address passes both, but doesn't know the server's token.
+fn check_submit_headers(headers: &[(&str, &str)], token: &str) -> Result<(), &'static str> {
+    let bearer = header(headers, "Authorization").and_then(|value| value.strip_prefix("Bearer ")).map(str::trim);
+    if !bearer.is_some_and(|bearer| constant_time_eq(bearer.as_bytes(), token.as_bytes())) {
+        return Err("Runs are only started with the server's token in Authorization: Bearer <token>");
+    }
+    let content_type = header(headers, "Content-Type").and_then(|value| value.split(';').next()).map(str::trim);
+    if !content_type.is_some_and(|content_type| content_type.eq_ignore_ascii_case("application/json")) {
+        return Err("Runs are only started with Content-Type: application/json");
+    }
+    if let Some(origin) = header(headers, "Origin") {
+        let own = header(headers, "Host").map(|host| format!("http://{}", host));
+        if own.is_none_or(|own| !origin.eq_ignore_ascii_case(&own)) {
+            return Err("Runs can't be started from another origin");
+        }
+    }
+    Ok(())
+}
+
+/// Compares without stopping at the first difference, so response times don't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// A random token for starting runs, 128 bits in hex
+fn random_token() -> Result<String, Box<dyn Error>> {
+    use std::io::Read;
+
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .map_err(|e| format!("Failed to generate a token, pass one with --token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Resolves the script of a submitted run, which has to be below the server's working directory
+fn submitted_script(script: &str) -> Result<PathBuf, String> {
+    let outside = || format!("Script {} is not below the server's working directory", script);
+    if !Path::new(script).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(outside());
+    }
+    let cwd = std::env::current_dir().and_then(|cwd| cwd.canonicalize()).map_err(|e| e.to_string())?;
+    // Symbolic links can point anywhere
+    let resolved = Path::new(script).canonicalize().map_err(|e| format!("Script {} not found: {}", script, e))?;
+    if !resolved.starts_with(&cwd) {
+        return Err(outside());
+    }
+    Ok(PathBuf::from(script))
+}
+
+/// Serves a file of a run, refusing paths that would leave the run directory
+fn run_file(run: &ArchivedRun, file: &str) -> Response {
+    let relative = Path::new(file);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Response::error(400, "Invalid file path");
+    }
+    match tarball::read_run_file(&run.run_dir, file) {
+        Ok(body) => {
+            let content_type = match relative.extension().and_then(|ext| ext.to_str()) {
+                Some("png") => "image/png",
+                Some("jpg") | Some("jpeg") => "image/jpeg",
+                Some("svg") => "image/svg+xml",
+                Some("json") => "application/json",
+                Some("html") => "text/html; charset=utf-8",
+                _ => "text/plain; charset=utf-8",
+            };
+            // Outputs of scripts are untrusted; sandboxed, an HTML or SVG file can't act as the dashboard
+            let headers = match content_type {
+                "text/html; charset=utf-8" | "image/svg+xml" => vec![("Content-Security-Policy", "sandbox")],
+                _ => Vec::new(),
+            };
+            Response { status: 200, content_type, headers, body }
+        }
+        Err(_) => Response::error(404, "File not found"),
+    }
+}
+
+pub struct Dashboard {
+    pub archive_dir: PathBuf,
+    /// Token that `POST /api/runs` has to present to execute a script; `None` disables it
+    pub run_token: Option<String>,
+}
+
+impl Dashboard {
+    /// Answers a request; `url` is the request target including the query string
+    pub fn handle_request(&self, method: &str, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let query = parse_query(query);
+        let segments: Vec<String> = path.split('/').filter(|s| !s.is_empty()).map(percent_decode).collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        if let ["api", "runs"] = segments.as_slice() {
+            if method == "POST" {
+                return self.submit_run(headers, body);
+            }
+        }
+        if method != "GET" {
+            return Ok(Response::error(405, "Method not allowed"));
+        }
+
+        let runs = find_runs(&self.archive_dir)?;
+        let find = |id: &str| runs.iter().find(|run| run.id() == id);
+        let response = match segments.as_slice() {
+            [] => Response::html("Runs", &run_table(&filter_runs(runs, &query), &query)),
+            ["metrics"] => Response::html("Metrics", &metrics_page(&filter_runs(runs, &query))),
+            ["runs", id] => match find(id) {
+                Some(run) => Response::html(id, &run_page(run)),
+                None => Response::error(404, "Run not found"),
+            },
+            ["runs", id, "files", file @ ..] | ["api", "runs", id, "files", file @ ..] => match find(id) {
+                Some(run) => run_file(run, &file.join("/")),
+                None => Response::error(404, "Run not found"),
+            },
+            ["api", "runs"] => {
+                let summaries: Vec<RunSummary> = filter_runs(runs, &query)
+                    .into_iter()
+                    .map(|run| RunSummary {
+                        id: run.id(),
+                        script: get_script_basename(&run.result.script_path),
+                        start_time: run.result.start_time,
+                        duration_ms: run.result.duration_ms,
+                        exit_code: run.result.exit_code,
+                        message: run.result.message,
+                        experiment: run.result.experiment,
+                    })
+                    .collect();
+                Response::json(200, &summaries)?
+            }
+            ["api", "runs", id] => match find(id) {
+                Some(run) => Response::json(200, &RunDetails { id: run.id(), result: &run.result })?,
+                None => Response::json_error(404, "Run not found")?,
+            },
+            ["api", ..] => Response::json_error(404, "Not found")?,
+            _ => Response::error(404, "Not found"),
+        };
+        Ok(response)
+    }
+
+    /// Executes a script like a normal fastsave run and responds with its manifest once it completes
+    fn submit_run(&self, headers: &[(&str, &str)], body: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let Some(token) = &self.run_token else {
+            return Response::json_error(403, "Starting runs is disabled; restart the server with --allow-run");
+        };
+        if let Err(e) = check_submit_headers(headers, token) {
+            return Response::json_error(403, e);
+        }
+        let submit: SubmitRun = match serde_json::from_slice(body) {
+            Ok(submit) => submit,
+            Err(e) => return Response::json_error(400, &format!("Invalid request: {}", e)),
+        };
+        let script = match submitted_script(&submit.script) {
+            Ok(script) => script,
+            Err(e) => return Response::json_error(403, &e),
+        };
+
+        let cli = Cli {
+            archive_dir: self.archive_dir.to_string_lossy().into_owned(),
+            script_args: submit.args,
+            message: submit.message,
+            metadata: submit.metadata.into_iter().collect(),
+            experiment: submit.experiment,
+            ..Cli::new(script.to_string_lossy())
+        };
+        match run_script(&cli) {
+            Ok(run) => Response::json(201, &RunDetails { id: run.id(), result: &run.result }),
+            Err(e) => Response::json_error(400, &e.to_string()),
+        }
+    }
+}
+
+fn respond(dashboard: &Dashboard, mut request: tiny_http::Request) {
+    let mut body = Vec::new();
+    let headers: Vec<(String, String)> =
+        request.headers().iter().map(|header| (header.field.to_string(), header.value.to_string())).collect();
+    let headers: Vec<(&str, &str)> = headers.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    let response = match request.as_reader().read_to_end(&mut body) {
+        Ok(_) => dashboard
+            .handle_request(request.method().as_str(), request.url(), &headers, &body)
+            .unwrap_or_else(|e| Response::error(500, &e.to_string())),
+        Err(e) => Response::error(400, &e.to_string()),
+    };
+
+    let mut reply = tiny_http::Response::from_data(response.body).with_status_code(response.status);
+    for (field, value) in std::iter::once(("Content-Type", response.content_type)).chain(response.headers) {
+        reply.add_header(tiny_http::Header::from_bytes(field, value).expect("valid header"));
+    }
+    if let Err(e) = request.respond(reply) {
+        tracing::warn!("Failed to send response: {}", e);
+    }
+}
+
+pub fn run_serve(args: &ServeArgs) -> Result<(), Box<dyn Error>> {
+    let address = format!("{}:{}", args.bind, args.port);
+    let server = tiny_http::Server::http(&address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    println!("Serving {} on http://{}", args.archive_dir, address);
+    let run_token = match (&args.token, args.allow_run) {
+        (Some(token), _) if token.trim().is_empty() => return Err("The token must not be empty".into()),
+        (Some(token), _) => Some(token.clone()),
+        (None, true) => Some(random_token()?),
+        (None, false) => None,
+    };
+    if let (Some(token), None) = (&run_token, &args.token) {
+        println!("Runs are started with the header: Authorization: Bearer {}", token);
+    }
+
+    let dashboard = Arc::new(Dashboard { archive_dir: PathBuf::from(&args.archive_dir), run_token });
+    // One thread per request so a long run started through the API doesn't block the dashboard
+    for request in server.incoming_requests() {
+        let dashboard = Arc::clone(&dashboard);
+        std::thread::spawn(move || respond(&dashboard, request));
+    }
+    Ok(())
+}
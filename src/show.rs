@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, ArchivedRun, STDIN_FILE};
+use crate::logs;
+use crate::table::format_size;
+use crate::tarball;
+
+#[derive(Args)]
+pub struct ShowArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+
+    /// Print the full standard output of the run instead of its details
+    #[arg(long = "stdout", conflicts_with = "stderr")]
+    pub stdout: bool,
+
+    /// Print the full standard error of the run instead of its details
+    #[arg(long = "stderr")]
+    pub stderr: bool,
+}
+
+/// A run in the lineage of another run; `depth` 0 is the run itself
+pub struct LineageEntry {
+    pub depth: usize,
+    pub id: String,
+    pub found: bool,
+}
+
+/// Walks the parent links of a run, depth first
+pub fn lineage(archive_dir: &Path, run: &ArchivedRun) -> Vec<LineageEntry> {
+    let mut entries = vec![LineageEntry { depth: 0, id: run.id(), found: true }];
+    let mut visited = HashSet::from([run.id()]);
+    collect_parents(archive_dir, &run.result.parents, 1, &mut visited, &mut entries);
+    entries
+}
+
+fn collect_parents(
+    archive_dir: &Path,
+    parents: &[String],
+    depth: usize,
+    visited: &mut HashSet<String>,
+    entries: &mut Vec<LineageEntry>,
+) {
+    for parent in parents {
+        let manifest = resolve_run(archive_dir, parent)
+            .ok()
+            .and_then(|dir| load_manifest(&manifest_path(&dir)).ok());
+        entries.push(LineageEntry { depth, id: parent.clone(), found: manifest.is_some() });
+
+        // Guard against cycles from hand-edited manifests
+        if let Some(result) = manifest {
+            if visited.insert(parent.clone()) {
+                collect_parents(archive_dir, &result.parents, depth + 1, visited, entries);
+            }
+        }
+    }
+}
+
+pub fn format_run(archive_dir: &Path, run: &ArchivedRun) -> String {
+    let result = &run.result;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "Run:        {}", run.id());
+    let _ = writeln!(out, "Directory:  {}", run.run_dir.display());
+    let _ = writeln!(out, "Script:     {}", result.script_path);
+    if let Some(root) = &result.project_root {
+        let _ = writeln!(out, "Project:    {}", root);
+    }
+    let _ = writeln!(out, "Command:    {}", result.command_line);
+    if let Some(interpreter) = &result.interpreter_path {
+        let hash = result.interpreter_hash.as_ref().map(|hash| format!(" (sha256 {})", &hash[..hash.len().min(12)])).unwrap_or_default();
+        let _ = writeln!(out, "Program:    {}{}", interpreter, hash);
+    }
+    if let Some(host) = &result.host {
+        let _ = writeln!(out, "Host:       {}", host);
+    }
+    for gpu in result.system_info.iter().flat_map(|system| &system.gpus) {
+        let driver = gpu.driver_version.as_deref().map(|driver| format!(" (driver {})", driver)).unwrap_or_default();
+        let _ = writeln!(out, "GPU:        {}{}", gpu.name, driver);
+    }
+    if let Some(container) = result.system_info.as_ref().and_then(|system| system.container.as_ref()) {
+        let image = container.image.as_deref().map(|image| format!(" ({})", image)).unwrap_or_default();
+        let _ = writeln!(out, "Container:  {}{}", container.runtime, image);
+    }
+    if result.network_isolated {
+        let _ = writeln!(out, "Network:    isolated");
+    }
+    if let Some(pinning) = &result.cpu_pinning {
+        let threads = pinning.threads.map(|threads| format!("{} threads", threads)).unwrap_or_default();
+        match &pinning.cpus {
+            Some(cpus) => {
+                let _ = writeln!(out, "CPUs:       {} ({})", cpus, threads);
+            }
+            None => {
+                let _ = writeln!(out, "CPUs:       {}", threads);
+            }
+        }
+    }
+    if let Some(priority) = result.priority {
+        let _ = writeln!(out, "Priority:   {}", priority.as_str());
+    }
+    if let Some(hash) = &result.stdin_hash {
+        let _ = writeln!(out, "Stdin:      {}  {}", &hash[..hash.len().min(12)], STDIN_FILE);
+    }
+    if let Some(params) = &result.params {
+        let _ = writeln!(out, "Params:     {}  {} (from {})", &params.sha256[..params.sha256.len().min(12)], params.file, params.source);
+    }
+    let _ = writeln!(out, "Started:    {}", result.start_time.to_rfc3339());
+    let _ = writeln!(out, "Duration:   {:.3}s", result.duration_ms as f64 / 1000.0);
+    if let Some(cpu) = &result.cpu_time {
+        let _ = writeln!(
+            out,
+            "CPU time:   {:.2}s user, {:.2}s system (efficiency {:.2})",
+            cpu.user_seconds, cpu.system_seconds, cpu.parallel_efficiency
+        );
+    }
+    if let Some(bytes) = result.peak_memory_bytes {
+        let _ = writeln!(out, "Memory:     {} peak", format_size(bytes));
+    }
+    let _ = writeln!(out, "Exit code:  {}", result.exit_code);
+    if let Some(digest) = &result.run_digest {
+        let _ = writeln!(out, "Digest:     {}", digest);
+    }
+    if result.compressed_logs {
+        let _ = writeln!(out, "Logs:       {}, {}", logs::STDOUT_LOG, logs::STDERR_LOG);
+    }
+    match (result.killed_reason, result.exit_signal) {
+        (Some(reason), Some(signal)) => {
+            let _ = writeln!(out, "Killed:     {} (signal {})", reason.as_str(), signal);
+        }
+        (Some(reason), None) => {
+            let _ = writeln!(out, "Killed:     {}", reason.as_str());
+        }
+        _ => {}
+    }
+    if let Some(experiment) = &result.experiment {
+        let _ = writeln!(out, "Experiment: {}", experiment);
+    }
+    if result.pinned {
+        let _ = writeln!(out, "Pinned:     yes");
+    }
+    if !result.tags.is_empty() {
+        let _ = writeln!(out, "Tags:       {}", result.tags.join(", "));
+    }
+    if let Some(baseline) = &result.baseline {
+        let verdict = if baseline.regression { "regression" } else { "matches" };
+        let _ = writeln!(out, "Baseline:   {} ({})", baseline.baseline_run, verdict);
+    }
+    if let Some(validation) = result.validation.as_ref().filter(|v| !v.passed) {
+        let problems: Vec<String> = validation
+            .missing
+            .iter()
+            .map(|pattern| format!("{} missing", pattern))
+            .chain(validation.empty.iter().map(|file| format!("{} empty", file)))
+            .collect();
+        let _ = writeln!(out, "Outputs:    {}", problems.join(", "));
+    }
+    if let Some(modified) = result.modified_sources.as_ref().filter(|modified| !modified.is_empty()) {
+        let _ = writeln!(out, "Modified:   {}", modified.join(", "));
+    }
+    if let Some(traced) = result.traced_inputs.as_ref().filter(|traced| !traced.is_empty()) {
+        let paths: Vec<&str> = traced.iter().map(|input| input.path.as_str()).collect();
+        let _ = writeln!(out, "Read:       {}", paths.join(", "));
+    }
+    if let Some(git) = &result.git_info {
+        let dirty = if git.is_dirty { " (dirty)" } else { "" };
+        let _ = writeln!(out, "Git:        {} @ {}{}", git.branch, git.commit_hash, dirty);
+    }
+    if let Some(message) = &result.message {
+        let _ = writeln!(out, "Message:");
+        for line in message.lines() {
+            let _ = writeln!(out, "  {}", line);
+        }
+    }
+
+    if !result.metadata.is_empty() {
+        let _ = writeln!(out, "Metadata:");
+        for (key, value) in &result.metadata {
+            let _ = writeln!(out, "  {} = {}", key, value);
+        }
+    }
+
+    if !result.metrics.is_empty() {
+        let _ = writeln!(out, "Metrics:");
+        for (name, value) in &result.metrics {
+            let value = serde_yaml::to_string(value).unwrap_or_default();
+            let _ = writeln!(out, "  {}: {}", name, value.trim_end().replace('\n', " "));
+        }
+    }
+
+    match result.total_output_bytes {
+        Some(total) => {
+            let _ = writeln!(out, "Files:      {} in total", format_size(total));
+        }
+        None => {
+            let _ = writeln!(out, "Files:");
+        }
+    }
+    if tarball::is_packed(&run.run_dir) {
+        let _ = writeln!(out, "  (packed in {})", tarball::RUN_TAR_FILE);
+    }
+    for (file, hash) in &result.file_hashes {
+        let details = result.files.get(file);
+        let size = details.map(|f| format_size(f.size_bytes)).unwrap_or_default();
+        let kind = details.and_then(|f| f.kind.as_deref()).unwrap_or("");
+        let _ = writeln!(out, "  {}  {:>10}  {:<7}  {}", &hash[..hash.len().min(12)], size, kind, file);
+    }
+
+    for (file, preview) in &result.previews {
+        let _ = writeln!(out, "Preview of {}:", file);
+        for line in preview.lines() {
+            let _ = writeln!(out, "  {}", line);
+        }
+    }
+
+    if !result.parents.is_empty() {
+        let _ = writeln!(out, "Lineage:");
+        for entry in lineage(archive_dir, run) {
+            let indent = "   ".repeat(entry.depth.saturating_sub(1));
+            let marker = if entry.depth == 0 { "" } else { "└─ " };
+            let missing = if entry.found { "" } else { " (not found)" };
+            let _ = writeln!(out, "  {}{}{}{}", indent, marker, entry.id, missing);
+        }
+    }
+
+    out
+}
+
+pub fn run_show(args: &ShowArgs) -> Result<(), Box<dyn Error>> {
+    let archive_dir = Path::new(&args.archive_dir);
+    let run_dir = resolve_run(archive_dir, &args.run)?;
+    let result = load_manifest(&manifest_path(&run_dir))?;
+    if args.stdout || args.stderr {
+        let (stdout, stderr) = logs::full_output(&run_dir, &result);
+        print!("{}", if args.stdout { stdout } else { stderr });
+        return Ok(());
+    }
+    print!("{}", format_run(archive_dir, &ArchivedRun { run_dir, result }));
+    Ok(())
+}
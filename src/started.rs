@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::heartbeat::Heartbeat;
+use crate::{get_git_info, run_git_command, Cli, GitInfo};
+
+/// Preliminary record written when a run starts and removed once its manifest is saved
+pub const STARTED_FILE: &str = "started.yaml";
+
+/// Uncommitted changes to tracked files of the repository when the run started
+pub const GIT_PATCH_FILE: &str = "uncommitted.patch";
+
+/// What is known about a run before the script executes, so even a run that never completes leaves a record
+#[derive(Serialize, Deserialize)]
+pub struct RunStart {
+    pub script_path: String,
+    pub script_args: Vec<String>,
+    pub start_time: DateTime<Utc>,
+    pub message: Option<String>,
+    pub git_info: Option<GitInfo>,
+    /// Process id of fastsave, not of the script
+    pub pid: u32,
+    pub host: String,
+    /// Latest sign of life while the script runs
+    #[serde(default)]
+    pub heartbeat: Option<Heartbeat>,
+}
+
+/// Name of this machine, or an empty string if it can't be determined
+pub fn host_name() -> String {
+    let from_env = std::env::var(if cfg!(windows) { "COMPUTERNAME" } else { "HOSTNAME" }).ok();
+    let from_proc = || fs::read_to_string("/proc/sys/kernel/hostname").ok();
+    let from_command = || {
+        Command::new("hostname")
+            .output()
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    from_env
+        .or_else(from_proc)
+        .or_else(from_command)
+        .map(|host| host.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Writes `started.yaml` into the run directory, and `uncommitted.patch` if the repository is dirty
+pub fn write_started(run_dir: &Path, cli: &Cli) -> Result<RunStart, Box<dyn Error>> {
+    let start = RunStart {
+        script_path: cli.script.clone(),
+        script_args: cli.script_args.clone(),
+        start_time: Utc::now(),
+        message: cli.message.clone(),
+        git_info: get_git_info(&cli.script),
+        pid: std::process::id(),
+        host: host_name(),
+        heartbeat: None,
+    };
+    fs::write(run_dir.join(STARTED_FILE), serde_yaml::to_string(&start)?)?;
+    if let Some(git) = start.git_info.as_ref().filter(|git| git.is_dirty) {
+        let patch = run_git_command(Path::new(&git.repo_root), &["diff", "HEAD"])?;
+        if !patch.is_empty() {
+            fs::write(run_dir.join(GIT_PATCH_FILE), patch + "\n")?;
+        }
+    }
+    Ok(start)
+}
+
+pub fn load_started(run_dir: &Path) -> Result<RunStart, Box<dyn Error>> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(run_dir.join(STARTED_FILE))?)?)
+}
+
+/// Removes the preliminary record once the final manifest is saved
+pub fn remove_started(run_dir: &Path) {
+    if let Err(e) = fs::remove_file(run_dir.join(STARTED_FILE)) {
+        tracing::debug!("Failed to remove {}: {}", STARTED_FILE, e);
+    }
+}
@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+
+use crate::archive::{find_runs, parse_since};
+use crate::get_script_basename;
+use crate::notify::run_succeeded;
+use crate::table::Table;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// Show the duration of the runs of this script over time (name with or without extension)
+    #[arg(long = "trend", value_name = "SCRIPT")]
+    pub trend: String,
+
+    /// Numeric metric to show next to the duration (repeatable)
+    #[arg(long = "metric", value_name = "NAME")]
+    pub metrics: Vec<String>,
+
+    /// Only include runs started after this time (e.g. 30d, 2025-01-17)
+    #[arg(long = "since", value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Modified z-score above which a value is flagged as an outlier
+    #[arg(long = "threshold", default_value_t = 3.5)]
+    pub threshold: f64,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// One successful run in the trend of a script
+pub struct TrendPoint {
+    pub run_id: String,
+    pub start_time: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// Values of the requested metrics, in the requested order
+    pub metrics: Vec<Option<f64>>,
+    /// `duration` and the names of the metrics whose value is an outlier
+    pub outliers: Vec<String>,
+}
+
+/// Flags values whose modified z-score, based on the median and the median absolute deviation, exceeds `threshold`.
+/// Unlike the mean and standard deviation, these are not dragged along by the outliers themselves.
+pub fn find_outliers(values: &[f64], threshold: f64) -> Vec<bool> {
+    let Some(center) = median(values.to_vec()) else {
+        return Vec::new();
+    };
+    let deviation = median(values.iter().map(|value| (value - center).abs()).collect()).unwrap_or(0.0);
+    values
+        .iter()
+        .map(|value| {
+            if deviation == 0.0 {
+                // More than half the values are identical, anything else stands out
+                *value != center
+            } else {
+                0.6745 * (value - center).abs() / deviation > threshold
+            }
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] })
+}
+
+/// Successful runs of `script` ordered by start time, with outliers in duration and each metric flagged
+pub fn duration_trend(
+    archive_dir: &Path,
+    script: &str,
+    metrics: &[String],
+    since: Option<DateTime<Utc>>,
+    threshold: f64,
+) -> Result<Vec<TrendPoint>, Box<dyn Error>> {
+    let script = get_script_basename(script);
+    let mut points: Vec<TrendPoint> = find_runs(archive_dir)?
+        .into_iter()
+        .filter(|run| get_script_basename(&run.result.script_path) == script)
+        .filter(|run| since.is_none_or(|since| run.result.start_time >= since))
+        // Failed runs end early and would only show up as outliers
+        .filter(|run| run_succeeded(&run.result))
+        .map(|run| TrendPoint {
+            run_id: run.id(),
+            start_time: run.result.start_time,
+            duration_ms: run.result.duration_ms,
+            metrics: metrics.iter().map(|name| run.result.metrics.get(name).and_then(|value| value.as_f64())).collect(),
+            outliers: Vec::new(),
+        })
+        .collect();
+
+    let durations: Vec<f64> = points.iter().map(|point| point.duration_ms as f64).collect();
+    for (point, outlier) in points.iter_mut().zip(find_outliers(&durations, threshold)) {
+        if outlier {
+            point.outliers.push("duration".to_string());
+        }
+    }
+    for (i, name) in metrics.iter().enumerate() {
+        let (indices, values): (Vec<usize>, Vec<f64>) =
+            points.iter().enumerate().filter_map(|(j, point)| Some((j, point.metrics[i]?))).unzip();
+        for (j, outlier) in indices.into_iter().zip(find_outliers(&values, threshold)) {
+            if outlier {
+                points[j].outliers.push(name.clone());
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+/// One bar per value, scaled between the minimum and maximum
+pub fn sparkline(values: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > min { (value - min) / (max - min) * (BARS.len() - 1) as f64 } else { 0.0 };
+            BARS[level.round() as usize]
+        })
+        .collect()
+}
+
+pub fn run_stats(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    let points = duration_trend(Path::new(&args.archive_dir), &args.trend, &args.metrics, args.since, args.threshold)?;
+    if points.is_empty() {
+        println!("No successful runs of {} found", args.trend);
+        return Ok(());
+    }
+
+    let mut headers = vec!["ID".to_string(), "START".to_string(), "DURATION".to_string()];
+    headers.extend(args.metrics.iter().map(|name| name.to_uppercase()));
+    headers.push("OUTLIER".to_string());
+    let mut table = Table::new(headers);
+    for point in &points {
+        let mut row = vec![
+            point.run_id.clone(),
+            point.start_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            format!("{:.1}s", point.duration_ms as f64 / 1000.0),
+        ];
+        row.extend(point.metrics.iter().map(|value| value.map(|v| v.to_string()).unwrap_or_default()));
+        row.push(point.outliers.join(", "));
+        table.rows.push(row);
+    }
+    print!("{}", table.to_text());
+
+    let durations: Vec<f64> = points.iter().map(|point| point.duration_ms as f64).collect();
+    println!("\nDuration {}", sparkline(&durations));
+    for (i, name) in args.metrics.iter().enumerate() {
+        let values: Vec<f64> = points.iter().filter_map(|point| point.metrics[i]).collect();
+        if !values.is_empty() {
+            println!("{} {}", name, sparkline(&values));
+        }
+    }
+
+    let outliers = points.iter().filter(|point| !point.outliers.is_empty()).count();
+    if outliers > 0 {
+        println!("{} of {} runs flagged as outliers", outliers, points.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use clap::Args;
+
+use crate::archive::{parse_duration, run_id};
+use crate::get_script_basename;
+use crate::notify::format_duration;
+use crate::started::{load_started, RunStart, STARTED_FILE};
+use crate::table::{format_size, Table};
+
+/// Heartbeats missed before a run counts as lost
+const MISSED_HEARTBEATS: i32 = 3;
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Report a run as hung when it neither wrote output nor used CPU time for this long
+    #[arg(long = "hung-after", value_name = "DURATION", default_value = "10m", value_parser = parse_duration)]
+    pub hung_after: Duration,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// What the heartbeat says about a run that hasn't finished
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunState {
+    /// Making progress, however slowly
+    Running,
+    /// Heartbeats arrive, but the script neither writes output nor uses CPU time
+    Hung,
+    /// Heartbeats stopped, so fastsave itself was killed or the machine went down
+    Lost,
+    /// No heartbeat was written yet, or heartbeats are disabled
+    Unknown,
+}
+
+impl RunState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunState::Running => "running",
+            RunState::Hung => "hung",
+            RunState::Lost => "lost",
+            RunState::Unknown => "unknown",
+        }
+    }
+}
+
+/// Classifies a run from its start record at time `now`
+pub fn run_state(start: &RunStart, now: DateTime<Utc>, hung_after: Duration) -> RunState {
+    let Some(heartbeat) = &start.heartbeat else {
+        return RunState::Unknown;
+    };
+    let interval = Duration::seconds(heartbeat.interval_secs as i64);
+    if now - heartbeat.time > interval * MISSED_HEARTBEATS {
+        RunState::Lost
+    } else if now - heartbeat.last_progress > hung_after {
+        RunState::Hung
+    } else {
+        RunState::Running
+    }
+}
+
+/// Run directories below the archive that have a start record but no manifest yet, oldest first
+pub fn unfinished_runs(archive_dir: &Path) -> Result<Vec<(PathBuf, RunStart)>, Box<dyn Error>> {
+    let mut runs = Vec::new();
+    if archive_dir.is_dir() {
+        collect_unfinished(archive_dir, &mut runs)?;
+    }
+    runs.sort_by_key(|(_, start)| start.start_time);
+    Ok(runs)
+}
+
+fn collect_unfinished(dir: &Path, runs: &mut Vec<(PathBuf, RunStart)>) -> Result<(), Box<dyn Error>> {
+    if dir.join(STARTED_FILE).is_file() {
+        match load_started(dir) {
+            Ok(start) => runs.push((dir.to_path_buf(), start)),
+            Err(e) => tracing::debug!("Skipping unreadable start record in {}: {}", dir.display(), e),
+        }
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_unfinished(&entry.path(), runs)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn run_status(args: &StatusArgs) -> Result<(), Box<dyn Error>> {
+    let runs = unfinished_runs(Path::new(&args.archive_dir))?;
+    if runs.is_empty() {
+        println!("No runs in progress");
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let age = |time: DateTime<Utc>| format_duration((now - time).num_milliseconds().max(0) as u64);
+    let headers = ["ID", "SCRIPT", "HOST", "ELAPSED", "LAST SEEN", "LINES", "CPU", "MEMORY", "STATE"];
+    let mut table = Table::new(headers.iter().map(|h| h.to_string()).collect());
+    for (run_dir, start) in &runs {
+        let heartbeat = start.heartbeat.as_ref();
+        table.rows.push(vec![
+            run_id(run_dir),
+            get_script_basename(&start.script_path),
+            start.host.clone(),
+            age(start.start_time),
+            heartbeat.map(|heartbeat| format!("{} ago", age(heartbeat.time))).unwrap_or_default(),
+            heartbeat.map(|heartbeat| (heartbeat.stdout_lines + heartbeat.stderr_lines).to_string()).unwrap_or_default(),
+            heartbeat.and_then(|heartbeat| heartbeat.cpu_seconds).map(|cpu| format!("{:.1}s", cpu)).unwrap_or_default(),
+            heartbeat.and_then(|heartbeat| heartbeat.memory_bytes).map(format_size).unwrap_or_default(),
+            run_state(start, now, args.hung_after).as_str().to_string(),
+        ]);
+    }
+    print!("{}", table.to_text());
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// The machine a run was executed on, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SystemInfo {
+    /// Operating system, like `linux` or `macos`
+    pub os: String,
+    /// CPU architecture, like `x86_64` or `aarch64`
+    pub arch: String,
+    #[serde(default)]
+    pub gpus: Vec<Gpu>,
+    /// Version of the CUDA toolkit, if one is installed
+    #[serde(default)]
+    pub cuda_version: Option<String>,
+    /// Version of ROCm, if it is installed
+    #[serde(default)]
+    pub rocm_version: Option<String>,
+    /// Container fastsave ran in, if any
+    #[serde(default)]
+    pub container: Option<Container>,
+}
+
+/// A container detected from the inside
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Container {
+    /// `docker`, `podman`, `apptainer` or `kubernetes`
+    pub runtime: String,
+    pub id: Option<String>,
+    /// Image name, the image file for Apptainer, or `FASTSAVE_CONTAINER_IMAGE` if set
+    pub image: Option<String>,
+    /// Image id or digest, where the runtime exposes it
+    pub image_id: Option<String>,
+}
+
+/// An accelerator found by `nvidia-smi` or `rocm-smi`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Gpu {
+    /// `nvidia` or `amd`
+    pub vendor: String,
+    pub name: String,
+    pub driver_version: Option<String>,
+    pub memory_mib: Option<u64>,
+}
+
+/// Standard output of a command, if it could be run and succeeded
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses `nvidia-smi --query-gpu=name,driver_version,memory.total --format=csv,noheader,nounits`
+pub fn parse_nvidia_gpus(csv: &str) -> Vec<Gpu> {
+    csv.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let name = fields.first().filter(|name| !name.is_empty())?;
+            Some(Gpu {
+                vendor: "nvidia".to_string(),
+                name: name.to_string(),
+                driver_version: fields.get(1).map(|version| version.to_string()),
+                memory_mib: fields.get(2).and_then(|memory| memory.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+fn nvidia_gpus() -> Vec<Gpu> {
+    command_output("nvidia-smi", &["--query-gpu=name,driver_version,memory.total", "--format=csv,noheader,nounits"])
+        .map(|csv| parse_nvidia_gpus(&csv))
+        .unwrap_or_default()
+}
+
+/// Parses the JSON of `rocm-smi --showproductname --showdriverversion --json`, whose keys
+/// differ between ROCm releases: one object per `cardN` and the driver in `system`
+pub fn parse_amd_gpus(json: &str) -> Vec<Gpu> {
+    let Ok(serde_json::Value::Object(objects)) = serde_json::from_str::<serde_json::Value>(json) else {
+        return Vec::new();
+    };
+    let field = |object: &serde_json::Value, names: &[&str]| {
+        let object = object.as_object()?;
+        object
+            .iter()
+            .find(|(key, _)| names.iter().any(|name| key.eq_ignore_ascii_case(name)))
+            .and_then(|(_, value)| value.as_str())
+            .map(str::to_string)
+    };
+    let driver_version = objects.get("system").and_then(|system| field(system, &["Driver version"]));
+    objects
+        .iter()
+        .filter(|(key, _)| key.starts_with("card"))
+        .filter_map(|(_, card)| {
+            Some(Gpu {
+                vendor: "amd".to_string(),
+                name: field(card, &["Card Series", "Card series", "Device Name", "Card model"])?,
+                driver_version: driver_version.clone(),
+                memory_mib: None,
+            })
+        })
+        .collect()
+}
+
+fn amd_gpus() -> Vec<Gpu> {
+    command_output("rocm-smi", &["--showproductname", "--showdriverversion", "--json"])
+        .map(|json| parse_amd_gpus(&json))
+        .unwrap_or_default()
+}
+
+/// CUDA toolkit version from `nvcc`, else from the default installation
+fn cuda_version() -> Option<String> {
+    let from_nvcc = || {
+        // "Cuda compilation tools, release 12.1, V12.1.105"
+        let output = command_output("nvcc", &["--version"])?;
+        let version = output.lines().find_map(|line| line.rsplit_once(", V"))?.1;
+        Some(version.trim().to_string())
+    };
+    let from_installation = || {
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string("/usr/local/cuda/version.json").ok()?).ok()?;
+        json["cuda"]["version"].as_str().map(str::to_string)
+    };
+    from_nvcc().or_else(from_installation)
+}
+
+fn rocm_version() -> Option<String> {
+    let version = fs::read_to_string("/opt/rocm/.info/version").ok()?;
+    Some(version.trim().to_string()).filter(|version| !version.is_empty())
+}
+
+/// Parses `/run/.containerenv`, where Podman puts `key="value"` lines about the container
+pub fn parse_containerenv(content: &str) -> Container {
+    let value = |key: &str| {
+        content.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?.trim_matches('"');
+            Some(value.to_string()).filter(|value| !value.is_empty())
+        })
+    };
+    Container { runtime: "podman".to_string(), id: value("id"), image: value("image"), image_id: value("imageid") }
+}
+
+/// Id of the container in the cgroup paths or mounts of this process, like the
+/// `/docker/<id>` cgroups of Docker or the `/containers/<id>/hostname` mount
+pub fn container_id(text: &str) -> Option<String> {
+    text.split(['/', ' ', '\n', '-', '.'])
+        .find(|part| part.len() == 64 && part.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_string)
+}
+
+fn detect_container() -> Option<Container> {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let process_info = || {
+        ["/proc/self/cgroup", "/proc/self/mountinfo"].iter().filter_map(|file| fs::read_to_string(file).ok()).collect::<Vec<_>>().join("\n")
+    };
+
+    let mut container = if let Some(image) = env("APPTAINER_CONTAINER").or_else(|| env("SINGULARITY_CONTAINER")) {
+        Container { runtime: "apptainer".to_string(), image: Some(image), ..Container::default() }
+    } else if let Ok(content) = fs::read_to_string("/run/.containerenv") {
+        parse_containerenv(&content)
+    } else if std::path::Path::new("/.dockerenv").exists() {
+        Container { runtime: "docker".to_string(), id: container_id(&process_info()), ..Container::default() }
+    } else if env("KUBERNETES_SERVICE_HOST").is_some() {
+        Container { runtime: "kubernetes".to_string(), id: container_id(&process_info()), ..Container::default() }
+    } else {
+        let info = process_info();
+        let runtime = ["kubepods", "docker", "libpod", "containerd"].into_iter().find(|runtime| info.contains(runtime))?;
+        let runtime = match runtime {
+            "kubepods" => "kubernetes",
+            "libpod" => "podman",
+            other => other,
+        };
+        Container { runtime: runtime.to_string(), id: container_id(&info), ..Container::default() }
+    };
+    // Docker doesn't tell a container its image, so it can be passed in with `-e`
+    if let Some(image) = env("FASTSAVE_CONTAINER_IMAGE") {
+        container.image = Some(image);
+    }
+    Some(container)
+}
+
+/// Collects the system information. Accelerators are looked for with the tools of their
+/// vendors, so on a machine without them this costs a few failed process starts.
+pub fn collect() -> SystemInfo {
+    let mut gpus = nvidia_gpus();
+    gpus.extend(amd_gpus());
+    SystemInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        gpus,
+        cuda_version: cuda_version(),
+        rocm_version: rocm_version(),
+        container: detect_container(),
+    }
+}
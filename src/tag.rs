@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::archive::{load_manifest, manifest_path, resolve_run, save_manifest};
+
+#[derive(Args)]
+pub struct TagArgs {
+    /// Run id or path to the run directory
+    pub run: String,
+
+    /// Tags to add to the run
+    #[arg(required = true, value_parser = parse_tag)]
+    pub tags: Vec<String>,
+
+    /// Remove the tags instead of adding them
+    #[arg(long = "remove")]
+    pub remove: bool,
+
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+/// Tags are single words, so they can be listed and matched without quoting
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(format!("invalid tag '{}': must be a single word without commas", tag));
+    }
+    Ok(tag.to_string())
+}
+
+/// Adds the tags the run doesn't have yet, or removes them
+pub fn update_tags(run_dir: &Path, tags: &[String], remove: bool) -> Result<(), Box<dyn Error>> {
+    let mut result = load_manifest(&manifest_path(run_dir))?;
+    if remove {
+        result.tags.retain(|tag| !tags.contains(tag));
+    } else {
+        for tag in tags {
+            if !result.tags.contains(tag) {
+                result.tags.push(tag.clone());
+            }
+        }
+    }
+    save_manifest(run_dir, &result)
+}
+
+pub fn run_tag(args: &TagArgs) -> Result<(), Box<dyn Error>> {
+    let run_dir = resolve_run(Path::new(&args.archive_dir), &args.run)?;
+    update_tags(&run_dir, &args.tags, args.remove)?;
+
+    let result = load_manifest(&manifest_path(&run_dir))?;
+    #[cfg(feature = "search")]
+    crate::search::update_index(Path::new(&args.archive_dir), &run_dir, &result)?;
+    if result.tags.is_empty() {
+        println!("{} has no tags", run_dir.display());
+    } else {
+        println!("Tags of {}: {}", run_dir.display(), result.tags.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::archive::{is_manifest, STDIN_FILE};
+
+/// Archive holding the files of a run packed with `--tar`
+pub const RUN_TAR_FILE: &str = "run.tar";
+
+const BLOCK: usize = 512;
+
+/// Whether the files of the run were packed into `run.tar`
+pub fn is_packed(run_dir: &Path) -> bool {
+    run_dir.join(RUN_TAR_FILE).is_file()
+}
+
+/// Writes `value` as a zero-padded octal number into a header field, leaving room for the terminator
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+/// Header of a ustar entry. Names longer than the 100 bytes of the header are written as
+/// a GNU long name entry first, which all common tar implementations read.
+fn write_header(out: &mut impl Write, name: &str, size: u64, mtime: u64, typeflag: u8) -> io::Result<()> {
+    if name.len() > 100 {
+        let mut long_name = name.as_bytes().to_vec();
+        long_name.push(0);
+        write_header(out, "././@LongLink", long_name.len() as u64, 0, b'L')?;
+        write_padded(out, &long_name)?;
+    }
+    let mut header = [0u8; BLOCK];
+    let short_name = &name.as_bytes()[..name.len().min(100)];
+    header[..short_name.len()].copy_from_slice(short_name);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field set to spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&byte| byte as u64).sum();
+    octal(&mut header[148..155], checksum);
+    out.write_all(&header)
+}
+
+fn write_padded(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    out.write_all(data)?;
+    out.write_all(&vec![0u8; (BLOCK - data.len() % BLOCK) % BLOCK])
+}
+
+/// Files below `dir` relative to it, with `/` separators, in a stable order
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), files)?;
+        } else if file_type.is_file() {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+/// Packs the files of a finished run into `run.tar` and removes them. The manifest is
+/// packed as well but stays next to the archive for listing and searching. The input fed
+/// with `--stdin` and the copy of the `--params` file, named by `params`, are left next to
+/// it unpacked, since reruns read them.
+pub fn pack_run(run_dir: &Path, params: Option<&str>) -> io::Result<()> {
+    let mut files = Vec::new();
+    collect_files(run_dir, "", &mut files)?;
+    files.retain(|(name, _)| name != RUN_TAR_FILE && name != STDIN_FILE && Some(name.as_str()) != params);
+
+    // Written under a temporary name, so a packing that fails midway leaves the files untouched
+    let temporary = run_dir.join(format!(".{}.tmp", RUN_TAR_FILE));
+    let mut out = BufWriter::new(File::create(&temporary)?);
+    for (name, path) in &files {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |age| age.as_secs());
+        write_header(&mut out, name, metadata.len(), mtime, b'0')?;
+        let copied = io::copy(&mut File::open(path)?, &mut out)?;
+        out.write_all(&vec![0u8; (BLOCK - copied as usize % BLOCK) % BLOCK])?;
+    }
+    // Two empty blocks end the archive
+    out.write_all(&[0u8; 2 * BLOCK])?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temporary, run_dir.join(RUN_TAR_FILE))?;
+
+    for (name, path) in &files {
+        if !is_manifest(name) {
+            fs::remove_file(path)?;
+        }
+    }
+    // Subdirectories emptied by packing
+    for entry in fs::read_dir(run_dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let text = String::from_utf8_lossy(field);
+    let digits = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid tar header"))
+}
+
+/// Calls `visit` with the name and a reader of the content of every file in the archive,
+/// until it returns false
+fn for_each_entry(tar: &Path, mut visit: impl FnMut(&str, &mut dyn Read) -> io::Result<bool>) -> io::Result<()> {
+    let mut file = BufReader::new(File::open(tar)?);
+    let mut long_name = None;
+    loop {
+        let mut header = [0u8; BLOCK];
+        file.read_exact(&mut header)?;
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        let size = parse_octal(&header[124..136])?;
+        let padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        match header[156] {
+            b'L' => {
+                let mut name = vec![0u8; size as usize];
+                file.read_exact(&mut name)?;
+                long_name = Some(String::from_utf8_lossy(&name).trim_end_matches('\0').to_string());
+                file.seek_relative(padding as i64)?;
+            }
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    String::from_utf8_lossy(&header[..100]).trim_end_matches('\0').to_string()
+                });
+                let mut content = (&mut file).take(size);
+                let more = visit(&name, &mut content)?;
+                if !more {
+                    return Ok(());
+                }
+                // Skips what `visit` didn't read
+                let rest = content.limit();
+                file.seek_relative((rest + padding) as i64)?;
+            }
+            _ => {
+                file.seek_relative((size + padding) as i64)?;
+            }
+        }
+    }
+}
+
+/// Content of one file of a packed run
+pub fn read_file(run_dir: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let mut content = None;
+    for_each_entry(&run_dir.join(RUN_TAR_FILE), |entry, reader| {
+        if entry != name {
+            return Ok(true);
+        }
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        content = Some(data);
+        Ok(false)
+    })?;
+    Ok(content)
+}
+
+/// Content of a file of a run, read from `run.tar` if the run was packed
+pub fn read_run_file(run_dir: &Path, name: &str) -> io::Result<Vec<u8>> {
+    match fs::read(run_dir.join(name)) {
+        Err(e) if is_packed(run_dir) => read_file(run_dir, name).and_then(|content| content.ok_or(e)),
+        content => content,
+    }
+}
+
+/// SHA-256 of every file of a packed run, by name
+pub fn file_hashes(run_dir: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    for_each_entry(&run_dir.join(RUN_TAR_FILE), |name, reader| {
+        let mut hasher = Sha256::new();
+        io::copy(reader, &mut hasher)?;
+        hashes.insert(name.to_string(), format!("{:x}", hasher.finalize()));
+        Ok(true)
+    })?;
+    Ok(hashes)
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::OutputFile;
+
+/// Directory within the run directory that holds the thumbnails
+pub const THUMBNAILS_DIR: &str = ".thumbnails";
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailConfig {
+    /// Generate thumbnails of PNG and JPEG outputs (requires the `thumbnails` feature)
+    pub enabled: bool,
+    /// Maximum width and height in pixels
+    pub max_size: u32,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        ThumbnailConfig { enabled: false, max_size: 256 }
+    }
+}
+
+/// Writes thumbnails of the image outputs and records their paths, relative to the run directory, in `files`
+pub fn create_thumbnails(output_dir: &Path, files: &mut BTreeMap<String, OutputFile>, config: &ThumbnailConfig) {
+    for (name, file) in files.iter_mut() {
+        if !matches!(file.kind.as_deref(), Some("png" | "jpeg")) {
+            continue;
+        }
+        let thumbnail = Path::new(THUMBNAILS_DIR).join(name);
+        match create_thumbnail(&output_dir.join(name), &output_dir.join(&thumbnail), config.max_size) {
+            Ok(()) => file.thumbnail = Some(thumbnail.to_string_lossy().into_owned()),
+            Err(e) => tracing::warn!("Failed to create thumbnail of {}: {}", name, e),
+        }
+    }
+}
+
+#[cfg(feature = "thumbnails")]
+fn create_thumbnail(image_path: &Path, thumbnail_path: &Path, max_size: u32) -> Result<(), String> {
+    if let Some(parent) = thumbnail_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let image = image::open(image_path).map_err(|e| e.to_string())?;
+    // The format is chosen by the extension, so thumbnails keep the format of their image
+    image.thumbnail(max_size, max_size).save(thumbnail_path).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "thumbnails"))]
+fn create_thumbnail(_: &Path, _: &Path, _: u32) -> Result<(), String> {
+    Err("fastsave was built without the `thumbnails` feature".to_string())
+}
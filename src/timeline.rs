@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, SystemTime};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+/// Interval at which the watcher checks whether the script has exited
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimelineConfig {
+    /// Watch the run directory for outputs while the script runs
+    pub enabled: bool,
+}
+
+impl Default for TimelineConfig {
+    fn default() -> Self {
+        TimelineConfig { enabled: true }
+    }
+}
+
+/// When an output file appeared and was last written, in milliseconds after the start of the script
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FileTimes {
+    pub created_ms: u64,
+    pub modified_ms: u64,
+}
+
+/// Watches the files in a run directory while the script writes them
+pub struct TimelineWatcher {
+    run_dir: PathBuf,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl TimelineWatcher {
+    /// Starts watching `run_dir`, before the script is started so its first outputs aren't missed
+    pub fn start(run_dir: &Path) -> Result<TimelineWatcher, String> {
+        // Backends report absolute paths, on macOS with symbolic links resolved
+        let run_dir = run_dir.canonicalize().map_err(|e| format!("Failed to watch the outputs in {}: {}", run_dir.display(), e))?;
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| format!("Failed to watch the outputs: {}", e))?;
+        watcher
+            .watch(&run_dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch the outputs in {}: {}", run_dir.display(), e))?;
+        Ok(TimelineWatcher { run_dir, _watcher: watcher, events })
+    }
+
+    /// Records the files created or written until `stopped` is signalled or dropped once the
+    /// script exits, keyed by their path relative to the run directory like `file_hashes`
+    pub fn collect(self, start: SystemTime, stopped: &Receiver<()>) -> BTreeMap<String, FileTimes> {
+        let mut timeline = BTreeMap::new();
+        loop {
+            match self.events.recv_timeout(POLL) {
+                Ok(event) => self.record(&mut timeline, start, event),
+                Err(RecvTimeoutError::Timeout) if matches!(stopped.try_recv(), Err(TryRecvError::Empty)) => {}
+                Err(_) => break,
+            }
+        }
+        // Events for the last writes may arrive after the script has exited
+        while let Ok(event) = self.events.try_recv() {
+            self.record(&mut timeline, start, event);
+        }
+        timeline
+    }
+
+    fn record(&self, timeline: &mut BTreeMap<String, FileTimes>, start: SystemTime, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("Failed to watch the outputs: {}", e);
+                return;
+            }
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        let elapsed_ms = SystemTime::now().duration_since(start).unwrap_or_default().as_millis() as u64;
+        for path in &event.paths {
+            let Ok(relative_path) = path.strip_prefix(&self.run_dir) else { continue };
+            timeline
+                .entry(relative_path.to_string_lossy().into_owned())
+                .and_modify(|times: &mut FileTimes| times.modified_ms = elapsed_ms)
+                .or_insert(FileTimes { created_ms: elapsed_ms, modified_ms: elapsed_ms });
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::parse_duration;
+use crate::notify::format_duration;
+
+/// Time a stopped script gets between SIGTERM and SIGKILL
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long scripts may run, and whether they are warned before they are stopped:
+///
+/// ```yaml
+/// timeout:
+///   limit: 12h
+///   warn_before: 15m
+///   notify: true
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Stop scripts running longer than this, like `--timeout`
+    pub limit: Option<String>,
+    /// Warn this long before the deadline, like `--warn-before`
+    pub warn_before: Option<String>,
+    /// Send the script SIGUSR1 with the warning, so it can save its state
+    pub signal: bool,
+    /// Send the warning to the webhooks and chats that are notified about failed runs
+    pub notify: bool,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig { limit: None, warn_before: None, signal: true, notify: false }
+    }
+}
+
+/// The timeout of a run, from the command line or the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeout {
+    pub limit: Duration,
+    pub warn_before: Option<Duration>,
+    pub signal: bool,
+    pub notify: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Signal {
+    Warning,
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: Signal) -> Result<(), String> {
+    let number = match signal {
+        Signal::Warning => libc::SIGUSR1,
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: plain system call on our own child process
+    if unsafe { libc::kill(pid as libc::pid_t, number) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: u32, _signal: Signal) -> Result<(), String> {
+    Err("signals are only supported on Unix".to_string())
+}
+
+/// Sleeps for `duration`; false if the script exited before
+pub(crate) fn still_running(stopped: &Receiver<()>, duration: Duration) -> bool {
+    matches!(stopped.recv_timeout(duration), Err(RecvTimeoutError::Timeout))
+}
+
+impl Timeout {
+    /// Durations given on the command line take precedence over the config. `None` without a limit.
+    pub fn new(limit: Option<chrono::Duration>, warn_before: Option<chrono::Duration>, config: &TimeoutConfig) -> Result<Option<Timeout>, String> {
+        let from_config = |value: &Option<String>| value.as_deref().map(parse_duration).transpose();
+        let Some(limit) = limit.or(from_config(&config.limit)?) else {
+            return Ok(None);
+        };
+        let warn_before = warn_before.or(from_config(&config.warn_before)?);
+        let to_std = |duration: chrono::Duration| duration.to_std().map_err(|_| format!("negative duration {}", duration));
+        let (limit, warn_before) = (to_std(limit)?, warn_before.map(to_std).transpose()?);
+        if warn_before.is_some_and(|warn_before| warn_before >= limit) {
+            return Err("the timeout warning must come before the timeout".to_string());
+        }
+        Ok(Some(Timeout { limit, warn_before, signal: config.signal, notify: config.notify }))
+    }
+
+    /// Watches the script with process id `pid` until `stopped` is signalled or dropped once
+    /// it exits. `warn_before` the limit, the script gets SIGUSR1 if `signal` is set and
+    /// `on_warning` is called; at the limit it gets SIGTERM, and SIGKILL if it's still
+    /// running after a grace period. Returns whether the script was stopped.
+    pub fn watch(&self, pid: u32, stopped: &Receiver<()>, on_warning: impl FnOnce()) -> bool {
+        let started = Instant::now();
+        if let Some(warn_before) = self.warn_before {
+            if !still_running(stopped, self.limit - warn_before) {
+                return false;
+            }
+            tracing::warn!("The script reaches its timeout in {}", format_duration(warn_before.as_millis() as u64));
+            if self.signal {
+                if let Err(e) = send_signal(pid, Signal::Warning) {
+                    tracing::warn!("Failed to send SIGUSR1 to the script: {}", e);
+                }
+            }
+            on_warning();
+        }
+        if !still_running(stopped, self.limit.saturating_sub(started.elapsed())) {
+            return false;
+        }
+        tracing::warn!("Stopping the script after its timeout of {}", format_duration(self.limit.as_millis() as u64));
+        stop_script(pid, stopped)
+    }
+}
+
+/// Sends the script SIGTERM, and SIGKILL if it is still running after a grace period.
+/// Returns whether the signal could be sent.
+pub(crate) fn stop_script(pid: u32, stopped: &Receiver<()>) -> bool {
+    if let Err(e) = send_signal(pid, Signal::Terminate) {
+        tracing::warn!("Failed to stop the script: {}", e);
+        return false;
+    }
+    if still_running(stopped, GRACE_PERIOD) {
+        tracing::warn!("The script didn't exit within {}s of SIGTERM, killing it", GRACE_PERIOD.as_secs());
+        let _ = send_signal(pid, Signal::Kill);
+    }
+    true
+}
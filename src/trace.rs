@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+use crate::calculate_file_hash;
+
+/// System call log written by strace into the run directory, removed once it is read
+pub const TRACE_LOG: &str = ".trace.log";
+
+/// Files of the system and of installed packages, which belong to the environment rather than the inputs
+const SYSTEM_PATHS: [&str; 21] = [
+    "/bin/**",
+    "/sbin/**",
+    "/lib/**",
+    "/lib32/**",
+    "/lib64/**",
+    "/usr/**",
+    "/etc/**",
+    "/opt/**",
+    "/nix/**",
+    "/proc/**",
+    "/sys/**",
+    "/dev/**",
+    "/run/**",
+    "/var/**",
+    "**/site-packages/**",
+    "**/dist-packages/**",
+    "**/lib/python3*/**",
+    "**/.julia/**",
+    "**/__pycache__/**",
+    "**/*.so",
+    "**/*.so.*",
+];
+
+/// Records which files outside the project a script reads:
+///
+/// ```yaml
+/// trace:
+///   enabled: true
+///   ignore: ["~/.matplotlib/**"]
+/// ```
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TraceConfig {
+    /// Always trace, like `--trace-inputs`
+    pub enabled: bool,
+    /// Globs of paths that are never reported, besides the system and package directories
+    pub ignore: Vec<String>,
+}
+
+/// A file outside the project the script read, recorded in the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TracedInput {
+    pub path: String,
+    /// Hash of the file after the run; `None` if it can't be read anymore
+    pub sha256: Option<String>,
+}
+
+/// Traces the files a script opens with strace, to find the inputs it doesn't declare
+pub struct InputTracer {
+    log: PathBuf,
+    ignore: Vec<Pattern>,
+    /// Directories whose files are known to the run: the project, the archive, the declared inputs
+    known: Vec<PathBuf>,
+}
+
+impl InputTracer {
+    /// Paths in `known` that don't exist are left out
+    pub fn new(run_dir: &Path, known: &[PathBuf], config: &TraceConfig) -> Result<InputTracer, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("--trace-inputs needs strace, which is only available on Linux".to_string());
+        }
+        let ignore = SYSTEM_PATHS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(config.ignore.iter().map(|pattern| shellexpand::tilde(pattern).into_owned()))
+            .map(|pattern| Pattern::new(&pattern).map_err(|e| format!("Invalid pattern '{}' in the trace config: {}", pattern, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(InputTracer {
+            log: run_dir.join(TRACE_LOG),
+            ignore,
+            known: known.iter().filter_map(|path| path.canonicalize().ok()).collect(),
+        })
+    }
+
+    /// Command running `cmd` under strace, following the processes it starts and logging
+    /// the files they open with their full paths
+    pub fn command(&self, cmd: &Command) -> Command {
+        let mut traced = Command::new("strace");
+        traced
+            .args(["-f", "-qq", "-s", "4096", "-e", "trace=/^open(at2?)?$", "-o"])
+            .arg(&self.log)
+            .arg("--")
+            .arg(cmd.get_program())
+            .args(cmd.get_args());
+        traced
+    }
+
+    /// Reads and removes the log, returning the unknown files that still exist, with their hashes
+    pub fn finish(self, cwd: &Path) -> Result<Vec<TracedInput>, Box<dyn Error>> {
+        let log = fs::read_to_string(&self.log).map_err(|e| format!("Failed to read the trace of the script: {}", e))?;
+        fs::remove_file(&self.log)?;
+        let mut paths: Vec<PathBuf> = read_files(&log, cwd)
+            .into_iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .filter(|path| path.is_file())
+            .filter(|path| !self.known.iter().any(|known| path.starts_with(known)))
+            .filter(|path| !self.ignore.iter().any(|pattern| pattern.matches_path(path)))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths
+            .into_iter()
+            .map(|path| TracedInput { sha256: calculate_file_hash(&path).ok(), path: path.to_string_lossy().into_owned() })
+            .collect())
+    }
+}
+
+/// Paths opened for reading in an strace log, resolving relative ones against `cwd`. Calls
+/// that failed, create files, open them for writing only or open directories are skipped.
+pub fn read_files(log: &str, cwd: &Path) -> Vec<PathBuf> {
+    log.lines()
+        .filter_map(|line| {
+            // Lines start with the process id, since strace follows child processes
+            let call = line.trim_start_matches(|c: char| c.is_ascii_digit() || c == ' ');
+            let (name, arguments) = call.split_once('(')?;
+            if !matches!(name, "open" | "openat" | "openat2") {
+                return None;
+            }
+            let (_, quoted) = arguments.split_once('"')?;
+            let (path, rest) = quoted.split_once('"')?;
+            let failed = rest.rsplit_once(" = ").is_some_and(|(_, result)| result.starts_with('-'));
+            if failed || ["O_WRONLY", "O_CREAT", "O_DIRECTORY"].iter().any(|flag| rest.contains(flag)) {
+                return None;
+            }
+            Some(cwd.join(path))
+        })
+        .collect()
+}
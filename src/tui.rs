@@ -0,0 +1,398 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::archive::{find_runs, ArchivedRun, STDIN_FILE};
+use crate::clean::remove_run;
+use crate::note::add_note;
+use crate::show::format_run;
+use crate::tag::{parse_tag, update_tags};
+use crate::{get_script_basename, run_script, Cli};
+
+#[derive(Args)]
+pub struct UiArgs {
+    /// Archive directory path
+    #[arg(short = 'a', long = "archive-dir", default_value = "archive")]
+    pub archive_dir: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Start,
+    Duration,
+    Script,
+    ExitCode,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Start => SortKey::Duration,
+            SortKey::Duration => SortKey::Script,
+            SortKey::Script => SortKey::ExitCode,
+            SortKey::ExitCode => SortKey::Start,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Start => "start",
+            SortKey::Duration => "duration",
+            SortKey::Script => "script",
+            SortKey::ExitCode => "exit code",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Details,
+    Stdout,
+    Stderr,
+}
+
+enum Mode {
+    Normal,
+    Filter,
+    Note(String),
+    Tag(String),
+    ConfirmDelete,
+}
+
+/// State of the archive browser, independent of the terminal
+pub struct Browser {
+    archive_dir: PathBuf,
+    runs: Vec<ArchivedRun>,
+    pub filter: String,
+    pub sort: SortKey,
+    /// Indices into `runs` of the runs passing the filter, in display order
+    visible: Vec<usize>,
+    table: TableState,
+    pane: Pane,
+    scroll: u16,
+    mode: Mode,
+    status: String,
+}
+
+impl Browser {
+    pub fn new(archive_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut browser = Browser {
+            archive_dir: archive_dir.to_path_buf(),
+            runs: Vec::new(),
+            filter: String::new(),
+            sort: SortKey::Start,
+            visible: Vec::new(),
+            table: TableState::default(),
+            pane: Pane::Details,
+            scroll: 0,
+            mode: Mode::Normal,
+            status: String::new(),
+        };
+        browser.reload()?;
+        Ok(browser)
+    }
+
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        self.runs = find_runs(&self.archive_dir)?;
+        self.refresh();
+        Ok(())
+    }
+
+    /// Reapplies filter and sort order, keeping the selection in range
+    pub fn refresh(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = (0..self.runs.len())
+            .filter(|&i| {
+                let run = &self.runs[i];
+                filter.is_empty()
+                    || run.id().to_lowercase().contains(&filter)
+                    || run.result.message.as_deref().unwrap_or_default().to_lowercase().contains(&filter)
+            })
+            .collect();
+
+        let runs = &self.runs;
+        match self.sort {
+            // Newest first
+            SortKey::Start => self.visible.sort_by_key(|&i| std::cmp::Reverse(runs[i].result.start_time)),
+            SortKey::Duration => self.visible.sort_by_key(|&i| std::cmp::Reverse(runs[i].result.duration_ms)),
+            SortKey::Script => self.visible.sort_by_key(|&i| get_script_basename(&runs[i].result.script_path)),
+            SortKey::ExitCode => self.visible.sort_by_key(|&i| std::cmp::Reverse(runs[i].result.exit_code)),
+        }
+
+        let selected = match self.table.selected() {
+            _ if self.visible.is_empty() => None,
+            Some(i) => Some(i.min(self.visible.len() - 1)),
+            None => Some(0),
+        };
+        self.table.select(selected);
+    }
+
+    /// Ids of the runs currently listed
+    pub fn visible_ids(&self) -> Vec<String> {
+        self.visible.iter().map(|&i| self.runs[i].id()).collect()
+    }
+
+    /// The message of the last action, shown in the status line
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    fn selected(&self) -> Option<&ArchivedRun> {
+        self.table.selected().and_then(|i| self.visible.get(i)).map(|&i| &self.runs[i])
+    }
+
+    fn select_offset(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.table.select(Some((current + offset).clamp(0, last) as usize));
+        self.scroll = 0;
+    }
+
+    /// Deletes the selected run, unless it is pinned or has no directory of its own
+    pub fn delete_selected(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(run) = self.selected() {
+            self.status = if run.result.pinned {
+                format!("{} is pinned, unpin it to delete it", run.id())
+            } else {
+                match remove_run(&self.archive_dir, run) {
+                    Ok(()) => format!("Deleted {}", run.id()),
+                    Err(e) => e.to_string(),
+                }
+            };
+        }
+        self.reload()
+    }
+
+    /// Adds the space-separated tags to the selected run; a tag written as `-tag` is removed
+    pub fn tag_selected(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let Some(run) = self.selected() else {
+            return Ok(());
+        };
+        let (removed, added): (Vec<&str>, Vec<&str>) = text.split_whitespace().partition(|tag| tag.starts_with('-'));
+        let parse = |tags: Vec<&str>| tags.into_iter().map(|tag| parse_tag(tag.trim_start_matches('-'))).collect::<Result<Vec<_>, _>>();
+        self.status = match parse(added).and_then(|added| Ok((added, parse(removed)?))) {
+            Ok((added, removed)) => {
+                update_tags(&run.run_dir, &added, false)?;
+                update_tags(&run.run_dir, &removed, true)?;
+                format!("Updated tags of {}", run.id())
+            }
+            Err(e) => e,
+        };
+        self.reload()
+    }
+
+    fn note_selected(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(run) = self.selected() {
+            add_note(&run.run_dir, text, false)?;
+            self.status = format!("Updated message of {}", run.id());
+        }
+        self.reload()
+    }
+
+    /// Runs the selected run's script or command again, recording the original as its parent
+    fn rerun_selected(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        let Some(run) = self.selected() else {
+            return Ok(());
+        };
+        let id = run.id();
+        // Feed the rerun the same input as the original
+        let stdin = run.run_dir.join(STDIN_FILE);
+        let cli = Cli {
+            stdin: stdin.is_file().then(|| stdin.to_string_lossy().into_owned()),
+            message: Some(format!("Rerun of {}", id)),
+            parents: vec![id],
+            experiment: run.result.experiment.clone(),
+            archive_dir: self.archive_dir.to_string_lossy().into_owned(),
+            exec: run.result.exec_command(),
+            ..Cli::new(run.result.script_location().to_string_lossy())
+        };
+
+        ratatui::restore();
+        let outcome = run_script(&cli);
+        println!("Press Enter to return to the browser");
+        let _ = std::io::stdin().read_line(&mut String::new());
+        *terminal = ratatui::init();
+
+        self.status = match outcome {
+            Ok(run) => format!("Rerun saved to {}", run.run_dir.display()),
+            Err(e) => format!("Rerun failed: {}", e),
+        };
+        self.reload()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, detail_area, status_area] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let rows: Vec<Row> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let run = &self.runs[i];
+                Row::new(vec![
+                    run.id(),
+                    get_script_basename(&run.result.script_path),
+                    run.result.start_time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+                    format!("{} ms", run.result.duration_ms),
+                    run.result.exit_code.to_string(),
+                ])
+            })
+            .collect();
+        let title = match &self.filter {
+            filter if filter.is_empty() => format!(" Runs ({}) sorted by {} ", rows.len(), self.sort.label()),
+            filter => format!(" Runs ({}) matching '{}' sorted by {} ", rows.len(), filter, self.sort.label()),
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Fill(2),
+                Constraint::Length(16),
+                Constraint::Length(12),
+                Constraint::Length(5),
+            ],
+        )
+        .header(Row::new(vec!["ID", "SCRIPT", "START", "DURATION", "EXIT"]).style(Style::new().add_modifier(Modifier::BOLD)))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::new().borders(Borders::ALL).title(title));
+        frame.render_stateful_widget(table, list_area, &mut self.table);
+
+        let (pane_title, text) = match (self.selected(), self.pane) {
+            (None, _) => (" Details ", String::new()),
+            (Some(run), Pane::Details) => (" Details ", format_run(&self.archive_dir, run)),
+            (Some(run), Pane::Stdout) => (" stdout ", run.result.stdout.clone()),
+            (Some(run), Pane::Stderr) => (" stderr ", run.result.stderr.clone()),
+        };
+        let details = Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .block(Block::new().borders(Borders::ALL).title(pane_title));
+        frame.render_widget(details, detail_area);
+
+        let status = match &self.mode {
+            Mode::Filter => format!("Filter: {}", self.filter),
+            Mode::Note(text) => format!("Note: {}", text),
+            Mode::Tag(text) => format!("Tags (-tag removes): {}", text),
+            Mode::ConfirmDelete => "Delete the selected run? (y/n)".to_string(),
+            Mode::Normal if !self.status.is_empty() => self.status.clone(),
+            Mode::Normal => {
+                "q quit  / filter  s sort  tab details/stdout/stderr  n note  t tag  r rerun  d delete".to_string()
+            }
+        };
+        frame.render_widget(Line::from(status), status_area);
+    }
+
+    /// Handles a key press; returns false when the browser should exit
+    fn handle_key(&mut self, code: KeyCode, terminal: &mut DefaultTerminal) -> Result<bool, Box<dyn Error>> {
+        match &mut self.mode {
+            Mode::Filter => match code {
+                KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refresh();
+                }
+                _ => {}
+            },
+            Mode::Note(text) => match code {
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Enter => {
+                    let text = std::mem::take(text);
+                    self.mode = Mode::Normal;
+                    if !text.is_empty() {
+                        self.note_selected(&text)?;
+                    }
+                }
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            },
+            Mode::Tag(text) => match code {
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Enter => {
+                    let text = std::mem::take(text);
+                    self.mode = Mode::Normal;
+                    self.tag_selected(&text)?;
+                }
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            },
+            Mode::ConfirmDelete => {
+                self.mode = Mode::Normal;
+                if code == KeyCode::Char('y') {
+                    self.delete_selected()?;
+                }
+            }
+            Mode::Normal => {
+                self.status.clear();
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                    KeyCode::Down | KeyCode::Char('j') => self.select_offset(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.select_offset(-1),
+                    KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+                    KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('s') => {
+                        self.sort = self.sort.next();
+                        self.refresh();
+                    }
+                    KeyCode::Tab => {
+                        self.pane = match self.pane {
+                            Pane::Details => Pane::Stdout,
+                            Pane::Stdout => Pane::Stderr,
+                            Pane::Stderr => Pane::Details,
+                        };
+                        self.scroll = 0;
+                    }
+                    KeyCode::Char('n') if self.selected().is_some() => self.mode = Mode::Note(String::new()),
+                    KeyCode::Char('t') if self.selected().is_some() => self.mode = Mode::Tag(String::new()),
+                    KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+                    KeyCode::Char('r') => self.rerun_selected(terminal)?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn run_ui(args: &UiArgs) -> Result<(), Box<dyn Error>> {
+    let mut browser = Browser::new(Path::new(&args.archive_dir))?;
+    let mut terminal = ratatui::init();
+
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| browser.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !browser.handle_key(key.code, &mut terminal)? {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,47 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of checking the expected output files of a run
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValidationResult {
+    pub passed: bool,
+    /// Expected patterns that matched nothing
+    pub missing: Vec<String>,
+    /// Matched files of size zero, or directories without entries
+    pub empty: Vec<String>,
+}
+
+fn is_empty(path: &Path) -> Result<bool, Box<dyn Error>> {
+    if path.is_dir() {
+        Ok(fs::read_dir(path)?.next().is_none())
+    } else {
+        Ok(fs::metadata(path)?.len() == 0)
+    }
+}
+
+/// Checks that every pattern, e.g. `plots/*.png`, matches at least one non-empty file in the output dir
+pub fn validate_outputs(output_dir: &Path, expected: &[String]) -> Result<ValidationResult, Box<dyn Error>> {
+    let base = glob::Pattern::escape(&output_dir.to_string_lossy());
+    let mut missing = Vec::new();
+    let mut empty = Vec::new();
+
+    for pattern in expected {
+        let matches = glob::glob(&format!("{}/{}", base, pattern))
+            .map_err(|e| format!("Invalid expected output pattern '{}': {}", pattern, e))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            missing.push(pattern.clone());
+        }
+        for path in matches {
+            if is_empty(&path)? {
+                let relative = path.strip_prefix(output_dir).unwrap_or(&path);
+                empty.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    Ok(ValidationResult { passed: missing.is_empty() && empty.is_empty(), missing, empty })
+}
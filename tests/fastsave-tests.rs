@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use std::fs;
 use std::path::Path;
 use tempfile::TempDir;
@@ -5,6 +7,43 @@ use fastsave::{Cli, ExecutionResult, run_script};
 use std::process::Command;
 use std::error::Error;
 use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use clap::Parser;
+
+// Tests that change the working directory or write ./fastsave.yaml must not overlap
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_cwd() -> MutexGuard<'static, ()> {
+    CWD_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Answers HTTP requests on a local port with 200 and forwards each request body
+fn capture_http_requests() -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+            let _ = sender.send(String::from_utf8(body).unwrap());
+        }
+    });
+    (url, receiver)
+}
 
 fn init_git_repo(dir: &Path) -> Result<(), Box<dyn Error>> {
     Command::new("git").args(&["init"]).current_dir(dir).output()?;
@@ -42,16 +81,27 @@ fn create_nested_git_repos() -> Result<(TempDir, PathBuf), Box<dyn Error>> {
     Ok((root_dir, script_path))
 }
 
+fn test_cli(script_path: &Path, archive_dir: &Path) -> Cli {
+    Cli {
+        archive_dir: archive_dir.to_string_lossy().to_string(),
+        ..Cli::new(script_path.to_string_lossy())
+    }
+}
+
 fn cleanup_config() {
     fs::remove_file("fastsave.yaml").unwrap_or(());
 }
 
-fn setup_test() {
+fn setup_test() -> MutexGuard<'static, ()> {
+    let guard = lock_cwd();
     cleanup_config();
+    guard
 }
 
 #[test]
 fn test_git_info_collection() -> Result<(), Box<dyn Error>> {
+    let _guard = lock_cwd();
+    let original_dir = std::env::current_dir()?;
     let (repo_dir, script_path) = create_nested_git_repos()?;
     
     // Test with absolute path
@@ -75,13 +125,13 @@ fn test_git_info_collection() -> Result<(), Box<dyn Error>> {
     );
     
     // Reset working directory
-    std::env::set_current_dir(repo_dir.path())?;
+    std::env::set_current_dir(original_dir)?;
     Ok(())
 }
 
 #[test]
 fn test_basic_script_execution() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create a simple test script
@@ -106,17 +156,9 @@ if __name__ == '__main__':
     let script_path = archive_dir.path().join("run_simulation.py");
     fs::write(&script_path, script_content).unwrap();
     // Create CLI args and run script
-    let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,
-        config_path: None,
-    };
+    let cli = test_cli(&script_path, archive_dir.path());
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Verify the output files exist
     let matrix_file = Path::new(&output_dir).join("matrix.txt");
@@ -130,7 +172,7 @@ if __name__ == '__main__':
     assert_eq!(matrix_content, "test matrix content");
     
     // Verify the output directory name format
-    assert!(output_dir.contains("run_simulation_run1"));
+    assert!(output_dir.to_string_lossy().contains("run_simulation_run1"));
     
     // Verify the YAML content
     let yaml_content = fs::read_to_string(fastsave_file).unwrap();
@@ -166,16 +208,11 @@ if __name__ == '__main__':
     fs::write(&script_path, script_content).unwrap();
     
     let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
         script_args: vec!["--rows".to_string(), "3".to_string(), "--cols".to_string(), "4".to_string()],
-        interpreter: None,
-        config_path: None,
+        ..test_cli(&script_path, archive_dir.path())
     };
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Verify the matrix content includes the passed arguments
     let matrix_file = Path::new(&output_dir).join("matrix.txt");
@@ -185,7 +222,7 @@ if __name__ == '__main__':
 
 #[test]
 fn test_custom_archive_directory() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create a simple test script
@@ -210,17 +247,9 @@ if __name__ == '__main__':
     let script_path = archive_dir.path().join("test_script.py");
     fs::write(&script_path, script_content).unwrap();
     
-    let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,
-        config_path: None,
-    };
+    let cli = test_cli(&script_path, archive_dir.path());
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Verify that the output directory is under our custom archive directory
     assert!(Path::new(&output_dir).starts_with(archive_dir.path()));
@@ -288,26 +317,23 @@ if __name__ == '__main__':
         .unwrap();
 
     // Create CLI args and run script
-    let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: repo_dir.path().join("archive").to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,
-        config_path: None,
-    };
+    let cli = test_cli(&script_path, &repo_dir.path().join("archive"));
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Read and parse the fastsave.yaml file
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
 
+    // Paths are recorded relative to the repository, which is the project root
+    assert_eq!(result.script_path, "test_script.py");
+    assert_eq!(result.project_root.as_deref().map(Path::new), Some(fs::canonicalize(repo_dir.path()).unwrap().as_path()));
+    assert_eq!(result.script_location(), fs::canonicalize(&script_path).unwrap());
+
     // Verify Git information
     let git_info = result.git_info.expect("Git info should be present");
     
-    assert!(git_info.repo_root.contains(repo_dir.path().to_string_lossy().as_ref()));
+    assert_eq!(git_info.repo_root, ".");
     assert!(!git_info.commit_hash.is_empty());
     assert!(!git_info.is_dirty);
     assert!(!git_info.branch.is_empty(), "Branch name should not be empty");
@@ -316,7 +342,7 @@ if __name__ == '__main__':
     // Test with uncommitted changes
     fs::write(repo_dir.path().join("new_file.txt"), "new content").unwrap();
     
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
     
@@ -327,7 +353,7 @@ if __name__ == '__main__':
 
 #[test]
 fn test_file_hashes() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create a test script that generates multiple files
@@ -354,17 +380,9 @@ if __name__ == '__main__':
     let script_path = archive_dir.path().join("test_script.py");
     fs::write(&script_path, script_content).unwrap();
     
-    let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,
-        config_path: None,
-    };
+    let cli = test_cli(&script_path, archive_dir.path());
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Read and parse the fastsave.yaml file
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
@@ -389,16 +407,11 @@ fn test_custom_interpreter() {
     fs::write(&script_path, "print('Hello from custom interpreter')").unwrap();
     
     let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
         interpreter: Some("python3".to_string()),
-        config_path: None,
+        ..test_cli(&script_path, archive_dir.path())
     };
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     
     // Read and verify the YAML output
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
@@ -417,26 +430,21 @@ fn test_interpreter_override() {
     
     // Test with command-line interpreter override
     let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
         interpreter: Some("python3".to_string()),
-        config_path: None,
+        ..test_cli(&script_path, archive_dir.path())
     };
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
     
     assert_eq!(result.exit_code, 0);
-    assert!(result.command_string.starts_with("python3 "));
+    assert!(result.command_line.starts_with("python3 "));
 }
 
 #[test]
 fn test_interpreter_config_file() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create config file with custom interpreter mapping
@@ -451,29 +459,21 @@ interpreters:
     let script_py = archive_dir.path().join("test.py");
     fs::write(&script_py, "print('Hello from Python')").unwrap();
     
-    // Test Python script with configured interpreter
-    let cli_py = Cli {
-        script: script_py.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,  // Use config file
-        config_path: None,
-    };
+    // Test Python script with configured interpreter (no override, use config file)
+    let cli_py = test_cli(&script_py, archive_dir.path());
 
-    let output_dir = run_script(&cli_py).unwrap();
+    let output_dir = run_script(&cli_py).unwrap().run_dir;
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
     
-    assert!(result.command_string.starts_with("python3 "));
+    assert!(result.command_line.starts_with("python3 "));
 
     cleanup_config();
 }
 
 #[test]
 fn test_interpreter_precedence() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create config file with interpreter mapping
@@ -488,27 +488,22 @@ interpreters:
     
     // Test that command-line override takes precedence over config file
     let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
         interpreter: Some("python3".to_string()),  // Use python3 instead of just python
-        config_path: None,
+        ..test_cli(&script_path, archive_dir.path())
     };
 
-    let output_dir = run_script(&cli).unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
     let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
     
-    assert!(result.command_string.starts_with("python3 "));
+    assert!(result.command_line.starts_with("python3 "));
 
     cleanup_config();
 }
 
 #[test]
 fn test_custom_config_path() {
-    setup_test();
+    let _guard = setup_test();
     let archive_dir = TempDir::new().unwrap();
     
     // Create custom config file in a different location
@@ -525,18 +520,13 @@ interpreters:
     fs::write(&script_path, "print('Hello')").unwrap();
     
     let cli = Cli {
-        script: script_path.to_string_lossy().to_string(),
-        archive_dir: archive_dir.path().to_string_lossy().to_string(),
-        message: None,
-        no_subfolder: false,
-        script_args: vec![],
-        interpreter: None,
         config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
     };
 
     // Run the script and handle potential errors
     let output_dir = match run_script(&cli) {
-        Ok(dir) => dir,
+        Ok(run) => run.run_dir,
         Err(e) => {
             println!("Failed to run script: {}", e);
             if let Some(source) = e.source() {
@@ -551,10 +541,3722 @@ interpreters:
     let result: ExecutionResult = serde_yaml::from_str(&yaml_content)
         .expect("Failed to parse YAML content");
     
-    assert!(result.command_string.starts_with("python3 "), 
-        "Expected command to start with 'python3', got: {}", result.command_string);
+    assert!(result.command_line.starts_with("python3 "), 
+        "Expected command to start with 'python3', got: {}", result.command_line);
     
     // Verify the script executed successfully
     assert_eq!(result.exit_code, 0, 
         "Script failed with exit code {}, stderr: {}", result.exit_code, result.stderr);
 }
+
+#[test]
+fn test_invalid_utf8_output() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("binary.sh");
+    // More than a pipe buffer after the invalid line, so the script blocks unless it is read
+    fs::write(&script_path, "printf '\\377\\376\\n'\nseq 1 20000\nprintf 'bad \\377\\n' >&2\necho done >&2").unwrap();
+
+    let run = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+    assert_eq!((run.result.exit_code, run.result.exit_signal), (0, None));
+    let lines: Vec<&str> = run.result.stdout.lines().collect();
+    assert_eq!(lines.len(), 20001);
+    assert_eq!((lines[0], lines[20000]), ("\u{FFFD}\u{FFFD}", "20000"));
+    assert_eq!(run.result.stderr, "bad \u{FFFD}\ndone\n");
+}
+
+#[test]
+fn test_query_runs() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("test_script.py");
+    fs::write(&script_path, "print('Hello')").unwrap();
+
+    let cli = Cli {
+        message: Some("first".to_string()),
+        interpreter: Some("python3".to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    run_script(&cli).unwrap();
+    run_script(&cli).unwrap();
+
+    let table = fastsave::query::query_runs(
+        archive_dir.path(),
+        "SELECT script, count(*) AS runs, max(exit_code) FROM runs GROUP BY script",
+    ).unwrap();
+
+    assert_eq!(table.headers, vec!["script", "runs", "max(exit_code)"]);
+    assert_eq!(table.rows, vec![vec!["test_script".to_string(), "2".to_string(), "0".to_string()]]);
+    assert_eq!(table.to_csv(), "script,runs,max(exit_code)\ntest_script,2,0\n");
+
+    // Invalid SQL is reported as an error rather than a panic
+    assert!(fastsave::query::query_runs(archive_dir.path(), "SELECT nope FROM runs").is_err());
+}
+
+#[test]
+fn test_stdout_metrics() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("train.py");
+    fs::write(&script_path, "for i, loss in enumerate([0.5, 0.25]):\n    print(f'epoch={i} loss={loss}')\n").unwrap();
+
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, r#"
+metrics:
+  patterns:
+    - 'loss=(?P<loss>[\d.]+)'
+    - 'epoch=(?P<epoch>\d+)'
+"#).unwrap();
+
+    let mut cli = Cli {
+        interpreter: Some("python3".to_string()),
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
+    assert_eq!(result.metrics["loss"], serde_yaml::Value::from(0.25));
+    assert_eq!(result.metrics["epoch"], serde_yaml::Value::from(1));
+
+    // With series enabled every matched value is kept
+    fs::write(&config_path, "metrics:\n  patterns: ['loss=(?P<loss>[\\d.]+)']\n  series: true\n").unwrap();
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
+    assert_eq!(result.metrics["loss"], serde_yaml::from_str::<serde_yaml::Value>("[0.5, 0.25]").unwrap());
+
+    // Invalid patterns are rejected before the script runs
+    fs::write(&config_path, "metrics:\n  patterns: ['loss=(?P<loss>']\n").unwrap();
+    cli.archive_dir = archive_dir.path().join("invalid").to_string_lossy().to_string();
+    assert!(run_script(&cli).is_err());
+}
+
+#[test]
+fn test_metrics_file() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse, json
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+args = parser.parse_args()
+print('loss=0.9')
+with (Path(args.output_dir)/'metrics.json').open('w') as f:
+    json.dump({'loss': 0.1, 'residuals': {'max': 3, 'mean': 1.5}}, f)
+"#;
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, script_content).unwrap();
+
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, "metrics:\n  patterns: ['loss=(?P<loss>[\\d.]+)']\n").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
+
+    // The metrics file overrides the value parsed from stdout
+    assert_eq!(result.metrics["loss"], serde_yaml::Value::from(0.1));
+    assert_eq!(result.metrics["residuals"]["max"], serde_yaml::Value::from(3));
+    assert!(result.file_hashes.contains_key("metrics.json"));
+}
+
+#[test]
+fn test_metadata() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("test_script.py");
+    fs::write(&script_path, "print('Hello')").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        metadata: vec![
+            ("dataset".to_string(), "v2".to_string()),
+            ("ticket".to_string(), "SIM-42".to_string()),
+        ],
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
+    assert_eq!(result.metadata["dataset"], "v2");
+    assert_eq!(result.metadata["ticket"], "SIM-42");
+
+    let table = fastsave::query::query_runs(
+        archive_dir.path(),
+        "SELECT value FROM metadata WHERE key = 'ticket'",
+    ).unwrap();
+    assert_eq!(table.rows, vec![vec!["SIM-42".to_string()]]);
+
+    // The flag parses KEY=VALUE and rejects anything else
+    let app = fastsave::App::try_parse_from(["fastsave", "--meta", "seed=1=2", "script.py"]).unwrap();
+    assert_eq!(app.run.unwrap().metadata, vec![("seed".to_string(), "1=2".to_string())]);
+    assert!(fastsave::App::try_parse_from(["fastsave", "--meta", "novalue", "script.py"]).is_err());
+}
+
+#[test]
+fn test_note_on_completed_run() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("test_script.py");
+    fs::write(&script_path, "print('Hello')").unwrap();
+
+    let cli = Cli {
+        message: Some("initial".to_string()),
+        interpreter: Some("python3".to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let run_id = Path::new(&output_dir).file_name().unwrap().to_string_lossy().to_string();
+
+    // Runs can be addressed by id relative to the archive
+    let run_dir = fastsave::archive::resolve_run(archive_dir.path(), &run_id).unwrap();
+    fastsave::note::add_note(&run_dir, "diverges after step 300", false).unwrap();
+    let result = fastsave::archive::load_manifest(&Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    assert_eq!(result.message.as_deref(), Some("initial\ndiverges after step 300"));
+    assert!(result.message_edited.is_some());
+
+    fastsave::note::add_note(Path::new(&output_dir), "replaced", true).unwrap();
+    let result = fastsave::archive::load_manifest(&Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    assert_eq!(result.message.as_deref(), Some("replaced"));
+
+    assert!(fastsave::archive::resolve_run(archive_dir.path(), "no_such_run").is_err());
+}
+
+#[test]
+fn test_experiment_grouping() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("test_script.py");
+    fs::write(&script_path, "print('Hello')").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        experiment: Some("mesh-study".to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let first = run_script(&cli).unwrap().run_dir;
+    let second = run_script(&cli).unwrap().run_dir;
+    run_script(&Cli { experiment: None, ..cli }).unwrap();
+
+    // Member runs are linked from the experiment folder
+    let experiment_dir = archive_dir.path().join("experiments").join("mesh-study");
+    for run in [&first, &second] {
+        let link = experiment_dir.join(Path::new(run).file_name().unwrap());
+        assert!(link.join("fastsave.yaml").is_file(), "{} should link to the run", link.display());
+    }
+
+    let members = fastsave::list::list_runs(archive_dir.path(), Some("mesh-study")).unwrap();
+    assert_eq!(members.len(), 2);
+    assert!(members.iter().all(|run| run.result.experiment.as_deref() == Some("mesh-study")));
+
+    // The links don't make runs show up twice
+    assert_eq!(fastsave::list::list_runs(archive_dir.path(), None).unwrap().len(), 3);
+
+    assert!(fastsave::App::try_parse_from(["fastsave", "-e", "../escape", "script.py"]).is_err());
+}
+
+#[test]
+fn test_run_lineage() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("step.py");
+    fs::write(&script_path, "print('step')").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let first = run_script(&cli).unwrap().run_dir;
+    let first_id = Path::new(&first).file_name().unwrap().to_string_lossy().to_string();
+
+    // Parents may be given by path and are recorded by run id
+    let second = run_script(&Cli { parents: vec![first.to_string_lossy().into_owned()], ..test_cli(&script_path, archive_dir.path()) }).unwrap().run_dir;
+    let second_id = Path::new(&second).file_name().unwrap().to_string_lossy().to_string();
+    let third = run_script(&Cli { parents: vec![second_id.clone()], ..test_cli(&script_path, archive_dir.path()) }).unwrap().run_dir;
+
+    let result = fastsave::archive::load_manifest(&Path::new(&second).join("fastsave.yaml")).unwrap();
+    assert_eq!(result.parents, vec![first_id.clone()]);
+
+    let run = fastsave::archive::ArchivedRun {
+        run_dir: PathBuf::from(&third),
+        result: fastsave::archive::load_manifest(&Path::new(&third).join("fastsave.yaml")).unwrap(),
+    };
+    let chain: Vec<(usize, String)> = fastsave::show::lineage(archive_dir.path(), &run)
+        .into_iter()
+        .map(|entry| (entry.depth, entry.id))
+        .collect();
+    assert_eq!(chain, vec![(0, run.id()), (1, second_id), (2, first_id)]);
+    assert!(fastsave::show::format_run(archive_dir.path(), &run).contains("Lineage:"));
+
+    // Unknown parents are rejected before the script runs
+    let missing = Cli { parents: vec!["no_such_run".to_string()], ..test_cli(&script_path, archive_dir.path()) };
+    assert!(run_script(&missing).is_err());
+    assert_eq!(fastsave::list::list_runs(archive_dir.path(), None).unwrap().len(), 3);
+}
+
+#[test]
+fn test_baseline_regression_detection() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+parser.add_argument('--value', default='1')
+args = parser.parse_args()
+(Path(args.output_dir)/'result.txt').write_text(args.value)
+print(f'value={args.value}')
+"#;
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, script_content).unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, "metrics:\n  patterns: ['value=(?P<value>\\d+)']\n").unwrap();
+
+    let run_with = |value: &str| {
+        let cli = Cli {
+            interpreter: Some("python3".to_string()),
+            config_path: Some(config_path.to_string_lossy().to_string()),
+            script_args: vec!["--value".to_string(), value.to_string()],
+            ..test_cli(&script_path, archive_dir.path())
+        };
+        let output_dir = run_script(&cli).unwrap().run_dir;
+        fastsave::archive::load_manifest(&Path::new(&output_dir).join("fastsave.yaml")).unwrap()
+    };
+
+    let first = run_with("1");
+    assert!(first.baseline.is_none(), "No baseline is set yet");
+    let first_id = fastsave::list::list_runs(archive_dir.path(), None).unwrap()[0].id();
+    assert_eq!(fastsave::baseline::set_baseline(archive_dir.path(), &first_id).unwrap(), "solver");
+
+    let same = run_with("1").baseline.expect("Run should be compared with the baseline");
+    assert_eq!(same.baseline_run, first_id);
+    assert!(!same.regression);
+
+    let different = run_with("2").baseline.unwrap();
+    assert!(different.regression);
+    assert_eq!(different.differences.changed_files, vec!["result.txt"]);
+    assert_eq!(different.differences.changed_metrics, vec!["value"]);
+
+    assert!(fastsave::baseline::clear_baseline(archive_dir.path(), "solver").unwrap());
+    assert!(run_with("2").baseline.is_none());
+}
+
+#[test]
+fn test_check_against_reference_manifest() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+parser.add_argument('--value', default='1')
+parser.add_argument('--residual', default='0.5')
+args = parser.parse_args()
+(Path(args.output_dir)/'result.txt').write_text(args.value)
+print(f'residual={args.residual}')
+"#;
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, script_content).unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, "metrics:\n  patterns: ['residual=(?P<residual>[\\d.]+)']\n").unwrap();
+
+    let cli_with = |args: &[&str]| Cli {
+        interpreter: Some("python3".to_string()),
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        script_args: args.iter().map(|a| a.to_string()).collect(),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    // Commit the manifest of a good run as the reference
+    let reference_dir = run_script(&cli_with(&[])).unwrap().run_dir;
+    let reference = archive_dir.path().join("reference.yaml");
+    fs::copy(Path::new(&reference_dir).join("fastsave.yaml"), &reference).unwrap();
+
+    let check = |args: &[&str], metrics: Vec<(String, f64)>| {
+        run_script(&Cli {
+            check_against: Some(reference.to_string_lossy().to_string()),
+            check_metrics: metrics,
+            ..cli_with(args)
+        })
+        .map(|run| run.run_dir)
+    };
+
+    // Small metric drift is accepted within the tolerance
+    let output_dir = check(&["--residual", "0.5001"], vec![("residual".to_string(), 1e-3)]).unwrap();
+    let result = fastsave::archive::load_manifest(&Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    assert!(result.check.unwrap().passed);
+
+    assert!(check(&["--residual", "0.6"], vec![("residual".to_string(), 1e-3)]).is_err());
+    assert!(check(&["--residual", "0.5001"], vec![("residual".to_string(), 0.0)]).is_err());
+
+    // A changed output file fails the check, and the manifest records why
+    let err = check(&["--value", "2"], vec![]).unwrap_err().to_string();
+    assert!(err.contains("result.txt"), "unexpected error: {}", err);
+    let latest = fastsave::list::list_runs(archive_dir.path(), None).unwrap().pop().unwrap();
+    let recorded = latest.result.check.expect("Check result should be saved");
+    assert!(!recorded.passed);
+    assert_eq!(recorded.differences.changed_files, vec!["result.txt"]);
+
+    assert!(fastsave::check::parse_metric_tolerance("loss=abc").is_err());
+    assert_eq!(fastsave::check::parse_metric_tolerance("loss").unwrap(), ("loss".to_string(), 0.0));
+}
+
+#[test]
+fn test_junit_report() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("failing.py");
+    fs::write(&script_path, "import sys\nprint('partial <output>')\nsys.exit('solver diverged & gave up')\n").unwrap();
+    let junit_path = archive_dir.path().join("junit.xml");
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        junit: Some(junit_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    run_script(&cli).unwrap();
+
+    let xml = fs::read_to_string(&junit_path).unwrap();
+    assert!(xml.contains("<testsuite name=\"fastsave\" tests=\"1\" failures=\"1\""));
+    assert!(xml.contains("classname=\"failing\""));
+    assert!(xml.contains("<failure message=\"Script exited with code 1\">solver diverged &amp; gave up"));
+    assert!(xml.contains("partial &lt;output&gt;"));
+}
+
+#[test]
+fn test_benchmark_repetitions() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, "print('solving')").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        repeat: Some(3),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let (benchmark_dir, returned) = fastsave::benchmark::run_benchmark(&cli, 3, 0).unwrap();
+    assert!(benchmark_dir.contains("solver_benchmark1"));
+
+    let summary: fastsave::benchmark::BenchmarkResult = serde_yaml::from_str(
+        &fs::read_to_string(Path::new(&benchmark_dir).join("benchmark.yaml")).unwrap()
+    ).unwrap();
+    assert_eq!(summary.repetitions, 3);
+    assert_eq!(summary.runs.len(), 3);
+    let stats = summary.duration.as_ref().unwrap();
+    assert!(stats.min_ms <= stats.max_ms);
+    assert!(returned.summary().starts_with("Duration over 3 runs: min "));
+
+    // CPU time and peak memory are aggregated like the duration
+    if cfg!(target_os = "linux") {
+        let memory = summary.peak_memory_bytes.as_ref().unwrap();
+        assert!(memory.min > 0.0 && memory.min <= memory.mean && memory.mean <= memory.max);
+        assert!(summary.runs.iter().all(|run| run.cpu_seconds.is_some()));
+        assert_eq!(summary.cpu_seconds, fastsave::benchmark::stats(&summary.runs.iter().filter_map(|run| run.cpu_seconds).collect::<Vec<_>>()));
+        assert!(returned.summary().contains("\nCPU time: min ") && returned.summary().contains("\nPeak memory: min "));
+    }
+
+    // Each repetition is a regular archived run
+    let runs = fastsave::list::list_runs(archive_dir.path(), None).unwrap();
+    let ids: Vec<String> = runs.iter().map(|run| run.id()).collect();
+    assert!(summary.runs.iter().all(|run| ids.contains(&run.id)));
+
+    let stats = fastsave::benchmark::duration_stats(&[10, 20, 30]).unwrap();
+    assert_eq!((stats.min_ms, stats.max_ms, stats.mean_ms, stats.stddev_ms), (10, 30, 20.0, 10.0));
+    let stats = fastsave::benchmark::stats(&[1.5, 0.5]).unwrap();
+    assert_eq!((stats.min, stats.max, stats.mean), (0.5, 1.5, 1.0));
+    assert!(fastsave::benchmark::stats(&[]).is_none());
+}
+
+#[test]
+fn test_benchmark_warmup() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, "print('solving')").unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let (benchmark_dir, _) = fastsave::benchmark::run_benchmark(&cli, 2, 1).unwrap();
+
+    let summary: fastsave::benchmark::BenchmarkResult = serde_yaml::from_str(
+        &fs::read_to_string(Path::new(&benchmark_dir).join("benchmark.yaml")).unwrap()
+    ).unwrap();
+    assert_eq!(summary.warmup, 1);
+    assert_eq!(summary.runs.iter().map(|run| run.warmup).collect::<Vec<_>>(), vec![true, false, false]);
+
+    // Statistics only cover the measured runs
+    let measured: Vec<u64> = summary.runs[1..].iter().map(|run| run.duration_ms).collect();
+    assert_eq!(summary.duration, fastsave::benchmark::duration_stats(&measured));
+
+    // Warmup runs are still archived
+    assert_eq!(fastsave::list::list_runs(archive_dir.path(), None).unwrap().len(), 3);
+
+    assert!(fastsave::App::try_parse_from(["fastsave", "--warmup", "2", "script.py"]).is_err());
+}
+
+#[test]
+fn test_benchmark_failed_checks_and_per_host_layout() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, "layout:\n  per_host: true\n").unwrap();
+    let script_path = dir.path().join("solver.sh");
+    fs::write(&script_path, "echo solving").unwrap();
+
+    // A repetition failing --expect is recorded and the benchmark keeps going
+    let cli = Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        expect: vec!["result.txt".to_string()],
+        ..test_cli(&script_path, &archive_dir)
+    };
+    let (benchmark_dir, returned) = fastsave::benchmark::run_benchmark(&cli, 2, 0).unwrap();
+    assert_eq!(returned.failed(), 2);
+    assert!(returned.runs.iter().all(|run| run.error.as_deref().unwrap().contains("result.txt")));
+    assert!(returned.summary().contains(&format!("Run {} failed: ", returned.runs[0].id)));
+    let summary: fastsave::benchmark::BenchmarkResult = serde_yaml::from_str(
+        &fs::read_to_string(Path::new(&benchmark_dir).join("benchmark.yaml")).unwrap()
+    ).unwrap();
+    assert_eq!(summary.runs.len(), 2);
+    assert!(summary.duration.is_some());
+
+    // The benchmark directory sits next to its runs in the host directory
+    let host_dir = archive_dir.join(fastsave::started::host_name());
+    assert_eq!(Path::new(&benchmark_dir).parent().unwrap(), host_dir);
+    assert_eq!(fastsave::archive::find_runs(&archive_dir).unwrap().len(), 2);
+
+    // A run that cannot start still leaves the summary of the runs so far
+    let broken = Cli { interpreter: Some("no-such-interpreter".to_string()), ..cli };
+    assert!(fastsave::benchmark::run_benchmark(&broken, 2, 0).is_err());
+    let summary_path = benchmark_dir.replace("solver_benchmark1", "solver_benchmark2") + "/benchmark.yaml";
+    let summary: fastsave::benchmark::BenchmarkResult = serde_yaml::from_str(&fs::read_to_string(summary_path).unwrap()).unwrap();
+    assert!(summary.runs.is_empty());
+}
+
+#[test]
+fn test_determinism_check() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse, random
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+args = parser.parse_args()
+out = Path(args.output_dir)
+(out/'fixed.txt').write_text('always the same')
+(out/'noisy.txt').write_text(str(random.random()))
+"#;
+    let script_path = archive_dir.path().join("simulate.py");
+    fs::write(&script_path, script_content).unwrap();
+
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        determinism_check: true,
+        ..test_cli(&script_path, archive_dir.path())
+    };
+    let report = fastsave::determinism::determinism_check(&cli).unwrap();
+
+    assert!(!report.is_deterministic());
+    assert_eq!(report.differences.changed_files, vec!["noisy.txt"]);
+    assert_ne!(report.first_run, report.second_run);
+    assert!(fastsave::determinism::run_determinism_check(&cli).is_err());
+
+    fs::write(&script_path, script_content.replace("str(random.random())", "'seeded'")).unwrap();
+    assert!(fastsave::determinism::determinism_check(&cli).unwrap().is_deterministic());
+}
+
+#[test]
+fn test_diff_with_numeric_tolerance() {
+    use fastsave::diff::FileStatus;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse, json
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+parser.add_argument('--eps', type=float, default=0.0)
+args = parser.parse_args()
+out = Path(args.output_dir)
+(out/'values.csv').write_text(f'x,y\n1,{2.0 + args.eps!r}\n3,4\n')
+(out/'summary.json').write_text(json.dumps({'norm': 10.0 + args.eps, 'label': 'run'}))
+(out/'static.txt').write_text('unchanged')
+"#;
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, script_content).unwrap();
+
+    let run_with = |eps: &str, tar: bool| {
+        run_script(&Cli {
+            interpreter: Some("python3".to_string()),
+            script_args: vec!["--eps".to_string(), eps.to_string()],
+            tar,
+            ..test_cli(&script_path, archive_dir.path())
+        }).unwrap().run_dir
+    };
+    let a = run_with("0", false);
+    let b = run_with("1e-12", false);
+
+    fn status_of<'a>(diff: &'a fastsave::diff::RunDiff, file: &str) -> &'a FileStatus {
+        &diff.files.iter().find(|f| f.file == file).unwrap().status
+    }
+
+    let exact = fastsave::diff::diff_runs(Path::new(&a), Path::new(&b), None).unwrap();
+    assert_eq!(status_of(&exact, "values.csv"), &FileStatus::Changed);
+    assert_eq!(status_of(&exact, "static.txt"), &FileStatus::Identical);
+
+    let tolerant = fastsave::diff::diff_runs(Path::new(&a), Path::new(&b), Some(1e-9)).unwrap();
+    assert_eq!(status_of(&tolerant, "values.csv"), &FileStatus::WithinTolerance);
+    assert_eq!(status_of(&tolerant, "summary.json"), &FileStatus::WithinTolerance);
+    let deviation = tolerant.files.iter().find(|f| f.file == "values.csv").unwrap().deviation.as_ref().unwrap();
+    assert!(deviation.max_abs > 0.0 && deviation.max_abs < 1e-11);
+
+    let strict = fastsave::diff::diff_runs(Path::new(&a), Path::new(&b), Some(1e-15)).unwrap();
+    assert_eq!(status_of(&strict, "values.csv"), &FileStatus::ExceedsTolerance);
+    assert!(fastsave::diff::format_diff(&strict).contains("exceeds tolerance"));
+
+    // Packed runs are compared inside their run.tar
+    let packed = fastsave::diff::diff_runs(&run_with("0", true), &run_with("1e-12", true), Some(1e-9)).unwrap();
+    assert_eq!(status_of(&packed, "values.csv"), &FileStatus::WithinTolerance);
+    assert_eq!(status_of(&packed, "summary.json"), &FileStatus::WithinTolerance);
+
+    // NaN or infinity on one side exceeds any tolerance; the same value on both sides doesn't
+    let nan = fastsave::diff::diff_runs(Path::new(&a), &run_with("nan", false), Some(1e9)).unwrap();
+    assert_eq!(status_of(&nan, "values.csv"), &FileStatus::ExceedsTolerance);
+    let deviation = |a: &str, b: &str| fastsave::diff::numeric_deviation("res.csv", a, b).unwrap();
+    assert_eq!(deviation("1.0", "nan").max_abs, f64::INFINITY);
+    assert_eq!(deviation("inf", "1e308").max_rel, f64::INFINITY);
+    assert_eq!(deviation("-inf", "inf").max_abs, f64::INFINITY);
+    assert_eq!(deviation("nan,inf,1", "NaN,inf,1"), fastsave::diff::NumericDeviation::default());
+}
+
+#[test]
+fn test_diff_file_content() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_content = r#"
+import argparse
+from pathlib import Path
+
+parser = argparse.ArgumentParser()
+parser.add_argument('--output_dir', default='')
+parser.add_argument('--residual', default='0.5')
+args = parser.parse_args()
+lines = [f'step {i}' for i in range(10)] + [f'residual {args.residual}']
+(Path(args.output_dir)/'log.txt').write_text('\n'.join(lines) + '\n')
+"#;
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, script_content).unwrap();
+
+    let run_with = |residual: &str| {
+        run_script(&Cli {
+            interpreter: Some("python3".to_string()),
+            script_args: vec!["--residual".to_string(), residual.to_string()],
+            ..test_cli(&script_path, archive_dir.path())
+        }).unwrap().run_dir
+    };
+    let a = run_with("0.5");
+    let b = run_with("0.7");
+
+    let unified = fastsave::diff::content_diff(Path::new(&a), Path::new(&b), "log.txt").unwrap();
+    assert!(unified.contains("--- A/log.txt"));
+    assert!(unified.contains("-residual 0.5\n+residual 0.7"));
+    // Only the context around the change is shown
+    assert!(!unified.contains("step 0"));
+}
+
+#[test]
+fn test_grep_across_runs() {
+    let archive_dir = TempDir::new().unwrap();
+    let solver = archive_dir.path().join("solver.py");
+    fs::write(&solver, "import sys\nfor i in range(5):\n    print(f'step {i}')\nprint('NaN encountered', file=sys.stderr)\n").unwrap();
+    let other = archive_dir.path().join("other.py");
+    fs::write(&other, "print('NaN encountered in other')").unwrap();
+
+    for script in [&solver, &other] {
+        run_script(&Cli { interpreter: Some("python3".to_string()), ..test_cli(script, archive_dir.path()) }).unwrap();
+    }
+
+    let pattern = regex::Regex::new("NaN").unwrap();
+    let all = fastsave::grep::grep_runs(archive_dir.path(), &pattern, None, None, 1).unwrap();
+    assert_eq!(all.len(), 2);
+
+    let solver_only = fastsave::grep::grep_runs(archive_dir.path(), &pattern, Some("solver"), None, 1).unwrap();
+    assert_eq!(solver_only.len(), 1);
+    assert_eq!(solver_only[0].stream, "stderr");
+    assert_eq!(solver_only[0].line_number, 1);
+
+    let context = fastsave::grep::grep_runs(archive_dir.path(), &regex::Regex::new("step 2").unwrap(), None, None, 1).unwrap();
+    let numbers: Vec<usize> = context[0].lines.iter().map(|(n, _)| *n).collect();
+    assert_eq!(numbers, vec![2, 3, 4]);
+
+    let future = fastsave::archive::parse_since("2999-01-01").unwrap();
+    assert!(fastsave::grep::grep_runs(archive_dir.path(), &pattern, None, Some(future), 0).unwrap().is_empty());
+    assert!(fastsave::archive::parse_since("7d").unwrap() < chrono::Utc::now());
+    assert!(fastsave::archive::parse_since("soon").is_err());
+}
+
+#[cfg(feature = "search")]
+#[test]
+fn test_full_text_search() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.py");
+    fs::write(&script_path, "print('converged')").unwrap();
+
+    run_script(&Cli { message: Some("Divergence after refinement".to_string()), ..test_cli(&script_path, archive_dir.path()) }).unwrap();
+    let hits = fastsave::search::search(archive_dir.path(), "divergence", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].script, "solver");
+
+    // Runs after the index exists are added as they complete
+    let later = run_script(&Cli { message: Some("clean run".to_string()), ..test_cli(&script_path, archive_dir.path()) }).unwrap().run_dir;
+    let hits = fastsave::search::search(archive_dir.path(), "clean", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, fastsave::archive::run_id(Path::new(&later)));
+    assert_eq!(fastsave::search::search(archive_dir.path(), "converged", 10).unwrap().len(), 2);
+}
+
+#[cfg(feature = "tui")]
+#[test]
+fn test_ui_browser_filter_and_sort() {
+    use fastsave::tui::{Browser, SortKey};
+
+    let archive_dir = TempDir::new().unwrap();
+    let fast = archive_dir.path().join("fast.py");
+    fs::write(&fast, "print('done')").unwrap();
+    let slow = archive_dir.path().join("slow.py");
+    fs::write(&slow, "import time\ntime.sleep(0.2)").unwrap();
+
+    let fast_dir = run_script(&Cli { message: Some("baseline mesh".to_string()), ..test_cli(&fast, archive_dir.path()) }).unwrap().run_dir;
+    let slow_dir = run_script(&test_cli(&slow, archive_dir.path())).unwrap().run_dir;
+    let fast_id = fastsave::archive::run_id(Path::new(&fast_dir));
+    let slow_id = fastsave::archive::run_id(Path::new(&slow_dir));
+
+    let mut browser = Browser::new(archive_dir.path()).unwrap();
+    assert_eq!(browser.visible_ids(), vec![slow_id.clone(), fast_id.clone()]);
+
+    browser.sort = SortKey::Script;
+    browser.refresh();
+    assert_eq!(browser.visible_ids(), vec![fast_id.clone(), slow_id]);
+
+    browser.filter = "MESH".to_string();
+    browser.refresh();
+    assert_eq!(browser.visible_ids(), vec![fast_id]);
+}
+
+#[cfg(feature = "tui")]
+#[test]
+fn test_ui_browser_tag_and_delete() {
+    use fastsave::archive::find_runs;
+    use fastsave::tui::Browser;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("sim.sh");
+    fs::write(&script_path, "echo done").unwrap();
+    let pinned = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    fastsave::pin::set_pinned(&pinned.run_dir, true).unwrap();
+    let flat = run_script(&Cli { no_subfolder: true, ..test_cli(&script_path, &archive_dir) }).unwrap();
+    let plain = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    let select = |id: &str| {
+        let mut browser = Browser::new(&archive_dir).unwrap();
+        browser.filter = id.to_string();
+        browser.refresh();
+        browser
+    };
+
+    let mut browser = select(&plain.id());
+    browser.tag_selected("paper draft").unwrap();
+    browser.tag_selected("-draft").unwrap();
+    assert_eq!(browser.status(), format!("Updated tags of {}", plain.id()));
+    assert!(browser.tag_selected("a,b").is_ok() && browser.status().contains("invalid tag"));
+    let tags = |run_dir: &Path| fastsave::archive::load_manifest(&run_dir.join("fastsave.yaml")).unwrap().tags;
+    assert_eq!(tags(&plain.run_dir), vec!["paper"]);
+
+    // Neither a pinned run nor one sharing the archive directory is deleted
+    let mut browser = select(&pinned.id());
+    browser.delete_selected().unwrap();
+    assert!(browser.status().contains("pinned"));
+    let mut browser = Browser::new(&archive_dir).unwrap();
+    browser.filter = "archive".to_string();
+    browser.refresh();
+    assert_eq!(browser.visible_ids(), vec![flat.id()]);
+    browser.delete_selected().unwrap();
+    assert!(browser.status().contains("--no-subfolder"));
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 3);
+
+    let mut browser = select(&plain.id());
+    browser.delete_selected().unwrap();
+    assert_eq!(browser.status(), format!("Deleted {}", plain.id()));
+    assert!(!plain.run_dir.exists());
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 2);
+}
+
+#[cfg(feature = "serve")]
+#[test]
+fn test_dashboard_pages() {
+    use fastsave::serve::Dashboard;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("plot.py");
+    fs::write(&script_path, r#"
+import sys, os
+output_dir = sys.argv[sys.argv.index('--output_dir') + 1]
+with open(os.path.join(output_dir, 'metrics.json'), 'w') as f:
+    f.write('{"loss": 0.5}')
+print('<done>')
+"#).unwrap();
+    let output_dir = run_script(&Cli { interpreter: Some("python3".to_string()), ..test_cli(&script_path, archive_dir.path()) }).unwrap().run_dir;
+    let id = fastsave::archive::run_id(Path::new(&output_dir));
+    let dashboard = Dashboard { archive_dir: archive_dir.path().to_path_buf(), run_token: None };
+    let get = |url: &str| dashboard.handle_request("GET", url, &[], b"").unwrap();
+    let body = |url: &str| String::from_utf8(get(url).body).unwrap();
+
+    assert!(body("/").contains(&format!("href=\"/runs/{}\"", id)));
+    assert!(!body("/?status=failed").contains(&id));
+    assert!(!body("/?script=other").contains(&id));
+
+    let page = body(&format!("/runs/{}", id));
+    assert!(page.contains("&lt;done&gt;"));
+    assert!(page.contains("metrics.json"));
+    assert_eq!(body(&format!("/runs/{}/files/metrics.json", id)), r#"{"loss": 0.5}"#);
+    assert!(body("/metrics").contains("<svg"));
+
+    assert_eq!(get(&format!("/runs/{}/files/..%2F..%2Fsecret", id)).status, 400);
+    assert_eq!(get("/runs/missing").status, 404);
+    assert_eq!(dashboard.handle_request("POST", "/", &[], b"").unwrap().status, 405);
+
+    // Outputs of the script can't act as the dashboard
+    fs::write(output_dir.join("report.html"), "<script>fetch('/api/runs')</script>").unwrap();
+    let report = get(&format!("/runs/{}/files/report.html", id));
+    assert_eq!(report.headers, vec![("Content-Security-Policy", "sandbox")]);
+    assert!(get(&format!("/runs/{}/files/metrics.json", id)).headers.is_empty());
+}
+
+#[cfg(feature = "serve")]
+#[test]
+fn test_http_api() {
+    use fastsave::serve::Dashboard;
+
+    let _guard = lock_cwd();
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("api.sh"), "for last; do :; done\necho \"$last\"").unwrap();
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("evil.sh"), "echo pwned").unwrap();
+    let json = |response: fastsave::serve::Response| serde_json::from_slice::<serde_json::Value>(&response.body).unwrap();
+    let submit = |script: &str| serde_json::json!({ "script": script, "args": ["--rows", "7"], "metadata": {"dataset": "v2"} }).to_string();
+    let api_json = [("content-type", "application/json"), ("Host", "localhost:8080"), ("Authorization", "Bearer secret")];
+
+    let read_only = Dashboard { archive_dir: dir.path().join("archive"), run_token: None };
+    assert_eq!(read_only.handle_request("POST", "/api/runs", &api_json, submit("api.sh").as_bytes()).unwrap().status, 403);
+
+    let original_dir = std::env::current_dir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    let dashboard = Dashboard { archive_dir: dir.path().join("archive"), run_token: Some("secret".to_string()) };
+    let post = |headers: &[(&str, &str)], body: &str| dashboard.handle_request("POST", "/api/runs", headers, body.as_bytes()).unwrap();
+    let created = post(&[("Content-Type", "application/json; charset=utf-8"), ("Origin", "http://localhost:8080"), ("Host", "localhost:8080"), ("Authorization", "Bearer secret")], &submit("api.sh"));
+    // A page elsewhere can send a simple request without a preflight, but not as JSON or from this origin
+    let plain = post(&[("Content-Type", "text/plain"), ("Authorization", "Bearer secret")], &submit("api.sh"));
+    let foreign = post(&[("Content-Type", "application/json"), ("Origin", "https://evil.example"), ("Host", "localhost:8080"), ("Authorization", "Bearer secret")], &submit("api.sh"));
+    // A page under a name rebound to the server is its own origin, but doesn't know the token
+    let rebound = post(&[("Content-Type", "application/json"), ("Origin", "http://evil.example:8080"), ("Host", "evil.example:8080")], &submit("api.sh"));
+    let wrong_token = post(&[("Content-Type", "application/json"), ("Authorization", "Bearer secreT")], &submit("api.sh"));
+    let absolute = post(&api_json, &submit(&outside.path().join("evil.sh").to_string_lossy()));
+    let parent = post(&api_json, &submit("../evil.sh"));
+    let interpreter = post(&api_json, &serde_json::json!({ "script": "api.sh", "interpreter": "python3" }).to_string());
+    let not_json = post(&api_json, "not json");
+    std::env::set_current_dir(original_dir).unwrap();
+
+    assert_eq!(created.status, 201);
+    let created = json(created);
+    let id = created["id"].as_str().unwrap().to_string();
+    assert_eq!(created["stdout"], "7\n");
+    assert_eq!(created["metadata"]["dataset"], "v2");
+    for rejected in [plain, foreign, rebound, wrong_token, absolute, parent] {
+        assert_eq!(rejected.status, 403, "{}", String::from_utf8_lossy(&rejected.body));
+    }
+    assert_eq!(interpreter.status, 400);
+    assert_eq!(not_json.status, 400);
+
+    let runs = json(dashboard.handle_request("GET", "/api/runs", &[], b"").unwrap());
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+    assert_eq!(runs[0]["id"], id.as_str());
+    assert_eq!(runs[0]["script"], "api");
+
+    let run = json(dashboard.handle_request("GET", &format!("/api/runs/{}", id), &[], b"").unwrap());
+    assert_eq!(run["exit_code"], 0);
+    assert_eq!(dashboard.handle_request("GET", "/api/runs/missing", &[], b"").unwrap().status, 404);
+}
+
+#[test]
+fn test_webhook_notifications() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("notify.py");
+    fs::write(&script_path, "print('done')").unwrap();
+
+    let (url, requests) = capture_http_requests();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, format!(r#"
+notifications:
+  dashboard_url: http://dashboard:8080/
+  webhooks:
+    - url: {url}?only=failure
+      on: failure
+    - url: {url}
+"#)).unwrap();
+
+    let output_dir = run_script(&Cli {
+        message: Some("nightly".to_string()),
+        config_path: Some(config_path.to_str().unwrap().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap().run_dir;
+    let id = fastsave::archive::run_id(Path::new(&output_dir));
+
+    let body = requests.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["run_id"], id.as_str());
+    assert_eq!(payload["script"], "notify");
+    assert_eq!(payload["status"], "success");
+    assert_eq!(payload["exit_code"], 0);
+    assert_eq!(payload["message"], "nightly");
+    assert_eq!(payload["link"], format!("http://dashboard:8080/runs/{}", id));
+
+    // The failure-only webhook is not called for a successful run
+    assert!(requests.recv_timeout(std::time::Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn test_chat_notifications() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.sh");
+    fs::write(&script_path, "echo 'diverged' >&2\nexit 3").unwrap();
+
+    let (url, requests) = capture_http_requests();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, format!(r#"
+notifications:
+  chat:
+    - url: {url}?long
+      min_duration: 1h
+    - url: {url}
+      service: mattermost
+      channel: simulations
+      on: failure
+"#)).unwrap();
+
+    let output_dir = run_script(&Cli {
+        message: Some("overnight".to_string()),
+        config_path: Some(config_path.to_str().unwrap().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap().run_dir;
+    let id = fastsave::archive::run_id(Path::new(&output_dir));
+
+    let body = requests.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["channel"], "simulations");
+    let text = payload["text"].as_str().unwrap();
+    assert!(text.starts_with(":x: *solver* failed (exit code 3) after"));
+    assert!(text.contains("\n> overnight\n"));
+    assert!(text.ends_with(&format!("[{}]({})", id, fs::canonicalize(&output_dir).unwrap().display())));
+
+    // The run was far shorter than the 1h threshold of the Slack entry
+    assert!(requests.recv_timeout(std::time::Duration::from_millis(200)).is_err());
+    assert_eq!(fastsave::notify::format_duration(3_723_000), "1h 02m");
+}
+
+#[cfg(feature = "email")]
+#[test]
+fn test_email_notifications() {
+    use std::io::{BufRead, BufReader, Write};
+
+    // Minimal SMTP server accepting a single message
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, received) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+        let mut transcript = String::new();
+        let mut in_data = false;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            transcript.push_str(&line);
+            let reply: &[u8] = if in_data {
+                if line == ".\r\n" { in_data = false; b"250 OK\r\n" } else { b"" }
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 Go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                stream.write_all(b"221 Bye\r\n").unwrap();
+                break;
+            } else {
+                b"250 OK\r\n"
+            };
+            stream.write_all(reply).unwrap();
+            line.clear();
+        }
+        let _ = sender.send(transcript);
+    });
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.sh");
+    fs::write(&script_path, "exit 2").unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, format!(r#"
+notifications:
+  email:
+    - smtp_server: 127.0.0.1
+      port: {port}
+      security: none
+      from: fastsave@example.com
+      to: [lab@example.com]
+      on: failure
+      subject: "{{script}} {{status}} with exit code {{exit_code}}"
+"#)).unwrap();
+
+    let output_dir = run_script(&Cli {
+        config_path: Some(config_path.to_str().unwrap().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap().run_dir;
+
+    let transcript = received.recv_timeout(std::time::Duration::from_secs(10)).unwrap();
+    assert!(transcript.contains("RCPT TO:<lab@example.com>"));
+    assert!(transcript.contains("Subject: solver failure with exit code 2"));
+    assert!(transcript.contains(&format!("Run:        {}", fastsave::archive::run_id(Path::new(&output_dir)))));
+}
+
+#[test]
+fn test_notify_arguments() {
+    let cli = Cli::try_parse_from(["fastsave", "--notify", "--notify-after", "10m", "script.py"]).unwrap();
+    assert!(cli.notify);
+    assert_eq!(cli.notify_after, Some(chrono::Duration::minutes(10)));
+    assert_eq!(cli.script, "script.py");
+
+    assert!(Cli::try_parse_from(["fastsave", "--notify-after", "10m", "script.py"]).is_err());
+    assert!(Cli::try_parse_from(["fastsave", "--notify", "--notify-after", "soon", "script.py"]).is_err());
+
+    // Runs below the threshold complete without trying to notify
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("quick.sh");
+    fs::write(&script_path, "true").unwrap();
+    run_script(&Cli { notify: true, notify_after: Some(chrono::Duration::hours(1)), ..test_cli(&script_path, archive_dir.path()) }).unwrap();
+}
+
+#[test]
+fn test_pre_and_post_run_hooks() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("hooked.py");
+    fs::write(&script_path, r#"
+import sys, os
+output_dir = sys.argv[sys.argv.index('--output_dir') + 1]
+print(open(os.path.join(output_dir, 'mounted.txt')).read())
+sys.exit(4)
+"#).unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, r#"
+hooks:
+  pre_run:
+    - echo mounted > "$FASTSAVE_OUTPUT_DIR/mounted.txt" && echo "pre $FASTSAVE_RUN_ID"
+  post_run:
+    - echo "post exit=$FASTSAVE_EXIT_CODE"; test -f "$FASTSAVE_OUTPUT_DIR/fastsave.yaml"
+"#).unwrap();
+    let cli = Cli {
+        interpreter: Some("python3".to_string()),
+        config_path: Some(config_path.to_str().unwrap().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    let output_dir = run_script(&cli).unwrap().run_dir;
+    let yaml_content = fs::read_to_string(Path::new(&output_dir).join("fastsave.yaml")).unwrap();
+    let result: ExecutionResult = serde_yaml::from_str(&yaml_content).unwrap();
+    assert_eq!(result.stdout.trim(), "mounted");
+    assert_eq!(result.hooks.len(), 2);
+    assert_eq!(result.hooks[0].stage, "pre_run");
+    assert_eq!(result.hooks[0].stdout.trim(), format!("pre {}", fastsave::archive::run_id(Path::new(&output_dir))));
+    assert_eq!(result.hooks[1].stage, "post_run");
+    assert_eq!(result.hooks[1].stdout.trim(), "post exit=4");
+    assert_eq!(result.hooks[1].exit_code, 0);
+
+    // A failing pre-run hook prevents the script from running
+    fs::write(&config_path, "hooks:\n  pre_run:\n    - echo 'no dataset' >&2; exit 1\n").unwrap();
+    let error = run_script(&cli).err().unwrap().to_string();
+    assert!(error.contains("Pre-run hook"), "{}", error);
+    assert!(error.contains("no dataset"), "{}", error);
+}
+
+#[test]
+fn test_on_failure_hooks() {
+    let archive_dir = TempDir::new().unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, r#"
+hooks:
+  on_failure:
+    - 'echo "collected from $FASTSAVE_OUTPUT_DIR"; grep -c Segfault'
+"#).unwrap();
+    let run = |name: &str, code: &str| {
+        let script_path = archive_dir.path().join(name);
+        fs::write(&script_path, code).unwrap();
+        let run = run_script(&Cli {
+            config_path: Some(config_path.to_str().unwrap().to_string()),
+            ..test_cli(&script_path, archive_dir.path())
+        }).unwrap();
+        (run.run_dir, run.result)
+    };
+
+    let (_, ok) = run("ok.sh", "echo fine");
+    assert!(ok.hooks.is_empty());
+
+    let (output_dir, failed) = run("crash.sh", "echo 'Segfault in solver' >&2\nexit 139");
+    assert_eq!(failed.hooks.len(), 1);
+    let hook = &failed.hooks[0];
+    assert_eq!(hook.stage, "on_failure");
+    assert_eq!(hook.stdout, format!("collected from {}\n1\n", output_dir.display()));
+    assert_eq!(hook.exit_code, 0);
+}
+
+#[test]
+fn test_run_observer() {
+    use fastsave::observer::RunObserver;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RunObserver for Recorder {
+        fn on_start(&self, _script_path: &str, output_dir: &str) {
+            self.events.lock().unwrap().push(format!("start {}", Path::new(output_dir).is_dir()));
+        }
+        fn on_stdout_line(&self, line: &str) {
+            self.events.lock().unwrap().push(format!("out {}", line));
+        }
+        fn on_stderr_line(&self, line: &str) {
+            self.events.lock().unwrap().push(format!("err {}", line));
+        }
+        fn on_complete(&self, result: &ExecutionResult) {
+            self.events.lock().unwrap().push(format!("complete {} {}", result.exit_code, result.file_hashes.len()));
+        }
+    }
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("progress.sh");
+    fs::write(&script_path, "echo 'step 1'\necho 'step 2'\necho 'warning' >&2\ntouch \"$2/out.txt\"").unwrap();
+
+    let recorder = Recorder::default();
+    fastsave::run_script_with_observer(&test_cli(&script_path, archive_dir.path()), &recorder).unwrap();
+
+    let events = recorder.events.into_inner().unwrap();
+    assert_eq!(events.first().unwrap(), "start true");
+    assert_eq!(events.last().unwrap(), "complete 0 1");
+    let stdout: Vec<&String> = events.iter().filter(|e| e.starts_with("out ")).collect();
+    assert_eq!(stdout, vec!["out step 1", "out step 2"]);
+    assert!(events.contains(&"err warning".to_string()));
+}
+
+#[test]
+fn test_runner_builder() {
+    use fastsave::runner::Runner;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("args.sh");
+    fs::write(&script_path, "echo \"$3 $4\" > \"$2/args.txt\"").unwrap();
+
+    let run = Runner::new(script_path.to_string_lossy())
+        .arg("--epochs")
+        .arg("10")
+        .interpreter("bash")
+        .archive_dir(archive_dir.path().to_string_lossy())
+        .message("from the builder")
+        .meta("dataset", "v2")
+        .execute()
+        .unwrap();
+
+    assert!(run.run_dir.starts_with(archive_dir.path()));
+    assert_eq!(fs::read_to_string(run.run_dir.join("args.txt")).unwrap(), "--epochs 10\n");
+    assert_eq!(run.result.message.as_deref(), Some("from the builder"));
+    assert_eq!(run.result.metadata.get("dataset").map(String::as_str), Some("v2"));
+
+    let invalid = Runner::new(script_path.to_string_lossy())
+        .archive_dir(archive_dir.path().to_string_lossy())
+        .experiment("no/slashes")
+        .execute();
+    assert!(invalid.is_err());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_execute_script_async() {
+    use fastsave::async_run::{execute_script_async, OutputLine};
+    use fastsave::FastsaveConfig;
+
+    let temp_dir = TempDir::new().unwrap();
+    let output_dir = temp_dir.path().to_string_lossy().to_string();
+    let config = FastsaveConfig::default();
+    let script_path = temp_dir.path().join("stream.sh");
+    fs::write(&script_path, "echo 'line 1'\necho 'oops' >&2\necho 'line 2'").unwrap();
+    let slow_path = temp_dir.path().join("slow.sh");
+    fs::write(&slow_path, "echo 'started'\nsleep 30 &\necho $!\nwait\necho 'finished'").unwrap();
+
+    let mut slow = execute_script_async(&slow_path.to_string_lossy(), &output_dir, None, &[], None, &config).unwrap();
+    let mut run = execute_script_async(&script_path.to_string_lossy(), &output_dir, Some("async".to_string()), &[], None, &config).unwrap();
+
+    let mut lines = Vec::new();
+    while let Some(line) = run.next_line().await {
+        lines.push(line);
+    }
+    let stdout: Vec<&OutputLine> = lines.iter().filter(|line| matches!(line, OutputLine::Stdout(_))).collect();
+    assert_eq!(stdout, vec![&OutputLine::Stdout("line 1".to_string()), &OutputLine::Stdout("line 2".to_string())]);
+    assert!(lines.contains(&OutputLine::Stderr("oops".to_string())));
+
+    let result = run.wait().await.unwrap();
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.stdout, "line 1\nline 2\n");
+    assert_eq!(result.message.as_deref(), Some("async"));
+
+    // The slow script is still running; cancelling kills it, including its `sleep`
+    assert_eq!(slow.next_line().await, Some(OutputLine::Stdout("started".to_string())));
+    let Some(OutputLine::Stdout(sleep_pid)) = slow.next_line().await else { panic!("no pid of the sleep") };
+    let start = std::time::Instant::now();
+    slow.cancel();
+    let cancelled = slow.wait().await.unwrap();
+    assert!(start.elapsed().as_secs() < 10);
+    assert_eq!(cancelled.exit_code, -1);
+    assert_eq!(cancelled.stdout, format!("started\n{}\n", sleep_pid));
+    if cfg!(target_os = "linux") {
+        // Gone, or a zombie if nothing reaps orphans here; the kill may take a moment to land
+        let exited = || {
+            let state = fs::read_to_string(format!("/proc/{}/stat", sleep_pid)).unwrap_or_default();
+            state.is_empty() || state.rsplit_once(')').unwrap().1.trim_start().starts_with('Z')
+        };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !exited() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(exited(), "sleep {} survived the cancel", sleep_pid);
+    }
+
+    // Invalid UTF-8 doesn't stop the capture
+    let binary_path = temp_dir.path().join("binary.sh");
+    fs::write(&binary_path, "printf '\\377\\n'\nseq 1 20000").unwrap();
+    let binary = execute_script_async(&binary_path.to_string_lossy(), &output_dir, None, &[], None, &config).unwrap().wait().await.unwrap();
+    assert_eq!(binary.exit_code, 0);
+    assert_eq!(binary.stdout.lines().count(), 20001);
+    assert!(binary.stdout.starts_with("\u{FFFD}\n1\n") && binary.stdout.ends_with("\n20000\n"));
+
+    // The cancelled run can be recorded like any other
+    fastsave::archive::save_manifest(temp_dir.path(), &cancelled).unwrap();
+    let recorded = fastsave::archive::load_manifest(&temp_dir.path().join("fastsave.yaml")).unwrap();
+    assert_eq!((recorded.exit_code, recorded.stdout), (-1, cancelled.stdout));
+}
+
+#[test]
+fn test_line_callbacks_and_channel() {
+    use fastsave::observer::{LineCallbacks, OutputLine};
+    use fastsave::runner::Runner;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("progress.sh");
+    fs::write(&script_path, "echo 'step 1'\necho 'loss 0.5'\necho 'step 2'\necho 'warning' >&2").unwrap();
+
+    let mut steps = Vec::new();
+    let mut warnings = 0;
+    let callbacks = LineCallbacks::new()
+        .on_stdout(|line| {
+            if let Some(step) = line.strip_prefix("step ") {
+                steps.push(step.to_string());
+            }
+        })
+        .on_stderr(|_| warnings += 1);
+    Runner::new(script_path.to_string_lossy())
+        .archive_dir(archive_dir.path().to_string_lossy())
+        .observer(&callbacks)
+        .execute()
+        .unwrap();
+    drop(callbacks);
+    assert_eq!(steps, vec!["1", "2"]);
+    assert_eq!(warnings, 1);
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    Runner::new(script_path.to_string_lossy())
+        .archive_dir(archive_dir.path().to_string_lossy())
+        .observer(&sender)
+        .execute()
+        .unwrap();
+    let lines: Vec<OutputLine> = receiver.try_iter().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines.iter().filter(|line| matches!(line, OutputLine::Stdout(_))).count(), 3);
+    assert!(lines.contains(&OutputLine::Stderr("warning".to_string())));
+}
+
+#[test]
+fn test_manifest_maps_are_sorted() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("many.sh");
+    fs::write(&script_path, "for f in zeta alpha mu beta; do echo $f > \"$2/$f.txt\"; done\necho 'loss: 0.1'\necho 'acc: 0.9'").unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "metrics:\n  patterns:\n    - 'loss: (?P<loss>[0-9.]+)'\n    - 'acc: (?P<acc>[0-9.]+)'\n").unwrap();
+
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        metadata: vec![("zone".to_string(), "b".to_string()), ("arch".to_string(), "x86".to_string())],
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+    // Positions of the keys within a top-level map of the manifest
+    let positions = |map: &str, keys: &[&str]| {
+        let section = &manifest[manifest.find(&format!("\n{}:\n", map)).unwrap()..];
+        keys.iter().map(|key| section.find(&format!("  {}:", key)).unwrap()).collect::<Vec<_>>()
+    };
+    assert!(positions("file_hashes", &["alpha.txt", "beta.txt", "mu.txt", "zeta.txt"]).is_sorted());
+    assert!(positions("metrics", &["acc", "loss"]).is_sorted());
+    assert!(positions("metadata", &["arch", "zone"]).is_sorted());
+}
+
+#[test]
+fn test_project_root_and_anonymized_paths() {
+    let home = TempDir::new().unwrap();
+    let scratch = TempDir::new().unwrap();
+    let setup = |root: &Path| {
+        let project = root.join("fastsave-tester").join("project");
+        fs::create_dir_all(project.join("scripts")).unwrap();
+        let script_path = project.join("scripts").join("train.sh");
+        fs::write(&script_path, "touch \"$2/out.txt\"").unwrap();
+        let config_path = project.join("fastsave.yaml");
+        fs::write(&config_path, format!("paths:\n  project_root: {}\n", project.display())).unwrap();
+        let cli = Cli {
+            config_path: Some(config_path.to_string_lossy().to_string()),
+            ..test_cli(&script_path, &project.join("archive"))
+        };
+        (cli, fs::canonicalize(script_path).unwrap())
+    };
+    let (in_home, in_home_script) = setup(home.path());
+    let (in_scratch, in_scratch_script) = setup(scratch.path());
+
+    // Relative to the configured root, even outside a git repository
+    let run = run_script(&in_scratch).unwrap();
+    assert_eq!(run.result.script_path, "scripts/train.sh");
+    assert_eq!(run.result.script_location(), in_scratch_script);
+
+    // Anonymized: the home directory becomes `~`, elsewhere the user name is replaced
+    let _guard = lock_cwd();
+    let previous = (std::env::var_os("HOME"), std::env::var_os("USER"));
+    std::env::set_var("HOME", fs::canonicalize(home.path()).unwrap());
+    std::env::set_var("USER", "fastsave-tester");
+    let home_run = run_script(&Cli { anonymize_paths: true, ..in_home });
+    // `~` resolves against the home of whoever reads the manifest
+    let home_location = home_run.as_ref().map(|run| run.result.script_location()).ok();
+    let scratch_run = run_script(&Cli { anonymize_paths: true, ..in_scratch });
+    for (key, value) in [("HOME", previous.0), ("USER", previous.1)] {
+        match value {
+            Some(value) => std::env::set_var(key, value),
+            None => std::env::remove_var(key),
+        }
+    }
+
+    let home_run = home_run.unwrap();
+    assert_eq!(home_run.result.script_path, "scripts/train.sh");
+    assert_eq!(home_run.result.project_root.as_deref(), Some("~/fastsave-tester/project"));
+    assert_eq!(home_location, Some(in_home_script));
+
+    let scratch_run = scratch_run.unwrap();
+    let root = scratch_run.result.project_root.unwrap();
+    assert!(root.ends_with("<user>/project"), "{}", root);
+    assert!(!scratch_run.result.command_line.contains("fastsave-tester"), "{}", scratch_run.result.command_line);
+}
+
+#[test]
+fn test_secret_redaction() {
+    use fastsave::observer::{LineCallbacks, NoObserver};
+
+    let archive_dir = TempDir::new().unwrap();
+    std::env::set_var("FASTSAVE_TEST_API_TOKEN", "tok-0123456789abcdef");
+    std::env::set_var("FASTSAVE_TEST_DEPLOY_ID", "deploy-42-production");
+    let script_path = archive_dir.path().join("leaky.sh");
+    fs::write(&script_path, "echo \"using $FASTSAVE_TEST_API_TOKEN\"\necho 'db password=hunter22 port=5432' >&2\necho \"target $FASTSAVE_TEST_DEPLOY_ID\"").unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, r#"
+redaction:
+  patterns:
+    - 'password=(\S+)'
+  env_vars:
+    - FASTSAVE_TEST_DEPLOY_ID
+hooks:
+  post_run:
+    - echo "hook sees $FASTSAVE_TEST_API_TOKEN"
+"#).unwrap();
+
+    let mut streamed = Vec::new();
+    let callbacks = LineCallbacks::new().on_stdout(|line| streamed.push(line.to_string()));
+    let run = fastsave::run_script_with_observer(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }, &callbacks).unwrap();
+    drop(callbacks);
+
+    assert_eq!(run.result.stdout, "using [REDACTED]\ntarget [REDACTED]\n");
+    assert_eq!(run.result.stderr, "db password=[REDACTED] port=5432\n");
+    assert_eq!(run.result.hooks[0].stdout, "hook sees [REDACTED]\n");
+    assert_eq!(streamed[0], "using [REDACTED]");
+    let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+    assert!(!manifest.contains("tok-0123456789abcdef") && !manifest.contains("hunter22"));
+
+    // Detection by name can be turned off; explicitly listed variables are still redacted
+    fs::write(&config_path, "redaction:\n  detect_env: false\n  env_vars: [FASTSAVE_TEST_DEPLOY_ID]\n").unwrap();
+    let result = fastsave::execute_script_observed(
+        &script_path.to_string_lossy(), &archive_dir.path().to_string_lossy(), None, &[], None,
+        &fastsave::FastsaveConfig::load_with_config_path(config_path.to_str()), &NoObserver,
+    ).unwrap();
+    assert_eq!(result.stdout, "using tok-0123456789abcdef\ntarget [REDACTED]\n");
+}
+
+#[test]
+fn test_expected_outputs() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("plots.sh");
+    fs::write(&script_path, "echo data > \"$2/matrix.txt\"\nmkdir \"$2/plots\"\necho png > \"$2/plots/a.png\"\ntouch \"$2/plots/b.png\"").unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "expect: [matrix.txt]\n").unwrap();
+    let cli = Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    };
+
+    let run = run_script(&cli).unwrap();
+    let validation = run.result.validation.unwrap();
+    assert!(validation.passed);
+
+    // An empty match and a pattern without matches fail the run, after the manifest is saved
+    let error = run_script(&Cli { expect: vec!["plots/*.png".to_string(), "report.pdf".to_string()], ..cli })
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("report.pdf"), "{}", error);
+    let latest = fastsave::list::list_runs(archive_dir.path(), None).unwrap().pop().unwrap();
+    let validation = latest.result.validation.as_ref().expect("Validation should be saved");
+    assert!(!validation.passed);
+    assert_eq!(validation.missing, vec!["report.pdf"]);
+    assert_eq!(validation.empty, vec!["plots/b.png"]);
+    assert!(!fastsave::notify::run_succeeded(&latest.result));
+}
+
+#[test]
+fn test_success_criteria() {
+    let archive_dir = TempDir::new().unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "success:\n  exit_codes: [0, 2]\n  failure_patterns: ['NaN detected']\n").unwrap();
+    let run = |name: &str, code: &str| {
+        let script_path = archive_dir.path().join(name);
+        fs::write(&script_path, code).unwrap();
+        run_script(&Cli {
+            config_path: Some(config_path.to_str().unwrap().to_string()),
+            ..test_cli(&script_path, archive_dir.path())
+        }).unwrap().result
+    };
+
+    let converged_late = run("partial.sh", "echo 'partial convergence'\nexit 2");
+    assert_eq!(converged_late.exit_code, 2);
+    assert_eq!(converged_late.success, Some(true));
+
+    let diverged = run("nan.sh", "echo 'step 3: NaN detected' >&2");
+    assert_eq!(diverged.exit_code, 0);
+    assert_eq!(diverged.success, Some(false));
+    assert!(!fastsave::notify::run_succeeded(&diverged));
+    let junit = fastsave::junit::junit_xml(&[("nan".to_string(), &diverged)]);
+    assert!(junit.contains("failures=\"1\""), "{}", junit);
+
+    assert_eq!(run("crash.sh", "exit 1").success, Some(false));
+}
+
+#[test]
+fn test_stdin_is_archived() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("upper.sh");
+    fs::write(&script_path, "tr a-z A-Z > \"$2/upper.txt\"").unwrap();
+    let input_path = archive_dir.path().join("input.txt");
+    fs::write(&input_path, "hello\nworld\n").unwrap();
+
+    let run = run_script(&Cli {
+        stdin: Some(input_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    assert_eq!(fs::read_to_string(run.run_dir.join("upper.txt")).unwrap(), "HELLO\nWORLD\n");
+    assert_eq!(fs::read_to_string(run.run_dir.join("stdin.txt")).unwrap(), "hello\nworld\n");
+    // The input is hashed separately from the outputs
+    assert_eq!(
+        run.result.stdin_hash.as_deref(),
+        Some("4a1e67f2fe1d1cc7b31d0ca2ec441da4778203a036a77da10344c85e24ff0f92")
+    );
+    assert!(!run.result.file_hashes.contains_key("stdin.txt"));
+}
+
+#[test]
+fn test_output_sizes() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("sizes.sh");
+    fs::write(&script_path, "printf 12345 > \"$2/five.txt\"\nmkdir \"$2/sub\"\nprintf 123 > \"$2/sub/three.txt\"").unwrap();
+
+    let run = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+
+    assert_eq!(run.result.files["five.txt"].size_bytes, 5);
+    assert_eq!(run.result.total_output_bytes, Some(8));
+    assert_eq!(fastsave::table::format_size(5), "5 B");
+    assert_eq!(fastsave::table::format_size(3 * 1024 * 1024 / 2), "1.5 MiB");
+
+    let columns = fastsave::query::query_runs(archive_dir.path(), "SELECT output_bytes FROM runs").unwrap();
+    assert_eq!(columns.rows, vec![vec!["8".to_string()]]);
+}
+
+#[test]
+fn test_output_file_kinds() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("kinds.sh");
+    fs::write(&script_path, "cd \"$2\"\nprintf 'a,b\\n1,2\\n3,4\\n' > table.dat\nprintf '\\211PNG\\r\\n\\032\\nrest' > plot\nprintf '\\211HDF\\r\\n\\032\\n' > data.h5\nprintf 'PAR1xyz' > frame.parquet\nprintf 'PK\\003\\004' > bundle.zip\nprintf 'PK\\003\\004%022d\\005\\000\\000\\000a.npy' 0 > arrays.npz\nprintf '{\"a\": 1}' > result.json\nprintf 'done\\n' > log.txt\ntouch empty").unwrap();
+
+    let run = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+
+    let kind = |file: &str| run.result.files[file].kind.clone();
+    assert_eq!(kind("table.dat").as_deref(), Some("csv"));
+    assert_eq!(kind("plot").as_deref(), Some("png"));
+    assert_eq!(kind("data.h5").as_deref(), Some("hdf5"));
+    assert_eq!(kind("frame.parquet").as_deref(), Some("parquet"));
+    assert_eq!(kind("result.json").as_deref(), Some("json"));
+    assert_eq!(kind("log.txt").as_deref(), Some("text"));
+    assert_eq!(kind("empty"), None);
+    // A zip archive only counts as npz if its first entry is a numpy array
+    assert_eq!(kind("arrays.npz").as_deref(), Some("npz"));
+    assert_eq!(kind("bundle.zip").as_deref(), Some("zip"));
+}
+
+#[test]
+fn test_output_previews() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("previews.sh");
+    fs::write(&script_path, "cd \"$2\"\nprintf 'x,y\\n1,2\\n' > small.csv\nseq 1 30 > long.txt\nseq 1 5000 > large.txt\nprintf '\\211PNG\\r\\n\\032\\n' > plot.png").unwrap();
+
+    let run = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+
+    let previews = &run.result.previews;
+    assert_eq!(previews["small.csv"], "x,y\n1,2\n");
+    assert!(previews["long.txt"].starts_with("1\n2\n"));
+    assert!(previews["long.txt"].ends_with("20\n...\n"));
+    assert!(!previews.contains_key("large.txt"));
+    assert!(!previews.contains_key("plot.png"));
+
+    let shown = fastsave::show::format_run(archive_dir.path(), &run);
+    assert!(shown.contains("Preview of small.csv:\n  x,y\n  1,2\n"), "{}", shown);
+}
+
+#[cfg(feature = "thumbnails")]
+#[test]
+fn test_image_thumbnails() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("plot.py");
+    fs::write(&script_path, r#"
+import sys, os, struct, zlib
+output_dir = sys.argv[sys.argv.index('--output_dir') + 1]
+width, height = 600, 300
+def chunk(kind, data):
+    return struct.pack('>I', len(data)) + kind + data + struct.pack('>I', zlib.crc32(kind + data))
+rows = b''.join(b'\x00' + b'\x80\x40\x20' * width for _ in range(height))
+with open(os.path.join(output_dir, 'plot.png'), 'wb') as f:
+    f.write(b'\x89PNG\r\n\x1a\n' + chunk(b'IHDR', struct.pack('>IIBBBBB', width, height, 8, 2, 0, 0, 0))
+            + chunk(b'IDAT', zlib.compress(rows)) + chunk(b'IEND', b''))
+"#).unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "thumbnails:\n  enabled: true\n  max_size: 100\n").unwrap();
+
+    let run = run_script(&Cli {
+        interpreter: Some("python3".to_string()),
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    let thumbnail = run.result.files["plot.png"].thumbnail.clone().expect("Thumbnail should be recorded");
+    assert_eq!(thumbnail, ".thumbnails/plot.png");
+    // Width and height from the IHDR chunk, scaled down with the aspect ratio kept
+    let png = fs::read(run.run_dir.join(&thumbnail)).unwrap();
+    assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 100);
+    assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 50);
+    assert!(!run.result.file_hashes.keys().any(|file| file.contains("thumbnails")));
+}
+
+#[test]
+fn test_html_report() {
+    use fastsave::report::ReportFormat;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("report.sh");
+    fs::write(&script_path, "printf 'a,b\\n' > \"$2/table.csv\"\nprintf '\\211PNG\\r\\n\\032\\n' > \"$2/plot.png\"\necho '<computed>'").unwrap();
+
+    let run = run_script(&Cli {
+        report: vec![ReportFormat::Html],
+        metadata: vec![("dataset".to_string(), "v2".to_string())],
+        script_args: vec!["--rows".to_string(), "3".to_string()],
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    let report = fs::read_to_string(run.run_dir.join("report.html")).unwrap();
+    assert!(report.starts_with("<!DOCTYPE html>"));
+    assert!(report.contains(&format!("<h1>{}</h1>", run.id())));
+    assert!(report.contains("<code>--rows 3</code>"));
+    assert!(report.contains("<th>dataset</th><td>v2</td>"));
+    assert!(report.contains("<a href=\"table.csv\">table.csv</a>"));
+    assert!(report.contains("<img src=\"plot.png\""));
+    assert!(report.contains("&lt;computed&gt;"));
+    assert!(!run.result.file_hashes.contains_key("report.html"));
+}
+
+#[test]
+fn test_markdown_summary() {
+    use fastsave::export::{run_export, ExportArgs};
+    use fastsave::report::ReportFormat;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("summary.sh");
+    fs::write(&script_path, "echo 'loss=0.25'\nprintf 'x|y\\n' > \"$2/table.csv\"").unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "metrics:\n  patterns: ['loss=(?P<loss>[\\d.]+)']\n").unwrap();
+
+    let run = run_script(&Cli {
+        report: vec![ReportFormat::Markdown],
+        message: Some("Coarse mesh".to_string()),
+        script_args: vec!["--rows".to_string(), "3".to_string()],
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    let summary = fs::read_to_string(run.run_dir.join("SUMMARY.md")).unwrap();
+    assert!(summary.starts_with(&format!("## {}\n\nCoarse mesh\n", run.id())));
+    assert!(summary.contains("| Status | succeeded (exit code 0) |"));
+    assert!(summary.contains(" --rows 3` |"));
+    assert!(summary.contains("| loss | 0.25 |"));
+    assert!(summary.contains("| table.csv | 4 B |"));
+
+    let exported = archive_dir.path().join("exported.md");
+    run_export(&ExportArgs {
+        run: run.id(),
+        markdown: true,
+        html: false,
+        output: Some(exported.to_string_lossy().to_string()),
+        archive_dir: archive_dir.path().to_string_lossy().to_string(),
+    }).unwrap();
+    // The script arguments are archived with the command
+    assert_eq!(fs::read_to_string(exported).unwrap(), summary);
+}
+
+#[test]
+fn test_duration_trend() {
+    use fastsave::archive::{load_manifest, save_manifest, MANIFEST_FILE};
+    use fastsave::stats::{duration_trend, find_outliers, sparkline};
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("solver.sh");
+    fs::write(&script_path, "echo solved").unwrap();
+    for (duration_ms, loss) in [(100, 0.5), (105, 0.4), (98, 0.45), (102, 0.41), (400, 0.43), (101, 0.42)] {
+        let run = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+        let mut result = load_manifest(&run.run_dir.join(MANIFEST_FILE)).unwrap();
+        result.duration_ms = duration_ms;
+        result.metrics.insert("loss".to_string(), serde_yaml::Value::from(loss));
+        save_manifest(&run.run_dir, &result).unwrap();
+    }
+    // Failed runs are left out
+    fs::write(&script_path, "exit 1").unwrap();
+    run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+
+    let points = duration_trend(archive_dir.path(), "solver.sh", &["loss".to_string()], None, 3.5).unwrap();
+    assert_eq!(points.iter().map(|p| p.duration_ms).collect::<Vec<_>>(), vec![100, 105, 98, 102, 400, 101]);
+    assert_eq!(points[4].outliers, vec!["duration"]);
+    assert!(points.iter().enumerate().all(|(i, p)| i == 4 || p.outliers.is_empty()));
+    assert_eq!(points[1].metrics, vec![Some(0.4)]);
+
+    assert_eq!(find_outliers(&[1.0, 1.0, 1.0, 5.0], 3.5), vec![false, false, false, true]);
+    assert_eq!(sparkline(&[1.0, 2.0, 8.0]), "▁▂█");
+}
+
+#[test]
+fn test_failure_groups() {
+    use fastsave::failures::{failure_signature, group_failures};
+
+    let archive_dir = TempDir::new().unwrap();
+    let run = |name: &str, code: &str| {
+        let script_path = archive_dir.path().join(name);
+        fs::write(&script_path, code).unwrap();
+        run_script(&test_cli(&script_path, archive_dir.path())).unwrap().id()
+    };
+    run("solver.sh", "echo 'Traceback:' >&2\necho 'ValueError: NaN at step 1200' >&2\nexit 1");
+    let latest_nan = run("solver.sh", "echo 'ValueError: NaN at step 3400' >&2\nexit 1");
+    run("solver.sh", "echo 'MemoryError' >&2\nexit 1");
+    run("solver.sh", "echo fine");
+    run("plot.sh", "exit 3");
+
+    let groups = group_failures(archive_dir.path(), None, None).unwrap();
+    let summary: Vec<_> = groups.iter().map(|g| (g.script.as_str(), g.signature.as_str(), g.count)).collect();
+    assert_eq!(summary, vec![
+        ("solver", "ValueError: NaN at step N", 2),
+        ("plot", "exit code 3 without stderr", 1),
+        ("solver", "MemoryError", 1),
+    ]);
+    assert_eq!(groups[0].latest_run, latest_nan);
+
+    assert_eq!(group_failures(archive_dir.path(), Some("plot"), None).unwrap().len(), 1);
+    assert_eq!(failure_signature("error at 0x7ffd3a in float64 array\n\n", 1), "error at N in float64 array");
+}
+
+#[test]
+fn test_archive_audit() {
+    use fastsave::audit::{audit_archive, IssueKind};
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("audited.sh");
+    fs::write(&script_path, "echo a > \"$2/a.txt\"\necho b > \"$2/b.txt\"").unwrap();
+    let cli = Cli { experiment: Some("study".to_string()), ..test_cli(&script_path, archive_dir.path()) };
+    let first = run_script(&cli).unwrap();
+    let second = run_script(&cli).unwrap();
+    fastsave::baseline::set_baseline(archive_dir.path(), &second.id()).unwrap();
+
+    let report = audit_archive(archive_dir.path()).unwrap();
+    assert_eq!(report.runs, 2);
+    assert_eq!(report.files, 4);
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    // Tamper with the archive out of band
+    fs::write(first.run_dir.join("a.txt"), "changed").unwrap();
+    fs::remove_file(first.run_dir.join("b.txt")).unwrap();
+    fs::remove_dir_all(&second.run_dir).unwrap();
+    let broken_dir = archive_dir.path().join("broken_run1");
+    fs::create_dir(&broken_dir).unwrap();
+    fs::write(broken_dir.join("fastsave.yaml"), "script_path: [").unwrap();
+
+    let report = audit_archive(archive_dir.path()).unwrap();
+    let kinds: Vec<IssueKind> = report.issues.iter().map(|issue| issue.kind).collect();
+    assert_eq!(kinds, vec![
+        IssueKind::Unreadable,
+        IssueKind::HashMismatch,
+        IssueKind::MissingFile,
+        IssueKind::BrokenLink,
+        IssueKind::MissingRun,
+    ]);
+    assert!(report.issues[4].detail.contains(&second.id()));
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["issues"][1]["kind"], "hash_mismatch");
+}
+
+#[test]
+fn test_index_verify_and_rebuild() {
+    use fastsave::index::{rebuild_indexes, verify_indexes};
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("indexed.sh");
+    fs::write(&script_path, "echo ok").unwrap();
+    let cli = Cli { experiment: Some("study".to_string()), ..test_cli(&script_path, archive_dir.path()) };
+    let kept = run_script(&cli).unwrap();
+    let deleted = run_script(&cli).unwrap();
+    assert!(verify_indexes(archive_dir.path()).unwrap()[0].is_consistent());
+
+    // Modify the archive out of band: delete a run and lose the link of another
+    fs::remove_dir_all(&deleted.run_dir).unwrap();
+    fs::remove_file(archive_dir.path().join("experiments/study").join(kept.id())).unwrap();
+
+    let diffs = verify_indexes(archive_dir.path()).unwrap();
+    assert_eq!(diffs[0].index, "experiments");
+    assert_eq!(diffs[0].added, vec![format!("study/{}", kept.id())]);
+    assert_eq!(diffs[0].removed, vec![format!("study/{}", deleted.id())]);
+
+    rebuild_indexes(archive_dir.path()).unwrap();
+    assert!(verify_indexes(archive_dir.path()).unwrap().iter().all(|diff| diff.is_consistent()));
+    assert!(archive_dir.path().join("experiments/study").join(kept.id()).join("fastsave.yaml").is_file());
+    assert!(archive_dir.path().join("experiments/study").join(deleted.id()).symlink_metadata().is_err());
+}
+
+#[cfg(feature = "search")]
+#[test]
+fn test_search_index_verify() {
+    use fastsave::index::{rebuild_indexes, verify_indexes};
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("searched.sh");
+    fs::write(&script_path, "echo ok").unwrap();
+    let first = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+    let second = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+    fastsave::search::rebuild_index(archive_dir.path()).unwrap();
+    // Runs completed later are added to an existing index
+    let third = run_script(&test_cli(&script_path, archive_dir.path())).unwrap();
+
+    // Modify the archive out of band: delete a run and copy one in
+    fs::remove_dir_all(&first.run_dir).unwrap();
+    let copied = archive_dir.path().join("copied_run1");
+    fs::create_dir(&copied).unwrap();
+    fs::copy(second.run_dir.join("fastsave.yaml"), copied.join("fastsave.yaml")).unwrap();
+
+    let diffs = verify_indexes(archive_dir.path()).unwrap();
+    let search = diffs.iter().find(|diff| diff.index == "search").unwrap();
+    assert_eq!(search.added, vec!["copied_run1"]);
+    assert_eq!(search.removed, vec![first.id()]);
+
+    rebuild_indexes(archive_dir.path()).unwrap();
+    assert_eq!(fastsave::search::indexed_runs(archive_dir.path()).unwrap().unwrap().into_iter().collect::<Vec<_>>(), vec![second.id(), third.id(), "copied_run1".to_string()]);
+}
+
+#[test]
+fn test_start_record() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("started.sh");
+    fs::write(&script_path, "cat \"$2/started.yaml\"").unwrap();
+
+    let run = run_script(&Cli {
+        script_args: vec!["--rows".to_string(), "3".to_string()],
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    // The record exists while the script runs and is replaced by the manifest afterwards
+    let start: fastsave::started::RunStart = serde_yaml::from_str(&run.result.stdout).unwrap();
+    assert_eq!(start.script_args, vec!["--rows", "3"]);
+    assert_eq!(start.pid, std::process::id());
+    assert!(start.start_time <= run.result.start_time);
+    assert!(!run.run_dir.join("started.yaml").exists());
+    assert!(!run.result.file_hashes.contains_key("started.yaml"));
+}
+
+#[test]
+fn test_heartbeat_and_status() {
+    use fastsave::status::{run_state, unfinished_runs, RunState};
+
+    let archive_dir = TempDir::new().unwrap();
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, "heartbeat:\n  interval_secs: 1\n").unwrap();
+    let script_path = archive_dir.path().join("slow.sh");
+    fs::write(&script_path, "echo one\necho two\nsleep 2.5\ncat \"$2/started.yaml\" >&2").unwrap();
+
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    // The heartbeat written while the script slept counts the lines printed before
+    let mut start: fastsave::started::RunStart = serde_yaml::from_str(&run.result.stderr).unwrap();
+    let heartbeat = start.heartbeat.clone().expect("heartbeat written during the run");
+    assert_eq!(heartbeat.interval_secs, 1);
+    assert_eq!(heartbeat.stdout_lines, 2);
+    assert!(heartbeat.time >= heartbeat.last_progress);
+    if cfg!(target_os = "linux") {
+        assert!(heartbeat.memory_bytes.is_some_and(|bytes| bytes > 0));
+    }
+    assert!(unfinished_runs(archive_dir.path()).unwrap().is_empty());
+
+    // Fresh heartbeats without progress mean hung, missing heartbeats mean lost
+    let hung_after = chrono::Duration::minutes(10);
+    let mut beat = heartbeat.clone();
+    beat.last_progress = beat.time;
+    start.heartbeat = Some(beat.clone());
+    assert_eq!(run_state(&start, beat.time + chrono::Duration::seconds(2), hung_after), RunState::Running);
+    beat.last_progress = beat.time - chrono::Duration::minutes(20);
+    start.heartbeat = Some(beat.clone());
+    assert_eq!(run_state(&start, beat.time + chrono::Duration::seconds(2), hung_after), RunState::Hung);
+    assert_eq!(run_state(&start, beat.time + chrono::Duration::seconds(5), hung_after), RunState::Lost);
+    start.heartbeat = None;
+    assert_eq!(run_state(&start, beat.time, hung_after), RunState::Unknown);
+
+    // A run whose fastsave died keeps its start record and shows up as unfinished
+    let crashed = archive_dir.path().join("2025-01-17_slow_run9");
+    fs::create_dir(&crashed).unwrap();
+    fs::write(crashed.join("started.yaml"), serde_yaml::to_string(&start).unwrap()).unwrap();
+    let unfinished = unfinished_runs(archive_dir.path()).unwrap();
+    assert_eq!(unfinished.len(), 1);
+    assert_eq!(unfinished[0].0, crashed);
+}
+
+#[test]
+fn test_attach_follows_running_script() {
+    use std::time::Duration;
+    use fastsave::logs::STDOUT_LIVE;
+    use fastsave::status::unfinished_runs;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let go = dir.path().join("go");
+    let script_path = dir.path().join("slow.sh");
+    fs::write(&script_path, format!("echo before\necho warning >&2\nwhile [ ! -e '{}' ]; do sleep 0.1; done\necho after\nexit 3", go.display())).unwrap();
+
+    let running = {
+        let cli = test_cli(&script_path, &archive_dir);
+        std::thread::spawn(move || run_script(&cli).unwrap().run_dir)
+    };
+    let run_dir = loop {
+        if let Some((run_dir, _)) = unfinished_runs(&archive_dir).unwrap().pop() {
+            break run_dir;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let live = run_dir.join(STDOUT_LIVE);
+    while fs::read_to_string(&live).unwrap_or_default() != "before\n" {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    // Attached while the script waits, it sees the earlier output and the output still to come
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(500));
+        fs::write(&go, "").unwrap();
+    });
+    let id = fastsave::archive::run_id(&run_dir);
+    let output = assert_cmd::Command::cargo_bin("fastsave").unwrap()
+        .args(["attach", "-a"])
+        .arg(&archive_dir)
+        .arg(&id)
+        .output()
+        .unwrap();
+    release.join().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\nafter\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("warning\n") && stderr.contains(&format!("Run {} failed with exit code 3", id)), "{}", stderr);
+
+    // The live logs go with the start record
+    assert_eq!(running.join().unwrap(), run_dir);
+    assert!(!live.exists());
+    assert!(!run_dir.join(fastsave::logs::STDERR_LIVE).exists());
+
+    // A finished run is replayed
+    assert_cmd::Command::cargo_bin("fastsave").unwrap()
+        .args(["attach", "-a"])
+        .arg(&archive_dir)
+        .arg(&id)
+        .assert()
+        .failure()
+        .stdout("before\nafter\n");
+}
+
+#[test]
+fn test_batch_jobs() {
+    use fastsave::batch::{run_batch_jobs, split_words, BatchArgs, JobStatus, BATCH_FILE};
+
+    assert_eq!(split_words(r#"run.py --name "two words" 'it''s' a\ b"#).unwrap(), vec!["run.py", "--name", "two words", "its", "a b"]);
+    assert!(split_words("run.py 'open").is_err());
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let ok_script = dir.path().join("ok.sh");
+    fs::write(&ok_script, "echo \"$3\" > \"$2/arg.txt\"").unwrap();
+    let failing_script = dir.path().join("failing.sh");
+    fs::write(&failing_script, "exit 3").unwrap();
+    let jobs_file = dir.path().join("jobs.txt");
+    fs::write(&jobs_file, format!(
+        "# nightly jobs\n{ok} 'first job'\n\n{failing}\n{missing}\n{ok} second\n",
+        ok = ok_script.display(),
+        failing = failing_script.display(),
+        missing = dir.path().join("notes.unknown").display(),
+    )).unwrap();
+
+    let (batch_dir, batch) = run_batch_jobs(&BatchArgs {
+        jobs_file: jobs_file.to_string_lossy().into_owned(),
+        jobs: 2,
+        message: Some("nightly".to_string()),
+        minimize: None,
+        maximize: None,
+        config_path: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    // Jobs are summarized in file order with their line numbers, whatever order they finished in
+    let lines: Vec<usize> = batch.jobs.iter().map(|job| job.job.line).collect();
+    assert_eq!(lines, vec![2, 4, 5, 6]);
+    let statuses: Vec<JobStatus> = batch.jobs.iter().map(|job| job.outcome.status).collect();
+    assert_eq!(statuses, vec![JobStatus::Success, JobStatus::Failed, JobStatus::Error, JobStatus::Success]);
+    assert_eq!(batch.jobs[1].outcome.exit_code, Some(3));
+    assert!(batch.jobs[2].outcome.id.is_none() && batch.jobs[2].outcome.error.is_some());
+
+    let first = archive_dir.join(batch.jobs[0].outcome.id.as_ref().unwrap());
+    assert_eq!(fs::read_to_string(first.join("arg.txt")).unwrap(), "first job\n");
+    let manifest: ExecutionResult = serde_yaml::from_str(&fs::read_to_string(first.join("fastsave.yaml")).unwrap()).unwrap();
+    assert_eq!(manifest.message.as_deref(), Some("nightly"));
+    assert!(Path::new(&batch_dir).join(BATCH_FILE).is_file());
+    assert!(fastsave::audit::audit_archive(&archive_dir).unwrap().issues.is_empty());
+}
+
+#[test]
+fn test_batch_jobs_from_stdin() {
+    use fastsave::batch::{BatchResult, JobStatus, BATCH_FILE};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("job.sh");
+    fs::write(&script_path, "echo \"$3\" > \"$2/arg.txt\"").unwrap();
+
+    assert_cmd::Command::cargo_bin("fastsave").unwrap()
+        .args(["batch", "-", "-a"])
+        .arg(&archive_dir)
+        .write_stdin(format!("{script} first\n{script} second\n", script = script_path.display()))
+        .assert()
+        .success();
+
+    let batch_dir = fs::read_dir(&archive_dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.file_name().unwrap().to_string_lossy().ends_with("_stdin_batch1"))
+        .expect("batch directory named after stdin");
+    let batch: BatchResult = serde_yaml::from_str(&fs::read_to_string(batch_dir.join(BATCH_FILE)).unwrap()).unwrap();
+    assert_eq!(batch.jobs_file, "-");
+    assert_eq!(batch.jobs.iter().map(|job| job.outcome.status).collect::<Vec<_>>(), vec![JobStatus::Success, JobStatus::Success]);
+    let args: Vec<String> = batch.jobs.iter()
+        .map(|job| fs::read_to_string(archive_dir.join(job.outcome.id.as_ref().unwrap()).join("arg.txt")).unwrap())
+        .collect();
+    assert_eq!(args, vec!["first\n", "second\n"]);
+}
+
+#[test]
+fn test_job_queue() {
+    use fastsave::queue::{pending_jobs, run_queue, submit, DaemonArgs, FinishedJob, QueuedJob, QUEUE_DIR};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("queued.sh");
+    fs::write(&script_path, "echo \"$3\" > \"$2/arg.txt\"").unwrap();
+
+    for arg in ["a", "b", "c"] {
+        submit(&archive_dir, &QueuedJob {
+            script: script_path.to_string_lossy().into_owned(),
+            script_args: vec![arg.to_string()],
+            message: Some(format!("job {}", arg)),
+            priority: Default::default(),
+            submitted_at: chrono::Utc::now(),
+        }).unwrap();
+    }
+    assert_eq!(pending_jobs(&archive_dir).unwrap().len(), 3);
+
+    let count = run_queue(&DaemonArgs {
+        workers: 2,
+        poll_secs: 1,
+        once: true,
+        config_path: None,
+        local_archive: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    // Every job ran exactly once and its outcome is kept in done/
+    assert_eq!(count, 3);
+    assert!(pending_jobs(&archive_dir).unwrap().is_empty());
+    let done: Vec<FinishedJob> = fs::read_dir(archive_dir.join(QUEUE_DIR).join("done"))
+        .unwrap()
+        .map(|entry| serde_yaml::from_str(&fs::read_to_string(entry.unwrap().path()).unwrap()).unwrap())
+        .collect();
+    assert_eq!(done.len(), 3);
+    let mut args: Vec<String> = done.iter().map(|job| {
+        let run_dir = archive_dir.join(job.outcome.id.as_ref().unwrap());
+        fs::read_to_string(run_dir.join("arg.txt")).unwrap().trim().to_string()
+    }).collect();
+    args.sort();
+    assert_eq!(args, vec!["a", "b", "c"]);
+    assert_eq!(fastsave::list::list_runs(&archive_dir, None).unwrap().len(), 3);
+}
+
+#[test]
+fn test_daemon_local_archive() {
+    use fastsave::queue::{run_queue, submit, DaemonArgs, FinishedJob, QueuedJob, QUEUE_DIR};
+
+    let dir = TempDir::new().unwrap();
+    let shared_archive = dir.path().join("shared");
+    let local_archive = dir.path().join("local");
+    let script_path = dir.path().join("remote.sh");
+    fs::write(&script_path, "echo result > \"$2/out.txt\"").unwrap();
+    submit(&shared_archive, &QueuedJob {
+        script: script_path.to_string_lossy().into_owned(),
+        script_args: Vec::new(),
+        message: None,
+        priority: Default::default(),
+        submitted_at: chrono::Utc::now(),
+    }).unwrap();
+
+    // A run with the same id from another machine is already in the shared archive
+    let taken = shared_archive.join(format!("{}_remote_run1", chrono::Local::now().format("%Y-%m-%d")));
+    fs::create_dir_all(&taken).unwrap();
+
+    run_queue(&DaemonArgs {
+        workers: 1,
+        poll_secs: 1,
+        once: true,
+        config_path: None,
+        local_archive: Some(local_archive.to_string_lossy().into_owned()),
+        archive_dir: shared_archive.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    let done = fs::read_dir(shared_archive.join(QUEUE_DIR).join("done")).unwrap().next().unwrap().unwrap().path();
+    let finished: FinishedJob = serde_yaml::from_str(&fs::read_to_string(done).unwrap()).unwrap();
+    let local_id = finished.local_id.as_ref().unwrap();
+    let shared_id = finished.outcome.id.as_ref().unwrap();
+    assert!(local_id.ends_with("_remote_run1"));
+    assert!(shared_id.ends_with("_remote_run2"));
+    assert_eq!(finished.host, fastsave::started::host_name());
+
+    // Both copies hold the outputs, and the manifest names the machine that ran the script
+    assert!(local_archive.join(local_id).join("out.txt").is_file());
+    let manifest: ExecutionResult = serde_yaml::from_str(&fs::read_to_string(shared_archive.join(shared_id).join("fastsave.yaml")).unwrap()).unwrap();
+    assert_eq!(manifest.host.as_deref(), Some(finished.host.as_str()).filter(|host| !host.is_empty()));
+    assert!(fastsave::audit::audit_archive(&shared_archive).unwrap().issues.is_empty());
+}
+
+#[test]
+fn test_queue_priorities() {
+    use fastsave::queue::{pending_jobs, run_queue, submit, DaemonArgs, Priority, QueuedJob};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("sweep.sh");
+    fs::write(&script_path, "echo \"$3\" > \"$2/arg.txt\"").unwrap();
+
+    for (arg, priority) in [("first", Priority::Low), ("second", Priority::Normal), ("third", Priority::High), ("fourth", Priority::Normal)] {
+        submit(&archive_dir, &QueuedJob {
+            script: script_path.to_string_lossy().into_owned(),
+            script_args: vec![arg.to_string()],
+            message: None,
+            priority,
+            submitted_at: chrono::Utc::now(),
+        }).unwrap();
+    }
+    assert_eq!(pending_jobs(&archive_dir).unwrap().len(), 4);
+
+    run_queue(&DaemonArgs {
+        workers: 1,
+        poll_secs: 1,
+        once: true,
+        config_path: None,
+        local_archive: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    // A single worker runs the urgent job first and jobs of equal priority in submission order
+    let runs = fastsave::list::list_runs(&archive_dir, None).unwrap();
+    let order: Vec<String> = runs.iter().map(|run| fs::read_to_string(run.run_dir.join("arg.txt")).unwrap().trim().to_string()).collect();
+    assert_eq!(order, vec!["third", "second", "fourth", "first"]);
+    let priorities: Vec<Option<Priority>> = runs.iter().map(|run| run.result.priority).collect();
+    assert_eq!(priorities, vec![Some(Priority::High), Some(Priority::Normal), Some(Priority::Normal), Some(Priority::Low)]);
+}
+
+#[test]
+fn test_run_lock() {
+    use std::time::{Duration, Instant};
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("update_database.sh");
+    fs::write(&script_path, "echo updated").unwrap();
+
+    let held = fastsave::lock::acquire(archive_dir.path(), "database", true).unwrap();
+
+    // Failing fast leaves no run directory behind
+    let err = run_script(&Cli {
+        lock: Some("database".to_string()),
+        no_wait: true,
+        ..test_cli(&script_path, archive_dir.path())
+    }).err().unwrap();
+    assert!(err.to_string().contains("Lock 'database' is held by process"), "{}", err);
+    assert!(fastsave::list::list_runs(archive_dir.path(), None).unwrap().is_empty());
+
+    // The lock of the script comes from the config as well; by default the run waits for it
+    let config_path = archive_dir.path().join("config.yaml");
+    fs::write(&config_path, "locks:\n  update_database: database\n").unwrap();
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(500));
+        drop(held);
+    });
+    let waiting_since = Instant::now();
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+    assert!(waiting_since.elapsed() >= Duration::from_millis(500));
+    assert_eq!(run.result.exit_code, 0);
+    release.join().unwrap();
+
+    assert!(fastsave::lock::acquire(archive_dir.path(), "database", false).is_ok());
+    assert!(fastsave::lock::parse_lock_name("../database").is_err());
+}
+
+#[test]
+fn test_sandbox_spec() {
+    use fastsave::sandbox::{SandboxConfig, SandboxSpec};
+
+    let dir = TempDir::new().unwrap();
+    let project = dir.path().join("project");
+    let data = dir.path().join("data.csv");
+    let output = dir.path().join("archive/run1");
+    fs::create_dir_all(&project).unwrap();
+    fs::create_dir_all(&output).unwrap();
+    fs::write(&data, "x\n1\n").unwrap();
+    let script = project.join("analyze.py");
+    fs::write(&script, "").unwrap();
+
+    let spec = SandboxSpec::new(
+        &script.to_string_lossy(),
+        &output.to_string_lossy(),
+        &[data.as_path()],
+        &SandboxConfig::default(),
+    ).unwrap();
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap().to_string_lossy().into_owned();
+    assert_eq!(spec.read_only, vec![canonical(&project), canonical(&data)]);
+    assert_eq!(spec.read_write, vec![canonical(&output)]);
+    // The test's working directory isn't mounted, so the script starts in its own directory
+    assert_eq!(spec.working_dir, canonical(&project));
+
+    let args = spec.bwrap_args();
+    let joined = args.join(" ");
+    assert!(joined.contains(&format!("--ro-bind {0} {0}", canonical(&data))));
+    assert!(joined.contains(&format!("--bind {0} {0}", canonical(&output))));
+    assert!(joined.contains("--unshare-all") && joined.contains("--share-net"));
+    assert_eq!(args.last().map(String::as_str), Some("--"));
+
+    // The spec goes into the manifest as is
+    let yaml = serde_yaml::to_string(&spec).unwrap();
+    assert_eq!(serde_yaml::from_str::<SandboxSpec>(&yaml).unwrap(), spec);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_no_network() {
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("offline.sh");
+    fs::write(&script_path, "cut -d: -f1 /proc/net/dev | tail -n +3 | tr -d ' '\necho done > \"$2/out.txt\"").unwrap();
+
+    let run = run_script(&Cli { no_network: true, ..test_cli(&script_path, archive_dir.path()) }).unwrap();
+
+    // Only the loopback interface exists in the namespace of the script
+    assert_eq!(run.result.exit_code, 0, "{}", run.result.stderr);
+    assert_eq!(run.result.stdout, "lo\n");
+    assert!(run.result.network_isolated);
+    assert!(run.run_dir.join("out.txt").is_file());
+}
+
+#[test]
+fn test_read_only_project() {
+    let repo_dir = TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        Command::new("git").current_dir(repo_dir.path()).args(args).output().unwrap();
+    };
+    git(&["init"]);
+    git(&["config", "user.email", "test@example.com"]);
+    git(&["config", "user.name", "Test User"]);
+    fs::write(repo_dir.path().join("params.txt"), "alpha=1\n").unwrap();
+    fs::write(repo_dir.path().join("notes.txt"), "todo\n").unwrap();
+    let script_path = repo_dir.path().join("tidy.sh");
+    fs::write(&script_path, "echo ok").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-m", "Initial commit"]);
+
+    // Already uncommitted changes are fine as long as the script leaves them alone
+    fs::write(repo_dir.path().join("notes.txt"), "todo: more\n").unwrap();
+    let input = repo_dir.path().join("input.csv");
+    fs::write(&input, "x\n1\n").unwrap();
+    let archive_dir = repo_dir.path().join("archive");
+    let cli = Cli {
+        read_only_project: true,
+        inputs: vec![input.to_string_lossy().into_owned()],
+        ..test_cli(&script_path, &archive_dir)
+    };
+    let run = run_script(&cli).unwrap();
+    assert_eq!(run.result.modified_sources, Some(Vec::new()));
+    assert_eq!(run.result.success, Some(true));
+
+    // Modifying a tracked file or an input fails the run after it is archived
+    fs::write(&script_path, format!("cd {}\necho beta=2 >> params.txt\necho 2 >> input.csv", repo_dir.path().display())).unwrap();
+    git(&["commit", "-am", "Modify sources"]);
+    let err = run_script(&cli).err().unwrap();
+    assert!(err.to_string().contains("modified sources"), "{}", err);
+    let runs = fastsave::list::list_runs(&archive_dir, None).unwrap();
+    let failed = &runs.last().unwrap().result;
+    assert_eq!(failed.modified_sources, Some(vec![input.to_string_lossy().into_owned(), "params.txt".to_string()]));
+    assert_eq!(failed.success, Some(false));
+}
+
+#[test]
+fn test_scratch_directory() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let scratch_base = dir.path().join("scratch");
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, format!("scratch:\n  dir: {}\n  keep: ['*.log']\n", scratch_base.display())).unwrap();
+    let script_path = dir.path().join("messy.sh");
+    fs::write(&script_path, "echo \"$TMPDIR\"\necho \"$FASTSAVE_SCRATCH\"\nhead -c 1000 /dev/zero > \"$TMPDIR/cache.bin\"\necho done > \"$TMPDIR/solver.log\"").unwrap();
+
+    let run = run_script(&Cli {
+        scratch: true,
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+
+    // The script was pointed at a directory of its own, which is gone after the run
+    let lines: Vec<&str> = run.result.stdout.lines().collect();
+    assert_eq!(lines[0], lines[1]);
+    assert!(Path::new(lines[0]).starts_with(&scratch_base));
+    assert!(!Path::new(lines[0]).exists());
+
+    // Only the kept file was archived
+    let usage = run.result.scratch.as_ref().unwrap();
+    assert_eq!(usage.peak_bytes, 1005);
+    assert_eq!(usage.kept, vec!["solver.log"]);
+    assert_eq!(fs::read_to_string(run.run_dir.join("scratch/solver.log")).unwrap(), "done\n");
+    assert!(!run.run_dir.join("scratch/cache.bin").exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_killed_by_signal() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("doomed.sh");
+    fs::write(&script_path, "echo started\nkill -9 $$\necho never").unwrap();
+
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    assert_eq!(run.result.exit_code, -1);
+    assert_eq!(run.result.exit_signal, Some(9));
+    // No OOM kill happened while the script ran, so it was killed by someone else
+    assert_eq!(run.result.killed_reason, Some(fastsave::killed::KilledReason::Signal));
+
+    let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+    assert!(manifest.contains("killed_reason: signal"));
+    assert!(manifest.contains("exit_signal: 9"));
+}
+
+#[test]
+fn test_cpu_pinning() {
+    assert_eq!(fastsave::cpus::cpu_count("0-7"), Ok(8));
+    assert_eq!(fastsave::cpus::cpu_count("0,2,4-6"), Ok(5));
+    assert!(fastsave::cpus::parse_cpu_list("7-0").is_err());
+    assert!(fastsave::cpus::parse_cpu_list("all").is_err());
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("threads.sh");
+    fs::write(&script_path, "echo \"$OMP_NUM_THREADS $JULIA_NUM_THREADS\"").unwrap();
+
+    // The thread count alone doesn't pin the script
+    let run = run_script(&Cli { threads: Some(3), ..test_cli(&script_path, &archive_dir) }).unwrap();
+    assert_eq!(run.result.stdout, "3 3\n");
+    assert_eq!(run.result.cpu_pinning, Some(fastsave::cpus::CpuPinning { cpus: None, threads: Some(3) }));
+
+    #[cfg(target_os = "linux")]
+    {
+        fs::write(&script_path, "echo $OMP_NUM_THREADS\ngrep Cpus_allowed_list /proc/self/status").unwrap();
+        let run = run_script(&Cli { cpus: Some("0".to_string()), ..test_cli(&script_path, &archive_dir) }).unwrap();
+        assert_eq!(run.result.exit_code, 0);
+        let lines: Vec<&str> = run.result.stdout.lines().collect();
+        assert_eq!(lines[0], "1");
+        assert!(lines[1].ends_with("\t0"));
+        let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+        assert!(manifest.contains("cpus: '0'"));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cpu_time() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("busy.sh");
+    fs::write(&script_path, "i=0\nwhile [ $i -lt 300000 ]; do i=$((i+1)); done").unwrap();
+
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    let cpu = run.result.cpu_time.as_ref().unwrap();
+    assert!(cpu.user_seconds > 0.0);
+    assert!(cpu.parallel_efficiency > 0.5 && cpu.parallel_efficiency < 1.5, "{:?}", cpu);
+
+    // A script that waits spends almost no CPU time, and sleep is a child the shell waited for
+    fs::write(&script_path, "sleep 0.5").unwrap();
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    let cpu = run.result.cpu_time.as_ref().unwrap();
+    assert!(cpu.parallel_efficiency < 0.2, "{:?}", cpu);
+}
+
+#[test]
+fn test_command_line() {
+    use fastsave::command::shell_quote;
+
+    assert_eq!(shell_quote("--rows=3"), "--rows=3");
+    assert_eq!(shell_quote("two words"), "'two words'");
+    assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    assert_eq!(shell_quote(""), "''");
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("args.sh");
+    fs::write(&script_path, "shift 2\nprintf '%s\\n' \"$@\"").unwrap();
+
+    let script_args = vec!["--title".to_string(), "it's a test".to_string(), "$HOME".to_string()];
+    let run = run_script(&Cli { script_args: script_args.clone(), ..test_cli(&script_path, &archive_dir) }).unwrap();
+    let command = run.result.command.as_ref().unwrap();
+    assert_eq!(command.program, "sh");
+    assert_eq!(command.args[1], "--output_dir");
+    assert_eq!(command.script_args(), &script_args[..]);
+
+    // Pasted into a shell, the command line passes the same arguments
+    let output = std::process::Command::new("sh").arg("-c").arg(&run.result.command_line).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), run.result.stdout);
+    assert_eq!(run.result.stdout, "--title\nit's a test\n$HOME\n");
+
+    // Manifests of older versions recorded the command as command_string
+    let manifest = run.run_dir.join("fastsave.yaml");
+    let content = fs::read_to_string(&manifest).unwrap();
+    let old = content
+        .lines()
+        .filter(|line| !line.starts_with("command:") && !line.starts_with("  "))
+        .map(|line| line.replacen("command_line:", "command_string:", 1))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let result: fastsave::ExecutionResult = serde_yaml::from_str(&old).unwrap();
+    assert!(result.command.is_none());
+    assert_eq!(result.command_line, run.result.command_line);
+}
+
+#[test]
+fn test_interpreter_path() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("hello.sh");
+    fs::write(&script_path, "echo hello").unwrap();
+
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    let interpreter = run.result.interpreter_path.as_ref().unwrap();
+    assert!(Path::new(interpreter).is_absolute());
+    assert!(interpreter.ends_with("sh"));
+    assert_eq!(run.result.command.as_ref().unwrap().program, "sh");
+
+    // A missing interpreter fails before the run directory is created
+    let error = run_script(&Cli {
+        interpreter: Some("no-such-interpreter".to_string()),
+        archive_dir: dir.path().join("other").to_string_lossy().into_owned(),
+        ..test_cli(&script_path, &archive_dir)
+    })
+    .err()
+    .unwrap();
+    assert!(error.to_string().contains("Interpreter 'no-such-interpreter' not found"), "{}", error);
+    assert!(!dir.path().join("other").exists());
+}
+
+#[test]
+fn test_hash_interpreter() {
+    use sha2::{Digest, Sha256};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("hello.sh");
+    fs::write(&script_path, "echo hello").unwrap();
+
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    assert_eq!(run.result.interpreter_hash, None);
+
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, "provenance:\n  hash_interpreter: true\n").unwrap();
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+    let interpreter = fs::read(run.result.interpreter_path.as_ref().unwrap()).unwrap();
+    let expected = format!("{:x}", Sha256::digest(&interpreter));
+    let shown = fastsave::show::format_run(&archive_dir, &run);
+    assert!(shown.contains(&format!("Program:    {} (sha256 {})\n", run.result.interpreter_path.as_ref().unwrap(), &expected[..12])), "{}", shown);
+    assert_eq!(run.result.interpreter_hash, Some(expected));
+}
+
+#[test]
+fn test_record_libraries() {
+    use fastsave::provenance::numerical_libraries;
+
+    let ldd = "\tlinux-vdso.so.1 (0x00007ffd)\n\tlibopenblas.so.0 => /nonexistent/libopenblas.so.0 (0x00007f01)\n\tlibc.so.6 => /lib/libc.so.6 (0x00007f02)\n";
+    assert_eq!(numerical_libraries(ldd), vec!["/nonexistent/libopenblas.so.0"]);
+    let otool = "/opt/julia:\n\t/opt/lib/libmkl_rt.2.dylib (compatibility version 0.0.0, current version 0.0.0)\n";
+    assert_eq!(numerical_libraries(otool), vec!["/opt/lib/libmkl_rt.2.dylib"]);
+
+    if !cfg!(target_os = "linux") {
+        return;
+    }
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("hello.sh");
+    fs::write(&script_path, "echo hello").unwrap();
+
+    let run = run_script(&Cli { record_libraries: true, ..test_cli(&script_path, &archive_dir) }).unwrap();
+    let listing = fs::read_to_string(run.run_dir.join("libraries.txt")).unwrap();
+    assert!(listing.contains("libc"), "{}", listing);
+    assert!(run.result.numerical_libraries.is_empty());
+    assert!(!run.result.file_hashes.contains_key("libraries.txt"));
+}
+
+#[test]
+fn test_system_info() {
+    use fastsave::system::{parse_amd_gpus, parse_nvidia_gpus};
+
+    let gpus = parse_nvidia_gpus("NVIDIA A100-SXM4-40GB, 535.104.05, 40960\nNVIDIA A100-SXM4-40GB, 535.104.05, 40960\n");
+    assert_eq!(gpus.len(), 2);
+    assert_eq!(gpus[0].name, "NVIDIA A100-SXM4-40GB");
+    assert_eq!(gpus[0].driver_version.as_deref(), Some("535.104.05"));
+    assert_eq!(gpus[0].memory_mib, Some(40960));
+
+    let gpus = parse_amd_gpus(r#"{"card0": {"Card series": "Instinct MI250X"}, "system": {"Driver version": "6.2.4"}}"#);
+    assert_eq!(gpus.len(), 1);
+    assert_eq!((gpus[0].vendor.as_str(), gpus[0].name.as_str()), ("amd", "Instinct MI250X"));
+    assert_eq!(gpus[0].driver_version.as_deref(), Some("6.2.4"));
+    assert!(parse_amd_gpus("ERROR: No AMD GPUs").is_empty());
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("hello.sh");
+    fs::write(&script_path, "echo hello").unwrap();
+    let run = run_script(&test_cli(&script_path, &dir.path().join("archive"))).unwrap();
+    let system = run.result.system_info.as_ref().unwrap();
+    assert_eq!(system.os, std::env::consts::OS);
+    assert_eq!(system.arch, std::env::consts::ARCH);
+}
+
+#[test]
+fn test_container_detection() {
+    use fastsave::system::{container_id, parse_containerenv};
+
+    let podman = parse_containerenv("engine=\"podman-4.9.3\"\nname=\"sim\"\nid=\"4f2a\"\nimage=\"ghcr.io/lab/sim:2.1\"\nimageid=\"9b1c\"\nrootless=1\n");
+    assert_eq!(podman.runtime, "podman");
+    assert_eq!(podman.id.as_deref(), Some("4f2a"));
+    assert_eq!(podman.image.as_deref(), Some("ghcr.io/lab/sim:2.1"));
+    assert_eq!(podman.image_id.as_deref(), Some("9b1c"));
+    // Unprivileged containers get an empty file
+    assert_eq!(parse_containerenv("").image, None);
+
+    let id = "a".repeat(64);
+    assert_eq!(container_id(&format!("12:memory:/docker/{}\n", id)), Some(id.clone()));
+    assert_eq!(container_id(&format!("0::/system.slice/docker-{}.scope\n", id)), Some(id.clone()));
+    assert_eq!(container_id("0::/\n"), None);
+}
+
+#[test]
+fn test_per_host_layout() {
+    use fastsave::archive::{find_runs, resolve_run};
+    use fastsave::started::host_name;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, "layout:\n  per_host: true\n").unwrap();
+    let script_path = dir.path().join("hello.sh");
+    fs::write(&script_path, "echo hello").unwrap();
+
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+    let host_dir = run.run_dir.parent().unwrap();
+    assert_eq!(host_dir.parent().unwrap(), archive_dir);
+    assert_eq!(host_dir.file_name().unwrap().to_string_lossy(), host_name());
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 1);
+    assert_eq!(resolve_run(&archive_dir, &run.id()).unwrap(), run.run_dir);
+
+    // Another node numbered its runs independently and archived one with the same id
+    let other = archive_dir.join("node17").join(run.id());
+    fs::create_dir_all(&other).unwrap();
+    fs::copy(run.run_dir.join("fastsave.yaml"), other.join("fastsave.yaml")).unwrap();
+    let error = resolve_run(&archive_dir, &run.id()).unwrap_err().to_string();
+    assert!(error.contains("is ambiguous") && error.contains(&format!("node17/{}", run.id())), "{}", error);
+    assert_eq!(resolve_run(&archive_dir, &format!("node17/{}", run.id())).unwrap(), other);
+}
+
+#[test]
+fn test_tar_packing() {
+    use fastsave::tarball::{read_run_file, RUN_TAR_FILE};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("packed.sh");
+    let long_name = format!("{}.csv", "x".repeat(120));
+    fs::write(&script_path, format!("echo a > \"$2/a.txt\"\necho long > \"$2/{}\"\nmkdir \"$2/plots\"\necho nested > \"$2/plots/loss.txt\"", long_name)).unwrap();
+
+    let run = run_script(&Cli { tar: true, ..test_cli(&script_path, &archive_dir) }).unwrap();
+    let mut left: Vec<String> = fs::read_dir(&run.run_dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    left.sort();
+    assert_eq!(left, vec!["fastsave.yaml", RUN_TAR_FILE]);
+    assert_eq!(run.result.file_hashes.len(), 2);
+
+    assert_eq!(read_run_file(&run.run_dir, "a.txt").unwrap(), b"a\n");
+    assert_eq!(read_run_file(&run.run_dir, &long_name).unwrap(), b"long\n");
+    assert_eq!(read_run_file(&run.run_dir, "plots/loss.txt").unwrap(), b"nested\n");
+    assert!(read_run_file(&run.run_dir, "missing.txt").is_err());
+    assert!(fastsave::show::format_run(&archive_dir, &run).contains(&format!("(packed in {})", RUN_TAR_FILE)));
+
+    let report = fastsave::audit::audit_archive(&archive_dir).unwrap();
+    assert_eq!(report.files, 2);
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    // The archive is a plain tar file
+    if let Ok(output) = Command::new("tar").arg("-tf").arg(run.run_dir.join(RUN_TAR_FILE)).output() {
+        let listing = String::from_utf8_lossy(&output.stdout);
+        for name in ["a.txt", "fastsave.yaml", "plots/loss.txt", long_name.as_str()] {
+            assert!(listing.lines().any(|line| line == name), "{} not in {}", name, listing);
+        }
+    }
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_compressed_logs() {
+    use fastsave::logs::{full_output, STDERR_LOG, STDOUT_LOG};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("verbose.sh");
+    fs::write(&script_path, "for i in $(seq 1 1500); do echo \"step $i\"; done\necho oops >&2\necho done > \"$2/out.txt\"").unwrap();
+
+    let run = run_script(&Cli { compress_logs: true, ..test_cli(&script_path, &archive_dir) }).unwrap();
+    assert!(run.result.compressed_logs);
+    assert!(run.result.stdout.starts_with(&format!("[500 earlier lines in {}]\nstep 501\n", STDOUT_LOG)), "{}", &run.result.stdout[..80]);
+    assert!(run.result.stdout.ends_with("step 1500\n"));
+    assert_eq!(run.result.stderr, "oops\n");
+    assert!(run.run_dir.join(STDOUT_LOG).is_file() && run.run_dir.join(STDERR_LOG).is_file());
+    assert_eq!(run.result.file_hashes.keys().collect::<Vec<_>>(), vec!["out.txt"]);
+
+    let (stdout, stderr) = full_output(&run.run_dir, &run.result);
+    assert_eq!(stdout.lines().count(), 1500);
+    assert!(stdout.starts_with("step 1\nstep 2\n"));
+    assert_eq!(stderr, "oops\n");
+    assert!(fastsave::show::format_run(&archive_dir, &run).contains(STDOUT_LOG));
+
+    // Lines no longer in the manifest are still found
+    let pattern = regex::Regex::new("^step 7$").unwrap();
+    let matches = fastsave::grep::grep_runs(&archive_dir, &pattern, None, None, 0).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].line_number, 7);
+
+    // A log still being written is readable up to its last flush, for `fastsave attach`
+    let running = dir.path().join("running");
+    fs::create_dir(&running).unwrap();
+    let mut log = fastsave::logs::CompressedLog::create(&running, STDOUT_LOG).unwrap();
+    log.write_line("first");
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    log.write_line("second");
+    let read = |offset| fastsave::logs::read_running_log(&running, STDOUT_LOG, fastsave::logs::STDOUT_LIVE, offset).unwrap();
+    assert_eq!(read(0), b"first\nsecond\n");
+    assert_eq!(read(6), b"second\n");
+    log.finish();
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_compressed_logs_keep_early_metrics() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("early.sh");
+    fs::write(&script_path, "echo 'loss: 0.25'\necho 'FATAL: diverged' >&2\nfor i in $(seq 1 1500); do echo \"step $i\"; echo \"warn $i\" >&2; done").unwrap();
+    let config_path = dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "metrics:\n  patterns:\n    - 'loss: (?P<loss>[0-9.]+)'\nsuccess:\n  failure_patterns: ['^FATAL']\n").unwrap();
+
+    let run = run_script(&Cli {
+        compress_logs: true,
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+    assert!(!run.result.stdout.contains("loss"));
+    assert_eq!(run.result.metrics.get("loss").and_then(|loss| loss.as_f64()), Some(0.25));
+    assert_eq!(run.result.success, Some(false));
+}
+
+#[cfg(all(feature = "compress", feature = "search"))]
+#[test]
+fn test_search_compressed_logs() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("early.sh");
+    fs::write(&script_path, "echo 'mesh refinement diverged'\necho 'singular jacobian' >&2\nfor i in $(seq 1 1500); do echo \"step $i\"; echo \"warn $i\" >&2; done").unwrap();
+    let cli = Cli { compress_logs: true, ..test_cli(&script_path, &archive_dir) };
+
+    // Indexed when the index is built and when a run is added to an existing one
+    let first = run_script(&cli).unwrap();
+    assert!(!first.result.stdout.contains("refinement"));
+    assert_eq!(fastsave::search::search(&archive_dir, "refinement", 10).unwrap().len(), 1);
+    let second = run_script(&cli).unwrap();
+    let hits = fastsave::search::search(&archive_dir, "jacobian", 10).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits.iter().any(|hit| hit.id == second.id()));
+}
+
+#[cfg(not(feature = "compress"))]
+#[test]
+fn test_compress_logs_needs_feature() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("quiet.sh");
+    fs::write(&script_path, "echo hi").unwrap();
+
+    let error = run_script(&Cli { compress_logs: true, ..test_cli(&script_path, &archive_dir) }).err().unwrap();
+    assert!(error.to_string().contains("`compress` feature"), "{}", error);
+    assert!(!archive_dir.exists() || fs::read_dir(&archive_dir).unwrap().next().is_none());
+}
+
+#[test]
+fn test_live_upload() {
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let remote_dir = dir.path().join("remote");
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, format!("remote:\n  dir: {}\n  live_upload: true\n  poll_secs: 1\n", remote_dir.display())).unwrap();
+    let script_path = dir.path().join("uploaded.sh");
+    // Sees whether its first output was uploaded while it was still running
+    fs::write(&script_path, format!(
+        "echo a > \"$2/a.txt\"\nsleep 3\nif [ -f \"{}/$(basename \"$2\")/a.txt\" ]; then echo yes > \"$2/seen.txt\"; fi",
+        remote_dir.display()
+    )).unwrap();
+
+    let run = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+    assert!(run.run_dir.join("seen.txt").is_file());
+
+    let uploaded = remote_dir.join(run.id());
+    let mut files: Vec<String> = fs::read_dir(&uploaded).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    files.sort();
+    assert_eq!(files, vec!["a.txt", "fastsave.yaml", "seen.txt"]);
+    assert_eq!(fs::read(uploaded.join("fastsave.yaml")).unwrap(), fs::read(run.run_dir.join("fastsave.yaml")).unwrap());
+
+    let error = run_script(&Cli { live_upload: true, ..test_cli(&script_path, &archive_dir) }).err().unwrap();
+    assert!(error.to_string().contains("needs a remote directory"), "{}", error);
+}
+
+#[test]
+fn test_resume_upload() {
+    use fastsave::remote::{run_upload, UploadArgs, UPLOAD_STATE_FILE};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let remote_dir = dir.path().join("remote");
+    let script_path = dir.path().join("large.sh");
+    fs::write(&script_path, "seq 1 2000 > \"$2/large.txt\"").unwrap();
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, format!("remote:\n  dir: {}\n", remote_dir.display())).unwrap();
+
+    // An upload interrupted within large.txt; the partial copy is marked to tell a
+    // continued upload from one starting over
+    let large = run.run_dir.join("large.txt");
+    let content = fs::read(&large).unwrap();
+    let target = remote_dir.join(run.id());
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("large.txt.part"), vec![b'x'; 100]).unwrap();
+    let modified = fs::metadata(&large).unwrap().modified().unwrap().duration_since(std::time::UNIX_EPOCH).unwrap();
+    fs::write(run.run_dir.join(UPLOAD_STATE_FILE), format!(
+        "target: {}\nfile: large.txt\nsize: {}\nmodified:\n  secs_since_epoch: {}\n  nanos_since_epoch: {}\n",
+        target.display(), content.len(), modified.as_secs(), modified.subsec_nanos()
+    )).unwrap();
+
+    run_upload(&UploadArgs {
+        run: run.id(),
+        resume: true,
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+    let uploaded = fs::read(target.join("large.txt")).unwrap();
+    assert_eq!(&uploaded[..100], &[b'x'; 100][..]);
+    assert_eq!(&uploaded[100..], &content[100..]);
+    assert!(!target.join("large.txt.part").exists());
+    assert!(target.join("fastsave.yaml").is_file());
+    assert!(!run.run_dir.join(UPLOAD_STATE_FILE).exists());
+}
+
+#[test]
+fn test_compare_runs() {
+    use fastsave::compare::comparison_table;
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("sweep.sh");
+    fs::write(&script_path, "echo fixed > \"$2/config.txt\"\necho \"$4\" > \"$2/result.txt\"\necho \"loss: $4\" > \"$2/metrics.yaml\"").unwrap();
+    let runs: Vec<_> = ["1", "2", "1"]
+        .iter()
+        .map(|seed| run_script(&Cli { script_args: vec!["--seed".to_string(), seed.to_string()], ..test_cli(&script_path, dir.path()) }).unwrap())
+        .collect();
+
+    let table = comparison_table(&runs, false);
+    assert_eq!(table.headers[1..], runs.iter().map(|run| run.id()).collect::<Vec<_>>()[..]);
+    let row = |name: &str| table.rows.iter().find(|row| row[0] == name).unwrap_or_else(|| panic!("no row {}", name)).clone();
+    assert_eq!(row("args")[1..], ["--seed 1", "--seed 2", "--seed 1"]);
+    assert_eq!(row("metric loss")[1..], ["1", "2", "1"]);
+    let result = row("file result.txt");
+    assert!(result[1] == result[3] && result[1] != result[2]);
+    assert!(table.rows.iter().any(|row| row[0] == "file config.txt"));
+
+    let differences = comparison_table(&runs, true);
+    let names: Vec<&str> = differences.rows.iter().map(|row| row[0].as_str()).collect();
+    assert!(names.contains(&"file result.txt") && names.contains(&"args"));
+    assert!(!names.contains(&"file config.txt") && !names.contains(&"exit code"));
+    assert!(differences.to_csv().starts_with(&format!(",{},", runs[0].id())));
+}
+
+#[test]
+fn test_batch_results() {
+    use fastsave::batch::{job_parameters, run_batch_jobs, BatchArgs, BatchResult, BATCH_FILE, RESULTS_FILE};
+
+    let args: Vec<String> = ["input.csv", "--lr", "0.1", "--layers=2", "--verbose", "--seed", "7"].iter().map(|arg| arg.to_string()).collect();
+    let parameters = job_parameters(&args);
+    assert_eq!(parameters.into_iter().collect::<Vec<_>>(), vec![
+        ("layers".to_string(), "2".to_string()),
+        ("lr".to_string(), "0.1".to_string()),
+        ("seed".to_string(), "7".to_string()),
+        ("verbose".to_string(), "true".to_string()),
+    ]);
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let train = dir.path().join("train.sh");
+    fs::write(&train, "echo \"loss: $4\" > \"$2/metrics.yaml\"").unwrap();
+    let diverging = dir.path().join("diverging.sh");
+    fs::write(&diverging, "echo \"loss: 99\" > \"$2/metrics.yaml\"\nexit 1").unwrap();
+    let jobs_file = dir.path().join("sweep.txt");
+    fs::write(&jobs_file, format!(
+        "{train} --lr 0.5 --layers=2\n{train} --lr 0.25 --layers=4\n{diverging} --lr 10\n",
+        train = train.display(),
+        diverging = diverging.display(),
+    )).unwrap();
+
+    let (batch_dir, _) = run_batch_jobs(&BatchArgs {
+        jobs_file: jobs_file.to_string_lossy().into_owned(),
+        jobs: 1,
+        message: None,
+        minimize: None,
+        maximize: None,
+        config_path: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    let batch: BatchResult = serde_yaml::from_str(&fs::read_to_string(Path::new(&batch_dir).join(BATCH_FILE)).unwrap()).unwrap();
+    let summary = batch.summary.unwrap();
+    assert_eq!((summary.succeeded, summary.failed), (2, 1));
+    let loss = &summary.metrics["loss"];
+    assert_eq!((loss.count, loss.min, loss.max, loss.mean), (2, 0.25, 0.5, 0.375));
+
+    let csv = fs::read_to_string(Path::new(&batch_dir).join(RESULTS_FILE)).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "line,script,status,run,duration_ms,layers,lr,loss");
+    assert!(lines[1].ends_with(",2,0.5,0.5") && lines[1].contains(",success,"), "{}", lines[1]);
+    assert!(lines[3].ends_with(",,10,99") && lines[3].contains(",failed (1),"), "{}", lines[3]);
+}
+
+#[test]
+fn test_batch_links_best() {
+    use fastsave::batch::{run_batch_jobs, BatchArgs, BatchResult, BATCH_FILE, BEST_LINK};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let train = dir.path().join("train.sh");
+    fs::write(&train, "echo \"loss: $4\" > \"$2/metrics.yaml\"").unwrap();
+    let crashing = dir.path().join("crashing.sh");
+    fs::write(&crashing, "echo \"loss: 0.01\" > \"$2/metrics.yaml\"\nexit 1").unwrap();
+    let jobs_file = dir.path().join("sweep.txt");
+    fs::write(&jobs_file, format!(
+        "{train} --lr .nan\n{train} --lr 0.5 --layers=2\n{train} --lr 0.25 --layers=4\n{crashing} --lr 0.01\n",
+        train = train.display(),
+        crashing = crashing.display(),
+    )).unwrap();
+    let batch_args = |minimize: Option<&str>, maximize: Option<&str>| BatchArgs {
+        jobs_file: jobs_file.to_string_lossy().into_owned(),
+        jobs: 1,
+        message: None,
+        minimize: minimize.map(str::to_string),
+        maximize: maximize.map(str::to_string),
+        config_path: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    };
+
+    let (batch_dir, _) = run_batch_jobs(&batch_args(Some("metrics.loss"), None)).unwrap();
+    let batch: BatchResult = serde_yaml::from_str(&fs::read_to_string(Path::new(&batch_dir).join(BATCH_FILE)).unwrap()).unwrap();
+    // The failed job had the lowest loss, but only successful jobs count, and NaN never wins
+    let best = batch.best.unwrap();
+    assert_eq!((best.line, best.value), (3, 0.25));
+    assert_eq!(batch.objective.unwrap().metric, "loss");
+    let linked: ExecutionResult = serde_yaml::from_str(&fs::read_to_string(Path::new(&batch_dir).join(BEST_LINK).join("fastsave.yaml")).unwrap()).unwrap();
+    assert_eq!(linked.command.unwrap().script_args(), ["--lr", "0.25", "--layers=4"]);
+
+    let (_, batch) = run_batch_jobs(&batch_args(None, Some("loss"))).unwrap();
+    assert_eq!(batch.best.unwrap().line, 2);
+}
+
+#[test]
+fn test_plot_batch() {
+    use fastsave::batch::{run_batch_jobs, BatchArgs};
+    use fastsave::plot::{run_plot, scatter_svg, PlotArgs, PlotPoint};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let train = dir.path().join("train.sh");
+    fs::write(&train, "echo \"loss: $4\" > \"$2/metrics.yaml\"").unwrap();
+    let jobs_file = dir.path().join("sweep.txt");
+    fs::write(&jobs_file, format!(
+        "{train} --lr 0.1\n{train} --lr 0.01\n{train} --lr 0.001\n{train} --layers 2\n",
+        train = train.display(),
+    )).unwrap();
+    let (batch_dir, _) = run_batch_jobs(&BatchArgs {
+        jobs_file: jobs_file.to_string_lossy().into_owned(),
+        jobs: 1,
+        message: None,
+        minimize: None,
+        maximize: None,
+        config_path: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+
+    let args = PlotArgs {
+        batch: batch_dir.clone(),
+        x: "lr".to_string(),
+        y: "metrics.loss".to_string(),
+        log_x: true,
+        log_y: false,
+        output: None,
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    };
+    run_plot(&args).unwrap();
+    let svg = fs::read_to_string(Path::new(&batch_dir).join("plot_lr_metrics_loss.svg")).unwrap();
+    assert!(svg.starts_with("<svg"));
+    // The job without a learning rate isn't plotted
+    assert_eq!(svg.matches("<circle").count(), 3);
+    assert!(svg.contains("lr = 0.01, metrics.loss = 0.01"));
+    assert!(svg.contains(">metrics.loss vs lr</text>"));
+
+    // A batch given by its name in the archive
+    let name = Path::new(&batch_dir).file_name().unwrap().to_string_lossy().into_owned();
+    let output = dir.path().join("plot.svg");
+    run_plot(&PlotArgs { batch: name, output: Some(output.to_string_lossy().into_owned()), ..args }).unwrap();
+    assert!(output.is_file());
+
+    let points = [PlotPoint { id: "a".to_string(), x: 0.0, y: 1.0 }, PlotPoint { id: "b".to_string(), x: 1.0, y: 2.0 }];
+    assert!(scatter_svg(&points, "x", "y", false, true).is_ok());
+    assert!(scatter_svg(&points, "x", "y", true, false).unwrap_err().contains("logarithmic"));
+}
+
+#[test]
+fn test_tag_retention() {
+    use fastsave::archive::find_runs;
+    use fastsave::clean::{expired_runs, run_clean, CleanArgs, RetentionConfig};
+    use fastsave::tag::{parse_tag, update_tags};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("train.sh");
+    fs::write(&script_path, "echo trained").unwrap();
+    let run = |tags: &[&str]| {
+        run_script(&Cli {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            experiment: Some("sweep".to_string()),
+            ..test_cli(&script_path, &archive_dir)
+        }).unwrap()
+    };
+    let untagged = run(&[]);
+    let scratch = run(&["scratch"]);
+    let paper = run(&["scratch", "paper"]);
+    let published = run(&[]);
+    update_tags(&published.run_dir, &["paper".to_string()], false).unwrap();
+    assert!(parse_tag("two words").is_err() && parse_tag("").is_err());
+
+    let config: RetentionConfig = serde_yaml::from_str("
+keep_tags: [paper]
+rules:
+  - untagged: true
+    older_than: 14d
+  - tag: scratch
+    older_than: 2d
+").unwrap();
+    let runs = find_runs(&archive_dir).unwrap();
+    let expired_ids = |days: i64| -> Vec<String> {
+        let now = chrono::Utc::now() + chrono::Duration::days(days);
+        expired_runs(&archive_dir, &runs, &config, now).unwrap().iter().map(|(run, _)| run.id()).collect()
+    };
+    assert!(expired_ids(0).is_empty());
+    assert_eq!(expired_ids(3), vec![scratch.id()]);
+    // Runs tagged paper are kept at any age, even if another rule selects them
+    assert_eq!(expired_ids(15), vec![untagged.id(), scratch.id()]);
+
+    // A dry run only lists what a clean deletes
+    let config_path = dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "retention:\n  keep_tags: [paper]\n  rules:\n    - older_than: 0s\n").unwrap();
+    let args = CleanArgs {
+        dry_run: true,
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    };
+    run_clean(&args).unwrap();
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 4);
+    run_clean(&CleanArgs { dry_run: false, ..args }).unwrap();
+    let remaining: Vec<String> = find_runs(&archive_dir).unwrap().iter().map(|run| run.id()).collect();
+    assert_eq!(remaining, vec![paper.id(), published.id()]);
+    // The experiment links of deleted runs go with them
+    let links = fs::read_dir(archive_dir.join("experiments").join("sweep")).unwrap().count();
+    assert_eq!(links, 2);
+}
+
+#[test]
+fn test_keep_last_retention() {
+    use fastsave::archive::find_runs;
+    use fastsave::clean::{expired_runs, RetentionConfig};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let run = |name: &str, tags: &[&str]| {
+        let script_path = dir.path().join(name);
+        fs::write(&script_path, "echo done").unwrap();
+        run_script(&Cli {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..test_cli(&script_path, &archive_dir)
+        }).unwrap().id()
+    };
+    let simulations = [run("run_simulation.sh", &[]), run("run_simulation.sh", &["paper"]), run("run_simulation.sh", &[]), run("run_simulation.sh", &[])];
+    let plots = [run("plot.sh", &[]), run("plot.sh", &[]), run("plot.sh", &[])];
+
+    let config: RetentionConfig = serde_yaml::from_str("
+keep_tags: [paper]
+scripts:
+  run_simulation:
+    keep_last: 2
+default:
+  keep_last: 1
+").unwrap();
+    let runs = find_runs(&archive_dir).unwrap();
+    let expired = expired_runs(&archive_dir, &runs, &config, chrono::Utc::now()).unwrap();
+    let expired: Vec<(String, &str)> = expired.iter().map(|(run, reason)| (run.id(), reason.as_str())).collect();
+    // The run tagged paper neither gets deleted nor takes one of the two places
+    assert_eq!(expired, vec![
+        (simulations[0].clone(), "keep last 2 of run_simulation"),
+        (plots[0].clone(), "keep last 1 of plot"),
+        (plots[1].clone(), "keep last 1 of plot"),
+    ]);
+}
+
+#[test]
+fn test_auto_clean() {
+    use fastsave::archive::find_runs;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("sim.sh");
+    fs::write(&script_path, "echo done").unwrap();
+    let config_path = dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "retention:\n  default:\n    keep_last: 1\n").unwrap();
+    let cli = Cli { config_path: Some(config_path.to_string_lossy().into_owned()), ..test_cli(&script_path, &archive_dir) };
+
+    // Without auto_clean the policy only applies to `fastsave clean`
+    for _ in 0..3 {
+        run_script(&cli).unwrap();
+    }
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 3);
+
+    fs::write(&config_path, "retention:\n  auto_clean: true\n  default:\n    keep_last: 1\n").unwrap();
+    let previous = find_runs(&archive_dir).unwrap().pop().unwrap().id();
+    let latest = run_script(&cli).unwrap().id();
+    // The policy runs before the new run is archived, which leaves the newest old run
+    let remaining: Vec<String> = find_runs(&archive_dir).unwrap().iter().map(|run| run.id()).collect();
+    assert_eq!(remaining, vec![previous, latest]);
+}
+
+#[test]
+fn test_auto_clean_keeps_archive_of_no_subfolder_run() {
+    use fastsave::archive::find_runs;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let a = dir.path().join("a.sh");
+    fs::write(&a, "echo a").unwrap();
+    let b = dir.path().join("b.sh");
+    fs::write(&b, "echo b").unwrap();
+    let flat = run_script(&Cli { no_subfolder: true, ..test_cli(&b, &archive_dir) }).unwrap();
+    let first = run_script(&test_cli(&a, &archive_dir)).unwrap();
+
+    // An ordinary run applies the policy, which selects the --no-subfolder run
+    let config_path = dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "retention:\n  auto_clean: true\n  scripts:\n    b:\n      keep_last: 0\n").unwrap();
+    let second = run_script(&Cli { config_path: Some(config_path.to_string_lossy().into_owned()), ..test_cli(&a, &archive_dir) }).unwrap();
+
+    let mut remaining: Vec<String> = find_runs(&archive_dir).unwrap().iter().map(|run| run.id()).collect();
+    remaining.sort();
+    let mut expected = vec![flat.id(), first.id(), second.id()];
+    expected.sort();
+    assert_eq!(remaining, expected);
+}
+
+#[test]
+fn test_clean_keeps_archive_of_no_subfolder_run() {
+    use fastsave::archive::find_runs;
+    use fastsave::clean::{remove_run, run_clean, CleanArgs};
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let a = dir.path().join("a.sh");
+    fs::write(&a, "echo a").unwrap();
+    let b = dir.path().join("b.sh");
+    fs::write(&b, "echo b").unwrap();
+    let kept: Vec<String> = (0..3).map(|_| run_script(&test_cli(&a, &archive_dir)).unwrap().id()).collect();
+    let flat = run_script(&Cli { no_subfolder: true, ..test_cli(&b, &archive_dir) }).unwrap();
+    assert_eq!(flat.run_dir, archive_dir);
+
+    let config_path = dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "retention:\n  scripts:\n    b:\n      keep_last: 0\n").unwrap();
+    run_clean(&CleanArgs {
+        dry_run: false,
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        archive_dir: archive_dir.to_string_lossy().into_owned(),
+    }).unwrap();
+    let mut remaining: Vec<String> = find_runs(&archive_dir).unwrap().iter().map(|run| run.id()).collect();
+    remaining.sort();
+    let mut expected = kept.clone();
+    expected.push(flat.id());
+    expected.sort();
+    assert_eq!(remaining, expected);
+
+    // Removing it directly is refused as well
+    assert!(remove_run(&archive_dir, &flat).is_err());
+    assert_eq!(find_runs(&archive_dir).unwrap().len(), 4);
+}
+
+#[test]
+fn test_pinned_runs() {
+    use fastsave::archive::find_runs;
+    use fastsave::clean::{auto_clean, RetentionConfig};
+    use fastsave::pin::set_pinned;
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("sim.sh");
+    fs::write(&script_path, "echo done").unwrap();
+    let runs: Vec<_> = (0..3).map(|_| run_script(&test_cli(&script_path, dir.path())).unwrap()).collect();
+    set_pinned(&runs[0].run_dir, true).unwrap();
+    set_pinned(&runs[1].run_dir, true).unwrap();
+    set_pinned(&runs[1].run_dir, false).unwrap();
+
+    // A pinned run survives even a policy that matches every run
+    let config: RetentionConfig = serde_yaml::from_str("rules:\n  - older_than: 0s\ndefault:\n  keep_last: 0\n").unwrap();
+    assert_eq!(auto_clean(dir.path(), &config).unwrap().0, 2);
+    let remaining = find_runs(dir.path()).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id(), runs[0].id());
+    assert!(remaining[0].result.pinned);
+}
+
+#[test]
+fn test_lock_env() {
+    let dir = TempDir::new().unwrap();
+    let project = dir.path().join("project");
+    fs::create_dir_all(project.join("scripts")).unwrap();
+    init_git_repo(&project).unwrap();
+    fs::write(project.join("Cargo.lock"), "version = 3\n").unwrap();
+    fs::write(project.join("package-lock.json"), "{\"lockfileVersion\": 3}\n").unwrap();
+    let script_path = project.join("scripts").join("build.sh");
+    fs::write(&script_path, "echo built").unwrap();
+    let archive_dir = dir.path().join("archive");
+
+    let run = run_script(&test_cli(&script_path, &archive_dir)).unwrap();
+    assert!(run.result.environment_files.is_empty());
+    assert!(!run.run_dir.join("environment").exists());
+
+    let run = run_script(&Cli { lock_env: true, ..test_cli(&script_path, &archive_dir) }).unwrap();
+    let files: Vec<&str> = run.result.environment_files.iter().map(|file| file.file.as_str()).collect();
+    // Found in the repository root above the script, and no pip freeze for a shell script
+    assert_eq!(files, vec!["environment/Cargo.lock", "environment/package-lock.json"]);
+    assert_eq!(fs::read_to_string(run.run_dir.join("environment").join("Cargo.lock")).unwrap(), "version = 3\n");
+    assert!(run.result.environment_files[0].source.ends_with("Cargo.lock"));
+    assert_eq!(run.result.environment_files[0].sha256.len(), 64);
+    // Archived by fastsave, not an output of the script
+    assert!(run.result.file_hashes.keys().all(|file| !file.contains("lock")));
+}
+
+#[test]
+fn test_repro_run() {
+    use fastsave::repro::reproduce;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "echo \"result $4\" > \"$2/out.txt\"\necho \"loss: 0.5\" > \"$2/metrics.yaml\"").unwrap();
+    let original = run_script(&Cli {
+        script_args: vec!["--seed".to_string(), "7".to_string()],
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+
+    let report = reproduce(&archive_dir, &original.run_dir, &[], None).unwrap();
+    assert!(report.is_reproduced(), "{:?}", report.differences);
+    assert_eq!(report.original, original.id());
+    assert!(report.notes.is_empty(), "{:?}", report.notes);
+    // The reproduction ran with the same seed and records the original as its parent
+    let reproduction = fastsave::archive::resolve_run(&archive_dir, &report.reproduction).unwrap();
+    assert_eq!(fs::read_to_string(reproduction.join("out.txt")).unwrap(), "result 7\n");
+    let manifest: ExecutionResult = serde_yaml::from_str(&fs::read_to_string(reproduction.join("fastsave.yaml")).unwrap()).unwrap();
+    assert_eq!(manifest.parents, vec![original.id()]);
+
+    // A metric off within its tolerance still reproduces, a changed output doesn't
+    fs::write(&script_path, "echo \"result $4\" > \"$2/out.txt\"\necho \"loss: 0.5000001\" > \"$2/metrics.yaml\"").unwrap();
+    let report = reproduce(&archive_dir, &original.run_dir, &[("loss".to_string(), 1e-6)], None).unwrap();
+    assert!(report.is_reproduced());
+    let report = reproduce(&archive_dir, &original.run_dir, &[], None).unwrap();
+    assert_eq!(report.differences.changed_metrics, vec!["loss"]);
+    assert!(report.differences.changed_files.is_empty());
+    fs::write(&script_path, "echo \"result 8\" > \"$2/out.txt\"").unwrap();
+    let report = reproduce(&archive_dir, &original.run_dir, &[], None).unwrap();
+    assert!(!report.is_reproduced());
+    assert_eq!(report.differences.changed_files, vec!["out.txt"]);
+    assert_eq!(report.differences.missing_files, vec!["metrics.yaml"]);
+}
+
+#[test]
+fn test_repro_packed_run_with_params() {
+    use fastsave::attest::verified_digest;
+    use fastsave::repro::reproduce;
+    use fastsave::tarball::RUN_TAR_FILE;
+
+    let dir = TempDir::new().unwrap();
+    let archive_dir = dir.path().join("archive");
+    let params_path = dir.path().join("sim.yaml");
+    fs::write(&params_path, "steps: 100\n").unwrap();
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "cp \"$3\" \"$2/used.yaml\"").unwrap();
+    let original = run_script(&Cli {
+        params: Some(params_path.to_string_lossy().into_owned()),
+        script_args: vec!["{params}".to_string()],
+        tar: true,
+        ..test_cli(&script_path, &archive_dir)
+    }).unwrap();
+
+    // The parameters stay next to the tar, like the manifest
+    let mut left: Vec<String> = fs::read_dir(&original.run_dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    left.sort();
+    assert_eq!(left, vec!["fastsave.yaml", "params.yaml", RUN_TAR_FILE]);
+    verified_digest(&original).unwrap();
+    let audit = fastsave::audit::audit_archive(&archive_dir).unwrap();
+    assert!(audit.issues.is_empty(), "{:?}", audit.issues);
+
+    let report = reproduce(&archive_dir, &original.run_dir, &[], None).unwrap();
+    assert!(report.is_reproduced(), "{:?}", report.differences);
+}
+
+#[test]
+fn test_diff_environments() {
+    use fastsave::archive::{load_manifest, save_manifest, MANIFEST_FILE};
+    use fastsave::diff::{diff_environments, format_environment_diff, parse_packages, FileStatus};
+    use fastsave::provenance::EnvironmentFile;
+
+    let packages = parse_packages("numpy==1.26.4\nmylib @ file:///src/mylib\n# comment\nname: sim\ndependencies:\n  - python=3.11.5=h955ad1f_0\n  - pip:\n    - torch==2.1.0\n");
+    assert_eq!(packages.into_iter().collect::<Vec<_>>(), vec![
+        ("mylib".to_string(), "file:///src/mylib".to_string()),
+        ("numpy".to_string(), "1.26.4".to_string()),
+        ("python".to_string(), "3.11.5".to_string()),
+        ("torch".to_string(), "2.1.0".to_string()),
+    ]);
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("train.sh");
+    fs::write(&script_path, "echo trained").unwrap();
+    let mut runs = Vec::new();
+    for (requirements, lockfile, interpreter) in [("numpy==1.26.0\nscipy==1.11.0\n", "a", "/usr/bin/python3"), ("numpy==1.26.4\nscipy==1.11.0\ntorch==2.1.0\n", "a", "/opt/conda/bin/python3")] {
+        let run = run_script(&test_cli(&script_path, dir.path())).unwrap();
+        let environment = run.run_dir.join("environment");
+        fs::create_dir_all(&environment).unwrap();
+        fs::write(environment.join("requirements.txt"), requirements).unwrap();
+        let mut result = load_manifest(&run.run_dir.join(MANIFEST_FILE)).unwrap();
+        result.environment_files = vec![
+            EnvironmentFile { file: "environment/Cargo.lock".to_string(), source: "Cargo.lock".to_string(), sha256: lockfile.to_string() },
+            EnvironmentFile { file: "environment/requirements.txt".to_string(), source: "pip freeze".to_string(), sha256: requirements.len().to_string() },
+        ];
+        result.interpreter_path = Some(interpreter.to_string());
+        save_manifest(&run.run_dir, &result).unwrap();
+        runs.push(run.run_dir);
+    }
+
+    let diff = diff_environments(&runs[0], &runs[1]).unwrap();
+    assert_eq!(diff.properties, vec![("interpreter".to_string(), "/usr/bin/python3".to_string(), "/opt/conda/bin/python3".to_string())]);
+    assert_eq!(diff.files[0].status, FileStatus::Identical);
+    assert_eq!(diff.files[1].status, FileStatus::Changed);
+    assert_eq!(diff.files[1].packages, vec![
+        ("numpy".to_string(), Some("1.26.0".to_string()), Some("1.26.4".to_string())),
+        ("torch".to_string(), None, Some("2.1.0".to_string())),
+    ]);
+    let text = format_environment_diff(&diff);
+    assert!(text.contains("    numpy: 1.26.0 -> 1.26.4\n"), "{}", text);
+    assert!(text.contains("  identical          environment/Cargo.lock\n"), "{}", text);
+
+    assert!(diff_environments(&runs[0], &runs[0]).unwrap().is_empty());
+}
+
+#[test]
+fn test_diff_git() {
+    use fastsave::diff::{diff_git, format_git_diff};
+    use fastsave::started::GIT_PATCH_FILE;
+
+    let repo = TempDir::new().unwrap();
+    let archive = TempDir::new().unwrap();
+    let script_path = repo.path().join("train.sh");
+    fs::write(&script_path, "echo trained").unwrap();
+    fs::write(repo.path().join("model.txt"), "layers: 2\n").unwrap();
+    init_git_repo(repo.path()).unwrap();
+    let clean = run_script(&test_cli(&script_path, archive.path())).unwrap();
+    assert!(!clean.run_dir.join(GIT_PATCH_FILE).exists());
+
+    fs::write(repo.path().join("model.txt"), "layers: 3\n").unwrap();
+    let dirty = run_script(&test_cli(&script_path, archive.path())).unwrap();
+    let patch = fs::read_to_string(dirty.run_dir.join(GIT_PATCH_FILE)).unwrap();
+    assert!(patch.contains("+layers: 3\n"), "{}", patch);
+    assert!(!dirty.result.file_hashes.contains_key(GIT_PATCH_FILE));
+
+    Command::new("git").args(["commit", "-am", "Deeper model"]).current_dir(repo.path()).output().unwrap();
+    fs::write(repo.path().join("model.txt"), "layers: 4\n").unwrap();
+    let later = run_script(&test_cli(&script_path, archive.path())).unwrap();
+
+    let diff = diff_git(&clean.run_dir, &later.run_dir).unwrap();
+    let log = diff.log.clone().unwrap();
+    assert_eq!(log.len(), 1);
+    assert!(log[0].starts_with('>') && log[0].ends_with("Deeper model"), "{:?}", log);
+    assert_eq!(diff.dirty_only_in_b, vec!["model.txt".to_string()]);
+    let text = format_git_diff(&diff);
+    assert!(text.contains("Commits between them"), "{}", text);
+    assert!(text.contains("+-layers: 3\n++layers: 4\n"), "{}", text);
+
+    let diff = diff_git(&dirty.run_dir, &later.run_dir).unwrap();
+    assert_eq!(diff.dirty_in_both, vec!["model.txt".to_string()]);
+    assert!(diff.patch_diff.is_some());
+    assert!(diff_git(&dirty.run_dir, &dirty.run_dir).unwrap().patch_diff.is_none());
+}
+
+#[test]
+fn test_params_file() {
+    use fastsave::archive::{load_manifest, MANIFEST_FILE};
+    use sha2::{Digest, Sha256};
+
+    let dir = TempDir::new().unwrap();
+    let params_path = dir.path().join("sim.yaml");
+    fs::write(&params_path, "steps: 100\n").unwrap();
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "cat \"$3\"\necho \"env: $FASTSAVE_PARAMS\"").unwrap();
+    let cli = Cli {
+        params: Some(params_path.to_string_lossy().into_owned()),
+        script_args: vec!["{params}".to_string()],
+        ..test_cli(&script_path, dir.path())
+    };
+    let run = run_script(&cli).unwrap();
+
+    let params = run.result.params.clone().unwrap();
+    assert_eq!(params.file, "params.yaml");
+    assert_eq!(fs::read_to_string(run.run_dir.join("params.yaml")).unwrap(), "steps: 100\n");
+    assert_eq!(params.sha256, format!("{:x}", Sha256::digest(b"steps: 100\n")));
+    assert!(!run.result.file_hashes.contains_key("params.yaml"));
+    let copy = run.run_dir.join("params.yaml").to_string_lossy().into_owned();
+    assert!(run.result.stdout.contains("steps: 100\n"), "{}", run.result.stdout);
+    assert!(run.result.stdout.contains(&format!("env: {}", copy)), "{}", run.result.stdout);
+    assert_eq!(load_manifest(&run.run_dir.join(MANIFEST_FILE)).unwrap().params, Some(params));
+
+    // Changing the original afterwards doesn't affect the archived copy
+    fs::write(&params_path, "steps: 200\n").unwrap();
+    assert_eq!(fs::read_to_string(&copy).unwrap(), "steps: 100\n");
+}
+
+#[test]
+fn test_default_script_args() {
+    use fastsave::command::merge_args;
+
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let defaults = args(&["--solver", "cg", "--tol=1e-8", "--verbose", "--shift", "-1"]);
+    assert_eq!(merge_args(&defaults, &args(&["input.dat"])), args(&["--solver", "cg", "--tol=1e-8", "--verbose", "--shift", "-1", "input.dat"]));
+    assert_eq!(
+        merge_args(&defaults, &args(&["--tol", "1e-4", "--solver=gmres", "--shift", "2"])),
+        args(&["--verbose", "--tol", "1e-4", "--solver=gmres", "--shift", "2"])
+    );
+    assert_eq!(merge_args(&defaults, &args(&["--", "--verbose"])), args(&["--solver", "cg", "--tol=1e-8", "--verbose", "--shift", "-1", "--", "--verbose"]));
+
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("config.yaml");
+    fs::write(&config_path, "scripts:\n  solve:\n    args: [--solver, cg, --tol, 1e-8]\n").unwrap();
+    let script_path = dir.path().join("solve.sh");
+    fs::write(&script_path, "shift 2\necho \"$@\"").unwrap();
+    let cli = Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        script_args: args(&["--tol", "1e-3", "mesh.msh"]),
+        ..test_cli(&script_path, dir.path())
+    };
+    let run = run_script(&cli).unwrap();
+    assert_eq!(run.result.stdout.trim(), "--solver cg --tol 1e-3 mesh.msh");
+    assert_eq!(run.result.command.unwrap().script_args(), args(&["--solver", "cg", "--tol", "1e-3", "mesh.msh"]));
+
+    let run = run_script(&Cli { no_default_args: true, ..cli }).unwrap();
+    assert_eq!(run.result.stdout.trim(), "--tol 1e-3 mesh.msh");
+}
+
+#[test]
+fn test_aliases() {
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("run_simulation.sh");
+    fs::write(&script_path, "shift 2\necho \"$@\"").unwrap();
+    let archive = dir.path().join("sims");
+    let config_path = dir.path().join("config.yaml");
+    fs::write(
+        &config_path,
+        format!(
+            "aliases:\n  sim:\n    script: {}\n    args: [--rows, 3, --cols, 4]\n    archive_dir: {}\n    experiment: sweep\n",
+            script_path.display(),
+            archive.display()
+        ),
+    )
+    .unwrap();
+    let cli = Cli {
+        config_path: Some(config_path.to_string_lossy().into_owned()),
+        script_args: vec!["--rows".to_string(), "10".to_string()],
+        ..Cli::new("@sim")
+    };
+
+    let run = run_script(&cli).unwrap();
+    assert!(run.run_dir.starts_with(&archive), "{}", run.run_dir.display());
+    assert!(run.run_dir.ends_with(format!("{}_run_simulation_run1", chrono::Local::now().format("%Y-%m-%d"))));
+    assert_eq!(run.result.stdout.trim(), "--cols 4 --rows 10");
+    assert_eq!(run.result.experiment.as_deref(), Some("sweep"));
+
+    // An archive given on the command line wins over the alias
+    let other = dir.path().join("other");
+    let run = run_script(&Cli { archive_dir: other.to_string_lossy().into_owned(), ..cli.clone() }).unwrap();
+    assert!(run.run_dir.starts_with(&other));
+
+    let error = run_script(&Cli { script: "@simulation".to_string(), ..cli }).err().unwrap().to_string();
+    assert_eq!(error, "Unknown alias @simulation, the config file defines @sim");
+}
+
+#[test]
+fn test_init_project() {
+    use fastsave::ignore::IgnorePatterns;
+    use fastsave::init::{init_project, InitAction};
+    use fastsave::FastsaveConfig;
+
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(".gitignore"), "target/").unwrap();
+    let actions = init_project(dir.path(), "archive", Some("analyze.sh"), false).unwrap();
+    let actions: Vec<&InitAction> = actions.iter().map(|(_, action)| action).collect();
+    assert_eq!(actions, [&InitAction::Created, &InitAction::Created, &InitAction::Updated, &InitAction::Created]);
+    assert_eq!(fs::read_to_string(dir.path().join(".gitignore")).unwrap(), "target/\n/archive/\n");
+    let config: FastsaveConfig = serde_yaml::from_str(&fs::read_to_string(dir.path().join("fastsave.yaml")).unwrap()).unwrap();
+    assert_eq!(config.get_interpreter("sh").map(String::as_str), Some("sh"));
+
+    // A second init leaves everything alone
+    let again = init_project(dir.path(), "archive/", Some("analyze.sh"), false).unwrap();
+    assert!(again.iter().all(|(_, action)| *action == InitAction::Kept));
+    assert_eq!(fs::read_to_string(dir.path().join(".gitignore")).unwrap(), "target/\n/archive/\n");
+    assert!(init_project(dir.path(), "archive", Some("analyze.R"), false).is_err());
+
+    let ignore = IgnorePatterns::parse("# scratch\n*.tmp\n\ncheckpoint_*\n").unwrap();
+    assert!(ignore.matches("a.tmp") && ignore.matches("checkpoint_3.pt") && !ignore.matches("result.txt"));
+
+    // The template follows the --output_dir convention, and ignored outputs aren't hashed
+    let ignore_path = dir.path().join(".fastsaveignore");
+    fs::write(&ignore_path, fs::read_to_string(&ignore_path).unwrap() + "metrics.json\n").unwrap();
+    let _guard = lock_cwd();
+    let original_dir = std::env::current_dir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    let run = run_script(&Cli::new("analyze.sh"));
+    std::env::set_current_dir(original_dir).unwrap();
+    let run = run.unwrap();
+    assert_eq!(run.result.file_hashes.keys().collect::<Vec<_>>(), ["result.txt"]);
+    assert_eq!(run.result.metrics["answer"], serde_yaml::Value::from(42));
+}
+
+#[test]
+fn test_multi_word_interpreter() {
+    use fastsave::command::split_command;
+
+    let words = |words: &[&str]| words.iter().map(|word| word.to_string()).collect::<Vec<_>>();
+    assert_eq!(split_command("mpirun -np 8 python").unwrap(), words(&["mpirun", "-np", "8", "python"]));
+    assert_eq!(split_command("nix run nixpkgs#python3 --").unwrap(), words(&["nix", "run", "nixpkgs#python3", "--"]));
+    assert_eq!(split_command(r#"env 'A=x y' "B=\"q\"" C=a\ b"#).unwrap(), words(&["env", "A=x y", "B=\"q\"", "C=a b"]));
+    assert!(split_command("sh -c 'echo").is_err());
+    assert!(split_command("  ").is_err());
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("greet.sh");
+    fs::write(&script_path, "echo \"$GREETING\"").unwrap();
+    let cli = Cli {
+        interpreter: Some("env 'GREETING=hello world' sh".to_string()),
+        ..test_cli(&script_path, dir.path())
+    };
+    let run = run_script(&cli).unwrap();
+    assert_eq!(run.result.stdout.trim(), "hello world");
+    let command = run.result.command.unwrap();
+    assert_eq!(command.program, "env");
+    assert_eq!(command.args[..3], words(&["GREETING=hello world", "sh", &script_path.to_string_lossy()]));
+    assert_eq!(command.interpreter(), "env 'GREETING=hello world' sh");
+    assert!(command.script_args().is_empty());
+    assert!(run.result.interpreter_path.unwrap().ends_with("/env"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_timeout_warning() {
+    use fastsave::archive::parse_duration;
+    use fastsave::timeout::{Timeout, TimeoutConfig};
+
+    let config = TimeoutConfig { limit: Some("1h".to_string()), ..TimeoutConfig::default() };
+    let timeout = Timeout::new(None, parse_duration("10m").ok(), &config).unwrap().unwrap();
+    assert_eq!(timeout.limit, std::time::Duration::from_secs(3600));
+    assert_eq!(timeout.warn_before, Some(std::time::Duration::from_secs(600)));
+    assert!(timeout.signal);
+    assert!(Timeout::new(None, None, &TimeoutConfig::default()).unwrap().is_none());
+    assert!(Timeout::new(parse_duration("5m").ok(), parse_duration("5m").ok(), &config).is_err());
+
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "trap 'echo step 7 > \"$2/checkpoint.txt\"' USR1\nwhile true; do sleep 0.1; done\n").unwrap();
+    let cli = Cli {
+        timeout: parse_duration("2s").ok(),
+        warn_before: parse_duration("1s").ok(),
+        ..test_cli(&script_path, dir.path())
+    };
+    let run = run_script(&cli).unwrap();
+    assert_eq!(fs::read_to_string(run.run_dir.join("checkpoint.txt")).unwrap(), "step 7\n");
+    assert!(run.result.file_hashes.contains_key("checkpoint.txt"));
+    assert_eq!(run.result.killed_reason, Some(fastsave::killed::KilledReason::Timeout));
+    assert!(run.result.duration_ms < 10_000);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_disk_full_stops_script() {
+    use fastsave::disk::{free_space, parse_size};
+
+    assert_eq!(parse_size("512").unwrap(), 512);
+    assert_eq!(parse_size("500M").unwrap(), 500 * 1024 * 1024);
+    assert_eq!(parse_size("1.5GiB").unwrap(), 3 * 512 * 1024 * 1024);
+    assert_eq!(parse_size("2 TB").unwrap(), 2 << 40);
+    assert!(parse_size("5X").is_err());
+    assert!(parse_size("G").is_err());
+
+    let dir = TempDir::new().unwrap();
+    assert!(free_space(dir.path()).unwrap() > 0);
+    let script_path = dir.path().join("fill.sh");
+    fs::write(&script_path, "while true; do sleep 0.1; done\n").unwrap();
+    let cli = Cli {
+        // More than any disk has free, so the script is stopped at the first check
+        min_free_space: Some(u64::MAX),
+        ..test_cli(&script_path, dir.path())
+    };
+    let run = run_script(&cli).unwrap();
+    assert_eq!(run.result.killed_reason, Some(fastsave::killed::KilledReason::DiskFull));
+    assert_eq!(run.result.success, Some(false));
+    let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+    assert!(manifest.contains("killed_reason: disk_full"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_file_timeline() {
+    let dir = TempDir::new().unwrap();
+    let script_path = dir.path().join("phases.sh");
+    fs::write(&script_path, "echo 1 > \"$2/first.txt\"\nsleep 0.5\necho 2 > \"$2/second.txt\"\nsleep 0.5\necho 3 >> \"$2/first.txt\"\n").unwrap();
+    let run = run_script(&test_cli(&script_path, dir.path())).unwrap();
+    let timeline = &run.result.file_timeline;
+    assert_eq!(timeline.keys().collect::<Vec<_>>(), ["first.txt", "second.txt"]);
+    assert!(timeline["second.txt"].created_ms >= timeline["first.txt"].created_ms + 400);
+    assert!(timeline["first.txt"].modified_ms >= timeline["second.txt"].modified_ms + 400);
+    assert!(timeline["second.txt"].modified_ms < timeline["first.txt"].modified_ms);
+    let manifest = fs::read_to_string(run.run_dir.join("fastsave.yaml")).unwrap();
+    assert!(manifest.contains("file_timeline:"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_trace_inputs() {
+    use fastsave::trace::{read_files, InputTracer, TraceConfig, TRACE_LOG};
+
+    let dir = TempDir::new().unwrap();
+    let project = dir.path().join("project");
+    let run_dir = dir.path().join("archive/run1");
+    fs::create_dir_all(&project).unwrap();
+    fs::create_dir_all(&run_dir).unwrap();
+    fs::write(project.join("analyze.py"), "").unwrap();
+    fs::write(dir.path().join("old_data.csv"), "a,b\n").unwrap();
+    fs::write(dir.path().join("ignored.cfg"), "").unwrap();
+    let outside = dir.path().join("old_data.csv").to_string_lossy().into_owned();
+
+    let log = format!(
+        "101 openat(AT_FDCWD, \"/etc/ld.so.cache\", O_RDONLY|O_CLOEXEC) = 3\n\
+         101 openat(AT_FDCWD, \"analyze.py\", O_RDONLY) = 3\n\
+         102 openat(AT_FDCWD, \"{outside}\", O_RDONLY|O_CLOEXEC <unfinished ...>\n\
+         102 <... openat resumed>) = 4\n\
+         102 openat(AT_FDCWD, \"{outside}\", O_RDONLY) = 5\n\
+         102 openat(AT_FDCWD, \"/nonexistent.csv\", O_RDONLY) = -1 ENOENT (No such file or directory)\n\
+         102 openat(AT_FDCWD, \"{dir}/new.csv\", O_WRONLY|O_CREAT|O_TRUNC, 0666) = 6\n\
+         102 openat(AT_FDCWD, \"{dir}\", O_RDONLY|O_DIRECTORY) = 7\n\
+         102 open(\"{dir}/ignored.cfg\", O_RDONLY) = 8\n",
+        dir = dir.path().display(),
+    );
+    let read = read_files(&log, &project);
+    assert_eq!(read, [PathBuf::from("/etc/ld.so.cache"), project.join("analyze.py"), PathBuf::from(&outside), PathBuf::from(&outside), dir.path().join("ignored.cfg")]);
+
+    let config = TraceConfig { enabled: true, ignore: vec!["**/*.cfg".to_string()] };
+    let tracer = InputTracer::new(&run_dir, &[project.clone(), run_dir.clone()], &config).unwrap();
+    fs::write(run_dir.join(TRACE_LOG), &log).unwrap();
+    let traced = tracer.finish(&project).unwrap();
+    assert!(!run_dir.join(TRACE_LOG).exists());
+    assert_eq!(traced.len(), 1);
+    assert_eq!(traced[0].path, dir.path().canonicalize().unwrap().join("old_data.csv").to_string_lossy());
+    assert_eq!(traced[0].sha256.as_ref().map(String::len), Some(64));
+}
+
+#[test]
+fn test_run_digest() {
+    use fastsave::archive::{load_manifest, save_manifest, MANIFEST_FILE};
+    use fastsave::audit::{audit_archive, IssueKind};
+    use fastsave::digest::run_digest;
+
+    let dir = TempDir::new().unwrap();
+    let params_path = dir.path().join("sim.yaml");
+    fs::write(&params_path, "steps: 100\n").unwrap();
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "echo \"$4\" > \"$2/result.txt\"\ndate +%N\n").unwrap();
+    let cli = |seed: &str| Cli {
+        params: Some(params_path.to_string_lossy().into_owned()),
+        script_args: vec!["--config={params}".to_string(), seed.to_string()],
+        ..test_cli(&script_path, dir.path())
+    };
+
+    // The output directory in the arguments and the captured output don't count
+    let first = run_script(&cli("1")).unwrap();
+    let second = run_script(&cli("1")).unwrap();
+    let other = run_script(&cli("2")).unwrap();
+    let digest = first.result.run_digest.clone().unwrap();
+    assert_eq!(digest.len(), 64);
+    assert_eq!(second.result.run_digest.as_ref(), Some(&digest));
+    assert_ne!(other.result.run_digest.as_ref(), Some(&digest));
+    assert_eq!(run_digest(&load_manifest(&first.run_dir.join(MANIFEST_FILE)).unwrap()), digest);
+    assert!(audit_archive(dir.path()).unwrap().issues.is_empty());
+
+    let mut tampered = load_manifest(&first.run_dir.join(MANIFEST_FILE)).unwrap();
+    tampered.exit_code = 1;
+    save_manifest(&first.run_dir, &tampered).unwrap();
+    let report = audit_archive(dir.path()).unwrap();
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].kind, IssueKind::HashMismatch);
+}
+
+#[test]
+fn test_attest_run() {
+    use fastsave::archive::{load_manifest, ArchivedRun, MANIFEST_FILE};
+    use fastsave::attest::{create_attestation, verify_attestation, ATTESTATION_FILE};
+
+    let dir = TempDir::new().unwrap();
+    let generate_key = |name: &str| {
+        let (key, public_key) = (dir.path().join(format!("{}.pem", name)), dir.path().join(format!("{}_pub.pem", name)));
+        assert!(Command::new("openssl").args(["genpkey", "-algorithm", "EC", "-pkeyopt", "ec_paramgen_curve:P-256", "-out"]).arg(&key).status().unwrap().success());
+        assert!(Command::new("openssl").args(["pkey", "-pubout", "-in"]).arg(&key).arg("-out").arg(&public_key).status().unwrap().success());
+        (key, public_key)
+    };
+    let (key, public_key) = generate_key("key");
+    let (_, other_public) = generate_key("other");
+
+    let script_path = dir.path().join("simulate.sh");
+    fs::write(&script_path, "echo 42 > \"$2/result.txt\"").unwrap();
+    let archive = dir.path().join("archive");
+    let run = run_script(&test_cli(&script_path, &archive)).unwrap();
+    let run = ArchivedRun { result: load_manifest(&run.run_dir.join(MANIFEST_FILE)).unwrap(), run_dir: run.run_dir };
+    let path = run.run_dir.join(ATTESTATION_FILE);
+
+    let attestation = create_attestation(&run, &key, "reviewer@lab", &path).unwrap();
+    assert_eq!(Some(&attestation.statement.run_digest), run.result.run_digest.as_ref());
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("signer: reviewer@lab\n"), "{}", text);
+    assert_eq!(verify_attestation(&run, &path, &public_key).unwrap(), attestation);
+
+    // A third party can check the signature with openssl alone
+    let signature: Vec<u8> = (0..attestation.signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&attestation.signature[i..i + 2], 16).unwrap())
+        .collect();
+    fs::write(dir.path().join("statement"), text.lines().filter(|line| !line.starts_with("signature:")).map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+    fs::write(dir.path().join("signature"), signature).unwrap();
+    let status = Command::new("openssl")
+        .args(["dgst", "-sha256", "-verify"])
+        .arg(&public_key)
+        .arg("-signature")
+        .arg(dir.path().join("signature"))
+        .arg(dir.path().join("statement"))
+        .output()
+        .unwrap();
+    assert!(status.status.success());
+
+    // A key of someone else, an edited statement and a changed output all fail
+    assert!(verify_attestation(&run, &path, &other_public).unwrap_err().to_string().contains("signature"));
+    let missing = verify_attestation(&run, &path, &dir.path().join("missing.pem")).unwrap_err().to_string();
+    assert!(missing.contains("Failed to read the public key"), "{}", missing);
+    fs::write(&path, text.replace("reviewer@lab", "someone@else")).unwrap();
+    assert!(verify_attestation(&run, &path, &public_key).is_err());
+    fs::write(&path, &text).unwrap();
+    fs::write(run.run_dir.join("result.txt"), "43\n").unwrap();
+    let error = verify_attestation(&run, &path, &public_key).unwrap_err().to_string();
+    assert!(error.contains("result.txt"), "{}", error);
+}
+
+#[test]
+fn test_exec_command() {
+    use fastsave::{App, Commands};
+
+    let _guard = setup_test();
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("input.txt"), "data\n").unwrap();
+    init_git_repo(dir.path()).unwrap();
+    let archive = dir.path().join("archive");
+    let archive_dir = archive.to_string_lossy().into_owned();
+    let exec_cli = |args: &[&str]| match App::try_parse_from(["fastsave", "exec", "-a", &archive_dir].iter().chain(args)).unwrap().command {
+        Some(Commands::Exec(args)) => args.cli().unwrap(),
+        _ => panic!("not an exec command"),
+    };
+
+    let original_dir = std::env::current_dir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    let run = run_script(&exec_cli(&["--", "sh", "-c", "echo hi; cp input.txt \"$FASTSAVE_OUTPUT_DIR/out.txt\""]));
+    let failed = run_script(&exec_cli(&["--name", "check", "--command", "sh -c 'exit 3'"]));
+    std::env::set_current_dir(original_dir).unwrap();
+
+    let run = run.unwrap();
+    assert!(run.result.exec);
+    assert!(run.run_dir.ends_with(format!("{}_sh_run1", chrono::Local::now().format("%Y-%m-%d"))));
+    assert_eq!(run.result.stdout, "hi\n");
+    assert_eq!(run.result.file_hashes.keys().collect::<Vec<_>>(), ["out.txt"]);
+    assert!(run.result.git_info.is_some());
+    assert_eq!(run.result.command_line, "sh -c 'echo hi; cp input.txt \"$FASTSAVE_OUTPUT_DIR/out.txt\"'");
+    assert_eq!(run.result.exec_command().unwrap()[..2], ["sh".to_string(), "-c".to_string()]);
+    assert!(run.result.run_digest.is_some());
+
+    let failed = failed.unwrap();
+    assert!(failed.run_dir.ends_with(format!("{}_check_run1", chrono::Local::now().format("%Y-%m-%d"))));
+    assert_eq!(failed.result.exit_code, 3);
+    assert!(App::try_parse_from(["fastsave", "exec", "--name", "a/b", "--", "true"]).is_err());
+    assert!(App::try_parse_from(["fastsave", "exec"]).is_err());
+}
+
+#[test]
+fn test_json_manifest() {
+    use fastsave::archive::{find_runs, load_manifest, manifest_path, resolve_run, ManifestFormat, JSON_MANIFEST_FILE, MANIFEST_FILE};
+    use fastsave::pin::set_pinned;
+
+    let archive_dir = TempDir::new().unwrap();
+    let script_path = archive_dir.path().join("json.sh");
+    fs::write(&script_path, "echo 42 > \"$2/out.txt\"\necho done").unwrap();
+    let config_path = archive_dir.path().join("fastsave.yaml");
+    fs::write(&config_path, "format: json\n").unwrap();
+
+    let from_config = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+    let from_flag = run_script(&Cli { format: Some(ManifestFormat::Json), ..test_cli(&script_path, archive_dir.path()) }).unwrap();
+    let yaml = run_script(&Cli {
+        config_path: Some(config_path.to_string_lossy().to_string()),
+        format: Some(ManifestFormat::Yaml),
+        ..test_cli(&script_path, archive_dir.path())
+    }).unwrap();
+
+    for run in [&from_config, &from_flag] {
+        assert!(!run.run_dir.join(MANIFEST_FILE).exists());
+        let manifest: ExecutionResult = serde_json::from_str(&fs::read_to_string(run.run_dir.join(JSON_MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest.stdout, "done\n");
+        assert_eq!(manifest.file_hashes, run.result.file_hashes);
+    }
+    assert!(yaml.run_dir.join(MANIFEST_FILE).is_file());
+    assert!(!yaml.run_dir.join(JSON_MANIFEST_FILE).exists());
+
+    // Runs of both formats are found, and rewriting a manifest keeps its format
+    assert_eq!(find_runs(archive_dir.path()).unwrap().len(), 3);
+    let run_dir = resolve_run(archive_dir.path(), &from_flag.id()).unwrap();
+    assert_eq!(manifest_path(&run_dir), run_dir.join(JSON_MANIFEST_FILE));
+    set_pinned(&run_dir, true).unwrap();
+    assert!(!run_dir.join(MANIFEST_FILE).exists());
+    assert!(load_manifest(&manifest_path(&run_dir)).unwrap().pinned);
+}